@@ -6,6 +6,7 @@ use serde::{Deserialize, Serialize};
 #[derive(Deserialize, Serialize)]
 struct BigValue(Vec<u8>);
 
+#[allow(clippy::while_let_loop)]
 fn main() {
     // Populate the map.
     let compression = BincodeCompression::new(Lz4 { level: 10 });
@@ -0,0 +1,25 @@
+#ifndef COMPRESSIBLE_MAP_H
+#define COMPRESSIBLE_MAP_H
+
+/* C bindings for compressible-map, built with the "ffi" feature. See src/ffi.rs for details.
+ * A function that panics returns NULL, false or 0 instead of unwinding into C. */
+
+#include <stdbool.h>
+#include <stddef.h>
+#include <stdint.h>
+
+typedef struct CompressibleByteMap CompressibleByteMap;
+
+CompressibleByteMap *compressible_map_create(uint32_t lz4_level);
+void compressible_map_destroy(CompressibleByteMap *map);
+
+void compressible_map_insert(CompressibleByteMap *map, uint64_t key, const uint8_t *data, size_t len);
+/* The returned pointer is only valid until the next call that modifies the map. */
+const uint8_t *compressible_map_get(CompressibleByteMap *map, uint64_t key, size_t *out_len);
+bool compressible_map_remove(CompressibleByteMap *map, uint64_t key);
+
+void compressible_map_compress_lru(CompressibleByteMap *map);
+size_t compressible_map_len_cached(const CompressibleByteMap *map);
+size_t compressible_map_len_compressed(const CompressibleByteMap *map);
+
+#endif
@@ -0,0 +1,238 @@
+use crate::ShardedHashMap;
+
+use alloc::collections::BTreeMap;
+use core::hash::{BuildHasher, Hash};
+
+/// Where a `CompressibleMap` keeps its compressed values. The default is a `ShardedHashMap`, but any
+/// other store can be used, e.g. one backed by a memory-mapped file, as long as it can lend out
+/// references to the stored values.
+pub trait CompressedStorage<K, Vc> {
+    /// Inserts a value and returns the old value for `key`, if any.
+    fn insert(&mut self, key: K, value: Vc) -> Option<Vc>;
+
+    fn get(&self, key: &K) -> Option<&Vc>;
+
+    fn remove(&mut self, key: &K) -> Option<Vc>;
+
+    fn clear(&mut self);
+
+    fn len(&self) -> usize;
+
+    fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Iterates over all entries in any order.
+    fn iter<'a>(&'a self) -> impl Iterator<Item = (&'a K, &'a Vc)>
+    where
+        K: 'a,
+        Vc: 'a;
+
+    fn into_entries(self) -> impl Iterator<Item = (K, Vc)>;
+
+    /// Makes room for `additional` more values, if the store supports it.
+    fn reserve(&mut self, _additional: usize) {}
+}
+
+#[cfg(feature = "std")]
+impl<K, Vc, H> CompressedStorage<K, Vc> for std::collections::HashMap<K, Vc, H>
+where
+    K: Eq + Hash,
+    H: BuildHasher,
+{
+    fn insert(&mut self, key: K, value: Vc) -> Option<Vc> {
+        std::collections::HashMap::insert(self, key, value)
+    }
+
+    fn get(&self, key: &K) -> Option<&Vc> {
+        std::collections::HashMap::get(self, key)
+    }
+
+    fn remove(&mut self, key: &K) -> Option<Vc> {
+        std::collections::HashMap::remove(self, key)
+    }
+
+    fn clear(&mut self) {
+        std::collections::HashMap::clear(self)
+    }
+
+    fn len(&self) -> usize {
+        std::collections::HashMap::len(self)
+    }
+
+    fn iter<'a>(&'a self) -> impl Iterator<Item = (&'a K, &'a Vc)>
+    where
+        K: 'a,
+        Vc: 'a,
+    {
+        std::collections::HashMap::iter(self)
+    }
+
+    fn into_entries(self) -> impl Iterator<Item = (K, Vc)> {
+        IntoIterator::into_iter(self)
+    }
+
+    fn reserve(&mut self, additional: usize) {
+        std::collections::HashMap::reserve(self, additional)
+    }
+}
+
+/// The same as the standard `HashMap`, for use without `std`.
+impl<K, Vc, H> CompressedStorage<K, Vc> for hashbrown::HashMap<K, Vc, H>
+where
+    K: Eq + Hash,
+    H: BuildHasher,
+{
+    fn insert(&mut self, key: K, value: Vc) -> Option<Vc> {
+        hashbrown::HashMap::insert(self, key, value)
+    }
+
+    fn get(&self, key: &K) -> Option<&Vc> {
+        hashbrown::HashMap::get(self, key)
+    }
+
+    fn remove(&mut self, key: &K) -> Option<Vc> {
+        hashbrown::HashMap::remove(self, key)
+    }
+
+    fn clear(&mut self) {
+        hashbrown::HashMap::clear(self)
+    }
+
+    fn len(&self) -> usize {
+        hashbrown::HashMap::len(self)
+    }
+
+    fn iter<'a>(&'a self) -> impl Iterator<Item = (&'a K, &'a Vc)>
+    where
+        K: 'a,
+        Vc: 'a,
+    {
+        hashbrown::HashMap::iter(self)
+    }
+
+    fn into_entries(self) -> impl Iterator<Item = (K, Vc)> {
+        IntoIterator::into_iter(self)
+    }
+
+    fn reserve(&mut self, additional: usize) {
+        hashbrown::HashMap::reserve(self, additional)
+    }
+}
+
+/// Keeps a map with millions of compressed values from rehashing all of them at once when it grows.
+impl<K, Vc, H> CompressedStorage<K, Vc> for ShardedHashMap<K, Vc, H>
+where
+    K: Eq + Hash,
+    H: BuildHasher + Default,
+{
+    fn insert(&mut self, key: K, value: Vc) -> Option<Vc> {
+        ShardedHashMap::insert(self, key, value)
+    }
+
+    fn get(&self, key: &K) -> Option<&Vc> {
+        ShardedHashMap::get(self, key)
+    }
+
+    fn remove(&mut self, key: &K) -> Option<Vc> {
+        ShardedHashMap::remove(self, key)
+    }
+
+    fn clear(&mut self) {
+        ShardedHashMap::clear(self)
+    }
+
+    fn len(&self) -> usize {
+        ShardedHashMap::len(self)
+    }
+
+    fn iter<'a>(&'a self) -> impl Iterator<Item = (&'a K, &'a Vc)>
+    where
+        K: 'a,
+        Vc: 'a,
+    {
+        ShardedHashMap::iter(self)
+    }
+
+    fn into_entries(self) -> impl Iterator<Item = (K, Vc)> {
+        IntoIterator::into_iter(self)
+    }
+
+    fn reserve(&mut self, additional: usize) {
+        ShardedHashMap::reserve(self, additional)
+    }
+}
+
+/// Keeps the compressed values sorted by key, so they're saved or iterated in a stable order.
+impl<K, Vc> CompressedStorage<K, Vc> for BTreeMap<K, Vc>
+where
+    K: Ord,
+{
+    fn insert(&mut self, key: K, value: Vc) -> Option<Vc> {
+        BTreeMap::insert(self, key, value)
+    }
+
+    fn get(&self, key: &K) -> Option<&Vc> {
+        BTreeMap::get(self, key)
+    }
+
+    fn remove(&mut self, key: &K) -> Option<Vc> {
+        BTreeMap::remove(self, key)
+    }
+
+    fn clear(&mut self) {
+        BTreeMap::clear(self)
+    }
+
+    fn len(&self) -> usize {
+        BTreeMap::len(self)
+    }
+
+    fn iter<'a>(&'a self) -> impl Iterator<Item = (&'a K, &'a Vc)>
+    where
+        K: 'a,
+        Vc: 'a,
+    {
+        BTreeMap::iter(self)
+    }
+
+    fn into_entries(self) -> impl Iterator<Item = (K, Vc)> {
+        IntoIterator::into_iter(self)
+    }
+}
+
+// ████████╗███████╗███████╗████████╗███████╗
+// ╚══██╔══╝██╔════╝██╔════╝╚══██╔══╝██╔════╝
+//    ██║   █████╗  ███████╗   ██║   ███████╗
+//    ██║   ██╔══╝  ╚════██║   ██║   ╚════██║
+//    ██║   ███████╗███████║   ██║   ███████║
+//    ╚═╝   ╚══════╝╚══════╝   ╚═╝   ╚══════╝
+
+#[cfg(test)]
+mod tests {
+    use crate::test_util::{FakeFooCompression, Foo};
+    use crate::CompressibleMap;
+
+    use std::collections::{hash_map::RandomState, BTreeMap};
+
+    #[test]
+    fn map_with_btree_storage() {
+        let mut map =
+            CompressibleMap::<_, _, _, RandomState, BTreeMap<_, _>>::new(FakeFooCompression);
+        for i in 0..4 {
+            map.insert(i, Foo(i));
+        }
+        map.compress_lru();
+        map.compress_lru();
+        assert_eq!(map.get(0), Some(&Foo(2)));
+
+        let storage = map.into_all_compressed();
+        let keys: Vec<_> = storage.keys().cloned().collect();
+        assert_eq!(keys, vec![0, 1, 2, 3]);
+
+        let mut map =
+            CompressibleMap::<_, _, _, RandomState, _>::with_storage(FakeFooCompression, storage);
+        assert_eq!(map.len_compressed(), 4);
+        assert_eq!(map.get(3), Some(&Foo(5)));
+    }
+}
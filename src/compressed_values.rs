@@ -0,0 +1,143 @@
+use crate::{Compressed, CompressedStorage, Compression};
+
+use core::marker::PhantomData;
+
+/// The compressed tier of a `CompressibleMap`. Keeps a running total of the compressed sizes, which
+/// is exact, since compressed values can't be modified in place.
+pub struct CompressedValues<K, A, S>
+where
+    A: Compression,
+{
+    values: S,
+    bytes: usize,
+    marker: PhantomData<fn() -> (K, A)>,
+}
+
+impl<K, A, S> Clone for CompressedValues<K, A, S>
+where
+    A: Compression,
+    S: Clone,
+{
+    fn clone(&self) -> Self {
+        Self {
+            values: self.values.clone(),
+            bytes: self.bytes,
+            marker: PhantomData,
+        }
+    }
+}
+
+impl<K, A, S> Default for CompressedValues<K, A, S>
+where
+    A: Compression,
+    S: Default,
+{
+    fn default() -> Self {
+        Self {
+            values: S::default(),
+            bytes: 0,
+            marker: PhantomData,
+        }
+    }
+}
+
+impl<K, A, S> CompressedValues<K, A, S>
+where
+    A: Compression,
+    S: CompressedStorage<K, Compressed<A>>,
+{
+    pub fn from_map(values: S) -> Self {
+        let bytes = values.iter().map(|(_, v)| v.size()).sum();
+
+        Self {
+            values,
+            bytes,
+            marker: PhantomData,
+        }
+    }
+
+    pub fn into_map(self) -> S {
+        self.values
+    }
+
+    /// The total size of all compressed values.
+    pub fn bytes(&self) -> usize {
+        self.bytes
+    }
+
+    pub fn get(&self, key: &K) -> Option<&Compressed<A>> {
+        self.values.get(key)
+    }
+
+    pub fn insert(&mut self, key: K, value: Compressed<A>) -> Option<Compressed<A>> {
+        self.bytes += value.size();
+        let old = self.values.insert(key, value);
+        if let Some(old) = &old {
+            self.bytes -= old.size();
+        }
+
+        old
+    }
+
+    pub fn remove(&mut self, key: &K) -> Option<Compressed<A>> {
+        let removed = self.values.remove(key);
+        if let Some(removed) = &removed {
+            self.bytes -= removed.size();
+        }
+
+        removed
+    }
+
+    pub fn clear(&mut self) {
+        self.values.clear();
+        self.bytes = 0;
+    }
+
+    pub fn reserve(&mut self, additional: usize) {
+        self.values.reserve(additional);
+    }
+
+    pub fn len(&self) -> usize {
+        self.values.len()
+    }
+
+    pub fn keys(&self) -> impl Iterator<Item = &K> {
+        self.values.iter().map(|(k, _)| k)
+    }
+
+    pub fn values(&self) -> impl Iterator<Item = &Compressed<A>> {
+        self.values.iter().map(|(_, v)| v)
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (&K, &Compressed<A>)> {
+        self.values.iter()
+    }
+
+    #[allow(clippy::should_implement_trait)]
+    pub fn into_iter(self) -> impl Iterator<Item = (K, Compressed<A>)> {
+        self.values.into_entries()
+    }
+}
+
+impl<K, A> CompressedValues<K, A, alloc::collections::BTreeMap<K, Compressed<A>>>
+where
+    K: Ord,
+    A: Compression,
+{
+    pub fn range(
+        &self,
+        range: impl core::ops::RangeBounds<K>,
+    ) -> impl DoubleEndedIterator<Item = (&K, &Compressed<A>)> {
+        self.values.range(range)
+    }
+}
+
+#[cfg(test)]
+impl<K, A, H> CompressedValues<K, A, crate::ShardedHashMap<K, Compressed<A>, H>>
+where
+    A: Compression,
+{
+    pub fn capacity(&self) -> usize {
+        self.values.capacity()
+    }
+}
@@ -1,11 +1,81 @@
+#[cfg(feature = "std")]
+use crate::local_cache::SyncLocalCache;
 use crate::{
+    compressed_values::CompressedValues,
+    events::{MapEvent, Subscribers},
     local_cache::{LocalAccess, LocalCache},
-    lru_cache::{EntryState, LruCache},
-    Compressed, Compression,
+    lru_cache::{EntryState, LastAccess, LruCache},
+    modification_stamps::ModificationStamps,
+    op_log::{Op, OpLog, OpRecorder},
+    reader::CompressibleMapReader,
+    size_histogram::SizeHistogram,
+    Compressed, CompressedStorage, Compression, DefaultHashBuilder, LossyCompression,
+    PartiallyDecompressible, SeekableCompression, ShardedHashMap, Summarize, SummarizedCompression,
 };
 
-use std::collections::{hash_map::RandomState, HashMap};
-use std::hash::{BuildHasher, Hash};
+use alloc::{borrow::Cow, collections::VecDeque, sync::Arc, vec::Vec};
+use core::hash::{BuildHasher, Hash};
+use core::ops::{Index, IndexMut, Range};
+use hashbrown::{HashMap, HashSet};
+#[cfg(feature = "std")]
+use std::{
+    sync::mpsc::Receiver,
+    time::{Duration, Instant},
+};
+
+#[cfg(feature = "async")]
+mod async_get;
+mod builder;
+mod bulk_load;
+mod compressor;
+#[cfg(feature = "std")]
+mod concurrent;
+mod cursor;
+mod entry;
+mod eviction;
+mod fallible;
+mod frozen;
+mod iter;
+mod jobs;
+#[cfg(feature = "left-right")]
+mod left_right_map;
+mod memory;
+mod namespaces;
+mod ordered;
+#[cfg(feature = "rayon")]
+mod par;
+#[cfg(feature = "std")]
+mod persistence;
+mod retrain;
+mod serialization;
+mod shared;
+mod slab;
+mod snapshot;
+mod stats;
+
+pub use builder::CompressibleMapBuilder;
+pub use bulk_load::BulkLoadOptions;
+pub use compressor::CompressorConfig;
+#[cfg(feature = "std")]
+pub use concurrent::ConcurrentCompressibleMap;
+pub use cursor::Cursor;
+pub use entry::{Entry, OccupiedEntry, VacantEntry};
+pub use eviction::EvictionDecision;
+pub use frozen::FrozenCompressibleMap;
+pub use iter::{IntoIter, Iter};
+pub use jobs::{Job, JobOutcome};
+#[cfg(feature = "left-right")]
+pub use left_right_map::{LeftRightReader, LeftRightWriter};
+pub use memory::{Full, MemoryPressure};
+pub use namespaces::{Namespace, NamespaceStats};
+pub use ordered::CompressibleBTreeMap;
+#[cfg(feature = "rayon")]
+pub use par::PerThreadLocalCaches;
+pub use retrain::{RetrainPolicy, RetrainReport};
+pub use shared::SharedCompressibleMap;
+pub use slab::{CompressibleSlab, IndexHasher, SlabStorage};
+pub use snapshot::CompressibleMapSnapshot;
+pub use stats::Stats;
 
 /// A hash map that allows compressing the least recently used values. Useful when you need to store
 /// a lot of large values in memory. You must define your own compression method for the value type
@@ -21,52 +91,306 @@ use std::hash::{BuildHasher, Hash};
 /// Any **immutable** access (`&self`, e.g. from multiple threads), like `get_const`, cannot update
 /// the cache. Instead, it will record accesses and store decompressed values in a `LocalCache` that
 /// can be used later to update the cache with `flush_local_cache`.
-pub struct CompressibleMap<K, V, A, H = RandomState>
+///
+/// Every modification of an entry is given a "stamp" from an increasing counter. Save the result of
+/// `modification_stamp` and later call `iter_changed_since` to find all entries that were modified
+/// in the meantime, e.g. for incremental autosaving or replication.
+///
+/// Observers that can't poll the map, e.g. because they live on another thread, can `subscribe` to
+/// a channel of `MapEvent`s instead.
+///
+/// Compressed values are kept in a `ShardedHashMap` by default, but any `CompressedStorage` can be
+/// used by naming it as the `S` parameter or passing it to `with_storage`. The keys, the LRU order
+/// and the default storage all keep up to 4 entries inline, so small maps, e.g. one per region,
+/// don't allocate until they outgrow that.
+pub struct CompressibleMap<K, V, A, H = DefaultHashBuilder, S = ShardedHashMap<K, Compressed<A>, H>>
 where
     A: Compression<Data = V>,
 {
     cache: LruCache<K, V, H>,
-    compressed: HashMap<K, Compressed<A>, H>,
+    compressed: CompressedValues<K, A, S>,
     compression_params: A,
+    modification_stamps: ModificationStamps<K, H>,
+    subscribers: Subscribers<K>,
+    recency_guard: Option<RecencyGuard>,
+    #[cfg(feature = "std")]
+    track_access_times: bool,
+    compressions_since_retrain: u64,
+    // Keys that were compressed with parameters from before the last retraining.
+    stale_compressed: VecDeque<K>,
+    jobs: VecDeque<Job<K>>,
+    namespaces: Option<namespaces::Namespaces<K>>,
+    op_recorder: OpRecorder<K>,
+    byte_cap: Option<usize>,
+    max_cached: Option<usize>,
+    // Values that were compressed, kept so their allocations can be reused for decompression.
+    recycled: Vec<V>,
+    max_recycled: usize,
+    // The compressed form of cached values that were decompressed for reading, if enabled by
+    // `set_reuse_compressed`.
+    clean_compressed: Option<HashMap<K, Compressed<A>, H>>,
+    pinned: HashSet<K, H>,
+    // Cached values for which this returns `true` are skipped by `compress_lru`, like pinned ones.
+    in_use: Option<fn(&V) -> bool>,
+    spill: Option<memory::Spill<K, A>>,
+    eviction: Option<eviction::Eviction<K, V>>,
+    compressor: Option<compressor::Compressor<K, V, A>>,
+    stats: Stats,
+}
+
+/// The time since a cached value was last accessed.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct AccessAge {
+    /// The number of accesses to the cache since this value was accessed.
+    pub accesses: u64,
+    /// `None` unless access times are tracked, see `CompressibleMap::set_track_access_times`.
+    #[cfg(feature = "std")]
+    pub elapsed: Option<Duration>,
+}
+
+/// What `CompressibleMap::iter_metadata` knows about an entry.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct EntryMetadata<'a, K> {
+    pub key: &'a K,
+    /// `None` if the value is cached.
+    pub compressed_size: Option<usize>,
+    /// `None` if the value is compressed.
+    pub access_age: Option<AccessAge>,
+}
+
+impl<'a, K> EntryMetadata<'a, K> {
+    pub fn is_cached(&self) -> bool {
+        self.compressed_size.is_none()
+    }
+}
+
+/// A decompressed value whose address won't change while the `PinnedRef` exists, returned by
+/// `CompressibleMap::get_pinned`. The map stays mutably borrowed for the lifetime of the guard, so
+/// nothing can be inserted, compressed, or removed in the meantime. This makes it safe to hand the
+/// pointers to C code or a GPU upload, as long as they aren't used after the guard is dropped.
+pub struct PinnedRef<'a, V> {
+    value: &'a mut V,
+}
+
+impl<'a, V> PinnedRef<'a, V> {
+    pub fn as_ptr(&self) -> *const V {
+        self.value
+    }
+
+    pub fn as_mut_ptr(&mut self) -> *mut V {
+        self.value
+    }
+}
+
+impl<'a, V> core::ops::Deref for PinnedRef<'a, V> {
+    type Target = V;
+
+    fn deref(&self) -> &V {
+        self.value
+    }
+}
+
+impl<'a, V> core::ops::DerefMut for PinnedRef<'a, V> {
+    fn deref_mut(&mut self) -> &mut V {
+        self.value
+    }
+}
+
+/// Bounds on the number of cached values for `CompressibleMap::compress_to_watermarks`.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct Watermarks {
+    /// Start compressing when there are more cached values than this.
+    pub high: usize,
+    /// Once started, keep compressing until there are at most this many cached values.
+    pub low: usize,
 }
 
-impl<K, V, H, A> CompressibleMap<K, V, A, H>
+/// Protects recently accessed values from being compressed by `compress_lru`. Without a guard, a
+/// value that was just decompressed can be compressed again right away if the map is under memory
+/// pressure, which wastes a lot of time.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum RecencyGuard {
+    /// Don't compress a value if it was accessed within the last `n` accesses to the cache.
+    Accesses(u64),
+    /// Don't compress a value if it was accessed within this amount of time.
+    #[cfg(feature = "std")]
+    Duration(Duration),
+}
+
+impl<K, V, A, H, S> CompressibleMap<K, V, A, H, S>
 where
     K: Clone + Eq + Hash,
     H: BuildHasher + Default,
     A: Compression<Data = V>,
+    S: CompressedStorage<K, Compressed<A>>,
 {
-    pub fn new(compression_params: A) -> Self {
+    pub fn new(compression_params: A) -> Self
+    where
+        S: Default,
+    {
+        Self::with_storage(compression_params, S::default())
+    }
+
+    /// Creates a map that keeps its compressed values in `storage`. Any values that are already in
+    /// the storage become compressed entries of the map.
+    pub fn with_storage(compression_params: A, storage: S) -> Self {
+        let mut cache = LruCache::default();
+        for (key, _) in storage.iter() {
+            cache.evict(key.clone());
+        }
+
         Self {
-            cache: LruCache::default(),
-            compressed: HashMap::default(),
+            cache,
+            compressed: CompressedValues::from_map(storage),
             compression_params,
+            modification_stamps: ModificationStamps::default(),
+            subscribers: Subscribers::default(),
+            recency_guard: None,
+            #[cfg(feature = "std")]
+            track_access_times: false,
+            compressions_since_retrain: 0,
+            stale_compressed: VecDeque::new(),
+            jobs: VecDeque::new(),
+            namespaces: None,
+            op_recorder: OpRecorder::default(),
+            byte_cap: None,
+            max_cached: None,
+            recycled: Vec::new(),
+            max_recycled: 0,
+            clean_compressed: None,
+            pinned: HashSet::with_hasher(Default::default()),
+            in_use: None,
+            spill: None,
+            eviction: None,
+            compressor: None,
+            stats: Stats::default(),
         }
     }
 
+    /// Creates a map with room for `capacity` entries, of which `max_cached` are expected to be
+    /// cached at any one time. See `reserve`.
+    pub fn with_capacity(compression_params: A, capacity: usize, max_cached: usize) -> Self
+    where
+        S: Default,
+    {
+        let mut map = Self::new(compression_params);
+        map.reserve(capacity, max_cached);
+
+        map
+    }
+
+    /// Creates a map that automatically compresses LRU values to keep at most `max_cached` values
+    /// cached. See `set_max_cached`.
+    pub fn with_max_cached(compression_params: A, max_cached: usize) -> Self
+    where
+        S: Default,
+    {
+        let mut map = Self::new(compression_params);
+        map.set_max_cached(Some(max_cached));
+
+        map
+    }
+
+    /// Reserves room for `additional` more entries, of which `additional_cached` will be cached.
+    ///
+    /// The keys and the default storage of compressed values are `ShardedHashMap`s, so growing a
+    /// large map only rehashes a fraction of them at a time, but other storages, like a `HashMap`,
+    /// rehash every entry at once. With those, reserve space up front, e.g. during a loading
+    /// screen, so inserts and compressions won't trigger a rehash at some unfortunate moment later
+    /// on.
+    pub fn reserve(&mut self, additional: usize, additional_cached: usize) {
+        self.cache.reserve(additional, additional_cached);
+        self.compressed.reserve(additional);
+        self.modification_stamps.reserve(additional);
+    }
+
+    /// Starts recording every operation that changes the contents of the map or the LRU order, so
+    /// the behavior of the cache can be replayed offline with `OpLog::replay`.
+    pub fn start_recording(&mut self) {
+        self.op_recorder.start();
+    }
+
+    /// Stops recording and returns the log, or `None` if the map wasn't recording.
+    pub fn stop_recording(&mut self) -> Option<OpLog<K>> {
+        self.op_recorder.stop()
+    }
+
     pub fn compression_params(&self) -> &A {
         &self.compression_params
     }
 
-    pub fn from_all_compressed(
-        compression_params: A,
-        compressed: HashMap<K, Compressed<A>, H>,
-    ) -> Self {
-        let mut cache = LruCache::<K, V, H>::default();
-        for key in compressed.keys() {
-            cache.evict(key.clone());
-        }
+    pub fn recency_guard(&self) -> Option<RecencyGuard> {
+        self.recency_guard
+    }
 
-        Self {
+    pub fn set_recency_guard(&mut self, guard: Option<RecencyGuard>) {
+        self.recency_guard = guard;
+        #[cfg(feature = "std")]
+        self.update_time_tracking();
+    }
+
+    /// Records the time of every access, which `compress_idle` and `AccessAge::elapsed` depend on.
+    /// Reading the clock on every access isn't free, so this is off by default, but a
+    /// `RecencyGuard::Duration` turns it on while it's set.
+    #[cfg(feature = "std")]
+    pub fn set_track_access_times(&mut self, track: bool) {
+        self.track_access_times = track;
+        self.update_time_tracking();
+    }
+
+    #[cfg(feature = "std")]
+    fn update_time_tracking(&mut self) {
+        let time_guard = matches!(self.recency_guard, Some(RecencyGuard::Duration(_)));
+        self.cache
+            .set_track_time(self.track_access_times || time_guard);
+    }
+
+    /// Returns a channel that receives a `MapEvent` for every insertion, removal, compression and
+    /// decompression from now on. Dropping the `Receiver` unsubscribes.
+    #[cfg(feature = "std")]
+    pub fn subscribe(&mut self) -> Receiver<MapEvent<K>> {
+        self.subscribers.subscribe()
+    }
+
+    pub fn from_all_compressed(compression_params: A, compressed: S) -> Self {
+        Self::with_storage(compression_params, compressed)
+    }
+
+    /// The inverse of `from_all_compressed`. Any cached values are compressed first.
+    pub fn into_all_compressed(mut self) -> S {
+        self.stop_compressor();
+        let CompressibleMap {
             cache,
-            compressed,
+            mut compressed,
             compression_params,
+            modification_stamps,
+            mut clean_compressed,
+            ..
+        } = self;
+        for (key, value) in cache.into_iter() {
+            let compressed_value = clean_compressed
+                .as_mut()
+                .and_then(|clean| clean.remove(&key))
+                .filter(|_| !modification_stamps.is_dirty(&key))
+                .unwrap_or_else(|| compression_params.compress(&value));
+            compressed.insert(key, compressed_value);
         }
+
+        compressed.into_map()
     }
 
-    /// Insert a new value and return the old one if it exists.
+    /// Insert a new value and return the old one if it exists. The old value is returned the way it
+    /// was stored, so a compressed value isn't decompressed just to be handed back.
     pub fn insert(&mut self, key: K, value: V) -> Option<MaybeCompressed<V, Compressed<A>>> {
-        self.cache
+        self.await_compressed(&key);
+        self.make_room_for(&key, self.cache.weigh(&key, &value));
+        self.forget_clean_compressed(&key);
+        self.modification_stamps.stamp(key.clone());
+        self.subscribers.notify(|| MapEvent::Inserted(key.clone()));
+        self.op_recorder.record(|| Op::Insert(key.clone()));
+
+        let old_value = self
+            .cache
             .insert(key.clone(), value)
             .map(|old_cache_entry| match old_cache_entry {
                 EntryState::Cached(v) => MaybeCompressed::Decompressed(v),
@@ -75,7 +399,11 @@ where
 
                     MaybeCompressed::Compressed(compressed_value)
                 }
-            })
+            });
+        // Now that the new value is weighed, its namespace may be over its byte budget.
+        self.make_room_in_namespace(&key);
+
+        old_value
     }
 
     /// Insert a compressed value, returning any pre-existing entry.
@@ -84,11 +412,21 @@ where
         key: K,
         value: Compressed<A>,
     ) -> Option<MaybeCompressed<V, Compressed<A>>> {
+        self.await_compressed(&key);
+        self.make_room_for_bytes(&key, value.size());
+        self.forget_clean_compressed(&key);
+        self.modification_stamps.stamp(key.clone());
+        self.subscribers.notify(|| MapEvent::Inserted(key.clone()));
+        self.op_recorder.record(|| Op::InsertCompressed {
+            key: key.clone(),
+            compressed_size: value.size(),
+        });
+        self.modification_stamps.mark_clean(&key);
+
         let old_cached_value = self
             .cache
             .evict(key.clone())
-            .map(|e| e.some_if_cached())
-            .flatten();
+            .and_then(|e| e.some_if_cached());
 
         self.compressed
             .insert(key, value)
@@ -107,270 +445,1785 @@ where
         }
     }
 
+    /// Compresses the least recently used value, unless it is protected by the `RecencyGuard`.
     pub fn compress_lru(&mut self) {
+        if self.lru_is_guarded() {
+            return;
+        }
+
         if let Some((lru_key, lru_value)) = self.cache.evict_lru() {
-            self.compressed
-                .insert(lru_key, self.compression_params.compress(&lru_value));
+            self.compress_evicted(lru_key, lru_value);
         }
     }
 
-    pub fn remove_lru(&mut self) -> Option<(K, V)> {
-        self.cache.remove_lru()
+    /// Like `compress_lru`, but returns the key that was compressed and the size of its compressed
+    /// value, e.g. for logging or to mirror the compression to disk. Returns `None` if nothing was
+    /// compressed, including when the eviction policy dropped the value instead. With a background
+    /// compressor, this waits for the value to be compressed.
+    pub fn compress_lru_info(&mut self) -> Option<(K, usize)> {
+        if self.lru_is_guarded() {
+            return None;
+        }
+
+        let (key, value) = self.cache.evict_lru()?;
+        self.compress_evicted(key.clone(), value);
+        self.await_compressed(&key);
+        let size = self.compressed.get(&key)?.size();
+
+        Some((key, size))
     }
 
-    pub fn get_mut(&mut self, key: K) -> Option<&mut V> {
-        let CompressibleMap {
-            cache, compressed, ..
-        } = self;
+    /// Compresses up to `n` LRU values. Stops early if there are no cached values left or the LRU
+    /// value is protected by the `RecencyGuard`. Returns the number of values compressed.
+    pub fn compress_lru_n(&mut self, n: usize) -> usize {
+        let mut remaining = n;
+        self.compress_while(|_| {
+            let more = remaining > 0;
+            remaining = remaining.saturating_sub(1);
 
-        cache.get_or_repopulate_with(key.clone(), || {
-            compressed.remove(&key).map(|v| v.decompress()).unwrap()
+            more
         })
     }
 
-    pub fn get(&mut self, key: K) -> Option<&V> {
-        // Hopefully downgrading the reference is a NOOP.
-        self.get_mut(key).map(|v| &*v)
+    /// Compresses LRU values as long as `predicate` returns `true`, e.g. until a number of values
+    /// are left or some time has passed. The predicate is called before each compression. Stops
+    /// early if there are no cached values left or the LRU value is protected by the
+    /// `RecencyGuard`. Returns the number of values compressed.
+    pub fn compress_while(&mut self, mut predicate: impl FnMut(&Self) -> bool) -> usize {
+        let mut num_compressed = 0;
+        while self.len_cached() > 0 && !self.lru_is_guarded() && predicate(self) {
+            self.compress_lru();
+            num_compressed += 1;
+        }
+
+        num_compressed
     }
 
-    pub fn get_or_insert_with(&mut self, key: K, on_missing: impl FnOnce() -> V) -> &mut V {
-        let CompressibleMap {
-            cache, compressed, ..
-        } = self;
+    /// Compresses every cached value that hasn't been accessed for at least `older_than`, e.g. to
+    /// demote entries nobody has visited recently. Stops early if the LRU value is protected by the
+    /// `RecencyGuard`. Returns the number of values compressed. Only values accessed while access
+    /// times are tracked can be idle, see `set_track_access_times`.
+    #[cfg(feature = "std")]
+    pub fn compress_idle(&mut self, older_than: Duration) -> usize {
+        self.compress_while(|map| {
+            map.cache
+                .lru_last_access()
+                .and_then(|access| access.time)
+                .is_some_and(|time| time.elapsed() >= older_than)
+        })
+    }
 
-        let on_evicted = || compressed.remove(&key).unwrap().decompress();
+    /// Compresses every cached value, even those protected by the `RecencyGuard`, e.g. before
+    /// saving the map. Returns the number of values compressed.
+    pub fn compress_all(&mut self) -> usize {
+        let mut num_compressed = 0;
+        while let Some((lru_key, lru_value)) = self.cache.evict_lru() {
+            self.compress_evicted(lru_key, lru_value);
+            num_compressed += 1;
+        }
 
-        cache.get_or_insert_with(key.clone(), on_evicted, on_missing)
+        num_compressed
     }
 
-    pub fn insert_if_vacant(&mut self, key: K, value: V) -> &mut V {
-        self.get_or_insert_with(key, || value)
+    /// Decompresses every compressed value into the cache, e.g. after loading the map. Returns the
+    /// number of values decompressed.
+    pub fn decompress_all(&mut self) -> usize {
+        self.decompress_n(usize::MAX)
     }
 
-    /// Used for thread-safe access or to borrow multiple values at once. The cache will not be
-    /// updated, but accesses will be recorded in the provided `LocalCache`. The interior
-    /// mutability of the local cache has a cost (more heap indirection), but it allows us to borrow
-    /// multiple values at once. Call `flush_local_cache` to update the "global" cache with
-    /// the local cache.
-    pub fn get_const<'a>(&'a self, key: K, local_cache: &'a LocalCache<K, V, H>) -> Option<&'a V> {
-        self.cache.get_const(&key).map(|entry| {
-            match entry {
-                EntryState::Cached(v) => {
-                    // For the sake of updating LRU order when we flush this local cache.
-                    local_cache.remember_cached_access(key.clone());
+    /// Decompresses up to `n` compressed values into the cache, in no particular order. Returns the
+    /// number of values decompressed.
+    pub fn decompress_n(&mut self, n: usize) -> usize {
+        self.finish_compressing();
+        let keys: Vec<K> = self
+            .compressed
+            .iter()
+            .take(n)
+            .map(|(key, _)| key.clone())
+            .collect();
+        for key in keys.iter() {
+            self.get(key.clone());
+        }
 
-                    v
-                }
-                EntryState::Evicted => {
-                    // Check the local cache before trying to decompress.
-                    local_cache.get_or_insert_with(key.clone(), || {
-                        self.compressed.get(&key).unwrap().decompress()
-                    })
-                }
-            }
-        })
+        keys.len()
     }
 
-    /// Returns a copy of the value at `key`.
-    /// WARNING: the cache will not be updated. This is useful for read-modify-write scenarios where
-    /// you would just insert the modified value back into the map, which defeats the purpose of
-    /// caching it on read.
-    pub fn get_copy_without_caching(&self, key: &K) -> Option<MaybeCompressed<V, Compressed<A>>>
-    where
-        V: Clone,
-        Compressed<A>: Clone,
-    {
-        self.cache.get_const(key).map(|entry| match entry {
-            EntryState::Cached(v) => MaybeCompressed::Decompressed(v.clone()),
-            EntryState::Evicted => {
-                MaybeCompressed::Compressed(self.compressed.get(key).unwrap().clone())
-            }
-        })
-    }
+    /// Compresses LRU values once there are more than `watermarks.high` cached values, until there
+    /// are only `watermarks.low` left. Compressing in batches like this leaves room for some new
+    /// values, so a map that's right at its limit doesn't compress a value on every access. Stops
+    /// early if the LRU value is protected by the `RecencyGuard`. Returns the number of values
+    /// compressed.
+    pub fn compress_to_watermarks(&mut self, watermarks: &Watermarks) -> usize {
+        debug_assert!(watermarks.low <= watermarks.high);
 
-    /// Updates the cache and it's approximate LRU order after calling `get_const` some number of
-    /// times. WARNING/TODO: There is currently no mechanism to prevent overwriting newer compressed
-    /// data with old data from a local cache.
-    pub fn flush_local_cache(&mut self, local_cache: LocalCache<K, V, H>) {
-        let CompressibleMap {
-            cache, compressed, ..
-        } = self;
-        for (key, access) in local_cache.into_iter() {
-            match access {
-                LocalAccess::Cached => {
-                    // We accessed this key and it was cached, so let's reflect that in the cache's
-                    // LRU order.
-                    cache.get(&key);
-                }
-                LocalAccess::Missed(value) => {
-                    // We accessed this key and it was missed, so let's repopulate the cache. Don't
-                    // replace a value that's already in the cache, since it might be newer than
-                    // what we're trying to flush (which must have come from a read).
-                    cache.get_or_repopulate_with(key.clone(), || {
-                        compressed.remove(&key);
+        if self.len_cached() <= watermarks.high {
+            return 0;
+        }
 
-                        value
-                    });
-                }
-            }
+        let mut num_compressed = 0;
+        while self.len_cached() > watermarks.low && !self.lru_is_guarded() {
+            self.compress_lru();
+            num_compressed += 1;
         }
+
+        num_compressed
     }
 
-    pub fn drop(&mut self, key: &K) {
-        self.cache.remove(key);
-        self.compressed.remove(key);
+    /// Compresses the cached value for `key`, if there is one, regardless of where it is in the LRU
+    /// order. Returns `true` if it was compressed.
+    pub fn compress_key(&mut self, key: &K) -> bool {
+        match self.cache.get_const(key) {
+            Some(EntryState::Cached(_)) => {}
+            _ => return false,
+        }
+
+        if let Some(EntryState::Cached(value)) = self.cache.evict(key.clone()) {
+            self.compress_evicted(key.clone(), value);
+        }
+
+        true
     }
 
-    /// Removes the value and returns it if it exists.
-    pub fn remove(&mut self, key: &K) -> Option<MaybeCompressed<V, Compressed<A>>> {
-        self.cache.remove(key).map(|entry| match entry {
-            EntryState::Cached(v) => MaybeCompressed::Decompressed(v),
-            EntryState::Evicted => {
-                MaybeCompressed::Compressed(self.compressed.remove(key).unwrap())
+    /// Stores `value` in compressed form after it was evicted from the cache, unless the eviction
+    /// policy decides otherwise.
+    fn compress_evicted(&mut self, key: K, value: V) {
+        match self.eviction_decision(&key, &value) {
+            EvictionDecision::Compress => {}
+            decision => return self.discard_evicted(key, value, decision),
+        }
+
+        // A value that wasn't modified since it was decompressed can reuse its compressed form.
+        let reused = self
+            .clean_compressed
+            .as_mut()
+            .and_then(|clean| clean.remove(&key))
+            .filter(|_| !self.modification_stamps.is_dirty(&key));
+        let (key, value, compressed) = match reused {
+            Some(compressed) => (key, value, compressed),
+            None => {
+                self.compressions_since_retrain += 1;
+                let (key, value) = match self.hand_off(key, value) {
+                    Some(key_value) => key_value,
+                    None => return,
+                };
+                let compressed = self.compression_params.compress(&value);
+                self.record_compression(&key, &value, &compressed);
+
+                (key, value, compressed)
             }
-        })
+        };
+        self.subscribers
+            .notify(|| MapEvent::Compressed(key.clone()));
+        self.op_recorder.record(|| Op::Compress {
+            key: key.clone(),
+            compressed_size: compressed.size(),
+        });
+        self.modification_stamps.mark_clean(&key);
+        self.compressed.insert(key, compressed);
+        if self.recycled.len() < self.max_recycled {
+            self.recycled.push(value);
+        }
     }
 
-    pub fn clear(&mut self) {
-        self.cache.clear();
-        self.compressed.clear();
+    /// Keeps up to `max_recycled` values after they're compressed, instead of dropping them, so
+    /// `Compression::decompress_into` can reuse their allocations the next time a value is
+    /// decompressed. This avoids the churn of allocating and freeing when many values of the same
+    /// size are repeatedly compressed and decompressed. Disabled (0) by default.
+    pub fn set_max_recycled(&mut self, max_recycled: usize) {
+        self.max_recycled = max_recycled;
+        self.recycled.truncate(max_recycled);
     }
 
-    pub fn len(&self) -> usize {
-        self.len_cached() + self.len_compressed()
+    /// Keeps the compressed form of values that are decompressed only for reading, e.g. by `get` or
+    /// `flush_local_cache`, so if they're compressed again without being modified in the meantime,
+    /// the compressed bytes are reused instead of compressing the value from scratch. This trades
+    /// memory for less compression work in read-heavy workloads. Disabled by default.
+    pub fn set_reuse_compressed(&mut self, reuse: bool) {
+        if !reuse {
+            self.clean_compressed = None;
+        } else if self.clean_compressed.is_none() {
+            self.clean_compressed = Some(HashMap::with_hasher(Default::default()));
+        }
     }
 
-    pub fn len_cached(&self) -> usize {
-        self.cache.len_cached()
+    /// Drops the kept compressed form of `key`, since the value is about to be modified.
+    fn forget_clean_compressed(&mut self, key: &K) {
+        if let Some(clean) = &mut self.clean_compressed {
+            clean.remove(key);
+        }
     }
 
-    pub fn len_compressed(&self) -> usize {
-        self.compressed.len()
+    /// Whether the next value to be compressed is protected, either by the `RecencyGuard` or
+    /// because every cached value is pinned. Pinned values are moved out of the way first.
+    fn lru_is_guarded(&mut self) -> bool {
+        if !self.skip_pinned() {
+            return true;
+        }
+
+        self.cache
+            .lru_last_access()
+            .is_some_and(|access| self.is_guarded(access))
     }
 
-    pub fn is_empty(&self) -> bool {
-        self.len() == 0
+    /// Whether a value last accessed at `access` is protected by the `RecencyGuard`.
+    fn is_guarded(&self, access: LastAccess) -> bool {
+        match self.recency_guard {
+            Some(RecencyGuard::Accesses(n)) => self.cache.clock() - access.tick < n,
+            #[cfg(feature = "std")]
+            Some(RecencyGuard::Duration(d)) => access.time.is_some_and(|t| t.elapsed() < d),
+            None => false,
+        }
     }
 
-    pub fn keys<'a>(&'a self) -> impl Iterator<Item = &K>
-    where
-        Compressed<A>: 'a,
-    {
-        self.cache.keys()
+    /// Whether `compress_lru` skips the cached `value` for `key` wherever it is in the LRU order.
+    fn is_skipped(&self, key: &K, value: &V) -> bool {
+        self.pinned.contains(key) || self.in_use.is_some_and(|f| f(value))
     }
 
-    /// Iterate over all (key, value) pairs, but compressed values will not be decompressed inline.
-    /// Does not affect the cache.
-    pub fn iter<'a>(&'a self) -> impl Iterator<Item = (&K, MaybeCompressed<&V, &Compressed<A>>)>
-    where
-        Compressed<A>: 'a,
-    {
-        self.cache
-            .iter()
-            .map(|(k, v)| (k, MaybeCompressed::Decompressed(v)))
-            .chain(
-                self.compressed
-                    .iter()
-                    .map(|(k, v)| (k, MaybeCompressed::Compressed(v))),
-            )
+    /// Re-queues pinned or in-use values that are next in line to be compressed as if they were
+    /// just inserted, until another value is next. Returns `false` if there's no such value.
+    fn skip_pinned(&mut self) -> bool {
+        if self.pinned.is_empty() && self.in_use.is_none() {
+            return self.cache.len_cached() > 0;
+        }
+        // Each value needs to be skipped at most once, unless the eviction policy is random.
+        for _ in 0..=self.cache.len_cached() {
+            let key = match self.cache.peek_lru() {
+                Some((key, value)) if self.is_skipped(key, value) => key.clone(),
+                Some(_) => return true,
+                None => return false,
+            };
+            self.cache.requeue(&key);
+        }
+
+        false
     }
 
-    pub fn into_iter(self) -> impl Iterator<Item = (K, MaybeCompressed<V, Compressed<A>>)> {
-        self.cache
-            .into_iter()
-            .map(|(k, v)| (k, MaybeCompressed::Decompressed(v)))
-            .chain(
-                self.compressed
-                    .into_iter()
-                    .map(|(k, v)| (k, MaybeCompressed::Compressed(v))),
-            )
+    /// The position of `key` in the LRU order of the cache, where 0 is the most recently used and,
+    /// without a cache policy, `len_cached() - 1` will be compressed next. Returns `None` if the
+    /// value isn't cached. This takes time linear in the rank.
+    pub fn recency_rank(&self, key: &K) -> Option<usize> {
+        self.cache.recency_rank(key)
     }
-}
 
-pub enum MaybeCompressed<D, C> {
-    Decompressed(D),
-    Compressed(C),
-}
+    /// Makes the cached value for `key` the most recently used, without handing out a reference.
+    /// Compressed values aren't decompressed. Returns `true` if the value is cached.
+    pub fn touch(&mut self, key: &K) -> bool {
+        match self.cache.get(key) {
+            Some(EntryState::Cached(_)) => {
+                self.op_recorder.record(|| Op::Access(key.clone()));
 
-impl<A: Compression> MaybeCompressed<A::Data, Compressed<A>> {
-    pub fn as_decompressed(self) -> A::Data {
-        match self {
-            MaybeCompressed::Compressed(c) => c.decompress(),
-            MaybeCompressed::Decompressed(d) => d,
+                true
+            }
+            _ => false,
         }
     }
 
-    pub fn unwrap_decompressed(self) -> A::Data {
-        match self {
-            MaybeCompressed::Compressed(_) => panic!("Must be decompressed"),
-            MaybeCompressed::Decompressed(d) => d,
+    /// Exempts the value for `key` from `compress_lru` and everything built on it, like
+    /// `max_cached`, the byte cap and `compress_idle`, regardless of how recently it was used. A
+    /// compressed value stays compressed until it's accessed. `compress_key` and `compress_all`
+    /// still compress pinned values. The pin is dropped when the entry is removed. Returns `false`
+    /// if there's no entry.
+    pub fn pin(&mut self, key: &K) -> bool {
+        if !self.contains_key(key) {
+            return false;
         }
-    }
-}
-
-// ████████╗███████╗███████╗████████╗███████╗
-// ╚══██╔══╝██╔════╝██╔════╝╚══██╔══╝██╔════╝
-//    ██║   █████╗  ███████╗   ██║   ███████╗
-//    ██║   ██╔══╝  ╚════██║   ██║   ╚════██║
-//    ██║   ███████╗███████║   ██║   ███████║
-//    ╚═╝   ╚══════╝╚══════╝   ╚═╝   ╚══════╝
+        self.pinned.insert(key.clone());
 
-#[cfg(test)]
-mod tests {
-    use super::*;
-
-    struct FakeFooCompression;
+        true
+    }
 
-    impl Compression for FakeFooCompression {
-        type Data = Foo;
-        type CompressedData = Foo;
+    /// Undoes `pin`. Returns `true` if the value was pinned.
+    pub fn unpin(&mut self, key: &K) -> bool {
+        self.pinned.remove(key)
+    }
 
-        fn compress(&self, data: &Self::Data) -> Compressed<Self> {
-            Compressed::new(Foo(data.0 + 1))
-        }
+    pub fn is_pinned(&self, key: &K) -> bool {
+        self.pinned.contains(key)
+    }
 
-        fn decompress(compressed: &Self::CompressedData) -> Self::Data {
-            Foo(compressed.0 + 1)
+    /// The cached value for `key`, without affecting the LRU order. Unlike `get_const`, compressed
+    /// values are never decompressed, so no `LocalCache` is needed; they're `None` just like
+    /// missing keys.
+    pub fn peek(&self, key: &K) -> Option<&V> {
+        match self.cache.get_const(key) {
+            Some(EntryState::Cached(value)) => Some(value),
+            _ => None,
         }
     }
 
-    #[derive(Clone, Debug, Default, Eq, PartialEq)]
-    struct Foo(u32);
+    /// The cached entry that `compress_lru` would compress next, without affecting the LRU order.
+    pub fn peek_lru(&self) -> Option<(&K, &V)> {
+        self.cache.peek_lru()
+    }
 
-    #[test]
-    fn get_after_compress() {
-        let mut map = CompressibleMap::<_, _, _>::new(FakeFooCompression);
+    /// Iterates over the keys of the cached values, starting with the least recently used. Without
+    /// a cache policy, this is the order in which `compress_lru` would compress them, e.g. to spill
+    /// the coldest values to disk ahead of time. Doesn't affect the LRU order.
+    pub fn keys_by_recency(&self) -> impl Iterator<Item = &K> {
+        self.cache.iter_lru_first().map(|(key, _)| key)
+    }
 
-        map.insert(1, Foo(0));
+    /// How long ago the cached value for `key` was last accessed. Returns `None` if the value isn't
+    /// cached.
+    pub fn access_age(&self, key: &K) -> Option<AccessAge> {
+        self.cache.last_access(key).map(|access| AccessAge {
+            accesses: self.cache.clock() - access.tick,
+            #[cfg(feature = "std")]
+            elapsed: access.time.map(|time| time.elapsed()),
+        })
+    }
 
-        map.compress_lru();
+    pub fn remove_lru(&mut self) -> Option<(K, V)> {
+        let removed = self.cache.remove_lru();
+        if let Some((key, _)) = &removed {
+            self.forget_removed(key);
+        }
 
-        assert_eq!(map.len_cached(), 0);
-        assert_eq!(map.len_compressed(), 1);
+        removed
+    }
 
-        assert_eq!(Some(&Foo(2)), map.get(1));
+    /// Since the returned reference allows modifying the value, this counts as a modification for
+    /// the purposes of `iter_changed_since`.
+    pub fn get_mut(&mut self, key: K) -> Option<&mut V> {
+        self.access(key, None, true)
+    }
 
-        assert_eq!(map.len_cached(), 1);
-        assert_eq!(map.len_compressed(), 0);
+    pub fn get(&mut self, key: K) -> Option<&V> {
+        // Hopefully downgrading the reference is a NOOP.
+        self.access(key, None, false).map(|v| &*v)
     }
 
-    #[test]
-    fn keys_iterator_has_both_cached_and_compressed() {
-        let mut map = CompressibleMap::<_, _, _>::new(FakeFooCompression);
+    /// Decompresses the values for `keys` into the cache ahead of time, e.g. before a phase that
+    /// can't afford to wait for decompression. Keys that are already cached become the most
+    /// recently used. Returns the number of values decompressed.
+    pub fn prefetch(&mut self, keys: impl IntoIterator<Item = K>) -> usize {
+        let mut num_decompressed = 0;
+        for key in keys {
+            if self.is_compressed(&key) {
+                num_decompressed += 1;
+            }
+            self.access(key, None, false);
+        }
 
-        map.insert(1, Foo(0));
-        map.insert(2, Foo(0));
+        num_decompressed
+    }
 
-        map.compress_lru();
+    /// Gets the values for all of `keys` at once, in the same order, decompressing any that are
+    /// compressed into the cache first. Missing keys are `None`.
+    ///
+    /// The keys are pinned while they're decompressed, so making room for one of them never
+    /// compresses another. A batch with more keys than `set_max_cached`, a namespace budget or the
+    /// byte cap allows leaves the cache over the limit until the next value is cached.
+    pub fn get_batch(&mut self, keys: impl IntoIterator<Item = K>) -> Vec<Option<&V>> {
+        let keys: Vec<K> = keys.into_iter().collect();
+        let newly_pinned: Vec<K> = keys
+            .iter()
+            .filter(|key| self.pinned.insert((*key).clone()))
+            .cloned()
+            .collect();
+        self.prefetch(keys.iter().cloned());
+        for key in newly_pinned.iter() {
+            self.pinned.remove(key);
+        }
+
+        let cache = &self.cache;
+        keys.iter()
+            .map(|key| match cache.get_const(key) {
+                Some(EntryState::Cached(value)) => Some(value),
+                _ => None,
+            })
+            .collect()
+    }
+
+    /// Gets the value for `key`, decompressing it into the cache if necessary. A value that was
+    /// already decompressed by the caller can be passed as `decompressed_value` to be used instead.
+    /// Mutable access is stamped as a modification.
+    fn access(&mut self, key: K, decompressed_value: Option<V>, mutable: bool) -> Option<&mut V> {
+        self.await_compressed(&key);
+        let decompressed_value = match decompressed_value {
+            Some(value) => Some(value),
+            None => self.decompress_value(&key),
+        };
+        if let Some(value) = &decompressed_value {
+            self.make_room_for(&key, self.cache.weigh(&key, value));
+        }
+
+        self.cache_access(key, decompressed_value, mutable)
+    }
+
+    /// Decompresses the value for `key` without changing the map, if it's compressed, so it can be
+    /// weighed before it's cached.
+    fn decompress_value(&mut self, key: &K) -> Option<V> {
+        let compressed = self.compressed.get(key)?;
+
+        Some(decompress_recycling(compressed, &mut self.recycled))
+    }
+
+    /// The part of `access` after making room for `decompressed_value`, which must be given if the
+    /// value is compressed.
+    fn cache_access(
+        &mut self,
+        key: K,
+        decompressed_value: Option<V>,
+        mutable: bool,
+    ) -> Option<&mut V> {
+        let CompressibleMap {
+            cache,
+            compressed,
+            modification_stamps,
+            subscribers,
+            op_recorder,
+            recycled,
+            clean_compressed,
+            stale_compressed,
+            stats,
+            ..
+        } = self;
+
+        let mut decompressed = false;
+        let value = cache.get_or_repopulate_with(key.clone(), || {
+            decompressed = true;
+            let compressed_value = compressed.remove(&key).unwrap();
+            let value = decompressed_value
+                .unwrap_or_else(|| decompress_recycling(&compressed_value, recycled));
+            keep_clean_compressed(
+                clean_compressed,
+                stale_compressed,
+                mutable,
+                &key,
+                compressed_value,
+            );
+
+            value
+        });
+        if decompressed {
+            subscribers.notify(|| MapEvent::Decompressed(key.clone()));
+            op_recorder.record(|| Op::Decompress(key.clone()));
+            stats.miss(true);
+        } else if value.is_some() {
+            op_recorder.record(|| Op::Access(key.clone()));
+            stats.hit();
+        } else {
+            stats.miss(false);
+        }
+        if mutable && value.is_some() {
+            if let Some(clean) = clean_compressed {
+                clean.remove(&key);
+            }
+            modification_stamps.stamp(key);
+        }
+
+        value
+    }
+
+    /// Like `get_mut`, but the returned guard makes the guarantees about the value's address
+    /// explicit. See `PinnedRef`.
+    pub fn get_pinned(&mut self, key: K) -> Option<PinnedRef<'_, V>> {
+        self.get_mut(key).map(|value| PinnedRef { value })
+    }
+
+    /// Only inserting a missing value counts as a modification for the purposes of
+    /// `iter_changed_since`. Use `get_mut` to record a change to an existing value.
+    pub fn get_or_insert_with(&mut self, key: K, on_missing: impl FnOnce() -> V) -> &mut V {
+        self.await_compressed(&key);
+        // A value that isn't cached yet is decompressed or created first, so it can be weighed.
+        let (decompressed_value, new_value) = if self.is_cached(&key) {
+            (None, None)
+        } else {
+            match self.decompress_value(&key) {
+                Some(value) => (Some(value), None),
+                None => (None, Some(on_missing())),
+            }
+        };
+        let new_bytes = match decompressed_value.as_ref().or(new_value.as_ref()) {
+            Some(value) => self.cache.weigh(&key, value),
+            None => 0,
+        };
+        self.make_room_for(&key, new_bytes);
+        let CompressibleMap {
+            cache,
+            compressed,
+            modification_stamps,
+            subscribers,
+            op_recorder,
+            clean_compressed,
+            stats,
+            ..
+        } = self;
+
+        if let Some(clean) = clean_compressed {
+            clean.remove(&key);
+        }
+
+        let (mut decompressed, mut inserted) = (false, false);
+        let on_evicted = || {
+            decompressed = true;
+            compressed.remove(&key);
+
+            decompressed_value.unwrap()
+        };
+        let on_missing = || {
+            inserted = true;
+
+            new_value.unwrap()
+        };
+
+        let value = cache.get_or_insert_with(key.clone(), on_evicted, on_missing);
+        if inserted {
+            modification_stamps.stamp(key.clone());
+        } else {
+            // The value might be modified through the returned reference, but that isn't a known
+            // change, so it doesn't get a new stamp.
+            modification_stamps.mark_dirty(&key);
+        }
+        if decompressed {
+            subscribers.notify(|| MapEvent::Decompressed(key.clone()));
+            op_recorder.record(|| Op::Decompress(key));
+            stats.miss(true);
+        } else if inserted {
+            subscribers.notify(|| MapEvent::Inserted(key.clone()));
+            op_recorder.record(|| Op::Insert(key));
+            stats.miss(false);
+        } else {
+            op_recorder.record(|| Op::Access(key));
+            stats.hit();
+        }
+
+        value
+    }
+
+    pub fn insert_if_vacant(&mut self, key: K, value: V) -> &mut V {
+        self.get_or_insert_with(key, || value)
+    }
+
+    /// Shorthand for `get_or_insert_with(key, Default::default)`.
+    pub fn get_mut_or_default(&mut self, key: K) -> &mut V
+    where
+        V: Default,
+    {
+        self.get_or_insert_with(key, Default::default)
+    }
+
+    /// Used for thread-safe access or to borrow multiple values at once. The cache will not be
+    /// updated, but accesses will be recorded in the provided `LocalCache`. The interior
+    /// mutability of the local cache has a cost (more heap indirection), but it allows us to borrow
+    /// multiple values at once. Call `flush_local_cache` to update the "global" cache with
+    /// the local cache.
+    pub fn get_const<'a>(&'a self, key: K, local_cache: &'a LocalCache<K, V, H>) -> Option<&'a V> {
+        self.cache.get_const(&key).map(|entry| {
+            match entry {
+                EntryState::Cached(v) => {
+                    // For the sake of updating LRU order when we flush this local cache.
+                    local_cache.remember_cached_access(key.clone());
+
+                    v
+                }
+                EntryState::Evicted => match self.get_evicted(&key) {
+                    MaybeCompressed::Decompressed(v) => v,
+                    MaybeCompressed::Compressed(compressed) => {
+                        // Check the local cache before trying to decompress.
+                        let stamp = self.modification_stamps.get(&key);
+                        local_cache
+                            .get_or_insert_with(key.clone(), stamp, || compressed.decompress())
+                    }
+                },
+            }
+        })
+    }
+
+    /// Like `get_const`, but with a `SyncLocalCache` that can be shared by all of the threads
+    /// reading the map, instead of one `LocalCache` per thread.
+    #[cfg(feature = "std")]
+    pub fn get_const_sync<'a>(
+        &'a self,
+        key: K,
+        local_cache: &'a SyncLocalCache<K, V, H>,
+    ) -> Option<&'a V> {
+        self.cache.get_const(&key).map(|entry| match entry {
+            EntryState::Cached(v) => {
+                local_cache.remember_cached_access(key.clone());
+
+                v
+            }
+            EntryState::Evicted => match self.get_evicted(&key) {
+                MaybeCompressed::Decompressed(v) => v,
+                MaybeCompressed::Compressed(compressed) => {
+                    let stamp = self.modification_stamps.get(&key);
+                    local_cache.get_or_insert_with(key.clone(), stamp, || compressed.decompress())
+                }
+            },
+        })
+    }
+
+    /// Like `get_const`, but the `LocalCache` is optional. Without one, a compressed value is
+    /// decompressed into an owned value that's simply returned to the caller, which is simpler for
+    /// one-off reads, but the work of decompressing won't benefit anyone else.
+    pub fn get_cow<'a>(
+        &'a self,
+        key: K,
+        local_cache: Option<&'a LocalCache<K, V, H>>,
+    ) -> Option<Cow<'a, V>>
+    where
+        V: Clone,
+    {
+        if let Some(local_cache) = local_cache {
+            return self.get_const(key, local_cache).map(Cow::Borrowed);
+        }
+
+        self.cache.get_const(&key).map(|entry| match entry {
+            EntryState::Cached(v) => Cow::Borrowed(v),
+            EntryState::Evicted => match self.get_evicted(&key) {
+                MaybeCompressed::Decompressed(v) => Cow::Borrowed(v),
+                MaybeCompressed::Compressed(compressed) => Cow::Owned(compressed.decompress()),
+            },
+        })
+    }
+
+    /// Changes the tolerance of lossy compression parameters. Only values compressed from now on
+    /// are affected.
+    pub fn set_tolerance(&mut self, tolerance: f32)
+    where
+        A: LossyCompression,
+    {
+        self.compression_params = self.compression_params.with_tolerance(tolerance);
+    }
+
+    /// Gets the part `P` of the value for `key`. If the value is compressed, only that part is
+    /// decompressed. Does not affect the cache.
+    pub fn get_part<P>(&self, key: &K) -> Option<A::Part>
+    where
+        A: PartiallyDecompressible<P>,
+    {
+        self.cache.get_const(key).map(|entry| match entry {
+            EntryState::Cached(value) => A::part(value),
+            EntryState::Evicted => match self.get_evicted(key) {
+                MaybeCompressed::Decompressed(value) => A::part(value),
+                MaybeCompressed::Compressed(compressed) => {
+                    A::decompress_part(&compressed.compressed_data)
+                }
+            },
+        })
+    }
+
+    /// Creates a read-only view of the map with its own `LocalCache`. This is the easiest way to
+    /// read from the map on many threads at once: give each thread a reader, then flush the
+    /// readers' caches with `flush_local_cache` once you have mutable access again.
+    pub fn reader(&self) -> CompressibleMapReader<'_, K, V, A, H, S> {
+        CompressibleMapReader::new(self)
+    }
+
+    /// Returns a copy of the value at `key`.
+    /// WARNING: the cache will not be updated. This is useful for read-modify-write scenarios where
+    /// you would just insert the modified value back into the map, which defeats the purpose of
+    /// caching it on read.
+    pub fn get_copy_without_caching(&self, key: &K) -> Option<MaybeCompressed<V, Compressed<A>>>
+    where
+        V: Clone,
+        Compressed<A>: Clone,
+    {
+        self.cache.get_const(key).map(|entry| match entry {
+            EntryState::Cached(v) => MaybeCompressed::Decompressed(v.clone()),
+            EntryState::Evicted => match self.get_evicted(key) {
+                MaybeCompressed::Decompressed(v) => MaybeCompressed::Decompressed(v.clone()),
+                MaybeCompressed::Compressed(c) => MaybeCompressed::Compressed(c.clone()),
+            },
+        })
+    }
+
+    /// Updates the cache and it's approximate LRU order after calling `get_const` some number of
+    /// times. Values that were modified since they were read into the local cache are stale, so
+    /// they aren't flushed, but the accesses still count.
+    pub fn flush_local_cache(&mut self, local_cache: LocalCache<K, V, H>) {
+        self.flush_accesses(local_cache.into_iter())
+    }
+
+    /// Same as `flush_local_cache`, but the local cache is left empty instead of being consumed, so
+    /// it can be reused for the next read phase without reallocating.
+    pub fn flush_local_cache_drain(&mut self, local_cache: &mut LocalCache<K, V, H>) {
+        self.flush_accesses(local_cache.drain())
+    }
+
+    fn flush_accesses(&mut self, accesses: impl Iterator<Item = (K, LocalAccess<V>)>) {
+        let CompressibleMap {
+            cache,
+            compressed,
+            modification_stamps,
+            subscribers,
+            op_recorder,
+            clean_compressed,
+            stale_compressed,
+            stats,
+            ..
+        } = self;
+        for (key, access) in accesses {
+            let access = match access {
+                // The entry was modified since the value was read, so only the access is
+                // remembered.
+                LocalAccess::Missed { stamp, .. } if modification_stamps.get(&key) != stamp => {
+                    LocalAccess::Cached
+                }
+                access => access,
+            };
+            match access {
+                LocalAccess::Cached => {
+                    // We accessed this key and it was cached, so let's reflect that in the cache's
+                    // LRU order.
+                    if let Some(EntryState::Cached(_)) = cache.get(&key) {
+                        op_recorder.record(|| Op::Access(key));
+                        stats.hit();
+                    }
+                }
+                LocalAccess::Missed { value, .. } => {
+                    // We accessed this key and it was missed, so let's repopulate the cache. Don't
+                    // replace a value that's already in the cache, since it might be newer than
+                    // what we're trying to flush (which must have come from a read).
+                    let mut repopulated = false;
+                    let found = cache
+                        .get_or_repopulate_with(key.clone(), || {
+                            repopulated = true;
+                            if let Some(compressed_value) = compressed.remove(&key) {
+                                keep_clean_compressed(
+                                    clean_compressed,
+                                    stale_compressed,
+                                    false,
+                                    &key,
+                                    compressed_value,
+                                );
+                            }
+
+                            value
+                        })
+                        .is_some();
+                    if repopulated {
+                        subscribers.notify(|| MapEvent::Decompressed(key.clone()));
+                        op_recorder.record(|| Op::Decompress(key));
+                        stats.miss(true);
+                    } else if found {
+                        op_recorder.record(|| Op::Access(key));
+                        stats.hit();
+                    }
+                }
+            }
+        }
+        self.compress_over_limits();
+    }
+
+    pub fn drop(&mut self, key: &K) {
+        self.remove(key);
+    }
+
+    /// Removes the value and returns it if it exists. A compressed value is returned as it is, e.g.
+    /// to be persisted without decompressing it. Use `try_remove` to get the value decompressed.
+    pub fn remove(&mut self, key: &K) -> Option<MaybeCompressed<V, Compressed<A>>> {
+        self.await_compressed(key);
+
+        let removed = self.cache.remove(key).map(|entry| match entry {
+            EntryState::Cached(v) => MaybeCompressed::Decompressed(v),
+            EntryState::Evicted => {
+                MaybeCompressed::Compressed(self.compressed.remove(key).unwrap())
+            }
+        });
+        if removed.is_some() {
+            self.forget_removed(key);
+        }
+
+        removed
+    }
+
+    /// Drops everything the map keeps about `key` besides its value, once the entry is removed.
+    fn forget_removed(&mut self, key: &K) {
+        self.forget_clean_compressed(key);
+        self.modification_stamps.remove(key);
+        self.pinned.remove(key);
+        self.subscribers.notify(|| MapEvent::Removed(key.clone()));
+        self.op_recorder.record(|| Op::Remove(key.clone()));
+    }
+
+    pub fn clear(&mut self) {
+        self.forget_in_flight();
+        self.cache.clear();
+        self.compressed.clear();
+        self.modification_stamps.clear();
+        self.stale_compressed.clear();
+        if let Some(clean) = &mut self.clean_compressed {
+            clean.clear();
+        }
+        self.pinned.clear();
+        self.subscribers.notify(|| MapEvent::Cleared);
+        self.op_recorder.record(|| Op::Clear);
+    }
+
+    /// Removes every entry for which `keep` returns `false`. Compressed values are passed to `keep`
+    /// as they are, so a predicate that only looks at the key doesn't cost any decompression.
+    pub fn retain(
+        &mut self,
+        mut keep: impl FnMut(&K, MaybeCompressed<&V, &Compressed<A>>) -> bool,
+    ) {
+        self.finish_compressing();
+        let to_remove: Vec<K> = self
+            .iter()
+            .filter_map(|(key, value)| {
+                if keep(key, value) {
+                    None
+                } else {
+                    Some(key.clone())
+                }
+            })
+            .collect();
+        for key in to_remove.iter() {
+            self.remove(key);
+        }
+    }
+
+    /// Whether the map has a value for `key`, cached or compressed. Doesn't decompress or affect
+    /// the LRU order.
+    pub fn contains_key(&self, key: &K) -> bool {
+        self.cache.get_const(key).is_some()
+    }
+
+    /// Whether the value for `key` is cached, i.e. accessing it won't require decompression.
+    /// Doesn't affect the LRU order.
+    pub fn is_cached(&self, key: &K) -> bool {
+        matches!(self.cache.get_const(key), Some(EntryState::Cached(_)))
+    }
+
+    /// Whether the value for `key` is compressed, or being compressed on the background compressor.
+    /// Doesn't affect the LRU order.
+    pub fn is_compressed(&self, key: &K) -> bool {
+        matches!(self.cache.get_const(key), Some(EntryState::Evicted))
+    }
+
+    /// Whether the cached value for `key` was modified by `insert`, `get_mut` or the like since it
+    /// was last compressed, i.e. whether compressing it again would change what's stored. Values
+    /// that were never compressed are dirty.
+    pub fn is_dirty(&self, key: &K) -> bool {
+        self.is_cached(key) && self.modification_stamps.is_dirty(key)
+    }
+
+    pub fn len(&self) -> usize {
+        self.len_cached() + self.len_compressed()
+    }
+
+    pub fn len_cached(&self) -> usize {
+        self.cache.len_cached()
+    }
+
+    /// Includes values in flight on the background compressor.
+    pub fn len_compressed(&self) -> usize {
+        self.compressed.len() + self.num_in_flight()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    pub fn keys<'a>(&'a self) -> impl Iterator<Item = &'a K>
+    where
+        Compressed<A>: 'a,
+    {
+        self.cache.keys()
+    }
+
+    /// Decompresses every value into the cache, then iterates over all (key, value) pairs. This can
+    /// use a lot of memory; see `for_each_mut_within_budget` for a pass that doesn't.
+    ///
+    /// Every value has to be cached at once, so `max_cached`, the namespace budgets and the byte cap
+    /// are ignored while decompressing. The cache stays over them until the next value is cached.
+    pub fn iter_decompressed(&mut self) -> impl Iterator<Item = (&K, &V)> {
+        self.decompress_all_over_limits();
+
+        self.cache.iter()
+    }
+
+    /// Like `iter_decompressed`, but the values can be modified, so every entry counts as a
+    /// modification for the purposes of `iter_changed_since`.
+    pub fn iter_mut(&mut self) -> impl Iterator<Item = (&K, &mut V)> {
+        self.decompress_all_over_limits();
+        for key in self.cache.keys() {
+            self.modification_stamps.stamp(key.clone());
+        }
+
+        self.cache.iter_mut()
+    }
+
+    /// Like `decompress_all`, but without compressing other values to make room for each one.
+    fn decompress_all_over_limits(&mut self) {
+        let max_cached = self.max_cached.take();
+        let namespaces = self.namespaces.take();
+        let byte_cap = self.byte_cap.take();
+        self.decompress_all();
+        self.max_cached = max_cached;
+        self.namespaces = namespaces;
+        self.byte_cap = byte_cap;
+    }
+
+    /// Calls `f` on every value, decompressing them one at a time and compressing LRU values after
+    /// each call until the cached values use at most `max_bytes`, as in
+    /// `compress_until_under_budget`. Cached values are visited first.
+    pub fn for_each_mut_within_budget(&mut self, max_bytes: usize, mut f: impl FnMut(&K, &mut V)) {
+        self.finish_compressing();
+        let keys: Vec<K> = self.iter().map(|(key, _)| key.clone()).collect();
+        for key in keys.into_iter() {
+            if let Some(value) = self.get_mut(key.clone()) {
+                f(&key, value);
+            }
+            self.compress_until_under_budget(max_bytes);
+        }
+    }
+
+    /// Iterates over the metadata of all entries without touching the values, which is cheap enough
+    /// to do every frame for a debug overlay or metrics.
+    pub fn iter_metadata<'a>(&'a self) -> impl Iterator<Item = EntryMetadata<'a, K>>
+    where
+        Compressed<A>: 'a,
+    {
+        let clock = self.cache.clock();
+        #[cfg(feature = "std")]
+        let now = Instant::now();
+
+        self.cache
+            .iter_last_access()
+            .map(move |(key, access)| EntryMetadata {
+                key,
+                compressed_size: None,
+                access_age: Some(AccessAge {
+                    accesses: clock - access.tick,
+                    #[cfg(feature = "std")]
+                    elapsed: access.time.map(|time| now.saturating_duration_since(time)),
+                }),
+            })
+            .chain(self.compressed.iter().map(|(key, value)| EntryMetadata {
+                key,
+                compressed_size: Some(value.size()),
+                access_age: None,
+            }))
+    }
+
+    /// Counts the cached and compressed values by size, so cache budgets can be based on the actual
+    /// distribution of sizes. Compressed sizes come from `Compression::compressed_size`, while the
+    /// size of cached values is measured by `value_size`, since only you know how much heap memory
+    /// a value owns.
+    pub fn size_histogram(&self, value_size: impl Fn(&V) -> usize) -> SizeHistogram {
+        let mut histogram = SizeHistogram::default();
+        for (_, value) in self.cache.iter() {
+            histogram.add_cached(value_size(value));
+        }
+        for compressed in self.compressed.values() {
+            histogram.add_compressed(compressed.size());
+        }
+
+        histogram
+    }
+
+    /// The stamp given to the most recent modification. Any modifications made after calling this
+    /// method will be yielded by `iter_changed_since` with the returned stamp.
+    pub fn modification_stamp(&self) -> u64 {
+        self.modification_stamps.latest()
+    }
+
+    /// Iterate over all (key, value) pairs that were inserted or mutably accessed after `stamp` was
+    /// returned from `modification_stamp`. Compressed values will not be decompressed inline, so
+    /// the compressed payloads can be forwarded directly. Removed entries are not reported.
+    pub fn iter_changed_since(
+        &self,
+        stamp: u64,
+    ) -> impl Iterator<Item = (&K, MaybeCompressed<&V, &Compressed<A>>)> {
+        self.modification_stamps
+            .changed_since(stamp)
+            .filter_map(move |k| {
+                self.cache.get_const(k).map(|entry| match entry {
+                    EntryState::Cached(v) => (k, MaybeCompressed::Decompressed(v)),
+                    EntryState::Evicted => (k, self.get_evicted(k)),
+                })
+            })
+    }
+
+    /// The value for a key that's evicted from the cache: compressed, or still uncompressed while
+    /// it's in flight on the background compressor.
+    pub(super) fn get_evicted(&self, key: &K) -> MaybeCompressed<&V, &Compressed<A>> {
+        match self.in_flight_value(key) {
+            Some(value) => MaybeCompressed::Decompressed(value),
+            None => MaybeCompressed::Compressed(self.compressed.get(key).unwrap()),
+        }
+    }
+}
+
+fn decompress_recycling<A: Compression>(
+    compressed: &Compressed<A>,
+    recycled: &mut Vec<A::Data>,
+) -> A::Data {
+    match recycled.pop() {
+        Some(mut value) => {
+            compressed.decompress_into(&mut value);
+
+            value
+        }
+        None => compressed.decompress(),
+    }
+}
+
+/// Keeps the compressed form of a value that was just decompressed, if enabled by
+/// `set_reuse_compressed`. Values decompressed for writing will be modified anyway, and stale
+/// values should be recompressed with the retrained parameters, so neither is kept.
+fn keep_clean_compressed<K, A, H>(
+    clean_compressed: &mut Option<HashMap<K, Compressed<A>, H>>,
+    stale_compressed: &VecDeque<K>,
+    mutable: bool,
+    key: &K,
+    compressed: Compressed<A>,
+) where
+    K: Clone + Eq + Hash,
+    H: BuildHasher,
+    A: Compression,
+{
+    if let Some(clean) = clean_compressed {
+        if !mutable && stale_compressed.is_empty() {
+            clean.insert(key.clone(), compressed);
+        }
+    }
+}
+
+impl<K, V, A, H, S> CompressibleMap<K, V, SummarizedCompression<A>, H, S>
+where
+    K: Clone + Eq + Hash,
+    V: Summarize,
+    V::Summary: Clone,
+    H: BuildHasher + Default,
+    A: Compression<Data = V>,
+    S: CompressedStorage<K, Compressed<SummarizedCompression<A>>>,
+{
+    /// The summary of the value for `key`. The summary of a compressed value was kept when it was
+    /// compressed, while a cached value is summarized on demand. Does not affect the cache.
+    pub fn summary(&self, key: &K) -> Option<Cow<'_, V::Summary>> {
+        self.cache.get_const(key).map(|entry| match entry {
+            EntryState::Cached(value) => Cow::Owned(value.summarize()),
+            EntryState::Evicted => match self.get_evicted(key) {
+                MaybeCompressed::Decompressed(value) => Cow::Owned(value.summarize()),
+                MaybeCompressed::Compressed(compressed) => {
+                    Cow::Borrowed(&compressed.compressed_data.summary)
+                }
+            },
+        })
+    }
+}
+
+/// Clones the cached and compressed values along with the settings of the map, like its limits,
+/// cache policy and size estimator. Subscribers, the op log, the background compressor and the
+/// eviction policy and spill sink belong to the original map and aren't cloned. Values in flight
+/// on the background compressor are compressed for the clone without waiting for them.
+impl<K, V, A, H, S> Clone for CompressibleMap<K, V, A, H, S>
+where
+    K: Clone + Eq + Hash,
+    V: Clone,
+    H: BuildHasher + Default + Clone,
+    A: Compression<Data = V> + Clone,
+    S: CompressedStorage<K, Compressed<A>> + Clone,
+{
+    fn clone(&self) -> Self {
+        let mut compressed = self.compressed.clone();
+        for (key, value) in self.iter_in_flight() {
+            compressed.insert(key.clone(), self.compression_params.compress(value));
+        }
+
+        Self {
+            cache: self.cache.clone(),
+            compressed,
+            compression_params: self.compression_params.clone(),
+            modification_stamps: self.modification_stamps.clone(),
+            subscribers: Subscribers::default(),
+            recency_guard: self.recency_guard,
+            #[cfg(feature = "std")]
+            track_access_times: self.track_access_times,
+            compressions_since_retrain: self.compressions_since_retrain,
+            stale_compressed: self.stale_compressed.clone(),
+            jobs: self.jobs.clone(),
+            namespaces: self.namespaces.clone(),
+            op_recorder: OpRecorder::default(),
+            byte_cap: self.byte_cap,
+            max_cached: self.max_cached,
+            recycled: Vec::new(),
+            max_recycled: self.max_recycled,
+            clean_compressed: self
+                .clean_compressed
+                .as_ref()
+                .map(|_| HashMap::with_hasher(Default::default())),
+            pinned: self.pinned.clone(),
+            in_use: self.in_use,
+            spill: None,
+            eviction: None,
+            compressor: None,
+            stats: self.stats,
+        }
+    }
+}
+
+/// Shows how many values are cached and compressed. The alternate format (`{:#?}`) also lists the
+/// keys.
+impl<K, V, A, H, S> core::fmt::Debug for CompressibleMap<K, V, A, H, S>
+where
+    K: Clone + Eq + Hash + core::fmt::Debug,
+    H: BuildHasher + Default,
+    A: Compression<Data = V>,
+    S: CompressedStorage<K, Compressed<A>>,
+{
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        let alternate = f.alternate();
+        let mut debug = f.debug_struct("CompressibleMap");
+        debug
+            .field("len_cached", &self.len_cached())
+            .field("len_compressed", &self.len_compressed())
+            .field("bytes_compressed", &self.bytes_compressed());
+        if alternate {
+            debug.field("keys", &self.keys().collect::<Vec<_>>());
+        }
+
+        debug.finish()
+    }
+}
+
+/// Reads a cached value, or a value in flight on the background compressor.
+///
+/// # Panics
+///
+/// Unlike `HashMap`, this panics for keys that **exist** but are compressed, since a compressed
+/// value can't be decompressed without mutable access. Any call to `compress_lru` or one of the
+/// limits can compress a value, so only index values that are pinned, or use `get`, `get_const` or
+/// `&mut map[&key]` to decompress them. Also panics if the key is missing.
+impl<K, V, A, H, S> Index<&K> for CompressibleMap<K, V, A, H, S>
+where
+    K: Clone + Eq + Hash,
+    H: BuildHasher + Default,
+    A: Compression<Data = V>,
+    S: CompressedStorage<K, Compressed<A>>,
+{
+    type Output = V;
+
+    fn index(&self, key: &K) -> &V {
+        match self.cache.get_const(key) {
+            Some(EntryState::Cached(value)) => value,
+            Some(EntryState::Evicted) => self.in_flight_value(key).unwrap_or_else(|| {
+                panic!(
+                    "The value for this key exists but is compressed, so it can't be indexed \
+                     immutably; use `get` or `&mut map[&key]` to decompress it"
+                )
+            }),
+            None => panic!("No entry found for key"),
+        }
+    }
+}
+
+/// Decompresses the value into the cache if necessary, like `get_mut`. Panics if the key is
+/// missing.
+impl<K, V, A, H, S> IndexMut<&K> for CompressibleMap<K, V, A, H, S>
+where
+    K: Clone + Eq + Hash,
+    H: BuildHasher + Default,
+    A: Compression<Data = V>,
+    S: CompressedStorage<K, Compressed<A>>,
+{
+    fn index_mut(&mut self, key: &K) -> &mut V {
+        self.get_mut(key.clone()).expect("No entry found for key")
+    }
+}
+
+pub enum MaybeCompressed<D, C> {
+    Decompressed(D),
+    Compressed(C),
+}
+
+impl<K, A, H, S> CompressibleMap<K, Vec<u8>, A, H, S>
+where
+    K: Clone + Eq + Hash,
+    H: BuildHasher + Default,
+    A: SeekableCompression,
+    S: CompressedStorage<K, Compressed<A>>,
+{
+    /// Reads `range` of the bytes for `key`. If the value is compressed, only the part of it that
+    /// contains the range is decompressed. Does not affect the cache. Panics if the range is out of
+    /// bounds, like slicing.
+    pub fn get_range(&self, key: &K, range: Range<usize>) -> Option<Cow<'_, [u8]>> {
+        self.cache.get_const(key).map(|entry| match entry {
+            EntryState::Cached(bytes) => Cow::Borrowed(&bytes[range]),
+            EntryState::Evicted => match self.get_evicted(key) {
+                MaybeCompressed::Decompressed(bytes) => Cow::Borrowed(&bytes[range]),
+                MaybeCompressed::Compressed(compressed) => {
+                    Cow::Owned(A::decompress_range(&compressed.compressed_data, range))
+                }
+            },
+        })
+    }
+}
+
+impl<K, T, A, H, S> CompressibleMap<K, Arc<T>, A, H, S>
+where
+    K: Clone + Eq + Hash,
+    H: BuildHasher + Default,
+    A: Compression<Data = Arc<T>>,
+    S: CompressedStorage<K, Compressed<A>>,
+{
+    /// Like `get`, but returns a clone of the `Arc` holding the value, so it can be kept, e.g. by a
+    /// worker thread, after the map compresses or removes it, without copying the value. Use with
+    /// `ArcCompression` to store any compressible type this way.
+    pub fn get_arc(&mut self, key: K) -> Option<Arc<T>> {
+        self.get(key).cloned()
+    }
+}
+
+impl<A: Compression> MaybeCompressed<A::Data, Compressed<A>> {
+    pub fn as_decompressed(self) -> A::Data {
+        match self {
+            MaybeCompressed::Compressed(c) => c.decompress(),
+            MaybeCompressed::Decompressed(d) => d,
+        }
+    }
+
+    pub fn unwrap_decompressed(self) -> A::Data {
+        match self {
+            MaybeCompressed::Compressed(_) => panic!("Must be decompressed"),
+            MaybeCompressed::Decompressed(d) => d,
+        }
+    }
+}
+
+// ████████╗███████╗███████╗████████╗███████╗
+// ╚══██╔══╝██╔════╝██╔════╝╚══██╔══╝██╔════╝
+//    ██║   █████╗  ███████╗   ██║   ███████╗
+//    ██║   ██╔══╝  ╚════██║   ██║   ╚════██║
+//    ██║   ███████╗███████║   ██║   ███████║
+//    ╚═╝   ╚══════╝╚══════╝   ╚═╝   ╚══════╝
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::{FakeFooCompression, Foo};
+    use crate::{FramedBytesCompression, QuantizedF32Compression, Rle};
+
+    #[test]
+    fn get_after_compress() {
+        let mut map = CompressibleMap::<_, _, _>::new(FakeFooCompression);
+
+        map.insert(1, Foo(0));
+
+        map.compress_lru();
+
+        assert_eq!(map.len_cached(), 0);
+        assert_eq!(map.len_compressed(), 1);
+
+        assert_eq!(Some(&Foo(2)), map.get(1));
+
+        assert_eq!(map.len_cached(), 1);
+        assert_eq!(map.len_compressed(), 0);
+    }
+
+    #[test]
+    fn keys_iterator_has_both_cached_and_compressed() {
+        let mut map = CompressibleMap::<_, _, _>::new(FakeFooCompression);
+
+        map.insert(1, Foo(0));
+        map.insert(2, Foo(0));
+
+        map.compress_lru();
 
         let mut keys: Vec<i32> = map.keys().cloned().collect();
         keys.sort();
         assert_eq!(keys, vec![1, 2]);
     }
 
+    #[test]
+    fn compress_only_idle_values() {
+        let mut map = CompressibleMap::<_, _, _>::new(FakeFooCompression);
+        map.set_track_access_times(true);
+        map.insert(1, Foo(1));
+        map.insert(2, Foo(2));
+        std::thread::sleep(Duration::from_millis(20));
+        map.insert(3, Foo(3));
+        map.get(1);
+
+        assert_eq!(map.compress_idle(Duration::from_secs(60)), 0);
+        assert_eq!(map.compress_idle(Duration::from_millis(20)), 1);
+        assert!(map.is_compressed(&2));
+        assert_eq!(map.len_cached(), 2);
+    }
+
+    #[test]
+    fn compress_and_decompress_everything() {
+        let mut map = CompressibleMap::<_, _, _>::new(FakeFooCompression);
+        for i in 0..5 {
+            map.insert(i, Foo(i));
+        }
+
+        assert_eq!(map.compress_all(), 5);
+        assert_eq!(map.len_cached(), 0);
+        assert_eq!(map.decompress_n(2), 2);
+        assert_eq!(map.len_cached(), 2);
+        assert_eq!(map.decompress_all(), 3);
+        assert_eq!(map.len_compressed(), 0);
+        assert_eq!(map.decompress_all(), 0);
+    }
+
+    #[test]
+    fn compress_specific_key() {
+        let mut map = CompressibleMap::<_, _, _>::new(FakeFooCompression);
+        map.insert(1, Foo(1));
+        map.insert(2, Foo(2));
+
+        assert!(map.compress_key(&2));
+        assert!(!map.compress_key(&2));
+        assert!(!map.compress_key(&3));
+        assert!(map.is_cached(&1));
+        assert_eq!(map.get(2), Some(&Foo(4)));
+    }
+
+    #[test]
+    fn tier_predicates_do_not_touch_lru_order() {
+        let mut map = CompressibleMap::<_, _, _>::new(FakeFooCompression);
+        map.insert(1, Foo(0));
+        map.insert(2, Foo(0));
+        map.insert(3, Foo(0));
+        map.compress_lru();
+
+        assert!(map.contains_key(&1) && map.is_compressed(&1) && !map.is_cached(&1));
+        assert!(map.contains_key(&2) && map.is_cached(&2) && !map.is_compressed(&2));
+        assert!(!map.contains_key(&4) && !map.is_cached(&4) && !map.is_compressed(&4));
+        assert_eq!(map.recency_rank(&2), Some(1));
+    }
+
+    #[test]
+    fn iter_changed_since_yields_only_modified_entries() {
+        let mut map = CompressibleMap::<_, _, _>::new(FakeFooCompression);
+
+        map.insert(1, Foo(0));
+        map.insert(2, Foo(0));
+        map.insert(3, Foo(0));
+        map.compress_lru();
+
+        let stamp = map.modification_stamp();
+
+        // Reads and compression don't count as modifications.
+        map.get(1);
+        map.compress_lru();
+
+        map.insert(3, Foo(5));
+        map.get_mut(2).unwrap().0 = 7;
+        map.compress_lru();
+
+        let mut changed: Vec<i32> = map.iter_changed_since(stamp).map(|(k, _)| *k).collect();
+        changed.sort();
+        assert_eq!(changed, vec![2, 3]);
+
+        assert_eq!(map.iter_changed_since(map.modification_stamp()).count(), 0);
+    }
+
+    #[test]
+    fn iter_changed_since_keeps_order_after_outgrowing_inline_storage() {
+        let mut map = CompressibleMap::<_, _, _>::new(FakeFooCompression);
+
+        for i in [3, 1, 2] {
+            map.insert(i, Foo(0));
+        }
+        let changed: Vec<i32> = map.iter_changed_since(1).map(|(k, _)| *k).collect();
+        assert_eq!(changed, vec![1, 2]);
+
+        for i in [9, 8, 7, 1] {
+            map.insert(i, Foo(0));
+        }
+        let changed: Vec<i32> = map.iter_changed_since(3).map(|(k, _)| *k).collect();
+        assert_eq!(changed, vec![9, 8, 7, 1]);
+    }
+
+    #[test]
+    fn remove_lru_forgets_stamps_and_kept_compressed_values() {
+        let mut map = CompressibleMap::<_, _, _>::new(FakeFooCompression);
+        map.set_reuse_compressed(true);
+        map.insert(1, Foo(0));
+        map.insert(2, Foo(0));
+        map.compress_lru();
+        map.get(1);
+        map.get(2);
+        assert_eq!(map.clean_compressed.as_ref().unwrap().len(), 1);
+
+        assert_eq!(map.remove_lru().map(|(k, _)| k), Some(1));
+        assert_eq!(map.modification_stamps.get(&1), 0);
+        assert!(map.clean_compressed.as_ref().unwrap().is_empty());
+        assert_eq!(map.modification_stamps.changed_since(0).count(), 1);
+    }
+
+    #[test]
+    fn insert_if_vacant_only_stamps_new_entries() {
+        let mut map = CompressibleMap::<_, _, _>::new(FakeFooCompression);
+        map.insert(1, Foo(0));
+
+        let stamp = map.modification_stamp();
+        map.insert_if_vacant(1, Foo(5));
+        assert_eq!(map.modification_stamp(), stamp);
+
+        map.insert_if_vacant(2, Foo(5));
+        map.insert(3, Foo(0));
+        map.insert(2, Foo(1));
+        let changed: Vec<i32> = map.iter_changed_since(stamp).map(|(k, _)| *k).collect();
+        assert_eq!(changed, vec![3, 2]);
+    }
+
+    #[test]
+    fn subscriber_receives_events_in_order() {
+        let mut map = CompressibleMap::<_, _, _>::new(FakeFooCompression);
+        let rx = map.subscribe();
+
+        map.insert(1, Foo(0));
+        map.compress_lru();
+        map.get(1);
+        map.remove(&1);
+        map.get_or_insert_with(2, Foo::default);
+        map.clear();
+
+        let events: Vec<_> = rx.try_iter().collect();
+        assert_eq!(
+            events,
+            vec![
+                MapEvent::Inserted(1),
+                MapEvent::Compressed(1),
+                MapEvent::Decompressed(1),
+                MapEvent::Removed(1),
+                MapEvent::Inserted(2),
+                MapEvent::Cleared,
+            ]
+        );
+    }
+
+    #[test]
+    fn recency_guard_protects_recent_accesses() {
+        let mut map = CompressibleMap::<_, _, _>::new(FakeFooCompression);
+        map.set_recency_guard(Some(RecencyGuard::Accesses(2)));
+
+        map.insert(1, Foo(0));
+        map.insert(2, Foo(0));
+        map.insert(3, Foo(0));
+
+        // 2 more recent accesses, so 1 is fair game.
+        map.compress_lru();
+        assert_eq!(map.len_compressed(), 1);
+        // 2 was one of the last 2 accesses.
+        map.compress_lru();
+        assert_eq!(map.len_compressed(), 1);
+
+        map.get(1);
+        map.insert(4, Foo(0));
+        map.compress_lru();
+        map.compress_lru();
+        map.compress_lru();
+        assert_eq!(map.len_compressed(), 2);
+        assert_eq!(map.len_cached(), 2);
+
+        map.set_recency_guard(Some(RecencyGuard::Duration(Duration::from_secs(60))));
+        map.compress_lru();
+        assert_eq!(map.len_compressed(), 2);
+
+        map.set_recency_guard(None);
+        map.compress_lru();
+        assert_eq!(map.len_compressed(), 3);
+    }
+
+    #[test]
+    fn recency_rank_and_age_of_cached_values() {
+        let mut map = CompressibleMap::<_, _, _>::new(FakeFooCompression);
+        map.insert(1, Foo(0));
+        map.insert(2, Foo(0));
+        map.insert(3, Foo(0));
+
+        assert_eq!(map.recency_rank(&1), Some(2));
+        assert_eq!(map.access_age(&1).unwrap().accesses, 2);
+
+        map.get(1);
+        assert_eq!(map.recency_rank(&1), Some(0));
+        assert_eq!(map.access_age(&1).unwrap().accesses, 0);
+        assert_eq!(map.access_age(&1).unwrap().elapsed, None);
+
+        map.set_track_access_times(true);
+        assert!(map.access_age(&1).unwrap().elapsed.is_some());
+
+        map.compress_lru();
+        assert_eq!(map.recency_rank(&2), None);
+        assert_eq!(map.access_age(&2), None);
+    }
+
+    #[test]
+    fn size_histogram_counts_both_tiers() {
+        let mut map = CompressibleMap::<_, _, _>::new(FakeFooCompression);
+        map.insert(1, Foo(0));
+        map.insert(2, Foo(100));
+        map.insert(3, Foo(0));
+        map.compress_lru();
+
+        let histogram = map.size_histogram(|v| v.0 as usize);
+
+        assert_eq!(histogram.cached, vec![1, 0, 0, 0, 0, 0, 1]);
+        // Foo is 4 bytes inline.
+        assert_eq!(histogram.compressed, vec![0, 0, 1]);
+    }
+
+    #[test]
+    fn into_all_compressed_round_trip() {
+        let mut map = CompressibleMap::<_, _, _>::new(FakeFooCompression);
+        map.insert(1, Foo(0));
+        map.insert(2, Foo(10));
+        map.compress_lru();
+
+        let compressed = map.into_all_compressed();
+        assert_eq!(compressed.len(), 2);
+
+        let mut map =
+            CompressibleMap::<_, _, _>::from_all_compressed(FakeFooCompression, compressed);
+        assert_eq!(map.len_compressed(), 2);
+        assert_eq!(map.get(1), Some(&Foo(2)));
+        assert_eq!(map.get(2), Some(&Foo(12)));
+    }
+
+    #[test]
+    fn with_capacity_does_not_reallocate_compressed_entries() {
+        let mut map = CompressibleMap::<_, _, _>::with_capacity(FakeFooCompression, 100, 10);
+        let capacity = map.compressed.capacity();
+        assert!(capacity >= 100);
+
+        for i in 0..100 {
+            map.insert(i, Foo(i));
+            if map.len_cached() > 10 {
+                map.compress_lru();
+            }
+        }
+        assert_eq!(map.len_compressed(), 90);
+        assert_eq!(map.compressed.capacity(), capacity);
+    }
+
+    #[test]
+    fn iter_metadata_reports_tier_and_age() {
+        let mut map = CompressibleMap::<_, _, _>::new(FakeFooCompression);
+        for i in 0..3 {
+            map.insert(i, Foo(i));
+        }
+        map.compress_lru();
+        map.get(1);
+
+        let mut metadata: Vec<_> = map
+            .iter_metadata()
+            .map(|m| {
+                (
+                    *m.key,
+                    m.is_cached(),
+                    m.compressed_size,
+                    m.access_age.map(|a| a.accesses),
+                )
+            })
+            .collect();
+        metadata.sort();
+        assert_eq!(
+            metadata,
+            vec![
+                (0, false, Some(core::mem::size_of::<Foo>()), None),
+                (1, true, None, Some(0)),
+                (2, true, None, Some(1)),
+            ]
+        );
+    }
+
+    #[test]
+    fn get_range_of_cached_and_compressed_bytes() {
+        let mut map = CompressibleMap::<_, _, _>::new(FramedBytesCompression::new(
+            2,
+            Rle { element_size: 1 },
+        ));
+        map.insert(1, vec![1, 2, 3, 4, 5]);
+        map.insert(2, vec![6, 7, 8]);
+        map.compress_lru();
+
+        assert_eq!(map.get_range(&1, 1..4).unwrap().as_ref(), &[2, 3, 4]);
+        assert_eq!(map.get_range(&2, 1..3).unwrap().as_ref(), &[7, 8]);
+        assert_eq!(map.get_range(&3, 0..0), None);
+        assert_eq!(map.len_cached(), 1);
+    }
+
+    #[test]
+    fn decompression_reuses_recycled_values() {
+        let mut map = CompressibleMap::<_, _, _>::new(FramedBytesCompression::new(
+            4,
+            Rle { element_size: 1 },
+        ));
+        map.set_max_recycled(1);
+        map.insert(1, vec![1; 100]);
+        let ptr = map.get(1).unwrap().as_ptr();
+        map.insert(2, vec![2; 100]);
+        map.compress_lru();
+        map.compress_lru();
+
+        assert_eq!(map.get(2).unwrap(), &vec![2; 100]);
+        assert_eq!(map.get(2).unwrap().as_ptr(), ptr);
+        assert_eq!(map.get(1).unwrap(), &vec![1; 100]);
+    }
+
+    #[derive(Debug, PartialEq)]
+    struct Entity {
+        health: u32,
+        mesh: Vec<u32>,
+    }
+
+    struct Health;
+
+    /// Leaves the health uncompressed next to the "compressed" mesh.
+    struct EntityCompression;
+
+    impl Compression for EntityCompression {
+        type Data = Entity;
+        type CompressedData = (u32, Vec<u32>);
+
+        fn compress(&self, data: &Entity) -> Compressed<Self> {
+            Compressed::new((data.health, data.mesh.iter().rev().cloned().collect()))
+        }
+
+        fn decompress(compressed: &Self::CompressedData) -> Entity {
+            Entity {
+                health: compressed.0,
+                mesh: compressed.1.iter().rev().cloned().collect(),
+            }
+        }
+    }
+
+    impl PartiallyDecompressible<Health> for EntityCompression {
+        type Part = u32;
+
+        fn part(data: &Entity) -> u32 {
+            data.health
+        }
+
+        fn decompress_part(compressed: &Self::CompressedData) -> u32 {
+            compressed.0
+        }
+    }
+
+    #[test]
+    fn get_part_of_cached_and_compressed_values() {
+        let mut map = CompressibleMap::<_, _, _>::new(EntityCompression);
+        map.insert(
+            1,
+            Entity {
+                health: 10,
+                mesh: vec![1, 2, 3],
+            },
+        );
+        map.insert(
+            2,
+            Entity {
+                health: 20,
+                mesh: vec![],
+            },
+        );
+        map.compress_lru();
+
+        assert_eq!(map.get_part::<Health>(&1), Some(10));
+        assert_eq!(map.get_part::<Health>(&2), Some(20));
+        assert_eq!(map.get_part::<Health>(&3), None);
+        assert_eq!(map.len_compressed(), 1);
+        assert_eq!(map.get(1).unwrap().mesh, vec![1, 2, 3]);
+    }
+
+    #[test]
+    fn watermarks_compress_in_batches() {
+        let mut map = CompressibleMap::<_, _, _>::new(FakeFooCompression);
+        let watermarks = Watermarks { high: 4, low: 2 };
+        for i in 0..4 {
+            map.insert(i, Foo(i));
+            assert_eq!(map.compress_to_watermarks(&watermarks), 0);
+        }
+
+        map.insert(4, Foo(4));
+        assert_eq!(map.compress_to_watermarks(&watermarks), 3);
+        assert_eq!(map.len_cached(), 2);
+        assert_eq!(map.len_compressed(), 3);
+
+        map.insert(5, Foo(5));
+        assert_eq!(map.compress_to_watermarks(&watermarks), 0);
+    }
+
+    #[test]
+    fn compress_batches_by_count_and_predicate() {
+        let mut map = CompressibleMap::<_, _, _>::new(FakeFooCompression);
+        for i in 0..6 {
+            map.insert(i, Foo(i));
+        }
+
+        assert_eq!(map.compress_lru_n(2), 2);
+        assert_eq!(map.compress_while(|map| map.len_cached() > 1), 3);
+        assert_eq!(
+            map.get_copy_without_caching(&5).unwrap().as_decompressed(),
+            Foo(5)
+        );
+        assert_eq!(map.compress_lru_n(10), 1);
+        assert_eq!(map.compress_while(|_| true), 0);
+    }
+
+    #[test]
+    fn pinned_value_is_decompressed_in_place() {
+        let mut map = CompressibleMap::<_, _, _>::new(FakeFooCompression);
+        map.insert(1, Foo(0));
+        map.compress_lru();
+
+        let ptr = {
+            let mut pinned = map.get_pinned(1).unwrap();
+            assert_eq!(*pinned, Foo(2));
+            unsafe {
+                (*pinned.as_mut_ptr()).0 = 5;
+            }
+
+            pinned.as_ptr()
+        };
+
+        assert_eq!(map.get(1).map(|v| v as *const Foo), Some(ptr));
+        assert_eq!(map.get(1), Some(&Foo(5)));
+        assert!(map.get_pinned(2).is_none());
+    }
+
+    #[test]
+    fn tolerance_is_per_map() {
+        let compression = QuantizedF32Compression {
+            tolerance: 0.5,
+            compression: Rle { element_size: 4 },
+        };
+        let mut coarse = CompressibleMap::<_, _, _>::new(compression);
+        let mut fine = CompressibleMap::<_, _, _>::new(compression);
+        fine.set_tolerance(0.001);
+        assert_eq!(fine.compression_params().tolerance(), 0.001);
+
+        for map in [&mut coarse, &mut fine].iter_mut() {
+            map.insert(1, vec![0.3]);
+            map.compress_lru();
+        }
+        assert_eq!(coarse.get(1), Some(&vec![0.0]));
+        assert!((fine.get(1).unwrap()[0] - 0.3).abs() <= 0.001);
+    }
+
+    #[test]
+    fn get_cow_borrows_cached_and_owns_decompressed() {
+        let mut map = CompressibleMap::<_, _, _>::new(FakeFooCompression);
+        map.insert(1, Foo(0));
+        map.insert(2, Foo(0));
+        map.compress_lru();
+
+        assert!(matches!(map.get_cow(2, None), Some(Cow::Borrowed(Foo(0)))));
+        assert!(matches!(map.get_cow(1, None), Some(Cow::Owned(Foo(2)))));
+        assert_eq!(map.get_cow(3, None), None);
+
+        let local_cache = LocalCache::new();
+        assert!(matches!(
+            map.get_cow(1, Some(&local_cache)),
+            Some(Cow::Borrowed(Foo(2)))
+        ));
+        map.flush_local_cache(local_cache);
+        assert_eq!(map.len_cached(), 2);
+    }
+
     #[test]
     fn flush_after_get_const_populates_cache() {
         // Use a function just to mimic the "global" lifetime of the map.
+        #[allow(clippy::vec_init_then_push)]
         fn do_test_with_global_cache(map: &mut CompressibleMap<i32, Foo, FakeFooCompression>) {
             map.insert(1, Foo(0));
             map.insert(2, Foo(1));
@@ -408,6 +2261,463 @@ mod tests {
         do_test_with_global_cache(&mut map);
     }
 
+    #[test]
+    fn drained_local_cache_can_be_reused() {
+        let mut map = CompressibleMap::<_, _, _>::new(FakeFooCompression);
+        map.insert(1, Foo(0));
+        map.insert(2, Foo(0));
+
+        let mut local_cache = LocalCache::new();
+        for key in [1, 2].iter() {
+            map.compress_lru();
+
+            assert_eq!(map.get_const(*key, &local_cache), Some(&Foo(2)));
+            map.flush_local_cache_drain(&mut local_cache);
+
+            assert!(local_cache.is_empty());
+            assert_eq!(map.len_cached(), 2);
+            assert_eq!(map.len_compressed(), 0);
+        }
+    }
+
+    #[test]
+    fn merged_local_caches_flush_once() {
+        let mut map = CompressibleMap::<_, _, _>::new(FakeFooCompression);
+        for i in 0..3 {
+            map.insert(i, Foo(0));
+        }
+        map.compress_lru();
+        map.compress_lru();
+
+        let mut merged = LocalCache::new();
+        assert_eq!(map.get_const(2, &merged), Some(&Foo(0)));
+        let other = LocalCache::new();
+        assert_eq!(map.get_const(0, &other), Some(&Foo(2)));
+        assert_eq!(map.get_const(1, &other), Some(&Foo(2)));
+        merged.merge(other);
+        map.flush_local_cache(merged);
+
+        assert_eq!(map.len_cached(), 3);
+        assert_eq!(map.len_compressed(), 0);
+    }
+
+    #[test]
+    fn threads_share_a_sync_local_cache() {
+        let mut map = CompressibleMap::<_, _, _>::new(FakeFooCompression);
+        for i in 0..8 {
+            map.insert(i, Foo(0));
+        }
+        for _ in 0..6 {
+            map.compress_lru();
+        }
+
+        let local_cache = SyncLocalCache::new();
+        std::thread::scope(|scope| {
+            for _ in 0..4 {
+                scope.spawn(|| {
+                    for i in 0..8 {
+                        let expected = if i < 6 { Foo(2) } else { Foo(0) };
+                        assert_eq!(map.get_const_sync(i, &local_cache), Some(&expected));
+                    }
+                    assert_eq!(map.get_const_sync(8, &local_cache), None);
+                });
+            }
+        });
+        map.flush_local_cache(local_cache.into_local_cache());
+
+        assert_eq!(map.len_cached(), 8);
+        assert_eq!(map.len_compressed(), 0);
+    }
+
+    #[test]
+    fn stale_local_cache_values_are_not_flushed() {
+        let mut map = CompressibleMap::<_, _, _>::new(FakeFooCompression);
+        map.insert(1, Foo(0));
+        map.compress_lru();
+
+        let local_cache = LocalCache::new();
+        assert_eq!(map.get_const(1, &local_cache), Some(&Foo(2)));
+        map.insert(1, Foo(10));
+        map.compress_lru();
+        map.flush_local_cache(local_cache);
+
+        assert_eq!(map.get(1), Some(&Foo(12)));
+    }
+
+    #[test]
+    fn values_are_dirty_until_compressed() {
+        let mut map = CompressibleMap::<_, _, _>::new(FakeFooCompression);
+        map.insert(1, Foo(0));
+        assert!(map.is_dirty(&1));
+
+        map.compress_lru();
+        assert!(!map.is_dirty(&1));
+        map.get(1);
+        assert!(!map.is_dirty(&1));
+        map.get_mut(1);
+        assert!(map.is_dirty(&1));
+
+        // A dirty value isn't overwritten by a stale read from a local cache.
+        map.compress_lru();
+        let local_cache = LocalCache::new();
+        assert_eq!(map.get_const(1, &local_cache), Some(&Foo(4)));
+        map.insert(1, Foo(10));
+        map.flush_local_cache(local_cache);
+        assert!(map.is_dirty(&1));
+        assert_eq!(map.get(1), Some(&Foo(10)));
+        assert!(!map.is_dirty(&2));
+    }
+
+    #[test]
+    fn reuse_compressed_form_of_clean_values() {
+        let mut map = CompressibleMap::<_, _, _>::new(FakeFooCompression);
+        map.set_reuse_compressed(true);
+        map.insert(1, Foo(0));
+        map.insert(2, Foo(0));
+        map.compress_all();
+        map.get(1);
+        map.get_mut(2);
+        map.compress_all();
+
+        // Only the modified value was compressed again.
+        assert_eq!(map.stats().compressions, 3);
+        assert_eq!(map.get(1), Some(&Foo(2)));
+        assert_eq!(map.get(2), Some(&Foo(4)));
+
+        let local_cache = LocalCache::new();
+        map.compress_all();
+        map.get_const(1, &local_cache);
+        map.flush_local_cache(local_cache);
+        map.compress_all();
+        assert_eq!(map.stats().compressions, 3);
+        assert_eq!(map.get(1), Some(&Foo(2)));
+    }
+
+    #[test]
+    fn get_mut_or_default_inserts_missing_values() {
+        let mut map = CompressibleMap::<_, _, _>::new(FakeFooCompression);
+        map.get_mut_or_default(1).0 += 1;
+        map.compress_lru();
+        map.get_mut_or_default(1).0 += 1;
+
+        assert_eq!(map.get(1), Some(&Foo(4)));
+        assert_eq!(map.len(), 1);
+    }
+
+    #[test]
+    fn insert_returns_displaced_value_as_stored() {
+        let mut map = CompressibleMap::<_, _, _>::new(FakeFooCompression);
+        assert!(map.insert(1, Foo(0)).is_none());
+        assert!(matches!(
+            map.insert(1, Foo(1)),
+            Some(MaybeCompressed::Decompressed(Foo(0)))
+        ));
+
+        map.compress_lru();
+        match map.insert(1, Foo(2)) {
+            Some(MaybeCompressed::Compressed(compressed)) => {
+                assert_eq!(compressed.decompress(), Foo(3))
+            }
+            _ => panic!("Expected the compressed value"),
+        }
+        assert_eq!(map.stats().decompressions, 0);
+    }
+
+    #[test]
+    fn remove_returns_compressed_value_without_decompressing() {
+        let mut map = CompressibleMap::<_, _, _>::new(FakeFooCompression);
+        map.insert(1, Foo(0));
+        map.insert(2, Foo(0));
+        map.compress_lru();
+
+        assert!(matches!(
+            map.remove(&1),
+            Some(MaybeCompressed::Compressed(_))
+        ));
+        assert!(matches!(
+            map.remove(&2),
+            Some(MaybeCompressed::Decompressed(Foo(0)))
+        ));
+        assert!(map.remove(&1).is_none());
+        assert_eq!(map.stats().decompressions, 0);
+    }
+
+    #[test]
+    fn compress_lru_reports_key_and_size() {
+        let mut map = CompressibleMap::<_, _, _>::new(FakeFooCompression);
+        map.insert(1, Foo(0));
+        map.insert(2, Foo(0));
+
+        assert_eq!(
+            map.compress_lru_info(),
+            Some((1, core::mem::size_of::<Foo>()))
+        );
+        map.compress_lru();
+        assert_eq!(map.compress_lru_info(), None);
+    }
+
+    #[test]
+    fn get_batch_decompresses_all_keys() {
+        let mut map = CompressibleMap::<_, _, _>::new(FakeFooCompression);
+        for i in 0..4 {
+            map.insert(i, Foo(0));
+        }
+        map.compress_lru_n(3);
+
+        assert_eq!(
+            map.get_batch(vec![2, 4, 0, 3]),
+            vec![Some(&Foo(2)), None, Some(&Foo(2)), Some(&Foo(0))]
+        );
+        assert_eq!(map.len_compressed(), 1);
+    }
+
+    #[test]
+    fn get_batch_keeps_its_keys_cached_over_max_cached() {
+        let mut map = CompressibleMap::<_, _, _>::new(FakeFooCompression);
+        map.set_max_cached(Some(2));
+        for i in 0..4 {
+            map.insert(i, Foo(i));
+        }
+        map.pin(&3);
+
+        assert_eq!(
+            map.get_batch(vec![0, 1, 2]),
+            vec![Some(&Foo(2)), Some(&Foo(3)), Some(&Foo(2))]
+        );
+        assert!(!map.is_pinned(&0));
+        assert!(map.is_pinned(&3));
+        // The next value to be cached catches up on the limit.
+        map.insert(4, Foo(4));
+        assert_eq!(map.len_cached(), 2);
+        assert!(map.is_cached(&3));
+    }
+
+    #[test]
+    fn prefetch_warms_the_cache() {
+        let mut map = CompressibleMap::<_, _, _>::new(FakeFooCompression);
+        for i in 0..4 {
+            map.insert(i, Foo(0));
+        }
+        map.compress_lru_n(3);
+
+        assert_eq!(map.prefetch(vec![0, 1, 3, 5]), 2);
+        assert!(map.is_compressed(&2));
+        assert_eq!(map.recency_rank(&3), Some(0));
+        assert_eq!(map.stats().decompressions, 2);
+    }
+
+    #[test]
+    fn index_cached_and_compressed_values() {
+        let mut map = CompressibleMap::<_, _, _>::new(FakeFooCompression);
+        map.insert(1, Foo(0));
+        map.compress_lru();
+
+        map[&1].0 += 1;
+        assert_eq!(map[&1], Foo(3));
+        assert!(map.is_dirty(&1));
+    }
+
+    #[test]
+    #[should_panic(expected = "exists but is compressed")]
+    fn index_compressed_value_immutably() {
+        let mut map = CompressibleMap::<_, _, _>::new(FakeFooCompression);
+        map.insert(1, Foo(0));
+        map.compress_lru();
+
+        let _ = &map[&1];
+    }
+
+    #[test]
+    fn clone_keeps_cached_and_compressed_values() {
+        let mut map = CompressibleMap::<_, _, _>::new(FakeFooCompression);
+        map.insert(1, Foo(0));
+        map.insert(2, Foo(0));
+        map.compress_lru();
+
+        let mut clone = map.clone();
+        map.clear();
+        assert_eq!(clone.len_compressed(), 1);
+        assert_eq!(clone.recency_rank(&2), Some(0));
+        assert_eq!(clone.get(1), Some(&Foo(2)));
+
+        assert_eq!(
+            format!("{:?}", clone),
+            "CompressibleMap { len_cached: 2, len_compressed: 0, bytes_compressed: 0 }"
+        );
+        assert!(format!("{:#?}", clone).contains("keys"));
+    }
+
+    #[test]
+    fn keys_by_recency_start_with_coldest() {
+        let mut map = CompressibleMap::<_, _, _>::new(FakeFooCompression);
+        for i in 0..4 {
+            map.insert(i, Foo(i));
+        }
+        map.get(1);
+        map.compress_lru();
+
+        let keys: Vec<u32> = map.keys_by_recency().copied().collect();
+        assert_eq!(keys, vec![2, 3, 1]);
+        map.compress_lru();
+        assert!(map.is_compressed(&2));
+    }
+
+    #[test]
+    fn peek_does_not_touch_lru_order() {
+        let mut map = CompressibleMap::<_, _, _>::new(FakeFooCompression);
+        for i in 0..3 {
+            map.insert(i, Foo(i));
+        }
+        map.compress_lru();
+
+        assert_eq!(map.peek(&0), None);
+        assert_eq!(map.peek(&1), Some(&Foo(1)));
+        assert_eq!(map.peek_lru(), Some((&1, &Foo(1))));
+        map.compress_lru();
+        assert!(map.is_compressed(&1));
+    }
+
+    #[test]
+    fn touch_refreshes_without_decompressing() {
+        let mut map = CompressibleMap::<_, _, _>::new(FakeFooCompression);
+        for i in 0..3 {
+            map.insert(i, Foo(i));
+        }
+        map.compress_lru();
+
+        assert!(!map.touch(&0));
+        assert!(map.touch(&1));
+        assert!(!map.touch(&3));
+        assert!(map.is_compressed(&0));
+        assert_eq!(map.recency_rank(&1), Some(0));
+        assert_eq!(map.stats().decompressions, 0);
+    }
+
+    #[test]
+    fn pinned_values_are_not_compressed() {
+        let mut map = CompressibleMap::<_, _, _>::new(FakeFooCompression);
+        for i in 0..4 {
+            map.insert(i, Foo(i));
+        }
+        assert!(map.pin(&0));
+        assert!(map.pin(&2));
+        assert!(!map.pin(&9));
+
+        assert_eq!(map.compress_lru_n(4), 2);
+        assert!(map.is_cached(&0));
+        assert!(map.is_cached(&2));
+        assert!(map.is_compressed(&1));
+        assert!(map.is_compressed(&3));
+
+        map.set_max_cached(Some(1));
+        assert_eq!(map.len_cached(), 2);
+
+        assert!(map.unpin(&0));
+        map.compress_lru();
+        assert!(map.is_compressed(&0));
+
+        map.remove(&2);
+        assert!(!map.is_pinned(&2));
+    }
+
+    #[test]
+    fn arc_values_outlive_compression() {
+        let mut map =
+            CompressibleMap::<_, _, _>::new(crate::ArcCompression::new(FakeFooCompression));
+        map.insert(1, Arc::new(Foo(1)));
+
+        let held = map.get_arc(1).unwrap();
+        map.compress_lru();
+        assert_eq!(*held, Foo(1));
+        assert_eq!(Arc::strong_count(&held), 1);
+
+        assert_eq!(map.get_arc(1).as_deref(), Some(&Foo(3)));
+        assert_eq!(map.get_arc(2), None);
+    }
+
+    #[test]
+    fn retain_cached_and_compressed_entries() {
+        let mut map = CompressibleMap::<_, _, _>::new(FakeFooCompression);
+        for i in 0..6 {
+            map.insert(i, Foo(i));
+        }
+        for _ in 0..3 {
+            map.compress_lru();
+        }
+
+        map.retain(|key, value| match value {
+            MaybeCompressed::Decompressed(foo) => foo.0 != 4,
+            MaybeCompressed::Compressed(_) => key % 2 == 0,
+        });
+
+        let mut keys: Vec<_> = map.iter().map(|(k, _)| *k).collect();
+        keys.sort_unstable();
+        assert_eq!(keys, vec![0, 2, 3, 5]);
+        assert_eq!(map.len_compressed(), 2);
+    }
+
+    #[test]
+    fn iter_mut_decompresses_everything() {
+        let mut map = CompressibleMap::<_, _, _>::new(FakeFooCompression);
+        map.set_size_estimator(|foo: &Foo| foo.0 as usize);
+        for i in 0..4 {
+            map.insert(i, Foo(i));
+        }
+        map.compress_lru();
+        map.compress_lru();
+        let stamp = map.modification_stamp();
+
+        for (_, foo) in map.iter_mut() {
+            foo.0 *= 10;
+        }
+        assert_eq!(map.len_compressed(), 0);
+        assert_eq!(map.iter_changed_since(stamp).count(), 4);
+        // Values are re-weighed after being modified.
+        assert_eq!(map.bytes_cached_estimate(), 20 + 30 + 20 + 30);
+
+        let mut values: Vec<_> = map.iter_decompressed().map(|(_, foo)| foo.0).collect();
+        values.sort_unstable();
+        assert_eq!(values, vec![20, 20, 30, 30]);
+    }
+
+    #[test]
+    fn iter_mut_visits_everything_over_max_cached() {
+        let mut map = CompressibleMap::<_, _, _>::new(FakeFooCompression);
+        map.set_max_cached(Some(2));
+        for i in 0..4 {
+            map.insert(i, Foo(i));
+        }
+        let stamp = map.modification_stamp();
+
+        assert_eq!(map.iter_mut().count(), 4);
+        assert_eq!(map.iter_changed_since(stamp).count(), 4);
+        assert_eq!(map.iter_decompressed().count(), 4);
+
+        map.insert(4, Foo(4));
+        assert_eq!(map.len_cached(), 2);
+    }
+
+    #[test]
+    fn for_each_mut_stays_within_budget() {
+        let mut map = CompressibleMap::<_, _, _>::new(FakeFooCompression);
+        map.set_size_estimator(|_| 1);
+        for i in 0..6 {
+            map.insert(i, Foo(i));
+        }
+        for _ in 0..4 {
+            map.compress_lru();
+        }
+
+        let mut visited = Vec::new();
+        map.for_each_mut_within_budget(2, |key, foo| {
+            visited.push(*key);
+            foo.0 = 0;
+        });
+        visited.sort_unstable();
+        assert_eq!(visited, vec![0, 1, 2, 3, 4, 5]);
+        assert_eq!(map.len_cached(), 2);
+    }
+
     #[test]
     fn multithreaded_borrows() {
         use crossbeam::thread;
@@ -451,6 +2761,7 @@ mod tests {
     }
 
     #[test]
+    #[allow(clippy::unnecessary_cast, clippy::while_let_loop)]
     fn multithreaded_decompression() {
         use crossbeam::{channel, thread};
 
@@ -0,0 +1,107 @@
+use super::{decompress_recycling, CompressibleMap, MaybeCompressed};
+use crate::{lru_cache::EntryState, Compressed, CompressedStorage, Compression};
+
+use std::borrow::Cow;
+use std::hash::{BuildHasher, Hash};
+
+impl<K, V, A, H, S> CompressibleMap<K, V, A, H, S>
+where
+    K: Clone + Eq + Hash,
+    H: BuildHasher + Default,
+    A: Compression<Data = V>,
+    S: CompressedStorage<K, Compressed<A>>,
+{
+    /// Like `get`, but a compressed value is decompressed on Tokio's blocking thread pool, so large
+    /// values don't stall the executor. Must be called from within a Tokio runtime.
+    ///
+    /// This is cancel safe: the compressed data is copied to the pool, so if the future is dropped
+    /// before decompression finishes, the value simply stays compressed.
+    pub async fn get_async(&mut self, key: K) -> Option<&V>
+    where
+        V: Send + 'static,
+        Compressed<A>: Clone + Send + 'static,
+    {
+        self.await_compressed(&key);
+
+        let mut decompressed = None;
+        if let Some(EntryState::Evicted) = self.cache.get_const(&key) {
+            let compressed = self.compressed.get(&key).unwrap().clone();
+            let mut recycled: Vec<V> = self.recycled.pop().into_iter().collect();
+            let value = tokio::task::spawn_blocking(move || {
+                decompress_recycling(&compressed, &mut recycled)
+            })
+            .await
+            .expect("Decompression panicked");
+
+            decompressed = Some(value);
+        }
+
+        self.access(key, decompressed, false).map(|v| &*v)
+    }
+
+    /// Like `get_cow` without a `LocalCache`, but a compressed value is decompressed on Tokio's
+    /// blocking thread pool. Must be called from within a Tokio runtime.
+    pub async fn get_const_async(&self, key: K) -> Option<Cow<'_, V>>
+    where
+        V: Clone + Send + 'static,
+        Compressed<A>: Clone + Send + 'static,
+    {
+        match self.cache.get_const(&key)? {
+            EntryState::Cached(value) => Some(Cow::Borrowed(value)),
+            EntryState::Evicted => match self.get_evicted(&key) {
+                MaybeCompressed::Decompressed(value) => Some(Cow::Borrowed(value)),
+                MaybeCompressed::Compressed(compressed) => {
+                    let compressed = compressed.clone();
+                    let value = tokio::task::spawn_blocking(move || compressed.decompress())
+                        .await
+                        .expect("Decompression panicked");
+
+                    Some(Cow::Owned(value))
+                }
+            },
+        }
+    }
+}
+
+// ████████╗███████╗███████╗████████╗███████╗
+// ╚══██╔══╝██╔════╝██╔════╝╚══██╔══╝██╔════╝
+//    ██║   █████╗  ███████╗   ██║   ███████╗
+//    ██║   ██╔══╝  ╚════██║   ██║   ╚════██║
+//    ██║   ███████╗███████║   ██║   ███████║
+//    ╚═╝   ╚══════╝╚══════╝   ╚═╝   ╚══════╝
+
+#[cfg(test)]
+mod tests {
+    use crate::test_util::{FakeFooCompression, Foo};
+    use crate::CompressibleMap;
+
+    use std::borrow::Cow;
+
+    #[test]
+    fn decompress_on_blocking_pool() {
+        let runtime = tokio::runtime::Builder::new_current_thread()
+            .build()
+            .unwrap();
+
+        let mut map = CompressibleMap::<_, _, _>::new(FakeFooCompression);
+        map.insert(1, Foo(1));
+        map.insert(2, Foo(2));
+        map.compress_lru();
+
+        runtime.block_on(async {
+            assert!(matches!(
+                map.get_const_async(1).await,
+                Some(Cow::Owned(Foo(3)))
+            ));
+            assert!(matches!(
+                map.get_const_async(2).await,
+                Some(Cow::Borrowed(Foo(2)))
+            ));
+            assert_eq!(map.get_const_async(3).await, None);
+
+            assert_eq!(map.get_async(1).await, Some(&Foo(3)));
+            assert_eq!(map.get_async(3).await, None);
+        });
+        assert_eq!(map.len_cached(), 2);
+    }
+}
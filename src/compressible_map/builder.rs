@@ -0,0 +1,204 @@
+use super::{eviction::Persist, CompressibleMap, EvictionDecision, RecencyGuard};
+use crate::{Compressed, CompressedStorage, Compression, EvictionPolicy};
+
+use alloc::boxed::Box;
+use core::hash::{BuildHasher, Hash};
+
+/// Configures a `CompressibleMap` in one expression, instead of calling its setters one by one
+/// after construction. Created by `CompressibleMap::builder`. The hasher and compressed storage are
+/// chosen by the type parameters of the map, e.g. `CompressibleMap::<K, V, A, MyHasher>::builder`.
+pub struct CompressibleMapBuilder<K, V, A, H, S>
+where
+    A: Compression<Data = V>,
+{
+    map: CompressibleMap<K, V, A, H, S>,
+    // Applied last, since it needs the eviction policy.
+    persist: Option<Persist<K, V>>,
+}
+
+impl<K, V, A, H, S> CompressibleMap<K, V, A, H, S>
+where
+    K: Clone + Eq + Hash,
+    H: BuildHasher + Default,
+    A: Compression<Data = V>,
+    S: CompressedStorage<K, Compressed<A>>,
+{
+    pub fn builder(compression_params: A) -> CompressibleMapBuilder<K, V, A, H, S>
+    where
+        S: Default,
+    {
+        CompressibleMapBuilder::with_storage(compression_params, S::default())
+    }
+}
+
+impl<K, V, A, H, S> CompressibleMapBuilder<K, V, A, H, S>
+where
+    K: Clone + Eq + Hash,
+    H: BuildHasher + Default,
+    A: Compression<Data = V>,
+    S: CompressedStorage<K, Compressed<A>>,
+{
+    /// Starts from a map that keeps its compressed values in `storage`. See
+    /// `CompressibleMap::with_storage`.
+    pub fn with_storage(compression_params: A, storage: S) -> Self {
+        Self {
+            map: CompressibleMap::with_storage(compression_params, storage),
+            persist: None,
+        }
+    }
+
+    /// Reserves room for `capacity` entries, of which `max_cached` will be cached. See
+    /// `CompressibleMap::reserve`.
+    pub fn capacity(mut self, capacity: usize, max_cached: usize) -> Self {
+        self.map.reserve(capacity, max_cached);
+
+        self
+    }
+
+    /// See `CompressibleMap::set_max_cached`.
+    pub fn max_cached(mut self, max_cached: usize) -> Self {
+        self.map.set_max_cached(Some(max_cached));
+
+        self
+    }
+
+    /// See `CompressibleMap::set_byte_cap`.
+    pub fn byte_cap(mut self, cap: usize) -> Self {
+        self.map.set_byte_cap(Some(cap));
+
+        self
+    }
+
+    /// See `CompressibleMap::set_size_estimator`.
+    pub fn size_estimator(
+        mut self,
+        estimate: impl Fn(&V) -> usize + Send + Sync + 'static,
+    ) -> Self {
+        self.map.set_size_estimator(estimate);
+
+        self
+    }
+
+    /// See `CompressibleMap::set_weigher`.
+    pub fn weigher(mut self, weigh: impl Fn(&K, &V) -> usize + Send + Sync + 'static) -> Self {
+        self.map.set_weigher(weigh);
+
+        self
+    }
+
+    /// See `CompressibleMap::set_recency_guard`.
+    pub fn recency_guard(mut self, guard: RecencyGuard) -> Self {
+        self.map.set_recency_guard(Some(guard));
+
+        self
+    }
+
+    /// See `CompressibleMap::set_track_access_times`.
+    #[cfg(feature = "std")]
+    pub fn track_access_times(mut self) -> Self {
+        self.map.set_track_access_times(true);
+
+        self
+    }
+
+    /// See `CompressibleMap::set_cache_policy`.
+    pub fn cache_policy(mut self, policy: impl EvictionPolicy + 'static) -> Self {
+        self.map.set_cache_policy(policy);
+
+        self
+    }
+
+    /// See `CompressibleMap::set_eviction_policy`.
+    pub fn eviction_policy(
+        mut self,
+        decide: impl Fn(&K, &V) -> EvictionDecision + Send + Sync + 'static,
+    ) -> Self {
+        self.map.set_eviction_policy(decide);
+
+        self
+    }
+
+    /// See `CompressibleMap::set_persistence_sink`. Unlike the setter, this can be called before
+    /// `eviction_policy`, but `build` panics if there's no eviction policy by then.
+    pub fn persistence_sink(mut self, persist: impl FnMut(K, V) + Send + Sync + 'static) -> Self {
+        self.persist = Some(Box::new(persist));
+
+        self
+    }
+
+    /// See `CompressibleMap::set_spill_sink`.
+    pub fn spill_sink(
+        mut self,
+        spill: impl FnMut(K, Compressed<A>) + Send + Sync + 'static,
+    ) -> Self {
+        self.map.set_spill_sink(spill);
+
+        self
+    }
+
+    /// See `CompressibleMap::set_reuse_compressed`.
+    pub fn reuse_compressed(mut self, reuse: bool) -> Self {
+        self.map.set_reuse_compressed(reuse);
+
+        self
+    }
+
+    /// See `CompressibleMap::set_max_recycled`.
+    pub fn max_recycled(mut self, max_recycled: usize) -> Self {
+        self.map.set_max_recycled(max_recycled);
+
+        self
+    }
+
+    pub fn build(self) -> CompressibleMap<K, V, A, H, S> {
+        let Self { mut map, persist } = self;
+        if let Some(persist) = persist {
+            map.set_boxed_persistence_sink(persist);
+        }
+
+        map
+    }
+}
+
+// ████████╗███████╗███████╗████████╗███████╗
+// ╚══██╔══╝██╔════╝██╔════╝╚══██╔══╝██╔════╝
+//    ██║   █████╗  ███████╗   ██║   ███████╗
+//    ██║   ██╔══╝  ╚════██║   ██║   ╚════██║
+//    ██║   ███████╗███████║   ██║   ███████║
+//    ╚═╝   ╚══════╝╚══════╝   ╚═╝   ╚══════╝
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::{FakeFooCompression, Foo};
+    use crate::FifoPolicy;
+
+    use std::sync::{Arc, Mutex};
+
+    #[test]
+    fn builder_applies_settings() {
+        let persisted = Arc::new(Mutex::new(Vec::new()));
+        let sink = persisted.clone();
+        let mut map = CompressibleMap::<_, _, _>::builder(FakeFooCompression)
+            .capacity(100, 10)
+            .max_cached(2)
+            .cache_policy(FifoPolicy::default())
+            .persistence_sink(move |key, _| sink.lock().unwrap().push(key))
+            .eviction_policy(|key: &u32, _: &Foo| {
+                if *key == 0 {
+                    EvictionDecision::Persist
+                } else {
+                    EvictionDecision::Compress
+                }
+            })
+            .build();
+        assert_eq!(map.max_cached(), Some(2));
+
+        for i in 0..4 {
+            map.insert(i, Foo(i));
+        }
+        assert_eq!(map.len_cached(), 2);
+        assert_eq!(*persisted.lock().unwrap(), vec![0]);
+        assert!(map.is_compressed(&1));
+    }
+}
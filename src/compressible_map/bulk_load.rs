@@ -0,0 +1,91 @@
+use super::CompressibleMap;
+use crate::{events::MapEvent, op_log::Op, Compressed, CompressedStorage, Compression};
+
+use core::hash::{BuildHasher, Hash};
+
+/// Configures `CompressibleMap::bulk_load`.
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+pub struct BulkLoadOptions {
+    /// Only the last `n` entries are cached, and the rest are compressed. `None` caches everything.
+    pub keep_cached: Option<usize>,
+}
+
+impl<K, V, A, H, S> CompressibleMap<K, V, A, H, S>
+where
+    K: Clone + Eq + Hash,
+    H: BuildHasher + Default,
+    A: Compression<Data = V>,
+    S: CompressedStorage<K, Compressed<A>>,
+{
+    /// Fills an empty map with `entries`. This is much faster than calling `insert` and then
+    /// `compress_lru` for each entry, because values that end up compressed never enter the cache,
+    /// and there are no old entries to look for. Keys must be unique.
+    ///
+    /// Panics if the map isn't empty.
+    pub fn bulk_load<I>(&mut self, entries: I, options: BulkLoadOptions)
+    where
+        I: IntoIterator<Item = (K, V)>,
+        I::IntoIter: ExactSizeIterator,
+    {
+        assert!(self.is_empty(), "Can only bulk load into an empty map");
+
+        let entries = entries.into_iter();
+        let len = entries.len();
+        let num_cached = options.keep_cached.unwrap_or(len).min(len);
+        let num_compressed = len - num_cached;
+        self.reserve(len, num_cached);
+
+        for (i, (key, value)) in entries.enumerate() {
+            self.modification_stamps.stamp(key.clone());
+            self.subscribers.notify(|| MapEvent::Inserted(key.clone()));
+            if i < num_compressed {
+                let compressed = self.compression_params.compress(&value);
+                self.op_recorder.record(|| Op::InsertCompressed {
+                    key: key.clone(),
+                    compressed_size: compressed.size(),
+                });
+                self.cache.evict(key.clone());
+                self.modification_stamps.mark_clean(&key);
+                self.compressed.insert(key, compressed);
+            } else {
+                self.op_recorder.record(|| Op::Insert(key.clone()));
+                self.cache.insert(key, value);
+            }
+        }
+        self.compress_over_limits();
+    }
+}
+
+// ████████╗███████╗███████╗████████╗███████╗
+// ╚══██╔══╝██╔════╝██╔════╝╚══██╔══╝██╔════╝
+//    ██║   █████╗  ███████╗   ██║   ███████╗
+//    ██║   ██╔══╝  ╚════██║   ██║   ╚════██║
+//    ██║   ███████╗███████║   ██║   ███████║
+//    ╚═╝   ╚══════╝╚══════╝   ╚═╝   ╚══════╝
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::{FakeFooCompression, Foo};
+
+    #[test]
+    fn bulk_load_compresses_all_but_last_entries() {
+        let mut map = CompressibleMap::<_, _, _>::new(FakeFooCompression);
+        map.bulk_load(
+            (0..10).map(|i| (i, Foo(i))),
+            BulkLoadOptions {
+                keep_cached: Some(3),
+            },
+        );
+
+        assert_eq!(map.len_cached(), 3);
+        assert_eq!(map.len_compressed(), 7);
+        assert_eq!(map.recency_rank(&9), Some(0));
+        assert_eq!(map.get(9), Some(&Foo(9)));
+        assert_eq!(map.get(0), Some(&Foo(2)));
+
+        let mut map = CompressibleMap::<_, _, _>::new(FakeFooCompression);
+        map.bulk_load((0..10).map(|i| (i, Foo(i))), BulkLoadOptions::default());
+        assert_eq!(map.len_cached(), 10);
+    }
+}
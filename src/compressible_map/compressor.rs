@@ -0,0 +1,391 @@
+use super::CompressibleMap;
+use crate::{events::MapEvent, op_log::Op, Compressed, CompressedStorage, Compression};
+
+use alloc::sync::Arc;
+use core::hash::{BuildHasher, Hash};
+use hashbrown::HashMap;
+#[cfg(feature = "std")]
+use std::sync::{
+    mpsc::{channel, Receiver, Sender, TryRecvError},
+    Mutex,
+};
+#[cfg(feature = "std")]
+use std::thread::JoinHandle;
+
+/// Configures the background thread started by `CompressibleMap::spawn_compressor`, which needs the
+/// `std` feature.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct CompressorConfig {
+    /// The maximum number of values handed to the thread that haven't come back compressed yet.
+    /// Compressing another value waits for one to come back, which bounds the memory used by
+    /// values in flight if the thread can't keep up.
+    pub max_in_flight: usize,
+}
+
+impl Default for CompressorConfig {
+    fn default() -> Self {
+        Self { max_in_flight: 64 }
+    }
+}
+
+// Each hand-off gets a ticket, so a result is ignored if its entry was forgotten in the meantime,
+// e.g. by `clear`. The value is shared with the map, so it can still be read while it's in flight.
+type Handoff<K, V> = (K, u64, Arc<V>);
+type Finished<K, A> = (K, u64, Compressed<A>);
+
+pub(super) struct Compressor<K, V, A>
+where
+    A: Compression<Data = V>,
+{
+    #[cfg(feature = "std")]
+    thread: Thread<K, V, A>,
+    // Without `std` there are no threads, so there's never a compressor.
+    #[cfg(not(feature = "std"))]
+    never: (
+        core::convert::Infallible,
+        core::marker::PhantomData<fn() -> A>,
+    ),
+    in_flight: HashMap<K, (u64, Arc<V>)>,
+    next_ticket: u64,
+    max_in_flight: usize,
+}
+
+#[cfg(feature = "std")]
+struct Thread<K, V, A>
+where
+    A: Compression<Data = V>,
+{
+    handoff: Sender<Handoff<K, V>>,
+    // Only used through `&mut self`, but the lock keeps the map `Sync`.
+    finished: Mutex<Receiver<Finished<K, A>>>,
+    handle: JoinHandle<()>,
+}
+
+impl<K, V, A, H, S> CompressibleMap<K, V, A, H, S>
+where
+    K: Clone + Eq + Hash,
+    H: BuildHasher + Default,
+    A: Compression<Data = V>,
+    S: CompressedStorage<K, Compressed<A>>,
+{
+    /// Moves compression onto a background thread with a copy of the current compression
+    /// parameters, so `compress_lru` and friends only hand values off instead of blocking on
+    /// compression. Compressed values are sent back and inserted by later mutable accesses, or
+    /// by `receive_compressed`.
+    ///
+    /// While a value is in flight, it counts as compressed, but immutable accessors (`get_const`,
+    /// iterators) read the uncompressed value instead of decompressing anything. Mutable accessors
+    /// of that key wait for it to come back. Call `finish_compressing` to wait for all of them. The
+    /// thread keeps using the parameters it was spawned with, so respawn it after
+    /// `retrain_compression`.
+    #[cfg(feature = "std")]
+    pub fn spawn_compressor(&mut self, config: CompressorConfig)
+    where
+        K: Send + 'static,
+        V: Send + Sync + 'static,
+        A: Clone + Send + 'static,
+        A::CompressedData: Send + 'static,
+    {
+        assert!(
+            config.max_in_flight > 0,
+            "Must allow at least one value in flight"
+        );
+
+        self.stop_compressor();
+
+        let (handoff, handoff_rx) = channel::<Handoff<K, V>>();
+        let (finished_tx, finished) = channel();
+        let params = self.compression_params.clone();
+        let handle = std::thread::spawn(move || {
+            for (key, ticket, value) in handoff_rx {
+                let compressed = params.compress(&value);
+                // Drop our share first, so the map can recycle the value.
+                drop(value);
+                if finished_tx.send((key, ticket, compressed)).is_err() {
+                    break;
+                }
+            }
+        });
+
+        self.compressor = Some(Compressor {
+            thread: Thread {
+                handoff,
+                finished: Mutex::new(finished),
+                handle,
+            },
+            in_flight: HashMap::new(),
+            next_ticket: 0,
+            max_in_flight: config.max_in_flight,
+        });
+    }
+
+    /// Waits for all values in flight, then stops the background thread. Compression happens
+    /// inline again afterwards.
+    pub fn stop_compressor(&mut self) {
+        self.finish_compressing();
+        if let Some(compressor) = self.compressor.take() {
+            compressor.stop();
+        }
+    }
+
+    pub fn has_compressor(&self) -> bool {
+        self.compressor.is_some()
+    }
+
+    /// The number of values handed to the background thread that haven't been received yet.
+    pub fn num_in_flight(&self) -> usize {
+        self.compressor.as_ref().map_or(0, |c| c.in_flight.len())
+    }
+
+    /// Inserts the values that the background thread has finished compressing so far, without
+    /// waiting for the rest. Returns the number of values inserted.
+    pub fn receive_compressed(&mut self) -> usize {
+        let mut num_received = 0;
+        while let Some(finished) = self.compressor.as_mut().and_then(|c| c.try_receive()) {
+            if self.insert_finished(finished) {
+                num_received += 1;
+            }
+        }
+
+        num_received
+    }
+
+    /// Waits until every value in flight is compressed and inserted.
+    pub fn finish_compressing(&mut self) {
+        while self.num_in_flight() > 0 {
+            let finished = self.compressor.as_mut().unwrap().receive();
+            self.insert_finished(finished);
+        }
+    }
+
+    /// Gives `value` to the background thread, or gives it back if there isn't one.
+    pub(super) fn hand_off(&mut self, key: K, value: V) -> Option<(K, V)> {
+        if self.compressor.is_none() {
+            return Some((key, value));
+        }
+
+        self.receive_compressed();
+        while self.num_in_flight() >= self.compressor.as_ref().unwrap().max_in_flight {
+            let finished = self.compressor.as_mut().unwrap().receive();
+            self.insert_finished(finished);
+        }
+
+        let compressor = self.compressor.as_mut().unwrap();
+        let ticket = compressor.next_ticket;
+        compressor.next_ticket += 1;
+        let value = Arc::new(value);
+        compressor
+            .in_flight
+            .insert(key.clone(), (ticket, value.clone()));
+        compressor.send((key, ticket, value));
+
+        None
+    }
+
+    /// Inserts what's finished, then waits for `key` if it's still in flight, so it can be
+    /// accessed.
+    pub(super) fn await_compressed(&mut self, key: &K) {
+        if self.compressor.is_none() {
+            return;
+        }
+
+        self.receive_compressed();
+        while self
+            .compressor
+            .as_ref()
+            .unwrap()
+            .in_flight
+            .contains_key(key)
+        {
+            let finished = self.compressor.as_mut().unwrap().receive();
+            self.insert_finished(finished);
+        }
+    }
+
+    /// The value for `key`, if it's in flight.
+    pub(super) fn in_flight_value(&self, key: &K) -> Option<&V> {
+        let (_, value) = self.compressor.as_ref()?.in_flight.get(key)?;
+
+        Some(value)
+    }
+
+    /// All values in flight.
+    pub(super) fn iter_in_flight(&self) -> impl Iterator<Item = (&K, &V)> {
+        self.compressor
+            .iter()
+            .flat_map(|c| c.in_flight.iter().map(|(key, (_, value))| (key, &**value)))
+    }
+
+    /// Drops any values in flight when they come back, e.g. because the map was cleared.
+    pub(super) fn forget_in_flight(&mut self) {
+        if let Some(compressor) = &mut self.compressor {
+            compressor.in_flight.clear();
+        }
+    }
+
+    fn insert_finished(&mut self, (key, ticket, compressed): Finished<K, A>) -> bool {
+        let compressor = self.compressor.as_mut().unwrap();
+        let value = match compressor.in_flight.remove(&key) {
+            Some((in_flight_ticket, value)) if in_flight_ticket == ticket => value,
+            Some(stale) => {
+                compressor.in_flight.insert(key, stale);
+
+                return false;
+            }
+            None => return false,
+        };
+
+        // The key was left in the cache as evicted when it was handed off.
+        self.record_compression(&key, &value, &compressed);
+        self.subscribers
+            .notify(|| MapEvent::Compressed(key.clone()));
+        self.op_recorder.record(|| Op::Compress {
+            key: key.clone(),
+            compressed_size: compressed.size(),
+        });
+        self.modification_stamps.mark_clean(&key);
+        self.compressed.insert(key, compressed);
+        if self.recycled.len() < self.max_recycled {
+            if let Ok(value) = Arc::try_unwrap(value) {
+                self.recycled.push(value);
+            }
+        }
+
+        true
+    }
+}
+
+#[cfg(feature = "std")]
+impl<K, V, A> Compressor<K, V, A>
+where
+    A: Compression<Data = V>,
+{
+    fn send(&mut self, handoff: Handoff<K, V>) {
+        self.thread
+            .handoff
+            .send(handoff)
+            .expect("Compressor thread panicked");
+    }
+
+    fn try_receive(&mut self) -> Option<Finished<K, A>> {
+        match self.thread.finished.get_mut().unwrap().try_recv() {
+            Ok(finished) => Some(finished),
+            Err(TryRecvError::Empty) => None,
+            Err(TryRecvError::Disconnected) => panic!("Compressor thread panicked"),
+        }
+    }
+
+    fn receive(&mut self) -> Finished<K, A> {
+        self.thread
+            .finished
+            .get_mut()
+            .unwrap()
+            .recv()
+            .expect("Compressor thread panicked")
+    }
+
+    /// Lets the thread finish once it's out of values, and waits for it.
+    fn stop(self) {
+        drop(self.thread.handoff);
+        if self.thread.handle.join().is_err() {
+            panic!("Compressor thread panicked");
+        }
+    }
+}
+
+#[cfg(not(feature = "std"))]
+impl<K, V, A> Compressor<K, V, A>
+where
+    A: Compression<Data = V>,
+{
+    fn send(&mut self, _handoff: Handoff<K, V>) {
+        match self.never.0 {}
+    }
+
+    fn try_receive(&mut self) -> Option<Finished<K, A>> {
+        match self.never.0 {}
+    }
+
+    fn receive(&mut self) -> Finished<K, A> {
+        match self.never.0 {}
+    }
+
+    fn stop(self) {
+        match self.never.0 {}
+    }
+}
+
+// ████████╗███████╗███████╗████████╗███████╗
+// ╚══██╔══╝██╔════╝██╔════╝╚══██╔══╝██╔════╝
+//    ██║   █████╗  ███████╗   ██║   ███████╗
+//    ██║   ██╔══╝  ╚════██║   ██║   ╚════██║
+//    ██║   ███████╗███████║   ██║   ███████║
+//    ╚═╝   ╚══════╝╚══════╝   ╚═╝   ╚══════╝
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        test_util::{FakeFooCompression, Foo},
+        LocalCache, MaybeCompressed,
+    };
+
+    #[test]
+    fn compress_on_background_thread() {
+        let mut map = CompressibleMap::<_, _, _>::new(FakeFooCompression);
+        map.spawn_compressor(CompressorConfig { max_in_flight: 2 });
+        for i in 0..4 {
+            map.insert(i, Foo(i));
+        }
+        for _ in 0..3 {
+            map.compress_lru();
+        }
+        assert!(map.num_in_flight() <= 2);
+
+        // Waits for the value to come back before decompressing it.
+        assert_eq!(map.get(0), Some(&Foo(2)));
+        map.finish_compressing();
+        assert_eq!(map.num_in_flight(), 0);
+        assert_eq!(map.len_cached(), 2);
+        assert_eq!(map.len_compressed(), 2);
+
+        map.compress_lru();
+        map.clear();
+        map.stop_compressor();
+        assert!(!map.has_compressor());
+        assert!(map.is_empty());
+    }
+
+    #[test]
+    fn read_values_in_flight() {
+        let mut map = CompressibleMap::<_, _, _>::new(FakeFooCompression);
+        map.spawn_compressor(CompressorConfig::default());
+        map.insert(1, Foo(1));
+        map.insert(2, Foo(2));
+        map.compress_lru();
+        assert_eq!(map.num_in_flight(), 1);
+
+        // The value in flight is read as it was, without a compression round trip.
+        assert!(map.contains_key(&1));
+        assert!(map.is_compressed(&1));
+        assert_eq!(map.len(), 2);
+        assert_eq!(map.len_compressed(), 1);
+        let local_cache = LocalCache::new();
+        assert_eq!(map.get_const(1, &local_cache), Some(&Foo(1)));
+        assert_eq!(map.get_cow(1, None).as_deref(), Some(&Foo(1)));
+        assert_eq!(map.iter().count(), 2);
+        assert!(matches!(
+            map.get_copy_without_caching(&1),
+            Some(MaybeCompressed::Decompressed(Foo(1)))
+        ));
+
+        let mut clone = map.clone();
+        assert_eq!(clone.len_compressed(), 1);
+        assert_eq!(clone.get(1), Some(&Foo(3)));
+
+        map.flush_local_cache(local_cache);
+        map.finish_compressing();
+        assert!(map.is_compressed(&1));
+        assert_eq!(map.get(1), Some(&Foo(3)));
+    }
+}
@@ -0,0 +1,220 @@
+use super::{CompressibleMap, CompressorConfig};
+use crate::{Compressed, Compression};
+
+use std::collections::hash_map::RandomState;
+use std::hash::{BuildHasher, Hash};
+use std::sync::{Arc, Mutex, MutexGuard, RwLock};
+
+/// A `CompressibleMap` that can be used from many threads at once, with a lock around each cached
+/// value. The map itself is only locked long enough to find or decompress an entry, so threads can
+/// read and modify different values at the same time, while others compress unrelated LRU values.
+///
+/// A value that's being accessed, or whose handle from `get` is still alive, is never compressed.
+/// `compress_lru` and the `max_cached` limit skip it, as if it was pinned.
+pub struct ConcurrentCompressibleMap<K, V, A, H = RandomState>
+where
+    A: Compression<Data = V>,
+{
+    map: Mutex<LockedEntries<K, V, A, H>>,
+}
+
+type LockedEntries<K, V, A, H> = CompressibleMap<K, Arc<RwLock<V>>, EntryLockCompression<A>, H>;
+
+impl<K, V, A, H> ConcurrentCompressibleMap<K, V, A, H>
+where
+    K: Clone + Eq + Hash,
+    H: BuildHasher + Default,
+    A: Compression<Data = V>,
+{
+    pub fn new(compression_params: A) -> Self {
+        let mut map = CompressibleMap::new(EntryLockCompression(compression_params));
+        map.in_use = Some(|value| Arc::strong_count(value) > 1);
+
+        Self {
+            map: Mutex::new(map),
+        }
+    }
+
+    /// Calls `f` with a shared reference to the value for `key`, decompressing it first if
+    /// necessary. Other threads can read the same value at the same time.
+    pub fn read<R>(&self, key: K, f: impl FnOnce(&V) -> R) -> Option<R> {
+        let value = self.get(key)?;
+        let value = value.read().unwrap();
+
+        Some(f(&value))
+    }
+
+    /// Calls `f` with a mutable reference to the value for `key`, decompressing it first if
+    /// necessary. Only the lock on this value is held while `f` runs.
+    pub fn write<R>(&self, key: K, f: impl FnOnce(&mut V) -> R) -> Option<R> {
+        let value = self.lock().get_mut(key).cloned()?;
+        let mut value = value.write().unwrap();
+
+        Some(f(&mut value))
+    }
+
+    /// A handle to the lock around the value for `key`, decompressing it first if necessary. The
+    /// value isn't compressed while the handle is alive. Modifications through the handle aren't
+    /// tracked by `CompressibleMap::is_dirty`.
+    pub fn get(&self, key: K) -> Option<Arc<RwLock<V>>> {
+        self.lock().get(key).cloned()
+    }
+
+    /// Returns `true` if there was an old value, which is dropped without decompressing it.
+    pub fn insert(&self, key: K, value: V) -> bool {
+        self.lock()
+            .insert(key, Arc::new(RwLock::new(value)))
+            .is_some()
+    }
+
+    /// Returns `true` if there was a value. Threads holding a handle to it can keep using it.
+    pub fn remove(&self, key: &K) -> bool {
+        self.lock().remove(key).is_some()
+    }
+
+    /// Compresses the least recently used value that isn't in use. See
+    /// `CompressibleMap::compress_lru`.
+    pub fn compress_lru(&self) {
+        self.lock().compress_lru()
+    }
+
+    /// See `CompressibleMap::set_max_cached`.
+    pub fn set_max_cached(&self, max_cached: Option<usize>) {
+        self.lock().set_max_cached(max_cached)
+    }
+
+    /// Moves compression onto a background thread, so compressing only holds the lock on the map
+    /// long enough to hand the value off. See `CompressibleMap::spawn_compressor`.
+    pub fn spawn_compressor(&self, config: CompressorConfig)
+    where
+        K: Send + 'static,
+        V: Send + Sync + 'static,
+        A: Clone + Send + 'static,
+        A::CompressedData: Send + 'static,
+    {
+        self.lock().spawn_compressor(config)
+    }
+
+    pub fn contains_key(&self, key: &K) -> bool {
+        self.lock().contains_key(key)
+    }
+
+    pub fn len(&self) -> usize {
+        self.lock().len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    pub fn len_cached(&self) -> usize {
+        self.lock().len_cached()
+    }
+
+    pub fn is_compressed(&self, key: &K) -> bool {
+        self.lock().is_compressed(key)
+    }
+
+    fn lock(&self) -> MutexGuard<'_, LockedEntries<K, V, A, H>> {
+        self.map.lock().unwrap()
+    }
+}
+
+/// Compresses the value behind an entry's lock with `A`, waiting for any writer to finish.
+#[derive(Clone)]
+struct EntryLockCompression<A>(A);
+
+impl<A> Compression for EntryLockCompression<A>
+where
+    A: Compression,
+{
+    type Data = Arc<RwLock<A::Data>>;
+    type CompressedData = A::CompressedData;
+
+    fn compress(&self, data: &Self::Data) -> Compressed<Self> {
+        Compressed::new(self.0.compress(&data.read().unwrap()).take())
+    }
+
+    fn decompress(compressed: &Self::CompressedData) -> Self::Data {
+        Arc::new(RwLock::new(A::decompress(compressed)))
+    }
+
+    fn compressed_size(compressed: &Self::CompressedData) -> usize {
+        A::compressed_size(compressed)
+    }
+}
+
+// ████████╗███████╗███████╗████████╗███████╗
+// ╚══██╔══╝██╔════╝██╔════╝╚══██╔══╝██╔════╝
+//    ██║   █████╗  ███████╗   ██║   ███████╗
+//    ██║   ██╔══╝  ╚════██║   ██║   ╚════██║
+//    ██║   ███████╗███████║   ██║   ███████║
+//    ╚═╝   ╚══════╝╚══════╝   ╚═╝   ╚══════╝
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::{FakeFooCompression, Foo};
+
+    #[test]
+    fn threads_mutate_different_keys() {
+        let map = ConcurrentCompressibleMap::<_, _, _>::new(FakeFooCompression);
+        for i in 0..8 {
+            map.insert(i, Foo(0));
+        }
+        map.compress_lru();
+        map.compress_lru();
+
+        std::thread::scope(|s| {
+            for i in 0..8 {
+                let map = &map;
+                s.spawn(move || {
+                    for _ in 0..100 {
+                        map.write(i, |value| value.0 += 1);
+                    }
+                    map.compress_lru();
+                });
+            }
+        });
+        assert_eq!(map.len(), 8);
+
+        // No write was lost, but each round trip through compression adds 2.
+        for i in 0..8 {
+            let value = map.read(i, |value| value.0).unwrap();
+            assert!(value >= 100 && (value - 100) % 2 == 0, "{}", value);
+        }
+    }
+
+    #[test]
+    fn values_in_use_are_not_compressed() {
+        let map = ConcurrentCompressibleMap::<_, _, _>::new(FakeFooCompression);
+        map.insert(1, Foo(1));
+        map.insert(2, Foo(2));
+
+        let held = map.get(1).unwrap();
+        map.compress_lru();
+        map.compress_lru();
+        assert!(!map.is_compressed(&1));
+        assert!(map.is_compressed(&2));
+
+        held.write().unwrap().0 = 5;
+        drop(held);
+        map.compress_lru();
+        assert!(map.is_compressed(&1));
+        assert_eq!(map.read(1, |value| value.clone()), Some(Foo(7)));
+    }
+
+    #[test]
+    fn values_in_flight_are_still_present() {
+        let map = ConcurrentCompressibleMap::<_, _, _>::new(FakeFooCompression);
+        map.spawn_compressor(CompressorConfig::default());
+        map.insert(1, Foo(1));
+        map.insert(2, Foo(2));
+        map.compress_lru();
+
+        assert!(map.contains_key(&1));
+        assert!(map.is_compressed(&1));
+        assert_eq!(map.len(), 2);
+        assert_eq!(map.len_cached(), 1);
+    }
+}
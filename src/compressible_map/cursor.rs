@@ -0,0 +1,164 @@
+use super::{CompressibleMap, MaybeCompressed};
+use crate::{lru_cache::EntryState, Compressed, CompressedStorage, Compression};
+
+use alloc::vec::Vec;
+use core::hash::{BuildHasher, Hash};
+
+/// Walks over the entries of a map, allowing each one to be compressed, decompressed, or removed.
+/// Created by `CompressibleMap::cursor_front_lru` or `CompressibleMap::cursor`.
+///
+/// The order of the walk is fixed when the cursor is created: cached entries from least to most
+/// recently used, followed by the compressed entries in arbitrary order. Operations on the current
+/// entry don't change which entries the cursor visits next.
+pub struct Cursor<'a, K, V, A, H, S>
+where
+    A: Compression<Data = V>,
+{
+    map: &'a mut CompressibleMap<K, V, A, H, S>,
+    keys: Vec<K>,
+    index: usize,
+}
+
+impl<K, V, A, H, S> CompressibleMap<K, V, A, H, S>
+where
+    K: Clone + Eq + Hash,
+    H: BuildHasher + Default,
+    A: Compression<Data = V>,
+    S: CompressedStorage<K, Compressed<A>>,
+{
+    /// Creates a cursor pointing at the least recently used entry.
+    pub fn cursor_front_lru(&mut self) -> Cursor<'_, K, V, A, H, S> {
+        let keys = self
+            .cache
+            .iter_lru_first()
+            .map(|(k, _)| k)
+            .chain(self.compressed.keys())
+            .cloned()
+            .collect();
+
+        Cursor {
+            map: self,
+            keys,
+            index: 0,
+        }
+    }
+
+    /// Creates a cursor pointing at the entry for `key`, or `None` if there is no such entry.
+    pub fn cursor(&mut self, key: &K) -> Option<Cursor<'_, K, V, A, H, S>> {
+        let mut cursor = self.cursor_front_lru();
+        cursor.index = cursor.keys.iter().position(|k| k == key)?;
+
+        Some(cursor)
+    }
+}
+
+impl<'a, K, V, A, H, S> Cursor<'a, K, V, A, H, S>
+where
+    K: Clone + Eq + Hash,
+    H: BuildHasher + Default,
+    A: Compression<Data = V>,
+    S: CompressedStorage<K, Compressed<A>>,
+{
+    /// The key of the current entry, or `None` if the cursor is past the last entry.
+    pub fn key(&self) -> Option<&K> {
+        self.keys.get(self.index)
+    }
+
+    /// The current entry, without decompressing it.
+    pub fn value(&self) -> Option<MaybeCompressed<&V, &Compressed<A>>> {
+        let key = self.key()?;
+
+        self.map.cache.get_const(key).map(|entry| match entry {
+            EntryState::Cached(v) => MaybeCompressed::Decompressed(v),
+            EntryState::Evicted => self.map.get_evicted(key),
+        })
+    }
+
+    pub fn is_cached(&self) -> bool {
+        matches!(self.value(), Some(MaybeCompressed::Decompressed(_)))
+    }
+
+    /// Moves to the next entry. Returns `false` if there are no more entries.
+    pub fn move_next(&mut self) -> bool {
+        if self.index < self.keys.len() {
+            self.index += 1;
+        }
+
+        self.index < self.keys.len()
+    }
+
+    /// Compresses the current entry if it's cached. Returns `true` if it was compressed.
+    pub fn compress(&mut self) -> bool {
+        match self.keys.get(self.index) {
+            Some(key) => self.map.compress_key(key),
+            None => false,
+        }
+    }
+
+    /// Decompresses the current entry into the cache if it's compressed. Returns `true` if it was
+    /// decompressed.
+    pub fn decompress(&mut self) -> bool {
+        if self.is_cached() {
+            return false;
+        }
+        match self.keys.get(self.index) {
+            Some(key) => self.map.get(key.clone()).is_some(),
+            None => false,
+        }
+    }
+
+    /// Removes the current entry and moves to the next one.
+    pub fn remove(&mut self) -> Option<MaybeCompressed<V, Compressed<A>>> {
+        let removed = self.map.remove(self.keys.get(self.index)?);
+        self.move_next();
+
+        removed
+    }
+}
+
+// ████████╗███████╗███████╗████████╗███████╗
+// ╚══██╔══╝██╔════╝██╔════╝╚══██╔══╝██╔════╝
+//    ██║   █████╗  ███████╗   ██║   ███████╗
+//    ██║   ██╔══╝  ╚════██║   ██║   ╚════██║
+//    ██║   ███████╗███████║   ██║   ███████║
+//    ╚═╝   ╚══════╝╚══════╝   ╚═╝   ╚══════╝
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::{FakeFooCompression, Foo};
+
+    #[test]
+    fn cursor_manages_tiers_in_one_pass() {
+        let mut map = CompressibleMap::<_, _, _>::new(FakeFooCompression);
+        for i in 0..4 {
+            map.insert(i, Foo(i));
+        }
+        map.compress_lru();
+
+        let mut cursor = map.cursor_front_lru();
+        let mut visited = Vec::new();
+        while let Some(&key) = cursor.key() {
+            visited.push((key, cursor.is_cached()));
+            match key {
+                0 => assert!(cursor.decompress()),
+                1 => assert!(cursor.compress()),
+                2 => {
+                    assert!(cursor.remove().is_some());
+                    continue;
+                }
+                _ => {}
+            }
+            cursor.move_next();
+        }
+        assert_eq!(visited, vec![(1, true), (2, true), (3, true), (0, false)]);
+
+        assert_eq!(map.len_cached(), 2);
+        assert_eq!(map.len_compressed(), 1);
+        assert_eq!(map.get(2), None);
+
+        let cursor = map.cursor(&3).unwrap();
+        assert!(cursor.is_cached());
+        assert!(map.cursor(&2).is_none());
+    }
+}
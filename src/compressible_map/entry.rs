@@ -0,0 +1,199 @@
+use super::{CompressibleMap, MaybeCompressed};
+use crate::{lru_cache::EntryState, Compressed, CompressedStorage, Compression};
+
+use core::hash::{BuildHasher, Hash};
+
+/// A view into a single entry of a map, which may be vacant or occupied. Created by
+/// `CompressibleMap::entry`.
+pub enum Entry<'a, K, V, A, H, S>
+where
+    A: Compression<Data = V>,
+{
+    Occupied(OccupiedEntry<'a, K, V, A, H, S>),
+    Vacant(VacantEntry<'a, K, V, A, H, S>),
+}
+
+/// An entry with a value, which is always cached, since it was decompressed (if necessary) when the
+/// entry was created.
+pub struct OccupiedEntry<'a, K, V, A, H, S>
+where
+    A: Compression<Data = V>,
+{
+    map: &'a mut CompressibleMap<K, V, A, H, S>,
+    key: K,
+}
+
+pub struct VacantEntry<'a, K, V, A, H, S>
+where
+    A: Compression<Data = V>,
+{
+    map: &'a mut CompressibleMap<K, V, A, H, S>,
+    key: K,
+}
+
+impl<K, V, A, H, S> CompressibleMap<K, V, A, H, S>
+where
+    K: Clone + Eq + Hash,
+    H: BuildHasher + Default,
+    A: Compression<Data = V>,
+    S: CompressedStorage<K, Compressed<A>>,
+{
+    /// Gets the entry for `key` for in-place manipulation. If the value is compressed, it's
+    /// decompressed into the cache, like `get`.
+    pub fn entry(&mut self, key: K) -> Entry<'_, K, V, A, H, S> {
+        if self.get(key.clone()).is_some() {
+            Entry::Occupied(OccupiedEntry { map: self, key })
+        } else {
+            Entry::Vacant(VacantEntry { map: self, key })
+        }
+    }
+}
+
+impl<'a, K, V, A, H, S> Entry<'a, K, V, A, H, S>
+where
+    K: Clone + Eq + Hash,
+    H: BuildHasher + Default,
+    A: Compression<Data = V>,
+    S: CompressedStorage<K, Compressed<A>>,
+{
+    pub fn key(&self) -> &K {
+        match self {
+            Entry::Occupied(entry) => entry.key(),
+            Entry::Vacant(entry) => entry.key(),
+        }
+    }
+
+    pub fn or_insert(self, default: V) -> &'a mut V {
+        self.or_insert_with(|| default)
+    }
+
+    pub fn or_insert_with(self, default: impl FnOnce() -> V) -> &'a mut V {
+        match self {
+            Entry::Occupied(entry) => entry.into_mut(),
+            Entry::Vacant(entry) => entry.insert(default()),
+        }
+    }
+
+    pub fn or_default(self) -> &'a mut V
+    where
+        V: Default,
+    {
+        self.or_insert_with(V::default)
+    }
+
+    /// Modifies the value if the entry is occupied.
+    pub fn and_modify(self, f: impl FnOnce(&mut V)) -> Self {
+        match self {
+            Entry::Occupied(mut entry) => {
+                f(entry.get_mut());
+
+                Entry::Occupied(entry)
+            }
+            vacant => vacant,
+        }
+    }
+}
+
+impl<'a, K, V, A, H, S> OccupiedEntry<'a, K, V, A, H, S>
+where
+    K: Clone + Eq + Hash,
+    H: BuildHasher + Default,
+    A: Compression<Data = V>,
+    S: CompressedStorage<K, Compressed<A>>,
+{
+    pub fn key(&self) -> &K {
+        &self.key
+    }
+
+    pub fn get(&self) -> &V {
+        match self.map.cache.get_const(&self.key) {
+            Some(EntryState::Cached(value)) => value,
+            _ => unreachable!("Occupied entries are cached"),
+        }
+    }
+
+    /// Counts as a modification, like `CompressibleMap::get_mut`.
+    pub fn get_mut(&mut self) -> &mut V {
+        self.map.get_mut(self.key.clone()).unwrap()
+    }
+
+    pub fn into_mut(self) -> &'a mut V {
+        self.map.get_mut(self.key).unwrap()
+    }
+
+    /// Replaces the value and returns the old one.
+    pub fn insert(&mut self, value: V) -> V {
+        match self.map.insert(self.key.clone(), value) {
+            Some(MaybeCompressed::Decompressed(old)) => old,
+            _ => unreachable!("Occupied entries are cached"),
+        }
+    }
+
+    pub fn remove(self) -> V {
+        self.remove_entry().1
+    }
+
+    pub fn remove_entry(self) -> (K, V) {
+        match self.map.remove(&self.key) {
+            Some(MaybeCompressed::Decompressed(value)) => (self.key, value),
+            _ => unreachable!("Occupied entries are cached"),
+        }
+    }
+}
+
+impl<'a, K, V, A, H, S> VacantEntry<'a, K, V, A, H, S>
+where
+    K: Clone + Eq + Hash,
+    H: BuildHasher + Default,
+    A: Compression<Data = V>,
+    S: CompressedStorage<K, Compressed<A>>,
+{
+    pub fn key(&self) -> &K {
+        &self.key
+    }
+
+    pub fn into_key(self) -> K {
+        self.key
+    }
+
+    pub fn insert(self, value: V) -> &'a mut V {
+        self.map.insert_if_vacant(self.key, value)
+    }
+}
+
+// ████████╗███████╗███████╗████████╗███████╗
+// ╚══██╔══╝██╔════╝██╔════╝╚══██╔══╝██╔════╝
+//    ██║   █████╗  ███████╗   ██║   ███████╗
+//    ██║   ██╔══╝  ╚════██║   ██║   ╚════██║
+//    ██║   ███████╗███████║   ██║   ███████║
+//    ╚═╝   ╚══════╝╚══════╝   ╚═╝   ╚══════╝
+
+#[cfg(test)]
+mod tests {
+    use crate::test_util::{FakeFooCompression, Foo};
+    use crate::{CompressibleMap, Entry};
+
+    #[test]
+    fn read_modify_write_through_entries() {
+        let mut map = CompressibleMap::<_, _, _>::new(FakeFooCompression);
+        map.insert(1, Foo(1));
+        map.compress_lru();
+
+        map.entry(1).and_modify(|foo| foo.0 *= 10).or_default();
+        map.entry(2).and_modify(|foo| foo.0 *= 10).or_default();
+        assert_eq!(map.entry(3).or_insert_with(|| Foo(3)), &mut Foo(3));
+        assert_eq!(map.get(1), Some(&Foo(30)));
+        assert_eq!(map.get(2), Some(&Foo(0)));
+        assert_eq!(map.len_cached(), 3);
+
+        map.compress_lru();
+        match map.entry(3) {
+            Entry::Occupied(entry) => {
+                assert_eq!(entry.get(), &Foo(5));
+                assert_eq!(entry.remove(), Foo(5));
+            }
+            Entry::Vacant(_) => panic!("Entry should be occupied"),
+        }
+        assert!(matches!(map.entry(3), Entry::Vacant(_)));
+    }
+}
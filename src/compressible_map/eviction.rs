@@ -0,0 +1,138 @@
+use super::CompressibleMap;
+use crate::{
+    events::MapEvent, op_log::Op, Compressed, CompressedStorage, Compression, EvictionPolicy,
+};
+
+use alloc::boxed::Box;
+use core::hash::{BuildHasher, Hash};
+
+/// What happens to a value when it leaves the cache.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum EvictionDecision {
+    /// Keep the value in the map, compressed. This is the default.
+    Compress,
+    /// Remove the entry, e.g. because the value is cheap to regenerate.
+    Drop,
+    /// Remove the entry and hand the value to the persistence sink.
+    Persist,
+}
+
+type Decide<K, V> = Box<dyn Fn(&K, &V) -> EvictionDecision + Send + Sync>;
+pub(super) type Persist<K, V> = Box<dyn FnMut(K, V) + Send + Sync>;
+
+pub(super) struct Eviction<K, V> {
+    decide: Decide<K, V>,
+    persist: Option<Persist<K, V>>,
+}
+
+impl<K, V, A, H, S> CompressibleMap<K, V, A, H, S>
+where
+    K: Clone + Eq + Hash,
+    H: BuildHasher + Default,
+    A: Compression<Data = V>,
+    S: CompressedStorage<K, Compressed<A>>,
+{
+    /// Sets the policy that chooses which cached value is compressed next, instead of the least
+    /// recently used one. `set_eviction_policy` decides what happens to that value afterwards.
+    ///
+    /// Methods that talk about "LRU" values, like `compress_lru` and the `RecencyGuard`, refer to
+    /// the value chosen by the policy. `recency_rank` and `Cursor` still follow the LRU order.
+    pub fn set_cache_policy(&mut self, policy: impl EvictionPolicy + 'static) {
+        self.cache.set_policy(Some(Box::new(policy)));
+    }
+
+    /// Sets the function that decides what happens to each value that leaves the cache, whether
+    /// by `compress_lru` or any other method that compresses values. Dropped and persisted entries
+    /// are removed from the map, and subscribers see a `MapEvent::Removed`.
+    pub fn set_eviction_policy(
+        &mut self,
+        decide: impl Fn(&K, &V) -> EvictionDecision + Send + Sync + 'static,
+    ) {
+        let persist = self.eviction.take().and_then(|e| e.persist);
+        self.eviction = Some(Eviction {
+            decide: Box::new(decide),
+            persist,
+        });
+    }
+
+    /// Sets the function that receives the values for which the eviction policy returns
+    /// `EvictionDecision::Persist`, e.g. to write them to disk.
+    ///
+    /// Panics if no policy was set with `set_eviction_policy`.
+    pub fn set_persistence_sink(&mut self, persist: impl FnMut(K, V) + Send + Sync + 'static) {
+        self.set_boxed_persistence_sink(Box::new(persist));
+    }
+
+    pub(super) fn set_boxed_persistence_sink(&mut self, persist: Persist<K, V>) {
+        self.eviction
+            .as_mut()
+            .expect("Must set an eviction policy before setting a persistence sink")
+            .persist = Some(persist);
+    }
+
+    pub(super) fn eviction_decision(&self, key: &K, value: &V) -> EvictionDecision {
+        self.eviction
+            .as_ref()
+            .map_or(EvictionDecision::Compress, |e| (e.decide)(key, value))
+    }
+
+    /// Removes an entry whose value was just evicted from the cache, instead of compressing it.
+    pub(super) fn discard_evicted(&mut self, key: K, value: V, decision: EvictionDecision) {
+        self.cache.remove(&key);
+        self.forget_clean_compressed(&key);
+        self.modification_stamps.remove(&key);
+        self.pinned.remove(&key);
+        self.subscribers.notify(|| MapEvent::Removed(key.clone()));
+        self.op_recorder.record(|| Op::Remove(key.clone()));
+
+        if decision == EvictionDecision::Persist {
+            let persist = self
+                .eviction
+                .as_mut()
+                .and_then(|e| e.persist.as_mut())
+                .expect("Must set a persistence sink before persisting");
+            persist(key, value);
+        }
+    }
+}
+
+// ████████╗███████╗███████╗████████╗███████╗
+// ╚══██╔══╝██╔════╝██╔════╝╚══██╔══╝██╔════╝
+//    ██║   █████╗  ███████╗   ██║   ███████╗
+//    ██║   ██╔══╝  ╚════██║   ██║   ╚════██║
+//    ██║   ███████╗███████║   ██║   ███████║
+//    ╚═╝   ╚══════╝╚══════╝   ╚═╝   ╚══════╝
+
+#[cfg(test)]
+mod tests {
+    use crate::test_util::{FakeFooCompression, Foo};
+    use crate::{CompressibleMap, EvictionDecision};
+
+    use std::sync::{Arc, Mutex};
+
+    #[test]
+    fn evicted_values_are_compressed_dropped_or_persisted() {
+        let mut map = CompressibleMap::<_, _, _>::new(FakeFooCompression);
+        map.set_eviction_policy(|key, _| match key % 3 {
+            0 => EvictionDecision::Compress,
+            1 => EvictionDecision::Drop,
+            _ => EvictionDecision::Persist,
+        });
+        let persisted = Arc::new(Mutex::new(Vec::new()));
+        let sink = persisted.clone();
+        map.set_persistence_sink(move |key, value| sink.lock().unwrap().push((key, value)));
+
+        for i in 0..6 {
+            map.insert(i, Foo(i));
+        }
+        for _ in 0..6 {
+            map.compress_lru();
+        }
+
+        assert_eq!(map.len_cached(), 0);
+        assert_eq!(map.len_compressed(), 2);
+        assert_eq!(map.get(3), Some(&Foo(5)));
+        assert_eq!(map.get(1), None);
+        assert_eq!(*persisted.lock().unwrap(), vec![(2, Foo(2)), (5, Foo(5))]);
+    }
+}
@@ -0,0 +1,107 @@
+use super::{CompressibleMap, MaybeCompressed};
+use crate::{lru_cache::EntryState, Compressed, CompressedStorage, Compression, CompressionError};
+
+use core::hash::{BuildHasher, Hash};
+
+impl<K, V, A, H, S> CompressibleMap<K, V, A, H, S>
+where
+    K: Clone + Eq + Hash,
+    H: BuildHasher + Default,
+    A: Compression<Data = V>,
+    S: CompressedStorage<K, Compressed<A>>,
+{
+    /// Like `get`, but returns an error instead of panicking if the value can't be decompressed.
+    /// The value stays compressed in that case, so it can be removed or replaced.
+    pub fn try_get(&mut self, key: K) -> Result<Option<&V>, CompressionError> {
+        let decompressed = self.try_decompress(&key)?;
+
+        Ok(self.access(key, decompressed, false).map(|v| &*v))
+    }
+
+    /// Like `get_mut`, but returns an error instead of panicking if the value can't be
+    /// decompressed. The value stays compressed in that case, so it can be removed or replaced.
+    pub fn try_get_mut(&mut self, key: K) -> Result<Option<&mut V>, CompressionError> {
+        let decompressed = self.try_decompress(&key)?;
+
+        Ok(self.access(key, decompressed, true))
+    }
+
+    /// Removes the entry and returns the value, decompressed if necessary. The entry is removed
+    /// even if the value can't be decompressed.
+    pub fn try_remove(&mut self, key: &K) -> Result<Option<V>, CompressionError> {
+        match self.remove(key) {
+            Some(MaybeCompressed::Decompressed(value)) => Ok(Some(value)),
+            Some(MaybeCompressed::Compressed(compressed)) => compressed.try_decompress().map(Some),
+            None => Ok(None),
+        }
+    }
+
+    /// Decompresses the value for `key` without changing the map, if it's compressed.
+    fn try_decompress(&mut self, key: &K) -> Result<Option<V>, CompressionError> {
+        self.await_compressed(key);
+
+        match self.cache.get_const(key) {
+            Some(EntryState::Evicted) => {
+                self.compressed.get(key).unwrap().try_decompress().map(Some)
+            }
+            _ => Ok(None),
+        }
+    }
+}
+
+// ████████╗███████╗███████╗████████╗███████╗
+// ╚══██╔══╝██╔════╝██╔════╝╚══██╔══╝██╔════╝
+//    ██║   █████╗  ███████╗   ██║   ███████╗
+//    ██║   ██╔══╝  ╚════██║   ██║   ╚════██║
+//    ██║   ███████╗███████║   ██║   ███████║
+//    ╚═╝   ╚══════╝╚══════╝   ╚═╝   ╚══════╝
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Fails to decompress odd numbers.
+    struct PickyCompression;
+
+    impl Compression for PickyCompression {
+        type Data = u32;
+        type CompressedData = u32;
+
+        fn compress(&self, data: &u32) -> Compressed<Self> {
+            Compressed::new(*data)
+        }
+
+        fn decompress(compressed: &u32) -> u32 {
+            Self::try_decompress(compressed).unwrap()
+        }
+
+        fn try_decompress(compressed: &u32) -> Result<u32, CompressionError> {
+            if compressed.is_multiple_of(2) {
+                Ok(*compressed)
+            } else {
+                Err(CompressionError::new("odd"))
+            }
+        }
+    }
+
+    #[test]
+    fn corrupt_values_are_errors() {
+        let mut map = CompressibleMap::<_, _, _>::new(PickyCompression);
+        for i in 1..5 {
+            map.insert(i, i);
+        }
+        for _ in 0..3 {
+            map.compress_lru();
+        }
+
+        assert_eq!(map.try_get(4).unwrap(), Some(&4));
+        assert!(map.try_get(1).is_err());
+        assert_eq!(map.len_compressed(), 3);
+        *map.try_get_mut(2).unwrap().unwrap() += 1;
+        assert_eq!(map.try_get(5).unwrap(), None);
+
+        assert!(map.try_remove(&3).is_err());
+        assert_eq!(map.try_remove(&2).unwrap(), Some(3));
+        assert_eq!(map.len(), 2);
+    }
+}
@@ -0,0 +1,157 @@
+use super::{CompressibleMap, MaybeCompressed};
+use crate::{Compressed, CompressedStorage, Compression, DefaultHashBuilder, ShardedHashMap};
+
+use alloc::borrow::Cow;
+use core::hash::{BuildHasher, Hash};
+use hashbrown::HashMap;
+
+/// A read-only `CompressibleMap`, created by `CompressibleMap::freeze`. Reads don't touch any
+/// cache, so there's no `LocalCache` to flush, and the map can be shared by reference or in an
+/// `Arc` between any number of threads, e.g. for static data like baked level geometry.
+///
+/// Values that were cached when the map was frozen are borrowed, while compressed values are
+/// decompressed on every read and not kept.
+pub struct FrozenCompressibleMap<
+    K,
+    V,
+    A,
+    H = DefaultHashBuilder,
+    S = ShardedHashMap<K, Compressed<A>, H>,
+> where
+    A: Compression<Data = V>,
+{
+    cached: HashMap<K, V, H>,
+    compressed: S,
+    marker: core::marker::PhantomData<fn() -> A>,
+}
+
+impl<K, V, A, H, S> CompressibleMap<K, V, A, H, S>
+where
+    K: Clone + Eq + Hash,
+    H: BuildHasher + Default,
+    A: Compression<Data = V>,
+    S: CompressedStorage<K, Compressed<A>>,
+{
+    /// Makes the map read-only. Cached values stay decompressed. Values in flight on the background
+    /// compressor are waited for, so nothing is lost.
+    pub fn freeze(mut self) -> FrozenCompressibleMap<K, V, A, H, S> {
+        self.stop_compressor();
+        let CompressibleMap {
+            cache, compressed, ..
+        } = self;
+
+        FrozenCompressibleMap {
+            cached: cache.into_iter().collect(),
+            compressed: compressed.into_map(),
+            marker: core::marker::PhantomData,
+        }
+    }
+}
+
+impl<K, V, A, H, S> FrozenCompressibleMap<K, V, A, H, S>
+where
+    K: Eq + Hash,
+    H: BuildHasher,
+    A: Compression<Data = V>,
+    S: CompressedStorage<K, Compressed<A>>,
+{
+    /// The value for `key`, decompressed into a new value if it's compressed.
+    pub fn get(&self, key: &K) -> Option<Cow<'_, V>>
+    where
+        V: Clone,
+    {
+        if let Some(value) = self.cached.get(key) {
+            return Some(Cow::Borrowed(value));
+        }
+
+        self.compressed
+            .get(key)
+            .map(|value| Cow::Owned(value.decompress()))
+    }
+
+    /// The value for `key` as it's stored, without decompressing it.
+    pub fn get_raw(&self, key: &K) -> Option<MaybeCompressed<&V, &Compressed<A>>> {
+        if let Some(value) = self.cached.get(key) {
+            return Some(MaybeCompressed::Decompressed(value));
+        }
+
+        self.compressed.get(key).map(MaybeCompressed::Compressed)
+    }
+
+    pub fn contains_key(&self, key: &K) -> bool {
+        self.cached.contains_key(key) || self.compressed.get(key).is_some()
+    }
+
+    pub fn len(&self) -> usize {
+        self.cached.len() + self.compressed.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    pub fn len_cached(&self) -> usize {
+        self.cached.len()
+    }
+
+    pub fn len_compressed(&self) -> usize {
+        self.compressed.len()
+    }
+
+    /// Iterates over all entries in arbitrary order, without decompressing anything.
+    pub fn iter(&self) -> impl Iterator<Item = (&K, MaybeCompressed<&V, &Compressed<A>>)> {
+        self.cached
+            .iter()
+            .map(|(k, v)| (k, MaybeCompressed::Decompressed(v)))
+            .chain(
+                self.compressed
+                    .iter()
+                    .map(|(k, v)| (k, MaybeCompressed::Compressed(v))),
+            )
+    }
+}
+
+// ████████╗███████╗███████╗████████╗███████╗
+// ╚══██╔══╝██╔════╝██╔════╝╚══██╔══╝██╔════╝
+//    ██║   █████╗  ███████╗   ██║   ███████╗
+//    ██║   ██╔══╝  ╚════██║   ██║   ╚════██║
+//    ██║   ███████╗███████║   ██║   ███████║
+//    ╚═╝   ╚══════╝╚══════╝   ╚═╝   ╚══════╝
+
+#[cfg(test)]
+mod tests {
+    use crate::test_util::{FakeFooCompression, Foo};
+    use crate::CompressibleMap;
+
+    use alloc::sync::Arc;
+
+    #[test]
+    fn frozen_map_is_shared_between_threads() {
+        let mut map = CompressibleMap::<_, _, _>::new(FakeFooCompression);
+        for i in 0..4 {
+            map.insert(i, Foo(i));
+        }
+        map.compress_lru_n(2);
+
+        let frozen = Arc::new(map.freeze());
+        assert_eq!(frozen.len(), 4);
+        assert_eq!(frozen.len_compressed(), 2);
+
+        let handles: Vec<_> = (0..4)
+            .map(|i| {
+                let frozen = frozen.clone();
+                std::thread::spawn(move || {
+                    let expected = if i < 2 { Foo(i + 2) } else { Foo(i) };
+                    assert_eq!(frozen.get(&i).as_deref(), Some(&expected));
+                    assert!(frozen.get(&10).is_none());
+                })
+            })
+            .collect();
+        for handle in handles {
+            handle.join().unwrap();
+        }
+
+        // Reads never decompress into the map.
+        assert_eq!(frozen.len_compressed(), 2);
+    }
+}
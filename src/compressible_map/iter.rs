@@ -0,0 +1,168 @@
+use super::{CompressibleMap, MaybeCompressed};
+use crate::{Compressed, CompressedStorage, Compression};
+
+use alloc::{boxed::Box, vec::Vec};
+use core::hash::{BuildHasher, Hash};
+
+type IterItem<'a, K, V, A> = (&'a K, MaybeCompressed<&'a V, &'a Compressed<A>>);
+
+/// Iterates over all entries of a map without decompressing anything, first the cached ones, then
+/// the compressed ones. Created by `CompressibleMap::iter`.
+pub struct Iter<'a, K, V, A>
+where
+    A: Compression,
+{
+    // Boxed, since the iterators of the compressed storage can't be named.
+    inner: Box<dyn Iterator<Item = IterItem<'a, K, V, A>> + 'a>,
+}
+
+impl<'a, K, V, A> Iterator for Iter<'a, K, V, A>
+where
+    A: Compression,
+{
+    type Item = IterItem<'a, K, V, A>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.inner.next()
+    }
+}
+
+/// Moves all entries out of a map without decompressing anything. Created by
+/// `CompressibleMap::into_iter`.
+pub struct IntoIter<K, V, A>
+where
+    A: Compression,
+{
+    inner: alloc::vec::IntoIter<(K, MaybeCompressed<V, Compressed<A>>)>,
+}
+
+impl<K, V, A> Iterator for IntoIter<K, V, A>
+where
+    A: Compression,
+{
+    type Item = (K, MaybeCompressed<V, Compressed<A>>);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.inner.next()
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.inner.size_hint()
+    }
+}
+
+impl<K, V, A> ExactSizeIterator for IntoIter<K, V, A> where A: Compression {}
+
+impl<K, V, A, H, S> CompressibleMap<K, V, A, H, S>
+where
+    K: Clone + Eq + Hash,
+    H: BuildHasher + Default,
+    A: Compression<Data = V>,
+    S: CompressedStorage<K, Compressed<A>>,
+{
+    /// Iterate over all (key, value) pairs, but compressed values will not be decompressed inline.
+    /// Values in flight on the background compressor are yielded uncompressed, after the cached
+    /// ones. Does not affect the cache.
+    pub fn iter(&self) -> Iter<'_, K, V, A> {
+        let cached = self
+            .cache
+            .iter()
+            .chain(self.iter_in_flight())
+            .map(|(k, v)| (k, MaybeCompressed::Decompressed(v)));
+        let compressed = self
+            .compressed
+            .iter()
+            .map(|(k, v)| (k, MaybeCompressed::Compressed(v)));
+
+        Iter {
+            inner: Box::new(cached.chain(compressed)),
+        }
+    }
+}
+
+impl<K, V, A, H, S> IntoIterator for CompressibleMap<K, V, A, H, S>
+where
+    K: Clone + Eq + Hash,
+    H: BuildHasher + Default,
+    A: Compression<Data = V>,
+    S: CompressedStorage<K, Compressed<A>>,
+{
+    type Item = (K, MaybeCompressed<V, Compressed<A>>);
+    type IntoIter = IntoIter<K, V, A>;
+
+    /// Values in flight on the background compressor are waited for, so nothing is lost.
+    fn into_iter(mut self) -> Self::IntoIter {
+        self.stop_compressor();
+        let mut entries = Vec::with_capacity(self.len());
+        let CompressibleMap {
+            cache, compressed, ..
+        } = self;
+        entries.extend(
+            cache
+                .into_iter()
+                .map(|(k, v)| (k, MaybeCompressed::Decompressed(v))),
+        );
+        entries.extend(
+            compressed
+                .into_iter()
+                .map(|(k, v)| (k, MaybeCompressed::Compressed(v))),
+        );
+
+        IntoIter {
+            inner: entries.into_iter(),
+        }
+    }
+}
+
+impl<'a, K, V, A, H, S> IntoIterator for &'a CompressibleMap<K, V, A, H, S>
+where
+    K: Clone + Eq + Hash,
+    H: BuildHasher + Default,
+    A: Compression<Data = V>,
+    S: CompressedStorage<K, Compressed<A>>,
+{
+    type Item = IterItem<'a, K, V, A>;
+    type IntoIter = Iter<'a, K, V, A>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.iter()
+    }
+}
+
+// ████████╗███████╗███████╗████████╗███████╗
+// ╚══██╔══╝██╔════╝██╔════╝╚══██╔══╝██╔════╝
+//    ██║   █████╗  ███████╗   ██║   ███████╗
+//    ██║   ██╔══╝  ╚════██║   ██║   ╚════██║
+//    ██║   ███████╗███████║   ██║   ███████║
+//    ╚═╝   ╚══════╝╚══════╝   ╚═╝   ╚══════╝
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::{FakeFooCompression, Foo};
+
+    use std::collections::HashMap;
+
+    #[test]
+    fn for_loops_and_collect() {
+        let mut map = CompressibleMap::<_, _, _>::new(FakeFooCompression);
+        map.insert(1, Foo(0));
+        map.insert(2, Foo(0));
+        map.compress_lru();
+
+        let mut num_compressed = 0;
+        for (_, value) in &map {
+            if let MaybeCompressed::Compressed(_) = value {
+                num_compressed += 1;
+            }
+        }
+        assert_eq!(num_compressed, 1);
+
+        let decompressed: HashMap<u32, Foo> = map
+            .into_iter()
+            .map(|(k, v)| (k, v.as_decompressed()))
+            .collect();
+        assert_eq!(decompressed[&1], Foo(2));
+        assert_eq!(decompressed[&2], Foo(0));
+    }
+}
@@ -0,0 +1,145 @@
+use super::CompressibleMap;
+use crate::{lru_cache::EntryState, Compressed, CompressedStorage, Compression};
+
+#[cfg(feature = "std")]
+use alloc::vec::Vec;
+use core::hash::{BuildHasher, Hash};
+#[cfg(feature = "std")]
+use std::time::{Duration, Instant};
+
+/// Work that can be queued on a `CompressibleMap` and executed later by `pump` or `run_next_job`.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum Job<K> {
+    /// Compress the value for this key if it's cached.
+    Compress(K),
+    /// Decompress the value for this key into the cache if it's compressed.
+    Prefetch(K),
+}
+
+/// A job that was taken off the queue by `pump` or `run_next_job`.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct JobOutcome<K> {
+    pub job: Job<K>,
+    /// `false` if there was nothing to do, e.g. because the value was already in the requested
+    /// state or had been removed.
+    pub performed: bool,
+}
+
+impl<K, V, A, H, S> CompressibleMap<K, V, A, H, S>
+where
+    K: Clone + Eq + Hash,
+    H: BuildHasher + Default,
+    A: Compression<Data = V>,
+    S: CompressedStorage<K, Compressed<A>>,
+{
+    pub fn submit_compress(&mut self, key: K) {
+        self.jobs.push_back(Job::Compress(key));
+    }
+
+    pub fn submit_prefetch(&mut self, key: K) {
+        self.jobs.push_back(Job::Prefetch(key));
+    }
+
+    pub fn num_pending_jobs(&self) -> usize {
+        self.jobs.len()
+    }
+
+    pub fn cancel_pending_jobs(&mut self) {
+        self.jobs.clear();
+    }
+
+    /// Executes queued jobs in submission order until the queue is empty or `budget` has elapsed.
+    /// This gives precise control over when time is spent compressing and decompressing, e.g. a
+    /// game can spend whatever is left of each frame. A job is never interrupted, so the budget
+    /// can be exceeded by the duration of one job.
+    #[cfg(feature = "std")]
+    pub fn pump(&mut self, budget: Duration) -> Vec<JobOutcome<K>> {
+        let start = Instant::now();
+        let mut outcomes = Vec::new();
+        while start.elapsed() < budget {
+            match self.run_next_job() {
+                Some(outcome) => outcomes.push(outcome),
+                None => break,
+            }
+        }
+
+        outcomes
+    }
+
+    /// Executes the oldest queued job, if there is one. Without `std` there's no clock for `pump`,
+    /// so call this as many times as the budget allows instead.
+    pub fn run_next_job(&mut self) -> Option<JobOutcome<K>> {
+        let job = self.jobs.pop_front()?;
+        let performed = match &job {
+            Job::Compress(key) => self.compress_key(key),
+            Job::Prefetch(key) => {
+                if let Some(EntryState::Evicted) = self.cache.get_const(key) {
+                    self.get(key.clone());
+
+                    true
+                } else {
+                    false
+                }
+            }
+        };
+
+        Some(JobOutcome { job, performed })
+    }
+}
+
+// ████████╗███████╗███████╗████████╗███████╗
+// ╚══██╔══╝██╔════╝██╔════╝╚══██╔══╝██╔════╝
+//    ██║   █████╗  ███████╗   ██║   ███████╗
+//    ██║   ██╔══╝  ╚════██║   ██║   ╚════██║
+//    ██║   ███████╗███████║   ██║   ███████║
+//    ╚═╝   ╚══════╝╚══════╝   ╚═╝   ╚══════╝
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::{FakeFooCompression, Foo};
+
+    #[test]
+    fn pump_executes_jobs_in_order() {
+        let mut map = CompressibleMap::<_, _, _>::new(FakeFooCompression);
+        map.insert(1, Foo(0));
+        map.insert(2, Foo(0));
+
+        map.submit_compress(1);
+        map.submit_compress(1);
+        map.submit_prefetch(1);
+        map.submit_prefetch(3);
+
+        assert!(map.pump(Duration::from_secs(0)).is_empty());
+        assert_eq!(map.num_pending_jobs(), 4);
+
+        let outcomes = map.pump(Duration::from_secs(60));
+        let performed: Vec<_> = outcomes.into_iter().map(|o| o.performed).collect();
+        assert_eq!(performed, vec![true, false, true, false]);
+        assert_eq!(map.num_pending_jobs(), 0);
+
+        assert_eq!(map.len_cached(), 2);
+        assert_eq!(map.get(1), Some(&Foo(2)));
+    }
+    #[test]
+    fn run_next_job_executes_one_job() {
+        let mut map = CompressibleMap::<_, _, _>::new(FakeFooCompression);
+        map.insert(1, Foo(0));
+        map.submit_compress(1);
+        map.submit_prefetch(1);
+
+        assert_eq!(
+            map.run_next_job(),
+            Some(JobOutcome {
+                job: Job::Compress(1),
+                performed: true,
+            })
+        );
+        assert_eq!(map.num_pending_jobs(), 1);
+        assert!(map.is_compressed(&1));
+
+        assert!(map.run_next_job().unwrap().performed);
+        assert_eq!(map.run_next_job(), None);
+        assert!(!map.is_compressed(&1));
+    }
+}
@@ -0,0 +1,326 @@
+use super::CompressibleMap;
+use crate::{Compressed, Compression};
+
+use left_right::{Absorb, ReadGuard, ReadHandle, WriteHandle};
+use std::borrow::Cow;
+use std::collections::hash_map::RandomState;
+use std::hash::{BuildHasher, Hash};
+
+/// One copy of the map. Only an empty copy can be cloned, which is all `left_right` needs to
+/// create the second copy.
+struct MapCopy<K, V, A, H>(CompressibleMap<K, V, A, H>)
+where
+    A: Compression<Data = V>;
+
+impl<K, V, A, H> Clone for MapCopy<K, V, A, H>
+where
+    K: Clone + Eq + Hash,
+    H: BuildHasher + Default,
+    A: Compression<Data = V> + Clone,
+{
+    fn clone(&self) -> Self {
+        debug_assert!(self.0.is_empty());
+
+        MapCopy(CompressibleMap::new(self.0.compression_params.clone()))
+    }
+}
+
+/// The operations are applied to one copy while readers are using the other, and then applied
+/// again to the second copy on the following publish. Expensive results from the first application
+/// are stored in the op, so the second copy doesn't need to compress or decompress anything.
+enum WriteOp<K, V, A>
+where
+    A: Compression<Data = V>,
+{
+    Insert(K, V),
+    Remove(K),
+    Touch { key: K, decompressed: Option<V> },
+    CompressLru(Option<(K, Compressed<A>)>),
+    Clear,
+}
+
+impl<K, V, A, H> MapCopy<K, V, A, H>
+where
+    K: Clone + Eq + Hash,
+    V: Clone,
+    H: BuildHasher + Default,
+    A: Compression<Data = V> + Clone,
+    A::CompressedData: Clone,
+{
+    fn apply(&mut self, op: &mut WriteOp<K, V, A>) {
+        let map = &mut self.0;
+        match op {
+            WriteOp::Insert(key, value) => {
+                map.insert(key.clone(), value.clone());
+            }
+            WriteOp::Remove(key) => {
+                map.remove(key);
+            }
+            WriteOp::Touch { key, decompressed } => match decompressed {
+                Some(value) => {
+                    map.compressed.remove(key);
+                    map.cache
+                        .get_or_repopulate_with(key.clone(), || value.clone());
+                }
+                None => {
+                    if let Some(compressed) = map.compressed.remove(key) {
+                        let value = compressed.decompress();
+                        *decompressed = Some(value.clone());
+                        map.cache.get_or_repopulate_with(key.clone(), || value);
+                    } else {
+                        map.cache.get(key);
+                    }
+                }
+            },
+            WriteOp::CompressLru(compressed) => match compressed {
+                Some((key, value)) => {
+                    map.cache.evict(key.clone());
+                    map.compressed.insert(key.clone(), value.clone());
+                }
+                None => {
+                    if let Some((key, value)) = map.cache.evict_lru() {
+                        let value = map.compression_params.compress(&value);
+                        *compressed = Some((key.clone(), value.clone()));
+                        map.compressed.insert(key, value);
+                    }
+                }
+            },
+            WriteOp::Clear => map.clear(),
+        }
+    }
+}
+
+impl<K, V, A, H> Absorb<WriteOp<K, V, A>> for MapCopy<K, V, A, H>
+where
+    K: Clone + Eq + Hash,
+    V: Clone,
+    H: BuildHasher + Default,
+    A: Compression<Data = V> + Clone,
+    A::CompressedData: Clone,
+{
+    fn absorb_first(&mut self, op: &mut WriteOp<K, V, A>, _other: &Self) {
+        self.apply(op);
+    }
+
+    fn absorb_second(&mut self, mut op: WriteOp<K, V, A>, _other: &Self) {
+        self.apply(&mut op);
+    }
+
+    fn sync_with(&mut self, first: &Self) {
+        let map = &mut self.0;
+        for (key, value) in first.0.cache.iter_lru_first() {
+            map.cache.insert(key.clone(), value.clone());
+        }
+        for (key, value) in first.0.compressed.iter() {
+            map.cache.evict(key.clone());
+            map.compressed.insert(key.clone(), value.clone());
+        }
+    }
+}
+
+type MapWriteHandle<K, V, A, H> = WriteHandle<MapCopy<K, V, A, H>, WriteOp<K, V, A>>;
+
+/// The single writer of a read-optimized map, following the left-right pattern: there are two
+/// copies of the map, and readers always see one of them while the writer modifies the other.
+/// Modifications are queued until `publish` swaps the copies, so readers never wait for the writer
+/// and don't need a `LocalCache` to be flushed back.
+///
+/// The cost is twice the memory and applying every modification twice. Compression and
+/// decompression only happen once, since the result is shared with the second copy. Reads don't
+/// affect the LRU order, so the writer must `touch` the values that should stay cached.
+pub struct LeftRightWriter<K, V, A, H = RandomState>
+where
+    K: Clone + Eq + Hash,
+    V: Clone,
+    H: BuildHasher + Default,
+    A: Compression<Data = V> + Clone,
+    A::CompressedData: Clone,
+{
+    handle: MapWriteHandle<K, V, A, H>,
+}
+
+/// A handle for reading the latest published copy of a `LeftRightWriter`'s map. Each thread should
+/// have its own clone.
+pub struct LeftRightReader<K, V, A, H = RandomState>
+where
+    A: Compression<Data = V>,
+{
+    handle: ReadHandle<MapCopy<K, V, A, H>>,
+}
+
+impl<K, V, A, H> Clone for LeftRightReader<K, V, A, H>
+where
+    A: Compression<Data = V>,
+{
+    fn clone(&self) -> Self {
+        Self {
+            handle: self.handle.clone(),
+        }
+    }
+}
+
+impl<K, V, A, H> LeftRightWriter<K, V, A, H>
+where
+    K: Clone + Eq + Hash,
+    V: Clone,
+    H: BuildHasher + Default,
+    A: Compression<Data = V> + Clone,
+    A::CompressedData: Clone,
+{
+    pub fn new(compression_params: A) -> Self {
+        let (handle, _) =
+            left_right::new_from_empty(MapCopy(CompressibleMap::new(compression_params)));
+
+        Self { handle }
+    }
+
+    pub fn reader(&self) -> LeftRightReader<K, V, A, H> {
+        LeftRightReader {
+            handle: self.handle.clone(),
+        }
+    }
+
+    pub fn insert(&mut self, key: K, value: V) {
+        self.handle.append(WriteOp::Insert(key, value));
+    }
+
+    pub fn remove(&mut self, key: K) {
+        self.handle.append(WriteOp::Remove(key));
+    }
+
+    /// Marks the value as the most recently used, decompressing it if necessary.
+    pub fn touch(&mut self, key: K) {
+        self.handle.append(WriteOp::Touch {
+            key,
+            decompressed: None,
+        });
+    }
+
+    /// Compresses the least recently used value. The `RecencyGuard` doesn't apply here.
+    pub fn compress_lru(&mut self) {
+        self.handle.append(WriteOp::CompressLru(None));
+    }
+
+    pub fn clear(&mut self) {
+        self.handle.append(WriteOp::Clear);
+    }
+
+    /// Makes all of the modifications since the last publish visible to readers. Waits for readers
+    /// that are still using the old copy to finish their reads.
+    pub fn publish(&mut self) {
+        self.handle.publish();
+    }
+
+    pub fn has_pending_modifications(&self) -> bool {
+        self.handle.has_pending_operations()
+    }
+
+    /// The copy of the map that readers currently see.
+    pub fn published(&self) -> ReadGuard<'_, CompressibleMap<K, V, A, H>> {
+        ReadGuard::map(self.handle.enter().unwrap(), |copy| &copy.0)
+    }
+}
+
+impl<K, V, A, H> LeftRightReader<K, V, A, H>
+where
+    K: Clone + Eq + Hash,
+    H: BuildHasher + Default,
+    A: Compression<Data = V>,
+{
+    /// Borrows the latest published copy of the map, or returns `None` if the writer was dropped.
+    /// The writer can't publish again until the guard is dropped, so don't hold on to it.
+    pub fn enter(&self) -> Option<ReadGuard<'_, CompressibleMap<K, V, A, H>>> {
+        self.handle
+            .enter()
+            .map(|guard| ReadGuard::map(guard, |copy| &copy.0))
+    }
+
+    /// Calls `f` on the value for `key`. Compressed values are decompressed for just this call.
+    pub fn get_with<R>(&self, key: &K, f: impl FnOnce(&V) -> R) -> Option<R>
+    where
+        V: Clone,
+    {
+        let map = self.enter()?;
+        let value: Cow<'_, V> = map.get_cow(key.clone(), None)?;
+
+        Some(f(&value))
+    }
+
+    pub fn get_cloned(&self, key: &K) -> Option<V>
+    where
+        V: Clone,
+    {
+        self.get_with(key, V::clone)
+    }
+
+    pub fn contains_key(&self, key: &K) -> bool {
+        self.enter()
+            .is_some_and(|map| map.cache.get_const(key).is_some())
+    }
+
+    pub fn len(&self) -> usize {
+        self.enter().map_or(0, |map| map.len())
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
+
+// ████████╗███████╗███████╗████████╗███████╗
+// ╚══██╔══╝██╔════╝██╔════╝╚══██╔══╝██╔════╝
+//    ██║   █████╗  ███████╗   ██║   ███████╗
+//    ██║   ██╔══╝  ╚════██║   ██║   ╚════██║
+//    ██║   ███████╗███████║   ██║   ███████║
+//    ╚═╝   ╚══════╝╚══════╝   ╚═╝   ╚══════╝
+
+#[cfg(test)]
+mod tests {
+    use crate::test_util::{FakeFooCompression, Foo};
+    use crate::LeftRightWriter;
+
+    #[test]
+    fn readers_see_published_modifications() {
+        use crossbeam::thread;
+
+        let mut writer = LeftRightWriter::<_, _, _>::new(FakeFooCompression);
+        for i in 0..10 {
+            writer.insert(i, Foo(i));
+        }
+        writer.publish();
+        for _ in 0..5 {
+            writer.compress_lru();
+        }
+        writer.touch(0);
+        writer.remove(9);
+
+        let reader = writer.reader();
+        assert_eq!(reader.len(), 10);
+        assert_eq!(reader.enter().unwrap().len_compressed(), 0);
+
+        writer.publish();
+        assert!(!writer.has_pending_modifications());
+
+        thread::scope(|s| {
+            for i in 0..9 {
+                let reader = reader.clone();
+                s.spawn(move |_| {
+                    let expected = if i < 5 { Foo(i + 2) } else { Foo(i) };
+                    assert_eq!(reader.get_cloned(&i), Some(expected));
+                    assert_eq!(reader.get_cloned(&9), None);
+                });
+            }
+        })
+        .unwrap();
+
+        // Both copies must agree after the ops are applied to the second one.
+        writer.insert(10, Foo(10));
+        writer.publish();
+        for map in [writer.published(), reader.enter().unwrap()].iter() {
+            assert_eq!(map.len_cached(), 6);
+            assert_eq!(map.len_compressed(), 4);
+            let lru_first: Vec<_> = map.cache.iter_lru_first().map(|(k, _)| *k).collect();
+            assert_eq!(lru_first, vec![5, 6, 7, 8, 0, 10]);
+        }
+    }
+}
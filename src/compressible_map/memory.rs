@@ -0,0 +1,463 @@
+use super::{CompressibleMap, MaybeCompressed};
+use crate::{
+    lru_cache::{EntryState, Weigher},
+    Compressed, CompressedStorage, Compression,
+};
+
+use alloc::{boxed::Box, vec::Vec};
+use core::hash::{BuildHasher, Hash};
+#[cfg(feature = "std")]
+use std::time::{Duration, Instant};
+
+/// The error returned by `CompressibleMap::try_insert` when the value doesn't fit under the byte
+/// cap. Gives the entry back to the caller.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct Full<K, V> {
+    pub key: K,
+    pub value: V,
+}
+
+impl<K, V> core::fmt::Display for Full<K, V> {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        f.write_str("the map is full")
+    }
+}
+
+impl<K: core::fmt::Debug, V: core::fmt::Debug> core::error::Error for Full<K, V> {}
+
+/// How urgently the application needs memory back, e.g. as reported by the OS or an allocator.
+/// Passed to `CompressibleMap::on_memory_pressure`.
+#[derive(Clone, Copy, Debug, Eq, Hash, Ord, PartialEq, PartialOrd)]
+pub enum MemoryPressure {
+    /// Compress the least recently used half of the cached values.
+    Moderate,
+    /// Compress every cached value, and drop the memory the map keeps around for reuse.
+    High,
+    /// Like `High`, then hand every compressed value to the spill sink.
+    Critical,
+}
+
+pub(super) type Spill<K, A> = Box<dyn FnMut(K, Compressed<A>) + Send + Sync>;
+
+type TryInsertResult<K, V, A> = Result<Option<MaybeCompressed<V, Compressed<A>>>, Full<K, V>>;
+
+impl<K, V, A, H, S> CompressibleMap<K, V, A, H, S>
+where
+    K: Clone + Eq + Hash,
+    H: BuildHasher + Default,
+    A: Compression<Data = V>,
+    S: CompressedStorage<K, Compressed<A>>,
+{
+    /// Sets the function used to estimate the number of bytes used by a cached value, including any
+    /// heap memory it owns. Without an estimator, cached values are assumed to use no memory. A
+    /// value that's modified through a mutable reference is measured again the next time the map
+    /// is modified.
+    pub fn set_size_estimator(&mut self, estimate: impl Fn(&V) -> usize + Send + Sync + 'static) {
+        self.set_weigher(move |_, value| estimate(value));
+    }
+
+    /// Like `set_size_estimator`, but the estimate can also depend on the key. With a
+    /// `LargestFirstPolicy`, the weights decide which values are compressed first.
+    pub fn set_weigher(&mut self, weigh: impl Fn(&K, &V) -> usize + Send + Sync + 'static) {
+        self.cache.set_weigher(Some(Weigher::new(weigh)));
+    }
+
+    pub fn byte_cap(&self) -> Option<usize> {
+        self.byte_cap
+    }
+
+    /// Limits the number of bytes used by cached and compressed values, as measured by the size
+    /// estimator and `Compression::compressed_size`, or removes the limit if `None`. While there's
+    /// a cap, inserting or decompressing a value compresses LRU values to make room for it, unless
+    /// they're pinned or protected by the `RecencyGuard`. Lowering the cap doesn't compress anything
+    /// right away, so the work can be spread over several frames with `compress_for`.
+    ///
+    /// Compressing can't always make enough room, e.g. when the compressed values alone exceed the
+    /// cap. `insert`, `get` and the like cache the value anyway, while `try_insert`,
+    /// `get_within_cap` and `get_mut_within_cap` give it back instead.
+    pub fn set_byte_cap(&mut self, cap: Option<usize>) {
+        self.byte_cap = cap;
+    }
+
+    /// Inserts a value unless that would exceed the byte cap. To make room, LRU values are
+    /// compressed first, as long as they aren't protected by the `RecencyGuard`. If there still
+    /// isn't enough room, the entry is returned in the error and the map stays the same size, but
+    /// the values compressed in the attempt stay compressed.
+    pub fn try_insert(&mut self, key: K, value: V) -> TryInsertResult<K, V, A> {
+        // A value in flight isn't counted anywhere yet.
+        self.await_compressed(&key);
+        if !self.make_room_for_bytes(&key, self.cache.weigh(&key, &value)) {
+            return Err(Full { key, value });
+        }
+
+        Ok(self.insert(key, value))
+    }
+
+    /// Like `get`, but a compressed value that doesn't fit under the byte cap, even after
+    /// compressing LRU values to make room, stays compressed. The error holds a decompressed copy
+    /// of the value instead.
+    pub fn get_within_cap(&mut self, key: K) -> Result<Option<&V>, Full<K, V>> {
+        self.access_within_cap(key, false).map(|v| v.map(|v| &*v))
+    }
+
+    /// Like `get_within_cap`, but the value can be modified, as with `get_mut`.
+    pub fn get_mut_within_cap(&mut self, key: K) -> Result<Option<&mut V>, Full<K, V>> {
+        self.access_within_cap(key, true)
+    }
+
+    fn access_within_cap(&mut self, key: K, mutable: bool) -> Result<Option<&mut V>, Full<K, V>> {
+        self.await_compressed(&key);
+        let decompressed_value = self.decompress_value(&key);
+        if let Some(value) = decompressed_value {
+            if !self.make_room_for(&key, self.cache.weigh(&key, &value)) {
+                return Err(Full { key, value });
+            }
+
+            return Ok(self.cache_access(key, Some(value), mutable));
+        }
+
+        Ok(self.cache_access(key, None, mutable))
+    }
+
+    pub fn max_cached(&self) -> Option<usize> {
+        self.max_cached
+    }
+
+    /// Limits the number of cached values, or removes the limit if `None`. While there's a limit,
+    /// inserting or decompressing a value compresses LRU values to make room for it, unless they're
+    /// protected by the `RecencyGuard`. Values over the new limit are compressed right away.
+    ///
+    /// Panics if `max_cached` is 0.
+    pub fn set_max_cached(&mut self, max_cached: Option<usize>) {
+        assert_ne!(max_cached, Some(0), "Must allow at least one cached value");
+        self.max_cached = max_cached;
+        self.compress_over_limits();
+    }
+
+    /// Compresses LRU values so that caching a value of `new_bytes` for `key` won't exceed
+    /// `max_cached`, the budget of its namespace or the byte cap. Returns `false` if there isn't
+    /// enough room under the byte cap.
+    pub(super) fn make_room_for(&mut self, key: &K, new_bytes: usize) -> bool {
+        self.make_room_in_namespace(key);
+        if let Some(max) = self.max_cached {
+            if !self.is_cached(key) {
+                self.compress_while(|map| map.len_cached() >= max);
+            }
+        }
+
+        self.make_room_for_bytes(key, new_bytes)
+    }
+
+    /// Compresses LRU values other than the one for `key` until replacing the entry for `key` with
+    /// `new_bytes` fits under the byte cap. Returns `false` if it doesn't.
+    pub(super) fn make_room_for_bytes(&mut self, key: &K, new_bytes: usize) -> bool {
+        let cap = match self.byte_cap {
+            Some(cap) => cap,
+            None => return true,
+        };
+        let fits = |map: &Self| map.total_bytes() - map.entry_bytes(key) + new_bytes <= cap;
+        if fits(self) {
+            return true;
+        }
+
+        let newly_pinned = self.pinned.insert(key.clone());
+        self.compress_while(|map| !fits(map));
+        if newly_pinned {
+            self.pinned.remove(key);
+        }
+
+        fits(self)
+    }
+
+    /// Compresses LRU values until the map is within `max_cached`, the namespace budgets and the
+    /// byte cap.
+    pub(super) fn compress_over_limits(&mut self) {
+        self.compress_namespaces_over_budget();
+        if let Some(max) = self.max_cached {
+            self.compress_while(|map| map.len_cached() > max);
+        }
+        if let Some(cap) = self.byte_cap {
+            self.compress_while(|map| map.total_bytes() > cap);
+        }
+    }
+
+    /// The estimated number of bytes used by cached values, as measured by the size estimator.
+    pub fn bytes_cached_estimate(&self) -> usize {
+        self.cache.total_weight()
+    }
+
+    /// The number of bytes used by compressed values, as measured by
+    /// `Compression::compressed_size`. Together with `bytes_cached_estimate`, this is the memory
+    /// footprint of the map's values.
+    pub fn bytes_compressed(&self) -> usize {
+        self.compressed.bytes()
+    }
+
+    /// Compresses LRU values until the cached values use at most `max_bytes`, as measured by the
+    /// size estimator. Stops early if the LRU value is protected by the `RecencyGuard`. Returns the
+    /// number of values compressed.
+    pub fn compress_until_under_budget(&mut self, max_bytes: usize) -> usize {
+        let mut num_compressed = 0;
+        while self.bytes_cached_estimate() > max_bytes
+            && self.len_cached() > 0
+            && !self.lru_is_guarded()
+        {
+            self.compress_lru();
+            num_compressed += 1;
+        }
+
+        num_compressed
+    }
+
+    /// Compresses LRU values until the map is back under its byte cap, or until `budget` has
+    /// passed, whichever comes first, so the cost of maintenance can be bounded per frame. Without
+    /// a byte cap, the map is always within its limits. The budget is checked before each
+    /// compression, so the last one can overrun it. Stops early if the LRU value is protected by
+    /// the `RecencyGuard`. Returns the number of values compressed.
+    #[cfg(feature = "std")]
+    pub fn compress_for(&mut self, budget: Duration) -> usize {
+        let deadline = Instant::now() + budget;
+
+        self.compress_while(|map| {
+            Instant::now() < deadline && map.byte_cap.is_some_and(|cap| map.total_bytes() > cap)
+        })
+    }
+
+    /// Frees memory according to `level`, so the map can be wired to low-memory signals. Pinned
+    /// values are never compressed or spilled. At `Moderate`, values protected by the
+    /// `RecencyGuard` aren't compressed either. Returns the number of values that were compressed
+    /// or spilled.
+    pub fn on_memory_pressure(&mut self, level: MemoryPressure) -> usize {
+        if level == MemoryPressure::Moderate {
+            return self.compress_lru_n(self.len_cached() / 2);
+        }
+
+        let mut num_freed = 0;
+        while self.skip_pinned() {
+            let (key, value) = self.cache.evict_lru().unwrap();
+            self.compress_evicted(key, value);
+            num_freed += 1;
+        }
+        self.recycled = Vec::new();
+        if let Some(clean) = &mut self.clean_compressed {
+            *clean = Default::default();
+        }
+
+        if level == MemoryPressure::Critical && self.spill.is_some() {
+            self.finish_compressing();
+            let keys: Vec<K> = self
+                .compressed
+                .keys()
+                .filter(|key| !self.pinned.contains(*key))
+                .cloned()
+                .collect();
+            for key in keys {
+                if let Some(MaybeCompressed::Compressed(value)) = self.remove(&key) {
+                    (self.spill.as_mut().unwrap())(key, value);
+                    num_freed += 1;
+                }
+            }
+        }
+
+        num_freed
+    }
+
+    /// Sets the function that receives every compressed value when there's `Critical` memory
+    /// pressure, e.g. to write it to disk. Spilled entries are removed from the map. Without a
+    /// sink, `Critical` is the same as `High`.
+    pub fn set_spill_sink(&mut self, spill: impl FnMut(K, Compressed<A>) + Send + Sync + 'static) {
+        self.spill = Some(Box::new(spill));
+    }
+
+    fn total_bytes(&self) -> usize {
+        self.cache.total_weight() + self.compressed.bytes()
+    }
+
+    /// The number of bytes used by the value for `key`, either cached or compressed.
+    fn entry_bytes(&self, key: &K) -> usize {
+        match self.cache.get_const(key) {
+            Some(EntryState::Cached(value)) => self.cache.weigh(key, value),
+            Some(EntryState::Evicted) => self.compressed.get(key).unwrap().size(),
+            None => 0,
+        }
+    }
+}
+
+// ████████╗███████╗███████╗████████╗███████╗
+// ╚══██╔══╝██╔════╝██╔════╝╚══██╔══╝██╔════╝
+//    ██║   █████╗  ███████╗   ██║   ███████╗
+//    ██║   ██╔══╝  ╚════██║   ██║   ╚════██║
+//    ██║   ███████╗███████║   ██║   ███████║
+//    ╚═╝   ╚══════╝╚══════╝   ╚═╝   ╚══════╝
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::{FakeFooCompression, Foo};
+
+    use std::sync::{Arc, Mutex};
+
+    #[test]
+    fn memory_pressure_escalates() {
+        let mut map = CompressibleMap::<_, _, _>::new(FakeFooCompression);
+        for i in 0..8 {
+            map.insert(i, Foo(i));
+        }
+        map.pin(&7);
+
+        assert_eq!(map.on_memory_pressure(MemoryPressure::Moderate), 4);
+        assert_eq!(map.len_cached(), 4);
+
+        assert_eq!(map.on_memory_pressure(MemoryPressure::High), 3);
+        assert_eq!(map.len_cached(), 1);
+        assert!(map.is_cached(&7));
+
+        // Without a sink, there's nowhere to spill to.
+        assert_eq!(map.on_memory_pressure(MemoryPressure::Critical), 0);
+        assert_eq!(map.len(), 8);
+
+        let spilled = Arc::new(Mutex::new(Vec::new()));
+        let sink = spilled.clone();
+        map.set_spill_sink(move |key, _| sink.lock().unwrap().push(key));
+        assert_eq!(map.on_memory_pressure(MemoryPressure::Critical), 7);
+        assert_eq!(spilled.lock().unwrap().len(), 7);
+        assert_eq!(map.len(), 1);
+        assert_eq!(map.get(7), Some(&Foo(7)));
+    }
+
+    #[test]
+    fn memory_pressure_without_pins_compresses_everything() {
+        let mut map = CompressibleMap::<_, _, _>::new(FakeFooCompression);
+        for i in 0..4 {
+            map.insert(i, Foo(i));
+        }
+
+        assert_eq!(map.on_memory_pressure(MemoryPressure::High), 4);
+        assert_eq!(map.len_cached(), 0);
+        assert_eq!(map.on_memory_pressure(MemoryPressure::High), 0);
+        assert_eq!(map.len(), 4);
+    }
+
+    #[test]
+    fn try_insert_compresses_then_pushes_back() {
+        let mut map = CompressibleMap::<_, _, _>::new(FakeFooCompression);
+        let compressed_size = core::mem::size_of::<Foo>();
+        map.set_size_estimator(|_| 10);
+        map.set_byte_cap(Some(10 + 5 * compressed_size));
+
+        for i in 0..6 {
+            assert!(map.try_insert(i, Foo(i)).is_ok());
+        }
+        assert_eq!(map.len_cached(), 1);
+        assert_eq!(map.len_compressed(), 5);
+
+        // Replacing an entry frees its bytes.
+        assert!(map.try_insert(0, Foo(100)).is_ok());
+        assert_eq!(map.len_compressed(), 5);
+
+        assert_eq!(
+            map.try_insert(6, Foo(6)).err(),
+            Some(Full {
+                key: 6,
+                value: Foo(6)
+            })
+        );
+        assert_eq!(map.len(), 6);
+        assert_eq!(map.len_cached(), 0);
+        assert_eq!(map.total_bytes(), 6 * compressed_size);
+    }
+
+    #[test]
+    fn byte_cap_holds_while_reading_compressed_values() {
+        let mut map = CompressibleMap::<_, _, _>::new(FakeFooCompression);
+        let compressed_size = core::mem::size_of::<Foo>();
+        map.set_size_estimator(|_| 10);
+        let cap = 10 + 3 * compressed_size;
+        map.set_byte_cap(Some(cap));
+
+        for i in 0..4 {
+            map.insert(i, Foo(i));
+            assert!(map.total_bytes() <= cap);
+        }
+        assert_eq!(map.len_cached(), 1);
+
+        // Decompressing makes room, like inserting.
+        assert_eq!(map.get(0), Some(&Foo(2)));
+        assert!(map.is_compressed(&3));
+        assert_eq!(map.get_or_insert_with(1, || Foo(100)), &mut Foo(3));
+        assert_eq!(map.get_within_cap(2), Ok(Some(&Foo(4))));
+        assert_eq!(map.total_bytes(), cap);
+
+        // Without room for even one cached value, the value stays compressed.
+        map.set_byte_cap(Some(cap - 1));
+        assert_eq!(
+            map.get_mut_within_cap(1),
+            Err(Full {
+                key: 1,
+                value: Foo(5)
+            })
+        );
+        assert!(map.is_compressed(&1));
+        assert_eq!(map.len_cached(), 0);
+        assert_eq!(map.get_within_cap(4), Ok(None));
+    }
+
+    #[test]
+    fn compress_until_cached_values_fit_budget() {
+        let mut map = CompressibleMap::<_, _, _>::new(FakeFooCompression);
+        map.set_size_estimator(|foo: &Foo| foo.0 as usize);
+        for i in 1..=4 {
+            map.insert(i, Foo(i));
+        }
+        assert_eq!(map.bytes_cached_estimate(), 10);
+
+        assert_eq!(map.compress_until_under_budget(10), 0);
+        assert_eq!(map.compress_until_under_budget(6), 3);
+        assert_eq!(map.bytes_cached_estimate(), 4);
+        assert_eq!(map.compress_until_under_budget(0), 1);
+        assert_eq!(map.len_cached(), 0);
+        assert_eq!(map.bytes_compressed(), 4 * core::mem::size_of::<Foo>());
+    }
+
+    #[test]
+    fn compress_within_time_budget() {
+        let mut map = CompressibleMap::<_, _, _>::new(FakeFooCompression);
+        let compressed_size = core::mem::size_of::<Foo>();
+        map.set_size_estimator(|_| 10);
+        for i in 0..4 {
+            map.insert(i, Foo(i));
+        }
+        let long_time = Duration::from_secs(60);
+        assert_eq!(map.compress_for(long_time), 0);
+
+        map.set_byte_cap(Some(20 + 2 * compressed_size));
+        assert_eq!(map.compress_for(Duration::ZERO), 0);
+        assert_eq!(map.compress_for(long_time), 2);
+        assert_eq!(map.compress_for(long_time), 0);
+        assert_eq!(map.len_cached(), 2);
+    }
+
+    #[test]
+    fn max_cached_compresses_on_insert_and_decompress() {
+        let mut map = CompressibleMap::<_, _, _>::with_max_cached(FakeFooCompression, 2);
+        for i in 0..4 {
+            map.insert(i, Foo(i));
+        }
+        assert_eq!(map.len_cached(), 2);
+        assert!(map.is_cached(&2) && map.is_cached(&3));
+
+        // Replacing a cached value doesn't need room.
+        map.insert(3, Foo(30));
+        assert!(map.is_cached(&2));
+
+        assert_eq!(map.get(0), Some(&Foo(2)));
+        assert_eq!(map.len_cached(), 2);
+        assert!(map.is_compressed(&2));
+        map.get_or_insert_with(4, Foo::default);
+        assert_eq!(map.len_cached(), 2);
+
+        map.set_max_cached(Some(1));
+        assert_eq!(map.len_cached(), 1);
+        assert!(map.is_cached(&4));
+    }
+}
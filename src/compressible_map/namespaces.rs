@@ -0,0 +1,323 @@
+use super::CompressibleMap;
+use crate::{lru_cache::EntryState, Compressed, CompressedStorage, Compression};
+
+use alloc::{sync::Arc, vec::Vec};
+use core::hash::{BuildHasher, Hash};
+use hashbrown::HashMap;
+
+/// Identifies a partition of the keys in a `CompressibleMap`.
+pub type Namespace = u32;
+
+/// Partitions the keys of a map into namespaces that each have their own budget of cached entries
+/// and bytes, so that one subsystem can't cause another's hot values to be compressed.
+#[derive(Clone)]
+pub(super) struct Namespaces<K> {
+    classify: Arc<dyn Fn(&K) -> Namespace + Send + Sync>,
+    max_cached: HashMap<Namespace, usize>,
+    max_bytes: HashMap<Namespace, usize>,
+}
+
+impl<K> Namespaces<K> {
+    fn has_budget(&self, namespace: Namespace) -> bool {
+        self.max_cached.contains_key(&namespace) || self.max_bytes.contains_key(&namespace)
+    }
+}
+
+/// The number of entries in a single namespace, and the bytes used by its cached values as
+/// measured by the size estimator.
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+pub struct NamespaceStats {
+    pub len_cached: usize,
+    pub len_compressed: usize,
+    pub bytes_cached: usize,
+}
+
+impl<K, V, A, H, S> CompressibleMap<K, V, A, H, S>
+where
+    K: Clone + Eq + Hash,
+    H: BuildHasher + Default,
+    A: Compression<Data = V>,
+    S: CompressedStorage<K, Compressed<A>>,
+{
+    /// Assigns every key to the namespace returned by `classify`. The classification of a key must
+    /// not change while it's in the map. Replaces any previous classifier, but keeps the budgets.
+    pub fn set_namespace_classifier(
+        &mut self,
+        classify: impl Fn(&K) -> Namespace + Send + Sync + 'static,
+    ) {
+        let (max_cached, max_bytes) = self
+            .namespaces
+            .take()
+            .map(|n| (n.max_cached, n.max_bytes))
+            .unwrap_or_default();
+        self.namespaces = Some(Namespaces {
+            classify: Arc::new(classify),
+            max_cached,
+            max_bytes,
+        });
+    }
+
+    /// Limits the number of cached values in `namespace` to `max_cached`, or removes the limit if
+    /// `None`. Like the map-wide `max_cached`, inserting or decompressing a value of the namespace
+    /// compresses its LRU values to make room, unless they're pinned or protected by the
+    /// `RecencyGuard`. Values over the new limit are compressed right away. Enforcing a namespace
+    /// budget takes time linear in the number of cached values.
+    ///
+    /// Panics if no classifier was set with `set_namespace_classifier`, or if `max_cached` is 0.
+    pub fn set_namespace_budget(&mut self, namespace: Namespace, max_cached: Option<usize>) {
+        assert_ne!(max_cached, Some(0), "Must allow at least one cached value");
+        let namespaces = self
+            .namespaces
+            .as_mut()
+            .expect("Must set a namespace classifier before setting budgets");
+        match max_cached {
+            Some(max) => namespaces.max_cached.insert(namespace, max),
+            None => namespaces.max_cached.remove(&namespace),
+        };
+        self.compress_namespace_over_budget(namespace, None);
+    }
+
+    /// Limits the bytes used by cached values in `namespace` to `max_bytes`, as measured by the
+    /// size estimator, or removes the limit if `None`. Enforced like `set_namespace_budget`, except
+    /// that a value that doesn't fit on its own is still cached.
+    ///
+    /// Panics if no classifier was set with `set_namespace_classifier`.
+    pub fn set_namespace_byte_budget(&mut self, namespace: Namespace, max_bytes: Option<usize>) {
+        let namespaces = self
+            .namespaces
+            .as_mut()
+            .expect("Must set a namespace classifier before setting budgets");
+        match max_bytes {
+            Some(max) => namespaces.max_bytes.insert(namespace, max),
+            None => namespaces.max_bytes.remove(&namespace),
+        };
+        self.compress_namespace_over_budget(namespace, None);
+    }
+
+    /// Counts the entries in `namespace`. This takes time linear in the size of the map.
+    pub fn namespace_stats(&self, namespace: Namespace) -> NamespaceStats {
+        let mut stats = NamespaceStats::default();
+        let namespaces = match &self.namespaces {
+            Some(n) => n,
+            None => return stats,
+        };
+        for key in self.cache.keys() {
+            if (namespaces.classify)(key) != namespace {
+                continue;
+            }
+            match self.cache.get_const(key) {
+                Some(EntryState::Cached(value)) => {
+                    stats.len_cached += 1;
+                    stats.bytes_cached += self.cache.weigh(key, value);
+                }
+                _ => stats.len_compressed += 1,
+            }
+        }
+
+        stats
+    }
+
+    /// Compresses the least recently used values of each namespace that is over its budget,
+    /// skipping pinned values and those protected by the `RecencyGuard`. Returns the number of
+    /// values compressed. This takes time linear in the number of cached values for each namespace
+    /// with a budget.
+    pub fn compress_namespaces_over_budget(&mut self) -> usize {
+        let budgeted: Vec<Namespace> = match &self.namespaces {
+            Some(n) => n
+                .max_cached
+                .keys()
+                .chain(n.max_bytes.keys())
+                .cloned()
+                .collect(),
+            None => return 0,
+        };
+
+        let mut num_compressed = 0;
+        for namespace in budgeted {
+            num_compressed += self.compress_namespace_over_budget(namespace, None);
+        }
+
+        num_compressed
+    }
+
+    /// Compresses LRU values of the namespace of `key` so that caching the value for `key` won't
+    /// exceed the namespace's budget.
+    pub(super) fn make_room_in_namespace(&mut self, key: &K) {
+        if let Some(namespaces) = &self.namespaces {
+            let namespace = (namespaces.classify)(key);
+            if namespaces.has_budget(namespace) {
+                self.compress_namespace_over_budget(namespace, Some(key));
+            }
+        }
+    }
+
+    /// Compresses LRU values of `namespace` until it's within its budget, or there are no values
+    /// left that can be compressed. If `incoming` is given, its value isn't compressed, and room is
+    /// made for it if it isn't cached yet. Returns the number of values compressed.
+    fn compress_namespace_over_budget(
+        &mut self,
+        namespace: Namespace,
+        incoming: Option<&K>,
+    ) -> usize {
+        let namespaces = match &self.namespaces {
+            Some(n) if n.has_budget(namespace) => n,
+            _ => return 0,
+        };
+        let max_cached = namespaces.max_cached.get(&namespace).cloned();
+        let max_bytes = namespaces.max_bytes.get(&namespace).cloned();
+
+        let mut len_cached = incoming.map_or(0, |key| !self.is_cached(key) as usize);
+        let mut bytes_cached = 0;
+        let mut candidates = Vec::new();
+        for (key, value) in self.cache.iter_lru_first() {
+            if (namespaces.classify)(key) != namespace {
+                continue;
+            }
+            let bytes = self.cache.weigh(key, value);
+            len_cached += 1;
+            bytes_cached += bytes;
+            let guarded = self
+                .cache
+                .last_access(key)
+                .is_some_and(|access| self.is_guarded(access));
+            if Some(key) != incoming && !self.is_skipped(key, value) && !guarded {
+                candidates.push((key.clone(), bytes));
+            }
+        }
+
+        let mut num_compressed = 0;
+        for (key, bytes) in candidates {
+            let over_len = max_cached.is_some_and(|max| len_cached > max);
+            let over_bytes = max_bytes.is_some_and(|max| bytes_cached > max);
+            if !over_len && !over_bytes {
+                break;
+            }
+            self.compress_key(&key);
+            len_cached -= 1;
+            bytes_cached -= bytes;
+            num_compressed += 1;
+        }
+
+        num_compressed
+    }
+}
+
+// ████████╗███████╗███████╗████████╗███████╗
+// ╚══██╔══╝██╔════╝██╔════╝╚══██╔══╝██╔════╝
+//    ██║   █████╗  ███████╗   ██║   ███████╗
+//    ██║   ██╔══╝  ╚════██║   ██║   ╚════██║
+//    ██║   ███████╗███████║   ██║   ███████║
+//    ╚═╝   ╚══════╝╚══════╝   ╚═╝   ╚══════╝
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        test_util::{FakeFooCompression, Foo},
+        RecencyGuard,
+    };
+
+    #[test]
+    fn over_budget_namespace_does_not_evict_other_namespaces() {
+        const TERRAIN: Namespace = 0;
+        const ENTITIES: Namespace = 1;
+
+        let mut map = CompressibleMap::<_, _, _>::new(FakeFooCompression);
+        map.set_namespace_classifier(|k: &u32| if *k < 100 { ENTITIES } else { TERRAIN });
+        map.set_namespace_budget(TERRAIN, Some(2));
+
+        map.insert(1, Foo(0));
+        for i in 100..105 {
+            map.insert(i, Foo(0));
+        }
+        map.insert(2, Foo(0));
+
+        // The budget is enforced on insert.
+        assert_eq!(
+            map.namespace_stats(TERRAIN),
+            NamespaceStats {
+                len_cached: 2,
+                len_compressed: 3,
+                bytes_cached: 0,
+            }
+        );
+        assert_eq!(
+            map.namespace_stats(ENTITIES),
+            NamespaceStats {
+                len_cached: 2,
+                len_compressed: 0,
+                bytes_cached: 0,
+            }
+        );
+
+        // The most recently used terrain stays cached.
+        assert_eq!(map.recency_rank(&104), Some(1));
+        assert_eq!(map.recency_rank(&103), Some(2));
+        assert_eq!(map.recency_rank(&102), None);
+
+        assert_eq!(map.compress_namespaces_over_budget(), 0);
+
+        // Decompressing makes room in the namespace too.
+        map.get(100);
+        assert_eq!(map.namespace_stats(TERRAIN).len_cached, 2);
+        assert_eq!(map.recency_rank(&103), None);
+    }
+
+    #[test]
+    fn byte_budget_is_enforced_on_insert() {
+        const TERRAIN: Namespace = 0;
+        const ENTITIES: Namespace = 1;
+
+        let mut map = CompressibleMap::<_, _, _>::new(FakeFooCompression);
+        map.set_size_estimator(|foo: &Foo| foo.0 as usize);
+        map.set_namespace_classifier(|k: &u32| if *k < 100 { ENTITIES } else { TERRAIN });
+        map.set_namespace_byte_budget(TERRAIN, Some(10));
+
+        map.insert(1, Foo(50));
+        map.insert(100, Foo(4));
+        map.insert(101, Foo(4));
+        assert_eq!(map.namespace_stats(TERRAIN).bytes_cached, 8);
+
+        map.insert(102, Foo(4));
+        assert_eq!(
+            map.namespace_stats(TERRAIN),
+            NamespaceStats {
+                len_cached: 2,
+                len_compressed: 1,
+                bytes_cached: 8,
+            }
+        );
+        assert!(map.is_compressed(&100));
+        assert!(!map.is_compressed(&1));
+
+        // A value that doesn't fit on its own is still cached.
+        map.insert(103, Foo(20));
+        assert_eq!(map.namespace_stats(TERRAIN).len_cached, 1);
+        assert!(!map.is_compressed(&103));
+    }
+
+    #[test]
+    fn budgets_skip_pinned_and_guarded_values() {
+        let mut map = CompressibleMap::<_, _, _>::new(FakeFooCompression);
+        map.set_namespace_classifier(|_: &u32| 0);
+        map.set_namespace_budget(0, Some(2));
+
+        map.insert(1, Foo(0));
+        map.pin(&1);
+        map.insert(2, Foo(0));
+        map.insert(3, Foo(0));
+        assert!(!map.is_compressed(&1));
+        assert!(map.is_compressed(&2));
+
+        map.set_recency_guard(Some(RecencyGuard::Accesses(100)));
+        map.insert(4, Foo(0));
+        assert!(!map.is_compressed(&3));
+        assert_eq!(map.namespace_stats(0).len_cached, 3);
+        assert_eq!(map.compress_namespaces_over_budget(), 0);
+
+        map.set_recency_guard(None);
+        assert_eq!(map.compress_namespaces_over_budget(), 1);
+        assert!(map.is_compressed(&3));
+        assert!(!map.is_compressed(&1));
+    }
+}
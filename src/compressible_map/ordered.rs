@@ -0,0 +1,107 @@
+use super::{CompressibleMap, MaybeCompressed};
+use crate::{Compressed, Compression, DefaultHashBuilder};
+
+use alloc::{collections::BTreeMap, vec::Vec};
+use core::hash::{BuildHasher, Hash};
+use core::iter::Peekable;
+use core::ops::RangeBounds;
+
+/// A `CompressibleMap` that keeps its compressed values in a `BTreeMap`, so entries can be visited
+/// in key order with `range`, e.g. for keys that are Morton codes of chunk coordinates.
+pub type CompressibleBTreeMap<K, V, A, H = DefaultHashBuilder> =
+    CompressibleMap<K, V, A, H, BTreeMap<K, Compressed<A>>>;
+
+impl<K, V, A, H> CompressibleMap<K, V, A, H, BTreeMap<K, Compressed<A>>>
+where
+    K: Clone + Eq + Hash + Ord,
+    H: BuildHasher + Default,
+    A: Compression<Data = V>,
+{
+    /// Iterates over the entries with keys in `range`, in key order, without decompressing
+    /// anything. Compressed entries are found in logarithmic time, but the cached entries in the
+    /// range are found and sorted up front, which takes time linear in the number of cached values.
+    /// Does not affect the cache.
+    pub fn range<R>(
+        &self,
+        range: R,
+    ) -> impl Iterator<Item = (&K, MaybeCompressed<&V, &Compressed<A>>)>
+    where
+        R: RangeBounds<K>,
+    {
+        let mut cached: Vec<(&K, &V)> = self
+            .cache
+            .iter()
+            .filter(|(key, _)| range.contains(*key))
+            .collect();
+        cached.sort_unstable_by_key(|(key, _)| *key);
+
+        MergeByKey {
+            cached: cached.into_iter().peekable(),
+            compressed: self.compressed.range(range).peekable(),
+        }
+    }
+}
+
+/// Merges the sorted cached and compressed entries. A key is never in both.
+struct MergeByKey<C: Iterator, Z: Iterator> {
+    cached: Peekable<C>,
+    compressed: Peekable<Z>,
+}
+
+impl<'a, K, V, A, C, Z> Iterator for MergeByKey<C, Z>
+where
+    K: Ord + 'a,
+    V: 'a,
+    A: Compression + 'a,
+    C: Iterator<Item = (&'a K, &'a V)>,
+    Z: Iterator<Item = (&'a K, &'a Compressed<A>)>,
+{
+    type Item = (&'a K, MaybeCompressed<&'a V, &'a Compressed<A>>);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let take_cached = match (self.cached.peek(), self.compressed.peek()) {
+            (Some((cached_key, _)), Some((compressed_key, _))) => cached_key < compressed_key,
+            (Some(_), None) => true,
+            (None, _) => false,
+        };
+
+        if take_cached {
+            self.cached
+                .next()
+                .map(|(k, v)| (k, MaybeCompressed::Decompressed(v)))
+        } else {
+            self.compressed
+                .next()
+                .map(|(k, v)| (k, MaybeCompressed::Compressed(v)))
+        }
+    }
+}
+
+// ████████╗███████╗███████╗████████╗███████╗
+// ╚══██╔══╝██╔════╝██╔════╝╚══██╔══╝██╔════╝
+//    ██║   █████╗  ███████╗   ██║   ███████╗
+//    ██║   ██╔══╝  ╚════██║   ██║   ╚════██║
+//    ██║   ███████╗███████║   ██║   ███████║
+//    ╚═╝   ╚══════╝╚══════╝   ╚═╝   ╚══════╝
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::{FakeFooCompression, Foo};
+
+    #[test]
+    fn range_merges_cached_and_compressed_in_key_order() {
+        let mut map = CompressibleBTreeMap::<_, _, _>::new(FakeFooCompression);
+        for i in [5, 1, 8, 3, 6, 2] {
+            map.insert(i, Foo(i));
+        }
+        map.compress_lru_n(3);
+
+        let entries: Vec<(u32, bool)> = map
+            .range(2..=6)
+            .map(|(k, v)| (*k, matches!(v, MaybeCompressed::Compressed(_))))
+            .collect();
+        assert_eq!(entries, vec![(2, false), (3, false), (5, true), (6, false)]);
+        assert_eq!(map.range(9..).count(), 0);
+    }
+}
@@ -0,0 +1,280 @@
+use super::CompressibleMap;
+use crate::{local_cache::LocalAccess, Compressed, CompressedStorage, Compression, LocalCache};
+
+use rayon::prelude::*;
+use std::collections::HashSet;
+use std::hash::{BuildHasher, Hash};
+use std::sync::OnceLock;
+use std::thread::ThreadId;
+
+/// One `LocalCache` for each thread of a rayon thread pool, for reading the map in parallel with
+/// `par_iter_const`. Afterwards, `into_merged` combines them so the map only needs to flush once.
+pub struct PerThreadLocalCaches<K, V, H> {
+    // Each cache belongs to the first thread that uses it.
+    caches: Vec<(OnceLock<ThreadId>, LocalCache<K, V, H>)>,
+}
+
+// SAFE: Each `LocalCache` is only ever accessed by the thread that claimed it, which `get` checks,
+// since the thread index alone is only unique within one pool. Values are only moved between
+// threads by `into_merged`, which takes ownership.
+unsafe impl<K: Send, V: Send, H: Send> Sync for PerThreadLocalCaches<K, V, H> {}
+
+impl<K, V, H> PerThreadLocalCaches<K, V, H>
+where
+    K: Eq + Hash,
+    H: Default + BuildHasher,
+{
+    /// Creates a cache for each thread of the current rayon thread pool. The caches must only be
+    /// used on that pool. Using them on another pool panics.
+    pub fn new() -> Self {
+        Self {
+            caches: (0..rayon::current_num_threads())
+                .map(|_| (OnceLock::new(), LocalCache::new()))
+                .collect(),
+        }
+    }
+
+    /// The cache of the current pool thread.
+    fn get(&self) -> &LocalCache<K, V, H> {
+        let (owner, cache) = rayon::current_thread_index()
+            .and_then(|index| self.caches.get(index))
+            .expect("PerThreadLocalCaches used outside of the thread pool it was created for");
+        let current = std::thread::current().id();
+        assert!(
+            *owner.get_or_init(|| current) == current,
+            "PerThreadLocalCaches used on more than one thread pool"
+        );
+
+        cache
+    }
+
+    /// Merges all of the caches into one, to be flushed with `CompressibleMap::flush_local_cache`.
+    pub fn into_merged(self) -> LocalCache<K, V, H> {
+        let mut merged = LocalCache::new();
+        for (_, cache) in self.caches.into_iter() {
+            merged.merge(cache);
+        }
+
+        merged
+    }
+}
+
+impl<K, V, H> Default for PerThreadLocalCaches<K, V, H>
+where
+    K: Eq + Hash,
+    H: Default + BuildHasher,
+{
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<K, V, A, H, S> CompressibleMap<K, V, A, H, S>
+where
+    K: Clone + Eq + Hash + Send + Sync,
+    V: Send,
+    H: BuildHasher + Default,
+    A: Compression<Data = V>,
+    A::CompressedData: Sync,
+    S: CompressedStorage<K, Compressed<A>>,
+{
+    /// Decompresses the values for all compressed `keys` on the rayon thread pool, then moves them
+    /// into the cache in a single pass. This is much faster than calling `get` for each key when
+    /// many values are needed at once, like when loading a new region. Keys that are cached or
+    /// missing are ignored. Returns the number of values decompressed.
+    pub fn prefetch_par(&mut self, keys: &[K]) -> usize {
+        let mut seen = HashSet::new();
+        let to_decompress: Vec<(&K, &A::CompressedData, u64)> = keys
+            .iter()
+            .filter(|key| seen.insert(*key))
+            .filter_map(|key| {
+                self.compressed.get(key).map(|compressed| {
+                    let stamp = self.modification_stamps.get(key);
+
+                    (key, &compressed.compressed_data, stamp)
+                })
+            })
+            .collect();
+
+        let decompressed: Vec<(K, LocalAccess<V>)> = to_decompress
+            .into_par_iter()
+            .map(|(key, data, stamp)| {
+                let access = LocalAccess::Missed {
+                    value: A::decompress(data),
+                    stamp,
+                };
+
+                (key.clone(), access)
+            })
+            .collect();
+
+        let num_decompressed = decompressed.len();
+        self.flush_accesses(decompressed.into_iter());
+
+        num_decompressed
+    }
+}
+
+impl<K, V, A, H, S> CompressibleMap<K, V, A, H, S>
+where
+    K: Clone + Eq + Hash + Send + Sync,
+    V: Send + Sync,
+    H: BuildHasher + Default + Send,
+    A: Compression<Data = V>,
+    S: CompressedStorage<K, Compressed<A>>,
+    Self: Sync,
+{
+    /// Like calling `get_const` for every key on the rayon thread pool, with values that have to be
+    /// decompressed stored in `cache_per_thread`. Afterwards, pass `cache_per_thread.into_merged()`
+    /// to `flush_local_cache` to update the cache.
+    pub fn par_iter_const<'a>(
+        &'a self,
+        cache_per_thread: &'a PerThreadLocalCaches<K, V, H>,
+    ) -> impl ParallelIterator<Item = (&'a K, &'a V)> {
+        let keys: Vec<&K> = self.keys().collect();
+
+        keys.into_par_iter().filter_map(move |key| {
+            self.get_const(key.clone(), cache_per_thread.get())
+                .map(|value| (key, value))
+        })
+    }
+}
+
+impl<K, V, A, H, S> CompressibleMap<K, V, A, H, S>
+where
+    K: Clone + Eq + Hash + Send,
+    V: Send,
+    H: BuildHasher + Default,
+    A: Compression<Data = V> + Sync,
+    A::CompressedData: Send,
+    S: CompressedStorage<K, Compressed<A>> + Default,
+{
+    /// Builds a map from a parallel iterator. The first `max_cached` values stay cached, and the
+    /// rest are compressed on the rayon thread pool before they're inserted, so a huge data set can
+    /// be loaded without ever holding all of it decompressed.
+    pub fn par_from_iter_compressing<I>(compression_params: A, max_cached: usize, iter: I) -> Self
+    where
+        I: IntoParallelIterator<Item = (K, V)>,
+    {
+        let mut map = Self::new(compression_params);
+        let mut entries: Vec<(K, V)> = iter.into_par_iter().collect();
+        let to_compress = entries.split_off(max_cached.min(entries.len()));
+
+        map.reserve(entries.len() + to_compress.len(), entries.len());
+        for (key, value) in entries {
+            map.insert(key, value);
+        }
+
+        let params = &map.compression_params;
+        let compressed: Vec<(K, A::CompressedData)> = to_compress
+            .into_par_iter()
+            .map(|(key, value)| (key, params.compress(&value).take()))
+            .collect();
+        for (key, data) in compressed {
+            map.insert_compressed(key, Compressed::new(data));
+        }
+
+        map
+    }
+}
+
+impl<K, V, A, H, S> FromParallelIterator<(K, V)> for CompressibleMap<K, V, A, H, S>
+where
+    K: Clone + Eq + Hash + Send,
+    V: Send,
+    H: BuildHasher + Default,
+    A: Compression<Data = V> + Default + Sync,
+    A::CompressedData: Send,
+    S: CompressedStorage<K, Compressed<A>> + Default,
+{
+    /// Keeps all values cached. See `par_from_iter_compressing`.
+    fn from_par_iter<I>(iter: I) -> Self
+    where
+        I: IntoParallelIterator<Item = (K, V)>,
+    {
+        Self::par_from_iter_compressing(A::default(), usize::MAX, iter)
+    }
+}
+
+// ████████╗███████╗███████╗████████╗███████╗
+// ╚══██╔══╝██╔════╝██╔════╝╚══██╔══╝██╔════╝
+//    ██║   █████╗  ███████╗   ██║   ███████╗
+//    ██║   ██╔══╝  ╚════██║   ██║   ╚════██║
+//    ██║   ███████╗███████║   ██║   ███████║
+//    ╚═╝   ╚══════╝╚══════╝   ╚═╝   ╚══════╝
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::{FakeFooCompression, Foo};
+
+    #[test]
+    fn par_collect_compresses_beyond_budget() {
+        let map: CompressibleMap<_, _, FakeFooCompression> =
+            (0..10).into_par_iter().map(|i| (i, Foo(i))).collect();
+        assert_eq!(map.len_cached(), 10);
+
+        let mut map = CompressibleMap::<_, _, _>::par_from_iter_compressing(
+            FakeFooCompression,
+            4,
+            (0..10).into_par_iter().map(|i| (i, Foo(i))),
+        );
+        assert_eq!(map.len_cached(), 4);
+        assert_eq!(map.len_compressed(), 6);
+        assert_eq!(map.get(0), Some(&Foo(0)));
+        assert_eq!(map.get(9), Some(&Foo(11)));
+    }
+
+    #[test]
+    fn par_iter_const_reads_every_value() {
+        let mut map = CompressibleMap::<_, _, _>::new(FakeFooCompression);
+        for i in 0..100 {
+            map.insert(i, Foo(i));
+        }
+        for _ in 0..50 {
+            map.compress_lru();
+        }
+
+        let caches = PerThreadLocalCaches::new();
+        let sum: u32 = map.par_iter_const(&caches).map(|(_, foo)| foo.0).sum();
+        assert_eq!(sum, (0..100).sum::<u32>() + 2 * 50);
+
+        map.flush_local_cache(caches.into_merged());
+        assert_eq!(map.len_cached(), 100);
+    }
+
+    #[test]
+    #[should_panic(expected = "more than one thread pool")]
+    fn caches_belong_to_one_pool() {
+        let mut map = CompressibleMap::<_, _, _>::new(FakeFooCompression);
+        map.insert(1, Foo(1));
+
+        let pool = || {
+            rayon::ThreadPoolBuilder::new()
+                .num_threads(1)
+                .build()
+                .unwrap()
+        };
+        let (first, second) = (pool(), pool());
+        let caches = first.install(PerThreadLocalCaches::new);
+        first.install(|| map.par_iter_const(&caches).count());
+        second.install(|| map.par_iter_const(&caches).count());
+    }
+
+    #[test]
+    fn prefetch_par_decompresses_only_compressed_keys() {
+        let mut map = CompressibleMap::<_, _, _>::new(FakeFooCompression);
+        for i in 0..10 {
+            map.insert(i, Foo(i));
+        }
+        for _ in 0..6 {
+            map.compress_lru();
+        }
+
+        assert_eq!(map.prefetch_par(&[0, 1, 1, 2, 8, 100]), 3);
+        assert_eq!(map.len_cached(), 7);
+        assert_eq!(map.len_compressed(), 3);
+        assert_eq!(map.get(1), Some(&Foo(3)));
+        assert_eq!(map.get(8), Some(&Foo(8)));
+    }
+}
@@ -0,0 +1,223 @@
+use super::{CompressibleMap, MaybeCompressed};
+use crate::{Compressed, CompressedStorage, Compression, CompressionError, SerFormat};
+
+use serde::{de::DeserializeOwned, Serialize};
+use std::hash::{BuildHasher, Hash};
+use std::io::{self, Read, Write};
+
+// The saved format is a little-endian `u64` count of entries, followed by that many entries. Each
+// entry is a key and its compressed data, both serialized with the format `F` and each prefixed by
+// its length in bytes as a little-endian `u64`. The compression parameters aren't saved.
+impl<K, V, A, H, S> CompressibleMap<K, V, A, H, S>
+where
+    K: Clone + Eq + Hash,
+    H: BuildHasher + Default,
+    A: Compression<Data = V>,
+    S: CompressedStorage<K, Compressed<A>>,
+{
+    /// Streams every entry to `writer` in compressed form, serializing keys and compressed data
+    /// with the format `F`. Cached values, and values in flight on the background compressor, are
+    /// compressed on the fly, without affecting the map.
+    pub fn save_compressed<F>(&self, mut writer: impl Write) -> io::Result<()>
+    where
+        F: SerFormat,
+        K: Serialize,
+        A::CompressedData: Serialize,
+    {
+        let entries: Vec<_> = self.iter().collect();
+        writer.write_all(&(entries.len() as u64).to_le_bytes())?;
+
+        let mut bytes = Vec::new();
+        for (key, value) in entries {
+            write_prefixed::<F, _>(&mut writer, &mut bytes, key)?;
+            match value {
+                MaybeCompressed::Compressed(c) => {
+                    write_prefixed::<F, _>(&mut writer, &mut bytes, &c.compressed_data)?
+                }
+                MaybeCompressed::Decompressed(v) => {
+                    let c = self.compression_params.compress(v);
+                    write_prefixed::<F, _>(&mut writer, &mut bytes, &c.compressed_data)?
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Reads a map saved by `save_compressed` with the same format `F`. Every entry starts out
+    /// compressed.
+    pub fn load_compressed<F>(mut reader: impl Read, compression_params: A) -> io::Result<Self>
+    where
+        F: SerFormat,
+        K: DeserializeOwned,
+        A::CompressedData: DeserializeOwned,
+        S: Default,
+    {
+        let mut len = [0; 8];
+        reader.read_exact(&mut len)?;
+        let len = u64::from_le_bytes(len);
+
+        let mut storage = S::default();
+        let mut bytes = Vec::new();
+        for _ in 0..len {
+            let key = read_prefixed::<F, _>(&mut reader, &mut bytes)?;
+            let value = read_prefixed::<F, _>(&mut reader, &mut bytes)?;
+            storage.insert(key, Compressed::new(value));
+        }
+
+        Ok(Self::from_all_compressed(compression_params, storage))
+    }
+
+    /// Compresses every cached value, then iterates over the compressed bytes of every entry, so
+    /// they can be streamed to a file or socket without copying them into another buffer first.
+    pub fn export_compressed(&mut self) -> impl Iterator<Item = (&K, &[u8])>
+    where
+        A::CompressedData: AsRef<[u8]>,
+    {
+        self.compress_all();
+        self.finish_compressing();
+
+        self.compressed
+            .iter()
+            .map(|(key, value)| (key, value.compressed_data.as_ref()))
+    }
+}
+
+fn write_prefixed<F: SerFormat, T: Serialize>(
+    writer: &mut impl Write,
+    bytes: &mut Vec<u8>,
+    value: &T,
+) -> io::Result<()> {
+    bytes.clear();
+    F::serialize(value, bytes).map_err(invalid_data)?;
+    writer.write_all(&(bytes.len() as u64).to_le_bytes())?;
+
+    writer.write_all(bytes)
+}
+
+fn read_prefixed<F: SerFormat, T: DeserializeOwned>(
+    reader: &mut impl Read,
+    bytes: &mut Vec<u8>,
+) -> io::Result<T> {
+    let mut len = [0; 8];
+    reader.read_exact(&mut len)?;
+    let len = u64::from_le_bytes(len);
+
+    bytes.clear();
+    reader.take(len).read_to_end(bytes)?;
+    if bytes.len() as u64 != len {
+        return Err(io::ErrorKind::UnexpectedEof.into());
+    }
+
+    F::deserialize(bytes).map_err(invalid_data)
+}
+
+fn invalid_data(e: CompressionError) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, e)
+}
+
+// ████████╗███████╗███████╗████████╗███████╗
+// ╚══██╔══╝██╔════╝██╔════╝╚══██╔══╝██╔════╝
+//    ██║   █████╗  ███████╗   ██║   ███████╗
+//    ██║   ██╔══╝  ╚════██║   ██║   ╚════██║
+//    ██║   ███████╗███████║   ██║   ███████║
+//    ╚═╝   ╚══════╝╚══════╝   ╚═╝   ╚══════╝
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::{FakeFooCompression, Foo};
+    use crate::{CompressorConfig, RawBytesCompression, Rle};
+
+    struct TestBincode;
+
+    impl SerFormat for TestBincode {
+        fn serialize<T: Serialize>(value: &T, bytes: &mut Vec<u8>) -> Result<(), CompressionError> {
+            bincode::serialize_into(bytes, value).map_err(CompressionError::new)
+        }
+
+        fn deserialize<T: DeserializeOwned>(bytes: &[u8]) -> Result<T, CompressionError> {
+            bincode::deserialize(bytes).map_err(CompressionError::new)
+        }
+    }
+
+    #[test]
+    fn save_then_load_compressed() {
+        let mut map = CompressibleMap::<_, _, _>::new(FakeFooCompression);
+        map.insert(1, Foo(1));
+        map.insert(2, Foo(2));
+        map.compress_lru();
+
+        let mut saved = Vec::new();
+        map.save_compressed::<TestBincode>(&mut saved).unwrap();
+        assert_eq!(map.len_cached(), 1);
+
+        let mut loaded = CompressibleMap::<u32, _, _>::load_compressed::<TestBincode>(
+            &saved[..],
+            FakeFooCompression,
+        )
+        .unwrap();
+        assert_eq!(loaded.len_compressed(), 2);
+        assert_eq!(loaded.get(1), Some(&Foo(3)));
+        assert_eq!(loaded.get(2), Some(&Foo(4)));
+
+        let truncated = &saved[..saved.len() - 1];
+        let err = CompressibleMap::<u32, _, _>::load_compressed::<TestBincode>(
+            truncated,
+            FakeFooCompression,
+        )
+        .err()
+        .unwrap();
+        assert_eq!(err.kind(), io::ErrorKind::UnexpectedEof);
+    }
+
+    #[test]
+    fn save_values_in_flight() {
+        let mut map = CompressibleMap::<_, _, _>::new(FakeFooCompression);
+        map.spawn_compressor(CompressorConfig::default());
+        map.insert(1, Foo(1));
+        map.insert(2, Foo(2));
+        map.compress_lru();
+        assert_eq!(map.num_in_flight(), 1);
+
+        let mut saved = Vec::new();
+        map.save_compressed::<TestBincode>(&mut saved).unwrap();
+
+        let mut loaded = CompressibleMap::<u32, _, _>::load_compressed::<TestBincode>(
+            &saved[..],
+            FakeFooCompression,
+        )
+        .unwrap();
+        assert_eq!(loaded.len(), 2);
+        assert_eq!(loaded.get(1), Some(&Foo(3)));
+
+        let snapshot = map.snapshot();
+        assert_eq!(snapshot.len(), 2);
+
+        let bytes = bincode::serialize(&map).unwrap();
+        let restored: CompressibleMap<u32, Foo, FakeFooCompression> =
+            bincode::deserialize(&bytes).unwrap();
+        assert_eq!(restored.len(), 2);
+    }
+
+    #[test]
+    fn export_compressed_bytes() {
+        let mut map = CompressibleMap::<_, Vec<u8>, _>::new(RawBytesCompression::new(Rle {
+            element_size: 1,
+        }));
+        map.insert(1, vec![7; 100]);
+        map.insert(2, vec![8; 50]);
+        map.compress_lru();
+
+        let mut out = Vec::new();
+        let mut keys = Vec::new();
+        for (key, bytes) in map.export_compressed() {
+            keys.push(*key);
+            out.write_all(bytes).unwrap();
+        }
+        keys.sort_unstable();
+        assert_eq!(keys, vec![1, 2]);
+        assert_eq!(out.len(), map.bytes_compressed());
+        assert_eq!(map.len_cached(), 0);
+    }
+}
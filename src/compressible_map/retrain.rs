@@ -0,0 +1,163 @@
+use super::CompressibleMap;
+use crate::{Compressed, CompressedStorage, TrainableCompression};
+
+use core::hash::{BuildHasher, Hash};
+
+/// Configures `CompressibleMap::maintain_compression`.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct RetrainPolicy {
+    /// Retrain once this many values have been compressed with the current parameters.
+    pub compressions_between_retrains: u64,
+    /// The maximum number of cached values used as training samples.
+    pub max_samples: usize,
+    /// The maximum number of stale compressed values to recompress per call.
+    pub max_recompressions: usize,
+}
+
+/// What happened during a call to `CompressibleMap::maintain_compression`.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct RetrainReport {
+    pub retrained: bool,
+    pub recompressed: usize,
+}
+
+impl<K, V, A, H, S> CompressibleMap<K, V, A, H, S>
+where
+    K: Clone + Eq + Hash,
+    H: BuildHasher + Default,
+    A: TrainableCompression<Data = V>,
+    S: CompressedStorage<K, Compressed<A>>,
+{
+    /// Fits the compression parameters to (up to) `max_samples` of the currently cached values.
+    /// Values compressed from now on will use the new parameters. Values that are already
+    /// compressed are marked as stale; they get recompressed lazily as they pass through the cache,
+    /// or eagerly by `recompress_stale`.
+    pub fn retrain_compression(&mut self, max_samples: usize) {
+        self.compression_params = self
+            .compression_params
+            .train(self.cache.iter().map(|(_, v)| v).take(max_samples));
+        self.compressions_since_retrain = 0;
+        self.stale_compressed = self.compressed.keys().cloned().collect();
+        if let Some(clean) = &mut self.clean_compressed {
+            clean.clear();
+        }
+    }
+
+    /// Recompresses up to `max` values that were compressed before the last call to
+    /// `retrain_compression`. Returns the number of values recompressed.
+    pub fn recompress_stale(&mut self, max: usize) -> usize {
+        let mut num_recompressed = 0;
+        while num_recompressed < max {
+            let key = match self.stale_compressed.pop_front() {
+                Some(key) => key,
+                None => break,
+            };
+            // The value might have been decompressed or removed since it went stale.
+            if let Some(compressed) = self.compressed.get(&key) {
+                let recompressed = self.compression_params.compress(&compressed.decompress());
+                self.compressed.insert(key, recompressed);
+                num_recompressed += 1;
+            }
+        }
+
+        num_recompressed
+    }
+
+    /// Meant to be called periodically, e.g. once per frame. Retrains the compression parameters
+    /// when enough values have been compressed since the last retraining, so the compression ratio
+    /// keeps up with the distribution of the data, then recompresses a bounded number of stale
+    /// values.
+    pub fn maintain_compression(&mut self, policy: &RetrainPolicy) -> RetrainReport {
+        let retrained = self.compressions_since_retrain >= policy.compressions_between_retrains
+            && self.len_cached() > 0;
+        if retrained {
+            self.retrain_compression(policy.max_samples);
+        }
+
+        RetrainReport {
+            retrained,
+            recompressed: self.recompress_stale(policy.max_recompressions),
+        }
+    }
+}
+
+// ████████╗███████╗███████╗████████╗███████╗
+// ╚══██╔══╝██╔════╝██╔════╝╚══██╔══╝██╔════╝
+//    ██║   █████╗  ███████╗   ██║   ███████╗
+//    ██║   ██╔══╝  ╚════██║   ██║   ╚════██║
+//    ██║   ███████╗███████║   ██║   ███████║
+//    ╚═╝   ╚══════╝╚══════╝   ╚═╝   ╚══════╝
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{Compressed, Compression};
+
+    /// Stores values relative to a trained offset, which must be recorded alongside the compressed
+    /// value.
+    struct OffsetCompression {
+        offset: u32,
+    }
+
+    impl Compression for OffsetCompression {
+        type Data = u32;
+        type CompressedData = (u32, u32);
+
+        fn compress(&self, data: &Self::Data) -> Compressed<Self> {
+            Compressed::new((self.offset, data.wrapping_sub(self.offset)))
+        }
+
+        fn decompress(compressed: &Self::CompressedData) -> Self::Data {
+            compressed.0.wrapping_add(compressed.1)
+        }
+    }
+
+    impl TrainableCompression for OffsetCompression {
+        fn train<'a>(&self, samples: impl Iterator<Item = &'a u32>) -> Self {
+            OffsetCompression {
+                offset: samples.min().cloned().unwrap_or(0),
+            }
+        }
+    }
+
+    #[test]
+    fn maintenance_retrains_and_recompresses_stale_values() {
+        let mut map = CompressibleMap::<_, _, _>::new(OffsetCompression { offset: 0 });
+        for i in 0..4 {
+            map.insert(i, 100 + i);
+        }
+        map.compress_lru();
+        map.compress_lru();
+
+        let policy = RetrainPolicy {
+            compressions_between_retrains: 2,
+            max_samples: 10,
+            max_recompressions: 1,
+        };
+        assert_eq!(
+            map.maintain_compression(&policy),
+            RetrainReport {
+                retrained: true,
+                recompressed: 1
+            }
+        );
+        assert_eq!(map.compression_params().offset, 102);
+        assert_eq!(
+            map.maintain_compression(&policy),
+            RetrainReport {
+                retrained: false,
+                recompressed: 1
+            }
+        );
+
+        for i in 0..2 {
+            assert_eq!(
+                map.compressed.get(&i).unwrap().compressed_data,
+                (102, (100 + i).wrapping_sub(102))
+            );
+        }
+        for i in 0..4 {
+            assert_eq!(map.get(i), Some(&(100 + i)));
+        }
+    }
+}
@@ -0,0 +1,133 @@
+use super::{CompressibleMap, MaybeCompressed};
+use crate::{Compressed, CompressedStorage, Compression};
+
+use core::hash::{BuildHasher, Hash};
+use serde::{
+    ser::{SerializeMap, SerializeStruct},
+    Deserialize, Deserializer, Serialize, Serializer,
+};
+
+/// The whole map is serialized in compressed form, along with the compression parameters. Cached
+/// values, and values in flight on the background compressor, are compressed on the fly, without
+/// affecting the map.
+impl<K, V, A, H, S> Serialize for CompressibleMap<K, V, A, H, S>
+where
+    K: Clone + Eq + Hash + Serialize,
+    H: BuildHasher + Default,
+    A: Compression<Data = V> + Serialize,
+    A::CompressedData: Serialize,
+    S: CompressedStorage<K, Compressed<A>>,
+{
+    fn serialize<Z: Serializer>(&self, serializer: Z) -> Result<Z::Ok, Z::Error> {
+        let mut state = serializer.serialize_struct("CompressibleMap", 2)?;
+        state.serialize_field("compression_params", &self.compression_params)?;
+        state.serialize_field("entries", &Entries(self))?;
+
+        state.end()
+    }
+}
+
+struct Entries<'a, K, V, A, H, S>(&'a CompressibleMap<K, V, A, H, S>)
+where
+    A: Compression<Data = V>;
+
+impl<'a, K, V, A, H, S> Serialize for Entries<'a, K, V, A, H, S>
+where
+    K: Clone + Eq + Hash + Serialize,
+    H: BuildHasher + Default,
+    A: Compression<Data = V>,
+    A::CompressedData: Serialize,
+    S: CompressedStorage<K, Compressed<A>>,
+{
+    fn serialize<Z: Serializer>(&self, serializer: Z) -> Result<Z::Ok, Z::Error> {
+        let map = self.0;
+        // Some formats need the length up front, which the chained iterator doesn't know.
+        let mut state = serializer.serialize_map(Some(map.len()))?;
+        for (key, value) in map.iter() {
+            let compressed = match value {
+                MaybeCompressed::Compressed(c) => CompressedRef::Borrowed(c),
+                MaybeCompressed::Decompressed(v) => {
+                    CompressedRef::Owned(map.compression_params.compress(v))
+                }
+            };
+            state.serialize_entry(key, &compressed)?;
+        }
+
+        state.end()
+    }
+}
+
+enum CompressedRef<'a, A>
+where
+    A: Compression,
+{
+    Borrowed(&'a Compressed<A>),
+    Owned(Compressed<A>),
+}
+
+impl<'a, A> Serialize for CompressedRef<'a, A>
+where
+    A: Compression,
+    A::CompressedData: Serialize,
+{
+    fn serialize<Z: Serializer>(&self, serializer: Z) -> Result<Z::Ok, Z::Error> {
+        match self {
+            CompressedRef::Borrowed(c) => c.serialize(serializer),
+            CompressedRef::Owned(c) => c.serialize(serializer),
+        }
+    }
+}
+
+#[derive(Deserialize)]
+struct SerializedMap<A, S> {
+    compression_params: A,
+    entries: S,
+}
+
+/// Every entry of the deserialized map starts out compressed.
+impl<'de, K, V, A, H, S> Deserialize<'de> for CompressibleMap<K, V, A, H, S>
+where
+    K: Clone + Eq + Hash,
+    H: BuildHasher + Default,
+    A: Compression<Data = V> + Deserialize<'de>,
+    S: CompressedStorage<K, Compressed<A>> + Deserialize<'de>,
+{
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let SerializedMap {
+            compression_params,
+            entries,
+        } = SerializedMap::deserialize(deserializer)?;
+
+        Ok(Self::with_storage(compression_params, entries))
+    }
+}
+
+// ████████╗███████╗███████╗████████╗███████╗
+// ╚══██╔══╝██╔════╝██╔════╝╚══██╔══╝██╔════╝
+//    ██║   █████╗  ███████╗   ██║   ███████╗
+//    ██║   ██╔══╝  ╚════██║   ██║   ╚════██║
+//    ██║   ███████╗███████║   ██║   ███████║
+//    ╚═╝   ╚══════╝╚══════╝   ╚═╝   ╚══════╝
+
+#[cfg(test)]
+mod tests {
+    use crate::test_util::{FakeFooCompression, Foo};
+    use crate::CompressibleMap;
+
+    #[test]
+    fn round_trip_through_bincode() {
+        let mut map = CompressibleMap::<_, _, _>::new(FakeFooCompression);
+        map.insert(1, Foo(1));
+        map.insert(2, Foo(2));
+        map.compress_lru();
+
+        let bytes = bincode::serialize(&map).unwrap();
+        assert_eq!(map.len_cached(), 1);
+
+        let mut restored: CompressibleMap<u32, Foo, FakeFooCompression> =
+            bincode::deserialize(&bytes).unwrap();
+        assert_eq!(restored.len_compressed(), 2);
+        assert_eq!(restored.get(1), Some(&Foo(3)));
+        assert_eq!(restored.get(2), Some(&Foo(4)));
+    }
+}
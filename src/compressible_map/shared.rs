@@ -0,0 +1,140 @@
+use super::{CompressibleMap, MaybeCompressed};
+use crate::{
+    lru_cache::EntryState, Compressed, CompressedStorage, Compression, DefaultHashBuilder,
+    ShardedHashMap,
+};
+
+use core::cell::{BorrowMutError, Ref, RefCell, RefMut};
+use core::hash::{BuildHasher, Hash};
+
+/// A `CompressibleMap` that can be modified through a shared reference, for use by many systems on
+/// one thread, e.g. as an ECS resource. This works like a `RefCell`: methods that modify the map
+/// return a `BorrowMutError` while a reference returned by the map is still alive, instead of
+/// panicking.
+pub struct SharedCompressibleMap<
+    K,
+    V,
+    A,
+    H = DefaultHashBuilder,
+    S = ShardedHashMap<K, Compressed<A>, H>,
+> where
+    A: Compression<Data = V>,
+{
+    map: RefCell<CompressibleMap<K, V, A, H, S>>,
+}
+
+type SharedResult<T> = Result<T, BorrowMutError>;
+type MapRefMut<'a, K, V, A, H, S> = RefMut<'a, CompressibleMap<K, V, A, H, S>>;
+
+impl<K, V, A, H, S> SharedCompressibleMap<K, V, A, H, S>
+where
+    K: Clone + Eq + Hash,
+    H: BuildHasher + Default,
+    A: Compression<Data = V>,
+    S: CompressedStorage<K, Compressed<A>>,
+{
+    pub fn new(map: CompressibleMap<K, V, A, H, S>) -> Self {
+        Self {
+            map: RefCell::new(map),
+        }
+    }
+
+    pub fn into_inner(self) -> CompressibleMap<K, V, A, H, S> {
+        self.map.into_inner()
+    }
+
+    /// Decompresses the value if necessary, like `CompressibleMap::get`. Any number of values can
+    /// be borrowed at once, but the map can't be modified while they are, so getting a compressed
+    /// value fails, and getting a cached value doesn't update the LRU order.
+    pub fn get(&self, key: K) -> SharedResult<Option<Ref<'_, V>>> {
+        match self.map.try_borrow_mut() {
+            Ok(mut map) => {
+                if map.get(key.clone()).is_none() {
+                    return Ok(None);
+                }
+            }
+            Err(e) => match self.map.try_borrow() {
+                Ok(map) if !matches!(map.cache.get_const(&key), Some(EntryState::Evicted)) => {}
+                _ => return Err(e),
+            },
+        }
+
+        let map = self.map.borrow();
+
+        Ok(Ref::filter_map(map, |map| match map.cache.get_const(&key) {
+            Some(EntryState::Cached(value)) => Some(value),
+            _ => None,
+        })
+        .ok())
+    }
+
+    pub fn get_mut(&self, key: K) -> SharedResult<Option<RefMut<'_, V>>> {
+        let map = self.map.try_borrow_mut()?;
+
+        Ok(RefMut::filter_map(map, |map| map.get_mut(key)).ok())
+    }
+
+    pub fn insert(
+        &self,
+        key: K,
+        value: V,
+    ) -> SharedResult<Option<MaybeCompressed<V, Compressed<A>>>> {
+        Ok(self.map.try_borrow_mut()?.insert(key, value))
+    }
+
+    pub fn remove(&self, key: &K) -> SharedResult<Option<MaybeCompressed<V, Compressed<A>>>> {
+        Ok(self.map.try_borrow_mut()?.remove(key))
+    }
+
+    pub fn compress_lru(&self) -> SharedResult<()> {
+        self.map.try_borrow_mut()?.compress_lru();
+
+        Ok(())
+    }
+
+    /// Borrows the whole map, e.g. to use methods without a shared equivalent. The map can't be
+    /// modified until the returned reference is dropped. Panics if a value is mutably borrowed.
+    pub fn borrow(&self) -> Ref<'_, CompressibleMap<K, V, A, H, S>> {
+        self.map.borrow()
+    }
+
+    pub fn try_borrow_mut(&self) -> SharedResult<MapRefMut<'_, K, V, A, H, S>> {
+        self.map.try_borrow_mut()
+    }
+}
+
+// ████████╗███████╗███████╗████████╗███████╗
+// ╚══██╔══╝██╔════╝██╔════╝╚══██╔══╝██╔════╝
+//    ██║   █████╗  ███████╗   ██║   ███████╗
+//    ██║   ██╔══╝  ╚════██║   ██║   ╚════██║
+//    ██║   ███████╗███████║   ██║   ███████║
+//    ╚═╝   ╚══════╝╚══════╝   ╚═╝   ╚══════╝
+
+#[cfg(test)]
+mod tests {
+    use crate::test_util::{FakeFooCompression, Foo};
+    use crate::{CompressibleMap, SharedCompressibleMap};
+
+    #[test]
+    fn borrows_are_checked() {
+        let map = SharedCompressibleMap::new(CompressibleMap::<_, _, _>::new(FakeFooCompression));
+        for i in 1..4 {
+            map.insert(i, Foo(i)).unwrap();
+        }
+        map.compress_lru().unwrap();
+        map.compress_lru().unwrap();
+
+        {
+            let one = map.get(1).unwrap().unwrap();
+            let three = map.get(3).unwrap().unwrap();
+            assert_eq!((&*one, &*three), (&Foo(3), &Foo(3)));
+            assert!(map.get(2).is_err());
+            assert!(map.get(4).unwrap().is_none());
+            assert!(map.insert(4, Foo(4)).is_err());
+        }
+
+        map.get_mut(2).unwrap().unwrap().0 = 5;
+        assert_eq!(map.borrow().len_cached(), 3);
+        assert_eq!(map.into_inner().get(2), Some(&Foo(5)));
+    }
+}
@@ -0,0 +1,137 @@
+use super::CompressibleMap;
+use crate::{Compressed, CompressedStorage};
+
+use alloc::vec::Vec;
+use core::hash::{BuildHasherDefault, Hasher};
+
+/// A `CompressibleMap` for dense `usize` handles, like entity or archetype indices. Compressed
+/// values live in a `Vec` indexed by handle, and the cache uses `IndexHasher`, so nothing gets
+/// hashed.
+pub type CompressibleSlab<V, A> =
+    CompressibleMap<usize, V, A, BuildHasherDefault<IndexHasher>, SlabStorage<Compressed<A>>>;
+
+/// A `Hasher` that passes `usize` keys through unchanged. Only meant for dense integer keys, where
+/// the low bits are already well distributed.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct IndexHasher(u64);
+
+impl Hasher for IndexHasher {
+    fn finish(&self) -> u64 {
+        self.0
+    }
+
+    fn write(&mut self, bytes: &[u8]) {
+        // Only reached by keys that aren't integers. Fold them in so they still work.
+        for &byte in bytes {
+            self.0 = self.0.rotate_left(8) ^ u64::from(byte);
+        }
+    }
+
+    fn write_usize(&mut self, i: usize) {
+        self.0 = i as u64;
+    }
+}
+
+/// Stores compressed values in a `Vec` indexed by key. Memory grows with the largest key, so keys
+/// should be dense.
+#[derive(Clone, Debug)]
+pub struct SlabStorage<Vc> {
+    // The key is kept alongside the value so it can be lent out by `iter`.
+    slots: Vec<Option<(usize, Vc)>>,
+    len: usize,
+}
+
+impl<Vc> Default for SlabStorage<Vc> {
+    fn default() -> Self {
+        Self {
+            slots: Vec::new(),
+            len: 0,
+        }
+    }
+}
+
+impl<Vc> CompressedStorage<usize, Vc> for SlabStorage<Vc> {
+    fn insert(&mut self, key: usize, value: Vc) -> Option<Vc> {
+        if key >= self.slots.len() {
+            self.slots.resize_with(key + 1, || None);
+        }
+        let old = self.slots[key].replace((key, value)).map(|(_, v)| v);
+        if old.is_none() {
+            self.len += 1;
+        }
+
+        old
+    }
+
+    fn get(&self, key: &usize) -> Option<&Vc> {
+        self.slots.get(*key)?.as_ref().map(|(_, v)| v)
+    }
+
+    fn remove(&mut self, key: &usize) -> Option<Vc> {
+        let old = self.slots.get_mut(*key)?.take().map(|(_, v)| v);
+        if old.is_some() {
+            self.len -= 1;
+        }
+
+        old
+    }
+
+    fn clear(&mut self) {
+        self.slots.clear();
+        self.len = 0;
+    }
+
+    fn len(&self) -> usize {
+        self.len
+    }
+
+    fn iter<'a>(&'a self) -> impl Iterator<Item = (&'a usize, &'a Vc)>
+    where
+        Vc: 'a,
+    {
+        self.slots.iter().flatten().map(|(k, v)| (k, v))
+    }
+
+    fn into_entries(self) -> impl Iterator<Item = (usize, Vc)> {
+        self.slots.into_iter().flatten()
+    }
+
+    fn reserve(&mut self, additional: usize) {
+        self.slots.reserve(additional)
+    }
+}
+
+// ████████╗███████╗███████╗████████╗███████╗
+// ╚══██╔══╝██╔════╝██╔════╝╚══██╔══╝██╔════╝
+//    ██║   █████╗  ███████╗   ██║   ███████╗
+//    ██║   ██╔══╝  ╚════██║   ██║   ╚════██║
+//    ██║   ███████╗███████║   ██║   ███████║
+//    ╚═╝   ╚══════╝╚══════╝   ╚═╝   ╚══════╝
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::{FakeFooCompression, Foo};
+
+    #[test]
+    fn slab_compresses_and_decompresses_by_index() {
+        let mut slab = CompressibleSlab::new(FakeFooCompression);
+        for i in 0..4 {
+            slab.insert(i, Foo(i as u32));
+        }
+        slab.compress_lru();
+        slab.compress_lru();
+        assert_eq!(slab.len_compressed(), 2);
+
+        assert_eq!(slab.get(0), Some(&Foo(2)));
+        assert_eq!(slab.get(3), Some(&Foo(3)));
+        assert_eq!(slab.get(7), None);
+
+        assert!(slab.remove(&1).is_some());
+        assert_eq!(slab.len(), 3);
+
+        let mut keys: Vec<usize> = slab.keys().cloned().collect();
+        keys.sort_unstable();
+        assert_eq!(keys, vec![0, 2, 3]);
+    }
+}
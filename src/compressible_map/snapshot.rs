@@ -0,0 +1,173 @@
+use super::{CompressibleMap, MaybeCompressed};
+use crate::{Compressed, CompressedStorage, Compression};
+
+use alloc::vec::Vec;
+use core::hash::{BuildHasher, Hash};
+use serde::{
+    ser::{SerializeMap, SerializeStruct},
+    Serialize, Serializer,
+};
+
+/// Every entry of a `CompressibleMap` at one point in time, all in compressed form. It doesn't
+/// borrow the map, so it can be sent to another thread, e.g. to be saved while the map keeps
+/// changing. It serializes the same way as the map, so it can be deserialized as one.
+pub struct CompressibleMapSnapshot<K, A>
+where
+    A: Compression,
+{
+    compression_params: A,
+    entries: Vec<(K, Compressed<A>)>,
+}
+
+impl<K, A> CompressibleMapSnapshot<K, A>
+where
+    A: Compression,
+{
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    pub fn compression_params(&self) -> &A {
+        &self.compression_params
+    }
+
+    /// Iterates over the entries in no particular order.
+    pub fn iter(&self) -> impl Iterator<Item = (&K, &Compressed<A>)> {
+        self.entries.iter().map(|(k, v)| (k, v))
+    }
+
+    /// Builds a map from the snapshot, with every entry compressed.
+    pub fn into_map<H, S>(self) -> CompressibleMap<K, A::Data, A, H, S>
+    where
+        K: Clone + Eq + Hash,
+        H: BuildHasher + Default,
+        S: CompressedStorage<K, Compressed<A>> + Default,
+    {
+        let mut storage = S::default();
+        storage.reserve(self.entries.len());
+        for (key, value) in self.entries {
+            storage.insert(key, value);
+        }
+
+        CompressibleMap::with_storage(self.compression_params, storage)
+    }
+}
+
+impl<K, V, A, H, S> CompressibleMap<K, V, A, H, S>
+where
+    K: Clone + Eq + Hash,
+    H: BuildHasher + Default,
+    A: Compression<Data = V> + Clone,
+    A::CompressedData: Clone,
+    S: CompressedStorage<K, Compressed<A>>,
+{
+    /// Captures every entry in compressed form. Compressed values are cloned, which only shares
+    /// them if their compressed data is reference-counted, like with `SharedBlobCompression`.
+    /// Cached values are compressed, unless they have a clean compressed form to reuse (see
+    /// `set_reuse_compressed`), and so are values in flight on the background compressor. Does not
+    /// affect the map.
+    pub fn snapshot(&self) -> CompressibleMapSnapshot<K, A> {
+        let entries = self
+            .iter()
+            .map(|(key, value)| {
+                let compressed = match value {
+                    MaybeCompressed::Compressed(c) => c.clone(),
+                    MaybeCompressed::Decompressed(v) => self
+                        .clean_compressed
+                        .as_ref()
+                        .and_then(|clean| clean.get(key))
+                        .filter(|_| !self.modification_stamps.is_dirty(key))
+                        .cloned()
+                        .unwrap_or_else(|| self.compression_params.compress(v)),
+                };
+
+                (key.clone(), compressed)
+            })
+            .collect();
+
+        CompressibleMapSnapshot {
+            compression_params: self.compression_params.clone(),
+            entries,
+        }
+    }
+}
+
+/// Has the same layout as a serialized `CompressibleMap`.
+impl<K, A> Serialize for CompressibleMapSnapshot<K, A>
+where
+    K: Serialize,
+    A: Compression + Serialize,
+    A::CompressedData: Serialize,
+{
+    fn serialize<Z: Serializer>(&self, serializer: Z) -> Result<Z::Ok, Z::Error> {
+        let mut state = serializer.serialize_struct("CompressibleMap", 2)?;
+        state.serialize_field("compression_params", &self.compression_params)?;
+        state.serialize_field("entries", &Entries(&self.entries))?;
+
+        state.end()
+    }
+}
+
+struct Entries<'a, K, A: Compression>(&'a [(K, Compressed<A>)]);
+
+impl<'a, K, A> Serialize for Entries<'a, K, A>
+where
+    K: Serialize,
+    A: Compression,
+    A::CompressedData: Serialize,
+{
+    fn serialize<Z: Serializer>(&self, serializer: Z) -> Result<Z::Ok, Z::Error> {
+        let mut state = serializer.serialize_map(Some(self.0.len()))?;
+        for (key, value) in self.0 {
+            state.serialize_entry(key, value)?;
+        }
+
+        state.end()
+    }
+}
+
+// ████████╗███████╗███████╗████████╗███████╗
+// ╚══██╔══╝██╔════╝██╔════╝╚══██╔══╝██╔════╝
+//    ██║   █████╗  ███████╗   ██║   ███████╗
+//    ██║   ██╔══╝  ╚════██║   ██║   ╚════██║
+//    ██║   ███████╗███████║   ██║   ███████║
+//    ╚═╝   ╚══════╝╚══════╝   ╚═╝   ╚══════╝
+
+#[cfg(test)]
+mod tests {
+    use crate::test_util::{FakeFooCompression, Foo};
+    use crate::{CompressibleMap, SharedBlobCompression};
+
+    use alloc::sync::Arc;
+
+    #[test]
+    fn snapshot_is_unaffected_by_later_writes() {
+        let mut map =
+            CompressibleMap::<_, _, _>::new(SharedBlobCompression::new(FakeFooCompression));
+        map.insert(1, Foo(1));
+        map.insert(2, Foo(2));
+        map.compress_lru();
+
+        let snapshot = map.snapshot();
+        let shared = snapshot.iter().find(|(k, _)| **k == 1).unwrap().1;
+        assert_eq!(Arc::strong_count(&shared.compressed_data), 2);
+
+        map.insert(2, Foo(7));
+        map.remove(&1);
+        assert_eq!(snapshot.len(), 2);
+
+        let bytes = bincode::serialize(&snapshot).unwrap();
+        let mut restored: CompressibleMap<u32, Foo, SharedBlobCompression<FakeFooCompression>> =
+            bincode::deserialize(&bytes).unwrap();
+        assert_eq!(restored.get(1), Some(&Foo(3)));
+        assert_eq!(restored.get(2), Some(&Foo(4)));
+
+        let mut rebuilt: CompressibleMap<_, _, _> = snapshot.into_map();
+        assert_eq!(rebuilt.len_compressed(), 2);
+        assert_eq!(rebuilt.get(2), Some(&Foo(4)));
+    }
+}
@@ -0,0 +1,127 @@
+use super::CompressibleMap;
+use crate::{Compressed, CompressedStorage, Compression};
+
+use core::hash::{BuildHasher, Hash};
+
+/// Counters for tuning the cache size and compression parameters, returned by
+/// `CompressibleMap::stats`. Only accesses through `&mut self` are counted, including those
+/// replayed by `flush_local_cache`.
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+pub struct Stats {
+    /// Accesses that found the value in the cache.
+    pub hits: u64,
+    /// Accesses that didn't find the value in the cache, whether it was compressed or absent.
+    pub misses: u64,
+    /// Values decompressed into the cache.
+    pub decompressions: u64,
+    /// Values compressed.
+    pub compressions: u64,
+    /// The total size of all values before they were compressed, as measured by the size
+    /// estimator, or `size_of::<V>()` without one.
+    pub bytes_before_compression: u64,
+    /// The total size of all values after they were compressed.
+    pub bytes_after_compression: u64,
+}
+
+impl Stats {
+    /// The fraction of accesses that were hits, or `None` if there were no accesses.
+    pub fn hit_rate(&self) -> Option<f64> {
+        let accesses = self.hits + self.misses;
+
+        (accesses > 0).then(|| self.hits as f64 / accesses as f64)
+    }
+
+    /// The average compression ratio, i.e. bytes before over bytes after, or `None` if nothing was
+    /// compressed.
+    pub fn compression_ratio(&self) -> Option<f64> {
+        (self.bytes_after_compression > 0)
+            .then(|| self.bytes_before_compression as f64 / self.bytes_after_compression as f64)
+    }
+
+    pub(super) fn hit(&mut self) {
+        self.hits += 1;
+    }
+
+    pub(super) fn miss(&mut self, decompressed: bool) {
+        self.misses += 1;
+        if decompressed {
+            self.decompressions += 1;
+        }
+    }
+
+    pub(super) fn compressed(&mut self, bytes_before: usize, bytes_after: usize) {
+        self.compressions += 1;
+        self.bytes_before_compression += bytes_before as u64;
+        self.bytes_after_compression += bytes_after as u64;
+    }
+}
+
+impl<K, V, A, H, S> CompressibleMap<K, V, A, H, S>
+where
+    K: Clone + Eq + Hash,
+    H: BuildHasher + Default,
+    A: Compression<Data = V>,
+    S: CompressedStorage<K, Compressed<A>>,
+{
+    pub fn stats(&self) -> Stats {
+        self.stats
+    }
+
+    pub fn reset_stats(&mut self) {
+        self.stats = Stats::default();
+    }
+
+    pub(super) fn record_compression(&mut self, key: &K, value: &V, compressed: &Compressed<A>) {
+        let bytes_before = if self.cache.has_weigher() {
+            self.cache.weigh(key, value)
+        } else {
+            core::mem::size_of::<V>()
+        };
+        self.stats.compressed(bytes_before, compressed.size());
+    }
+}
+
+// ████████╗███████╗███████╗████████╗███████╗
+// ╚══██╔══╝██╔════╝██╔════╝╚══██╔══╝██╔════╝
+//    ██║   █████╗  ███████╗   ██║   ███████╗
+//    ██║   ██╔══╝  ╚════██║   ██║   ╚════██║
+//    ██║   ███████╗███████║   ██║   ███████║
+//    ╚═╝   ╚══════╝╚══════╝   ╚═╝   ╚══════╝
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::{FakeFooCompression, Foo};
+
+    #[test]
+    fn count_hits_misses_and_bytes() {
+        let mut map = CompressibleMap::<_, _, _>::new(FakeFooCompression);
+        map.set_size_estimator(|_| 8);
+        map.insert(1, Foo(1));
+        map.insert(2, Foo(2));
+        map.compress_lru();
+
+        map.get(1);
+        map.get(2);
+        map.get(3);
+        map.get_or_insert_with(4, Foo::default);
+
+        let stats = map.stats();
+        assert_eq!(
+            stats,
+            Stats {
+                hits: 1,
+                misses: 3,
+                decompressions: 1,
+                compressions: 1,
+                bytes_before_compression: 8,
+                bytes_after_compression: core::mem::size_of::<Foo>() as u64,
+            }
+        );
+        assert_eq!(stats.hit_rate(), Some(0.25));
+        assert_eq!(stats.compression_ratio(), Some(2.0));
+
+        map.reset_stats();
+        assert_eq!(map.stats().hit_rate(), None);
+    }
+}
@@ -1,19 +1,119 @@
+#[cfg(feature = "std")]
+mod adaptive;
+mod boxed;
+#[cfg(feature = "brotli")]
+mod brotli_compression;
+#[cfg(feature = "std")]
+mod channel_array3;
 #[cfg(feature = "bincode")]
 mod compressed_bincode;
+#[cfg(feature = "ciborium")]
+mod compressed_cbor;
+#[cfg(feature = "std")]
+mod delta;
+#[cfg(feature = "std")]
+mod framed;
+#[cfg(feature = "image")]
+mod image_compression;
 #[cfg(feature = "lz4")]
 mod lz4_compression;
+#[cfg(feature = "lz4_flex")]
+mod lz4_flex_compression;
+#[cfg(feature = "mmap")]
+mod mmap_compression;
+#[cfg(feature = "std")]
+mod quantized;
+#[cfg(feature = "std")]
+mod raw_bytes;
+#[cfg(feature = "std")]
+mod rle;
+#[cfg(feature = "std")]
+mod serde_compression;
+mod shared_blob;
+#[cfg(feature = "sled")]
+mod sled_compression;
 #[cfg(feature = "snap")]
 mod snappy_compression;
+mod summarized;
+mod wrapper;
+#[cfg(feature = "zstd")]
+mod zstd_compression;
 
+#[cfg(feature = "std")]
+pub use adaptive::{AdaptiveLevel, LeveledCompression};
+pub use boxed::{BoxedCompression, CompressBoxed, DecompressBoxed};
+#[cfg(feature = "brotli")]
+pub use brotli_compression::Brotli;
+#[cfg(feature = "std")]
+pub use channel_array3::{
+    ChannelArray3, ChannelArray3Compression, ChannelCodec, CompressedChannelArray3,
+};
 #[cfg(feature = "bincode")]
-pub use compressed_bincode::BincodeCompression;
+pub use compressed_bincode::{Bincode, BincodeCompression};
+#[cfg(feature = "ciborium")]
+pub use compressed_cbor::{Cbor, CborCompression};
+#[cfg(feature = "std")]
+pub use delta::{DeltaBytes, DeltaCompressed, DeltaCompression};
+#[cfg(feature = "std")]
+pub use framed::{FramedBytes, FramedBytesCompression};
+#[cfg(feature = "image")]
+pub use image_compression::{ImageCodec, ImageCompression};
 #[cfg(feature = "lz4")]
-pub use lz4_compression::Lz4;
+pub use lz4_compression::{Lz4, Lz4Block};
+#[cfg(feature = "lz4_flex")]
+pub use lz4_flex_compression::Lz4Flex;
+#[cfg(feature = "mmap")]
+pub use mmap_compression::{MmapArena, MmapBlob, MmapCompression};
+#[cfg(feature = "std")]
+pub use quantized::{QuantizedF32Compression, QuantizedF32s};
+#[cfg(feature = "std")]
+pub use raw_bytes::RawBytesCompression;
+#[cfg(feature = "std")]
+pub use rle::Rle;
+#[cfg(feature = "std")]
+pub use serde_compression::{SerFormat, SerdeCompression};
+pub use shared_blob::SharedBlobCompression;
+#[cfg(feature = "sled")]
+pub use sled_compression::{SledBlob, SledCompression, SledStore};
 #[cfg(feature = "snap")]
 pub use snappy_compression::Snappy;
+pub use summarized::{Summarize, Summarized, SummarizedCompression};
+pub use wrapper::{
+    ArcCompression, BoxCompression, CowCompression, RcCompression, Wrapper, WrapperCompression,
+};
+#[cfg(feature = "zstd")]
+pub use zstd_compression::{Zstd, ZstdDict, ZstdDictBytes};
 
+use alloc::{boxed::Box, vec::Vec};
 use serde::{Deserialize, Serialize};
 
+/// The error returned by the fallible methods of `Compression`, wrapping whatever went wrong in the
+/// codec or serializer.
+#[derive(Debug)]
+pub struct CompressionError {
+    source: Box<dyn core::error::Error + Send + Sync>,
+}
+
+impl CompressionError {
+    pub fn new(source: impl Into<Box<dyn core::error::Error + Send + Sync>>) -> Self {
+        Self {
+            source: source.into(),
+        }
+    }
+}
+
+impl core::fmt::Display for CompressionError {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        write!(f, "compression failed: {}", self.source)
+    }
+}
+
+impl core::error::Error for CompressionError {
+    fn source(&self) -> Option<&(dyn core::error::Error + 'static)> {
+        Some(&*self.source)
+    }
+}
+
 /// An algorithm for compressing a specific type `Data` into type `Compressed`.
 pub trait Compression: Sized {
     type Data;
@@ -21,15 +121,65 @@ pub trait Compression: Sized {
 
     fn compress(&self, data: &Self::Data) -> Compressed<Self>;
     fn decompress(compressed: &Self::CompressedData) -> Self::Data;
+
+    /// Like `compress`, but returns an error instead of panicking. The default can't fail, so
+    /// implementations that can should override this and make `compress` unwrap it.
+    fn try_compress(&self, data: &Self::Data) -> Result<Compressed<Self>, CompressionError> {
+        Ok(self.compress(data))
+    }
+
+    /// Like `decompress`, but returns an error instead of panicking, e.g. on corrupt data. The
+    /// default can't fail, so implementations that can should override this and make `decompress`
+    /// unwrap it.
+    fn try_decompress(compressed: &Self::CompressedData) -> Result<Self::Data, CompressionError> {
+        Ok(Self::decompress(compressed))
+    }
+
+    /// Decompresses into an existing value, which may have come from any other decompression, so
+    /// implementations must handle any shape of `out`. The default just overwrites `out`, so
+    /// override this to reuse its allocations instead.
+    fn decompress_into(compressed: &Self::CompressedData, out: &mut Self::Data) {
+        *out = Self::decompress(compressed);
+    }
+
+    /// The number of bytes used by `compressed`. The default only counts the inline size, so
+    /// implementations with heap-allocated compressed data should override this.
+    fn compressed_size(compressed: &Self::CompressedData) -> usize {
+        core::mem::size_of_val(compressed)
+    }
 }
 
-#[derive(Clone, Deserialize, Serialize)]
+#[derive(Deserialize, Serialize)]
 pub struct Compressed<A>
 where
     A: Compression,
 {
     pub compressed_data: A::CompressedData,
-    marker: std::marker::PhantomData<A>,
+    marker: core::marker::PhantomData<A>,
+}
+
+// Implemented by hand, since deriving would require the compression parameters to be `Clone` and
+// `Debug` too.
+impl<A> Clone for Compressed<A>
+where
+    A: Compression,
+    A::CompressedData: Clone,
+{
+    fn clone(&self) -> Self {
+        Compressed::new(self.compressed_data.clone())
+    }
+}
+
+impl<A> core::fmt::Debug for Compressed<A>
+where
+    A: Compression,
+    A::CompressedData: core::fmt::Debug,
+{
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        f.debug_struct("Compressed")
+            .field("compressed_data", &self.compressed_data)
+            .finish()
+    }
 }
 
 impl<T, A> Compressed<A>
@@ -47,13 +197,80 @@ where
         A::decompress(&self.compressed_data)
     }
 
+    pub fn decompress_into(&self, out: &mut A::Data) {
+        A::decompress_into(&self.compressed_data, out)
+    }
+
+    pub fn try_decompress(&self) -> Result<A::Data, CompressionError> {
+        A::try_decompress(&self.compressed_data)
+    }
+
     pub fn take(self) -> A::CompressedData {
         self.compressed_data
     }
+
+    pub fn size(&self) -> usize {
+        A::compressed_size(&self.compressed_data)
+    }
+}
+
+/// A compression algorithm whose parameters can be fit to a sample of the data, e.g. by training a
+/// shared dictionary. Note that `decompress` doesn't take any parameters, so any trained state
+/// that's needed for decompression must be referenced by the `CompressedData`.
+pub trait TrainableCompression: Compression {
+    /// Returns new parameters fit to `samples`.
+    fn train<'a>(&self, samples: impl Iterator<Item = &'a Self::Data>) -> Self
+    where
+        Self::Data: 'a;
+}
+
+/// A compression algorithm for bytes that can decompress any range of the bytes without
+/// decompressing all of them.
+pub trait SeekableCompression: Compression<Data = Vec<u8>> {
+    /// Decompresses the bytes in `range`. Panics if the range is out of bounds, like slicing.
+    fn decompress_range(
+        compressed: &Self::CompressedData,
+        range: core::ops::Range<usize>,
+    ) -> Vec<u8>;
+}
+
+/// A compression algorithm whose compressed data contains a part `P` of the value that can be
+/// decompressed on its own, e.g. the header of a large composite value, so cheap queries don't need
+/// to decompress the whole thing. `P` is usually a marker type naming the part, which allows one
+/// algorithm to expose several parts.
+pub trait PartiallyDecompressible<P>: Compression {
+    type Part;
+
+    /// Gets the part from a decompressed value.
+    fn part(data: &Self::Data) -> Self::Part;
+
+    /// Gets the part from a compressed value, without decompressing the rest of it.
+    fn decompress_part(compressed: &Self::CompressedData) -> Self::Part;
 }
 
-/// A compression algorithm that acts directly on a slice of bytes.
+/// A compression algorithm that loses some precision, such that every decompressed number is within
+/// `tolerance` of the original.
+pub trait LossyCompression: Compression {
+    fn tolerance(&self) -> f32;
+
+    /// Returns the same parameters with a different tolerance.
+    fn with_tolerance(&self, tolerance: f32) -> Self;
+}
+
+/// A compression algorithm that acts directly on a slice of bytes. Needs the `std` feature, since
+/// the bytes are written to an `io::Write`.
+#[cfg(feature = "std")]
 pub trait BytesCompression {
     fn compress_bytes(&self, bytes: &[u8], compressed_bytes: impl std::io::Write);
     fn decompress_bytes(compressed_bytes: &[u8], bytes: &mut impl std::io::Write);
+
+    /// Like `decompress_bytes`, but returns an error instead of panicking, e.g. on corrupt data.
+    fn try_decompress_bytes(
+        compressed_bytes: &[u8],
+        bytes: &mut impl std::io::Write,
+    ) -> std::io::Result<()> {
+        Self::decompress_bytes(compressed_bytes, bytes);
+
+        Ok(())
+    }
 }
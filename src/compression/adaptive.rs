@@ -0,0 +1,213 @@
+use super::BytesCompression;
+
+use std::io::Write;
+use std::ops::RangeInclusive;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+/// A compression algorithm with a tunable level that trades speed for compression ratio.
+pub trait LeveledCompression: Sized {
+    /// From the fastest and least aggressive level to the slowest and most aggressive.
+    const LEVELS: RangeInclusive<i32>;
+
+    fn level(&self) -> i32;
+
+    /// Returns the same parameters with a different level.
+    fn with_level(&self, level: i32) -> Self;
+}
+
+/// Wraps a leveled compression algorithm, measuring how long each value takes to compress and
+/// adjusting the level to stay within `target_latency`. The level goes down when the moving average
+/// of the latency is over the target, and up when it's under half of the target, so values are
+/// compressed as well as possible without stalling whoever compresses them.
+pub struct AdaptiveLevel<A> {
+    pub compression: A,
+    pub target_latency: Duration,
+    state: Mutex<AdaptiveState>,
+}
+
+#[derive(Clone, Copy, Debug)]
+struct AdaptiveState {
+    level: i32,
+    // Exponential moving averages, `None` before the first value is compressed.
+    latency: Option<Duration>,
+    ratio: Option<f64>,
+}
+
+// The weight of each new measurement in the moving averages.
+const SMOOTHING: f64 = 0.25;
+
+impl<A: LeveledCompression> AdaptiveLevel<A> {
+    /// Starts at the level of `compression`.
+    pub fn new(compression: A, target_latency: Duration) -> Self {
+        let level = compression.level();
+
+        Self {
+            compression,
+            target_latency,
+            state: Mutex::new(AdaptiveState {
+                level,
+                latency: None,
+                ratio: None,
+            }),
+        }
+    }
+
+    /// The level that the next value will be compressed at.
+    pub fn level(&self) -> i32 {
+        self.state.lock().unwrap().level
+    }
+
+    /// The moving average of the time it takes to compress a value.
+    pub fn average_latency(&self) -> Option<Duration> {
+        self.state.lock().unwrap().latency
+    }
+
+    /// The moving average of the compression ratio, i.e. bytes before over bytes after.
+    pub fn average_ratio(&self) -> Option<f64> {
+        self.state.lock().unwrap().ratio
+    }
+}
+
+impl<A: LeveledCompression + Clone> Clone for AdaptiveLevel<A> {
+    fn clone(&self) -> Self {
+        Self {
+            compression: self.compression.clone(),
+            target_latency: self.target_latency,
+            state: Mutex::new(*self.state.lock().unwrap()),
+        }
+    }
+}
+
+impl AdaptiveState {
+    fn record<A: LeveledCompression>(&mut self, latency: Duration, ratio: f64, target: Duration) {
+        let latency = match self.latency {
+            Some(average) => average.mul_f64(1.0 - SMOOTHING) + latency.mul_f64(SMOOTHING),
+            None => latency,
+        };
+        self.latency = Some(latency);
+        self.ratio = Some(match self.ratio {
+            Some(average) => average * (1.0 - SMOOTHING) + ratio * SMOOTHING,
+            None => ratio,
+        });
+
+        if latency > target && self.level > *A::LEVELS.start() {
+            self.level -= 1;
+        } else if latency < target / 2 && self.level < *A::LEVELS.end() {
+            self.level += 1;
+        }
+    }
+}
+
+impl<A> BytesCompression for AdaptiveLevel<A>
+where
+    A: LeveledCompression + BytesCompression,
+{
+    fn compress_bytes(&self, bytes: &[u8], compressed_bytes: impl Write) {
+        let compression = self.compression.with_level(self.level());
+        let mut counter = CountingWriter {
+            inner: compressed_bytes,
+            count: 0,
+        };
+
+        let start = Instant::now();
+        compression.compress_bytes(bytes, &mut counter);
+        let latency = start.elapsed();
+
+        let ratio = bytes.len() as f64 / counter.count.max(1) as f64;
+        self.state
+            .lock()
+            .unwrap()
+            .record::<A>(latency, ratio, self.target_latency);
+    }
+
+    fn decompress_bytes(compressed_bytes: &[u8], bytes: &mut impl Write) {
+        A::decompress_bytes(compressed_bytes, bytes)
+    }
+
+    fn try_decompress_bytes(
+        compressed_bytes: &[u8],
+        bytes: &mut impl Write,
+    ) -> std::io::Result<()> {
+        A::try_decompress_bytes(compressed_bytes, bytes)
+    }
+}
+
+struct CountingWriter<W> {
+    inner: W,
+    count: usize,
+}
+
+impl<W: Write> Write for CountingWriter<W> {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        let written = self.inner.write(buf)?;
+        self.count += written;
+
+        Ok(written)
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        self.inner.flush()
+    }
+}
+
+// ████████╗███████╗███████╗████████╗███████╗
+// ╚══██╔══╝██╔════╝██╔════╝╚══██╔══╝██╔════╝
+//    ██║   █████╗  ███████╗   ██║   ███████╗
+//    ██║   ██╔══╝  ╚════██║   ██║   ╚════██║
+//    ██║   ███████╗███████║   ██║   ███████║
+//    ╚═╝   ╚══════╝╚══════╝   ╚═╝   ╚══════╝
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Takes `level` milliseconds to copy the bytes.
+    #[derive(Clone)]
+    struct SlowCopy {
+        level: i32,
+    }
+
+    impl LeveledCompression for SlowCopy {
+        const LEVELS: RangeInclusive<i32> = 0..=10;
+
+        fn level(&self) -> i32 {
+            self.level
+        }
+
+        fn with_level(&self, level: i32) -> Self {
+            Self { level }
+        }
+    }
+
+    impl BytesCompression for SlowCopy {
+        fn compress_bytes(&self, bytes: &[u8], mut compressed_bytes: impl Write) {
+            std::thread::sleep(Duration::from_millis(self.level as u64));
+            compressed_bytes.write_all(bytes).unwrap();
+        }
+
+        fn decompress_bytes(compressed_bytes: &[u8], bytes: &mut impl Write) {
+            bytes.write_all(compressed_bytes).unwrap();
+        }
+    }
+
+    #[test]
+    fn level_adapts_to_target_latency() {
+        let slow = AdaptiveLevel::new(SlowCopy { level: 10 }, Duration::from_micros(2500));
+        for _ in 0..30 {
+            slow.compress_bytes(&[1, 2, 3], Vec::new());
+        }
+        assert!(slow.level() <= 3);
+        assert_eq!(slow.average_ratio(), Some(1.0));
+
+        let fast = AdaptiveLevel::new(SlowCopy { level: 0 }, Duration::from_secs(1));
+        for _ in 0..3 {
+            fast.compress_bytes(&[1, 2, 3], Vec::new());
+        }
+        assert_eq!(fast.level(), 3);
+
+        let mut bytes = Vec::new();
+        AdaptiveLevel::<SlowCopy>::decompress_bytes(&[4, 5], &mut bytes);
+        assert_eq!(bytes, vec![4, 5]);
+    }
+}
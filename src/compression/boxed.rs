@@ -0,0 +1,129 @@
+use super::{Compressed, Compression};
+
+use alloc::boxed::Box;
+
+/// A compressed value that knows how to decompress itself into a `Box<T>`.
+pub trait DecompressBoxed<T: ?Sized> {
+    fn decompress_boxed(&self) -> Box<T>;
+
+    /// The number of bytes used by the compressed value, including heap memory.
+    fn compressed_size(&self) -> usize {
+        core::mem::size_of_val(self)
+    }
+}
+
+/// A value that knows how to compress itself. Implement this for a trait object type, like
+/// `dyn Asset`, by delegating to a method of the trait, so every implementation of the trait can
+/// use its own compression.
+pub trait CompressBoxed<T: ?Sized> {
+    fn compress_boxed(&self) -> Box<dyn DecompressBoxed<T>>;
+}
+
+/// Compresses `Box<T>` values by letting each value compress itself. This makes it possible to
+/// store values of different types in one map, like a cache of meshes, textures, and sounds behind
+/// a `Box<dyn Asset>`.
+pub struct BoxedCompression<T: ?Sized> {
+    marker: core::marker::PhantomData<fn() -> Box<T>>,
+}
+
+impl<T: ?Sized> Default for BoxedCompression<T> {
+    fn default() -> Self {
+        Self {
+            marker: Default::default(),
+        }
+    }
+}
+
+impl<T> Compression for BoxedCompression<T>
+where
+    T: ?Sized + CompressBoxed<T>,
+{
+    type Data = Box<T>;
+    type CompressedData = Box<dyn DecompressBoxed<T>>;
+
+    fn compress(&self, data: &Self::Data) -> Compressed<Self> {
+        Compressed::new(data.compress_boxed())
+    }
+
+    fn decompress(compressed: &Self::CompressedData) -> Self::Data {
+        compressed.decompress_boxed()
+    }
+
+    fn compressed_size(compressed: &Self::CompressedData) -> usize {
+        compressed.compressed_size()
+    }
+}
+
+// ████████╗███████╗███████╗████████╗███████╗
+// ╚══██╔══╝██╔════╝██╔════╝╚══██╔══╝██╔════╝
+//    ██║   █████╗  ███████╗   ██║   ███████╗
+//    ██║   ██╔══╝  ╚════██║   ██║   ╚════██║
+//    ██║   ███████╗███████║   ██║   ███████║
+//    ╚═╝   ╚══════╝╚══════╝   ╚═╝   ╚══════╝
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::CompressibleMap;
+
+    trait Asset {
+        fn name(&self) -> String;
+        fn compress_asset(&self) -> Box<dyn DecompressBoxed<dyn Asset>>;
+    }
+
+    impl CompressBoxed<dyn Asset> for dyn Asset {
+        fn compress_boxed(&self) -> Box<dyn DecompressBoxed<dyn Asset>> {
+            self.compress_asset()
+        }
+    }
+
+    struct Mesh(Vec<u32>);
+    struct CompressedMesh(u32, usize);
+
+    impl Asset for Mesh {
+        fn name(&self) -> String {
+            format!("mesh {:?}", self.0)
+        }
+
+        fn compress_asset(&self) -> Box<dyn DecompressBoxed<dyn Asset>> {
+            Box::new(CompressedMesh(self.0[0], self.0.len()))
+        }
+    }
+
+    impl DecompressBoxed<dyn Asset> for CompressedMesh {
+        fn decompress_boxed(&self) -> Box<dyn Asset> {
+            Box::new(Mesh(vec![self.0; self.1]))
+        }
+    }
+
+    struct Sound(String);
+
+    impl Asset for Sound {
+        fn name(&self) -> String {
+            format!("sound {}", self.0)
+        }
+
+        fn compress_asset(&self) -> Box<dyn DecompressBoxed<dyn Asset>> {
+            Box::new(Sound(self.0.to_uppercase()))
+        }
+    }
+
+    impl DecompressBoxed<dyn Asset> for Sound {
+        fn decompress_boxed(&self) -> Box<dyn Asset> {
+            Box::new(Sound(self.0.to_lowercase()))
+        }
+    }
+
+    #[test]
+    fn heterogeneous_values_compress_themselves() {
+        let mut map = CompressibleMap::<_, Box<dyn Asset>, _>::new(BoxedCompression::default());
+        map.insert(1, Box::new(Mesh(vec![7, 7, 7])));
+        map.insert(2, Box::new(Sound("Boom".to_string())));
+        map.compress_lru();
+        map.compress_lru();
+        assert_eq!(map.len_compressed(), 2);
+
+        assert_eq!(map.get(1).unwrap().name(), "mesh [7, 7, 7]");
+        assert_eq!(map.get(2).unwrap().name(), "sound boom");
+    }
+}
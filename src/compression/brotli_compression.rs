@@ -0,0 +1,95 @@
+use super::{BytesCompression, LeveledCompression};
+
+use serde::{Deserialize, Serialize};
+
+/// The [Brotli compression algorithm](https://en.wikipedia.org/wiki/Brotli). Slower than the other
+/// backends, but compresses better, so it suits values that are rarely accessed.
+#[derive(Clone, Copy, Debug, Serialize, Deserialize)]
+pub struct Brotli {
+    /// The compression quality, from 0 to 11. 0 is fastest and least aggressive. 11 is slowest and
+    /// most aggressive.
+    pub quality: u32,
+    /// The base 2 logarithm of the sliding window size, from 10 to 24. Larger windows find more
+    /// repetition in large values, but use more memory.
+    pub lg_window_size: u32,
+}
+
+impl Default for Brotli {
+    fn default() -> Self {
+        Self {
+            quality: 11,
+            lg_window_size: 22,
+        }
+    }
+}
+
+/// The level is the quality.
+impl LeveledCompression for Brotli {
+    const LEVELS: std::ops::RangeInclusive<i32> = 0..=11;
+
+    fn level(&self) -> i32 {
+        self.quality as i32
+    }
+
+    fn with_level(&self, level: i32) -> Self {
+        Self {
+            quality: level as u32,
+            ..*self
+        }
+    }
+}
+
+const BUFFER_SIZE: usize = 4096;
+
+impl BytesCompression for Brotli {
+    fn compress_bytes(&self, bytes: &[u8], compressed_bytes: impl std::io::Write) {
+        let mut encoder = brotli::CompressorWriter::new(
+            compressed_bytes,
+            BUFFER_SIZE,
+            self.quality,
+            self.lg_window_size,
+        );
+        std::io::copy(&mut std::io::Cursor::new(bytes), &mut encoder).unwrap();
+        // Finishes the stream.
+        encoder.into_inner();
+    }
+
+    fn decompress_bytes(compressed_bytes: &[u8], bytes: &mut impl std::io::Write) {
+        Self::try_decompress_bytes(compressed_bytes, bytes).unwrap();
+    }
+
+    fn try_decompress_bytes(
+        compressed_bytes: &[u8],
+        bytes: &mut impl std::io::Write,
+    ) -> std::io::Result<()> {
+        let mut decoder = brotli::Decompressor::new(compressed_bytes, BUFFER_SIZE);
+        std::io::copy(&mut decoder, bytes)?;
+
+        Ok(())
+    }
+}
+
+// ████████╗███████╗███████╗████████╗███████╗
+// ╚══██╔══╝██╔════╝██╔════╝╚══██╔══╝██╔════╝
+//    ██║   █████╗  ███████╗   ██║   ███████╗
+//    ██║   ██╔══╝  ╚════██║   ██║   ╚════██║
+//    ██║   ███████╗███████║   ██║   ███████║
+//    ╚═╝   ╚══════╝╚══════╝   ╚═╝   ╚══════╝
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn compress_and_decompress_serializable_type() {
+        let bytes: Vec<u8> = (0u8..100).cycle().take(1000).collect();
+
+        let mut compressed_bytes = Vec::new();
+        Brotli::default().compress_bytes(&bytes, &mut compressed_bytes);
+        assert!(compressed_bytes.len() < bytes.len());
+        let mut decompressed_bytes = Vec::new();
+        Brotli::decompress_bytes(&compressed_bytes, &mut decompressed_bytes);
+
+        assert_eq!(bytes, decompressed_bytes);
+    }
+}
@@ -0,0 +1,271 @@
+#[cfg(feature = "lz4")]
+use super::Lz4;
+#[cfg(feature = "snap")]
+use super::Snappy;
+use super::{BytesCompression, Compressed, Compression, CompressionError, Rle};
+
+use serde::{Deserialize, Serialize};
+use std::convert::TryInto;
+
+/// A 3-dimensional array with multiple channels of data per point, e.g. the material ID and light
+/// level of each voxel in a chunk. Each channel is stored as a separate slice of bytes (structure
+/// of arrays), so channels with different statistics can be compressed with different codecs.
+#[derive(Clone, Debug, Deserialize, Eq, PartialEq, Serialize)]
+pub struct ChannelArray3 {
+    shape: [usize; 3],
+    channels: Vec<Vec<u8>>,
+}
+
+impl ChannelArray3 {
+    /// Creates an array where each point has one element of `fill[i]` in channel `i`.
+    pub fn fill(shape: [usize; 3], fill: &[&[u8]]) -> Self {
+        let volume = shape[0] * shape[1] * shape[2];
+        let channels = fill.iter().map(|element| element.repeat(volume)).collect();
+
+        Self { shape, channels }
+    }
+
+    /// Creates an array from the raw bytes of each channel. The length of each channel must be a
+    /// multiple of the number of points.
+    pub fn from_channels(shape: [usize; 3], channels: Vec<Vec<u8>>) -> Self {
+        let volume = shape[0] * shape[1] * shape[2];
+        for channel in channels.iter() {
+            assert_eq!(channel.len() % volume.max(1), 0);
+        }
+
+        Self { shape, channels }
+    }
+
+    pub fn shape(&self) -> [usize; 3] {
+        self.shape
+    }
+
+    pub fn volume(&self) -> usize {
+        self.shape[0] * self.shape[1] * self.shape[2]
+    }
+
+    pub fn num_channels(&self) -> usize {
+        self.channels.len()
+    }
+
+    /// The number of bytes per point in `channel`.
+    pub fn element_size(&self, channel: usize) -> usize {
+        self.channels[channel].len() / self.volume().max(1)
+    }
+
+    pub fn channel(&self, channel: usize) -> &[u8] {
+        &self.channels[channel]
+    }
+
+    pub fn channel_mut(&mut self, channel: usize) -> &mut [u8] {
+        &mut self.channels[channel]
+    }
+
+    fn linear_index(&self, point: [usize; 3]) -> usize {
+        point[0] + self.shape[0] * (point[1] + self.shape[1] * point[2])
+    }
+
+    /// The bytes of the element of `channel` at `point`.
+    pub fn get(&self, channel: usize, point: [usize; 3]) -> &[u8] {
+        let size = self.element_size(channel);
+        let start = size * self.linear_index(point);
+
+        &self.channels[channel][start..start + size]
+    }
+
+    pub fn get_mut(&mut self, channel: usize, point: [usize; 3]) -> &mut [u8] {
+        let size = self.element_size(channel);
+        let start = size * self.linear_index(point);
+
+        &mut self.channels[channel][start..start + size]
+    }
+}
+
+/// The codec used for one channel of a `ChannelArray3`.
+#[derive(Clone, Copy, Debug, Deserialize, Serialize)]
+pub enum ChannelCodec {
+    /// Store the bytes as-is.
+    Uncompressed,
+    /// Run-length encode whole elements. Best for channels with large homogeneous regions, like
+    /// material IDs.
+    Rle,
+    #[cfg(feature = "lz4")]
+    Lz4(Lz4),
+    #[cfg(feature = "snap")]
+    Snappy,
+}
+
+impl ChannelCodec {
+    fn compress_channel(&self, bytes: &[u8], element_size: usize) -> Vec<u8> {
+        let mut compressed_bytes = Vec::new();
+        match self {
+            ChannelCodec::Uncompressed => compressed_bytes.extend_from_slice(bytes),
+            ChannelCodec::Rle => Rle {
+                element_size: element_size
+                    .try_into()
+                    .expect("RLE elements must be smaller than 64 KiB"),
+            }
+            .compress_bytes(bytes, &mut compressed_bytes),
+            #[cfg(feature = "lz4")]
+            ChannelCodec::Lz4(lz4) => lz4.compress_bytes(bytes, &mut compressed_bytes),
+            #[cfg(feature = "snap")]
+            ChannelCodec::Snappy => Snappy.compress_bytes(bytes, &mut compressed_bytes),
+        }
+
+        compressed_bytes
+    }
+
+    fn try_decompress_channel(&self, compressed_bytes: &[u8]) -> std::io::Result<Vec<u8>> {
+        let mut bytes = Vec::new();
+        match self {
+            ChannelCodec::Uncompressed => bytes.extend_from_slice(compressed_bytes),
+            ChannelCodec::Rle => Rle::try_decompress_bytes(compressed_bytes, &mut bytes)?,
+            #[cfg(feature = "lz4")]
+            ChannelCodec::Lz4(_) => Lz4::try_decompress_bytes(compressed_bytes, &mut bytes)?,
+            #[cfg(feature = "snap")]
+            ChannelCodec::Snappy => Snappy::try_decompress_bytes(compressed_bytes, &mut bytes)?,
+        }
+
+        Ok(bytes)
+    }
+}
+
+/// Compresses each channel of a `ChannelArray3` with its own codec. Channels without a configured
+/// codec are left uncompressed.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct ChannelArray3Compression {
+    pub channel_codecs: Vec<ChannelCodec>,
+}
+
+/// The compressed channels of a `ChannelArray3`, each tagged with the codec used to compress it.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct CompressedChannelArray3 {
+    shape: [usize; 3],
+    channels: Vec<(ChannelCodec, Vec<u8>)>,
+}
+
+impl Compression for ChannelArray3Compression {
+    type Data = ChannelArray3;
+    type CompressedData = CompressedChannelArray3;
+
+    fn compress(&self, data: &Self::Data) -> Compressed<Self> {
+        let channels = (0..data.num_channels())
+            .map(|i| {
+                let codec = self
+                    .channel_codecs
+                    .get(i)
+                    .cloned()
+                    .unwrap_or(ChannelCodec::Uncompressed);
+
+                (
+                    codec,
+                    codec.compress_channel(data.channel(i), data.element_size(i)),
+                )
+            })
+            .collect();
+
+        Compressed::new(CompressedChannelArray3 {
+            shape: data.shape,
+            channels,
+        })
+    }
+
+    fn decompress(compressed: &Self::CompressedData) -> Self::Data {
+        Self::try_decompress(compressed).unwrap()
+    }
+
+    fn try_decompress(compressed: &Self::CompressedData) -> Result<Self::Data, CompressionError> {
+        let shape = compressed.shape;
+        let volume = shape[0] * shape[1] * shape[2];
+        let channels = compressed
+            .channels
+            .iter()
+            .map(|(codec, bytes)| {
+                let channel = codec
+                    .try_decompress_channel(bytes)
+                    .map_err(CompressionError::new)?;
+                if channel.len() % volume.max(1) != 0 {
+                    return Err(CompressionError::new(
+                        "channel length is not a multiple of the volume",
+                    ));
+                }
+
+                Ok(channel)
+            })
+            .collect::<Result<_, _>>()?;
+
+        Ok(ChannelArray3 { shape, channels })
+    }
+
+    fn compressed_size(compressed: &Self::CompressedData) -> usize {
+        compressed
+            .channels
+            .iter()
+            .map(|(_, bytes)| bytes.len())
+            .sum()
+    }
+}
+
+// ████████╗███████╗███████╗████████╗███████╗
+// ╚══██╔══╝██╔════╝██╔════╝╚══██╔══╝██╔════╝
+//    ██║   █████╗  ███████╗   ██║   ███████╗
+//    ██║   ██╔══╝  ╚════██║   ██║   ╚════██║
+//    ██║   ███████╗███████║   ██║   ███████║
+//    ╚═╝   ╚══════╝╚══════╝   ╚═╝   ╚══════╝
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn compress_and_decompress_with_codec_per_channel() {
+        // A u16 material channel and a u8 light channel.
+        let mut array = ChannelArray3::fill([16, 16, 16], &[&7u16.to_le_bytes(), &[15]]);
+        array
+            .get_mut(0, [1, 2, 3])
+            .copy_from_slice(&9u16.to_le_bytes());
+        for (i, light) in array.channel_mut(1).iter_mut().enumerate() {
+            *light = (i % 16) as u8;
+        }
+
+        let compression = ChannelArray3Compression {
+            channel_codecs: vec![ChannelCodec::Rle, ChannelCodec::Uncompressed],
+        };
+        let compressed = compression.compress(&array);
+        assert!(compressed.size() < array.channel(0).len() / 100 + array.channel(1).len());
+
+        let decompressed = compressed.decompress();
+        assert_eq!(decompressed, array);
+        assert_eq!(decompressed.get(0, [1, 2, 3]), &9u16.to_le_bytes());
+        assert_eq!(decompressed.get(1, [3, 0, 0]), &[3]);
+    }
+
+    #[test]
+    fn rle_handles_empty_arrays_and_wide_elements() {
+        let compression = ChannelArray3Compression {
+            channel_codecs: vec![ChannelCodec::Rle, ChannelCodec::Rle],
+        };
+
+        let empty = ChannelArray3::fill([0, 4, 4], &[&[1, 2]]);
+        assert_eq!(compression.compress(&empty).decompress(), empty);
+
+        let wide = ChannelArray3::fill([2, 2, 2], &[&[7; 256], &[]]);
+        assert_eq!(compression.compress(&wide).decompress(), wide);
+    }
+
+    #[test]
+    fn corrupt_channels_are_errors() {
+        let compression = ChannelArray3Compression {
+            channel_codecs: vec![ChannelCodec::Rle, ChannelCodec::Uncompressed],
+        };
+        let array = ChannelArray3::fill([2, 2, 2], &[&[1, 2], &[3]]);
+
+        let mut truncated_run = compression.compress(&array).take();
+        truncated_run.channels[0].1.pop();
+        assert!(ChannelArray3Compression::try_decompress(&truncated_run).is_err());
+
+        let mut wrong_length = compression.compress(&array).take();
+        wrong_length.channels[1].1.pop();
+        assert!(ChannelArray3Compression::try_decompress(&wrong_length).is_err());
+    }
+}
@@ -1,48 +1,26 @@
-use super::{BytesCompression, Compressed, Compression};
+use super::{CompressionError, SerFormat, SerdeCompression};
 
 use serde::{de::DeserializeOwned, Serialize};
 
-/// Run some compression algorithm `A` after bincode serializing a type `T`. This provides a decent
-/// default compression for any serializable type.
-#[derive(Clone, Copy)]
-pub struct BincodeCompression<T, A> {
-    pub compression: A,
-    marker: std::marker::PhantomData<T>,
-}
-
-impl<T, A> BincodeCompression<T, A> {
-    pub fn new(compression: A) -> Self {
-        Self {
-            compression,
-            marker: Default::default(),
-        }
-    }
-}
-
-impl<T, A> Compression for BincodeCompression<T, A>
-where
-    T: DeserializeOwned + Serialize,
-    A: BytesCompression,
-{
-    type Data = T;
-    type CompressedData = Vec<u8>;
-
-    fn compress(&self, data: &Self::Data) -> Compressed<Self> {
-        let mut compressed_bytes = Vec::new();
-        self.compression
-            .compress_bytes(&bincode::serialize(data).unwrap(), &mut compressed_bytes);
+/// The [bincode](https://github.com/bincode-org/bincode) format. Compact and fast, which makes it a
+/// decent default.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct Bincode;
 
-        Compressed::new(compressed_bytes)
+impl SerFormat for Bincode {
+    fn serialize<T: Serialize>(value: &T, bytes: &mut Vec<u8>) -> Result<(), CompressionError> {
+        bincode::serialize_into(bytes, value).map_err(CompressionError::new)
     }
 
-    fn decompress(compressed: &Self::CompressedData) -> Self::Data {
-        let mut decompressed_bytes = Vec::new();
-        A::decompress_bytes(compressed, &mut decompressed_bytes);
-
-        bincode::deserialize(&decompressed_bytes).unwrap()
+    fn deserialize<T: DeserializeOwned>(bytes: &[u8]) -> Result<T, CompressionError> {
+        bincode::deserialize(bytes).map_err(CompressionError::new)
     }
 }
 
+/// Run some compression algorithm `A` after bincode serializing a type `T`. This provides a decent
+/// default compression for any serializable type.
+pub type BincodeCompression<T, A> = SerdeCompression<T, Bincode, A>;
+
 // ████████╗███████╗███████╗████████╗███████╗
 // ╚══██╔══╝██╔════╝██╔════╝╚══██╔══╝██╔════╝
 //    ██║   █████╗  ███████╗   ██║   ███████╗
@@ -53,7 +31,7 @@ where
 #[cfg(all(test, feature = "snap"))]
 mod tests {
     use super::*;
-    use crate::Snappy;
+    use crate::{Compression, Snappy};
     use serde::Deserialize;
 
     #[derive(Clone, Debug, Eq, Deserialize, Serialize, PartialEq)]
@@ -69,4 +47,13 @@ mod tests {
 
         assert_eq!(foo, decompressed_foo);
     }
+
+    #[test]
+    fn corrupt_data_is_an_error() {
+        let compression = BincodeCompression::<Foo, _>::new(Snappy);
+        let mut compressed = compression.compress(&Foo(vec![1, 2, 3]));
+        compressed.compressed_data.truncate(5);
+
+        assert!(compressed.try_decompress().is_err());
+    }
 }
@@ -0,0 +1,59 @@
+use super::{CompressionError, SerFormat, SerdeCompression};
+
+use serde::{de::DeserializeOwned, Serialize};
+
+/// The [CBOR](https://en.wikipedia.org/wiki/CBOR) format. Unlike bincode, CBOR is self-describing,
+/// so decompressed bytes can be inspected with external tools, e.g. when debugging a persisted map.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct Cbor;
+
+impl SerFormat for Cbor {
+    fn serialize<T: Serialize>(value: &T, bytes: &mut Vec<u8>) -> Result<(), CompressionError> {
+        ciborium::ser::into_writer(value, bytes).map_err(CompressionError::new)
+    }
+
+    fn deserialize<T: DeserializeOwned>(bytes: &[u8]) -> Result<T, CompressionError> {
+        ciborium::de::from_reader(bytes).map_err(CompressionError::new)
+    }
+}
+
+/// Run some compression algorithm `A` after serializing a type `T` as CBOR.
+pub type CborCompression<T, A> = SerdeCompression<T, Cbor, A>;
+
+// ████████╗███████╗███████╗████████╗███████╗
+// ╚══██╔══╝██╔════╝██╔════╝╚══██╔══╝██╔════╝
+//    ██║   █████╗  ███████╗   ██║   ███████╗
+//    ██║   ██╔══╝  ╚════██║   ██║   ╚════██║
+//    ██║   ███████╗███████║   ██║   ███████║
+//    ╚═╝   ╚══════╝╚══════╝   ╚═╝   ╚══════╝
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{BytesCompression, Compression, Rle};
+    use serde::Deserialize;
+
+    #[derive(Clone, Debug, Eq, Deserialize, Serialize, PartialEq)]
+    struct Foo {
+        name: String,
+        bytes: Vec<u8>,
+    }
+
+    #[test]
+    fn compress_and_decompress_serializable_type() {
+        let foo = Foo {
+            name: "foo".to_string(),
+            bytes: vec![0; 100],
+        };
+
+        let compression = CborCompression::new(Rle { element_size: 1 });
+        let compressed = compression.compress(&foo);
+
+        // The field names are in the decompressed bytes.
+        let mut cbor = Vec::new();
+        Rle::decompress_bytes(&compressed.compressed_data, &mut cbor);
+        assert!(cbor.windows(4).any(|w| w == b"name"));
+
+        assert_eq!(foo, compressed.decompress());
+    }
+}
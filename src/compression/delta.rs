@@ -0,0 +1,238 @@
+use super::{BytesCompression, Compressed, Compression, CompressionError};
+
+use std::ops::{Deref, DerefMut};
+use std::sync::Arc;
+
+/// Bytes that remember the snapshot they were decompressed from, so `DeltaCompression` can
+/// compress the next version as a diff against it. Modify the bytes in place, e.g. through
+/// `CompressibleMap::get_mut`, to keep the snapshot; a value created with `new` starts without one.
+#[derive(Clone, Debug, Default)]
+pub struct DeltaBytes {
+    pub bytes: Vec<u8>,
+    base: Option<Base>,
+}
+
+#[derive(Clone, Debug)]
+struct Base {
+    snapshot: Arc<Vec<u8>>,
+    num_deltas: u32,
+}
+
+impl DeltaBytes {
+    pub fn new(bytes: Vec<u8>) -> Self {
+        Self { bytes, base: None }
+    }
+
+    pub fn into_bytes(self) -> Vec<u8> {
+        self.bytes
+    }
+}
+
+impl Deref for DeltaBytes {
+    type Target = Vec<u8>;
+
+    fn deref(&self) -> &Vec<u8> {
+        &self.bytes
+    }
+}
+
+impl DerefMut for DeltaBytes {
+    fn deref_mut(&mut self) -> &mut Vec<u8> {
+        &mut self.bytes
+    }
+}
+
+impl PartialEq for DeltaBytes {
+    fn eq(&self, other: &Self) -> bool {
+        self.bytes == other.bytes
+    }
+}
+
+impl Eq for DeltaBytes {}
+
+/// A full snapshot compressed with `A`, shared by all of the versions that are diffed against it,
+/// and optionally the compressed diff of this version.
+#[derive(Clone, Debug)]
+pub struct DeltaCompressed {
+    snapshot: Arc<Vec<u8>>,
+    delta: Option<Vec<u8>>,
+    num_deltas: u32,
+}
+
+impl DeltaCompressed {
+    /// Whether this version is stored as a diff against an earlier snapshot.
+    pub fn is_delta(&self) -> bool {
+        self.delta.is_some()
+    }
+}
+
+/// Compresses values that change only slightly between versions as a diff against a full snapshot
+/// of an earlier version, instead of compressing every version from scratch. The diff is the XOR of
+/// the two versions, which is mostly zeros and compresses very well with `A`.
+///
+/// After `snapshot_interval` versions, or when the diff doesn't come out smaller than the snapshot,
+/// a new full snapshot is taken, so versions don't drift too far from their base.
+#[derive(Clone, Debug)]
+pub struct DeltaCompression<A> {
+    pub compression: A,
+    pub snapshot_interval: u32,
+}
+
+impl<A: BytesCompression> DeltaCompression<A> {
+    pub fn new(compression: A, snapshot_interval: u32) -> Self {
+        Self {
+            compression,
+            snapshot_interval,
+        }
+    }
+
+    fn compress_bytes(&self, bytes: &[u8]) -> Vec<u8> {
+        let mut compressed = Vec::new();
+        self.compression.compress_bytes(bytes, &mut compressed);
+
+        compressed
+    }
+
+    fn try_delta(&self, data: &DeltaBytes) -> Option<DeltaCompressed> {
+        let base = data.base.as_ref()?;
+        if base.num_deltas >= self.snapshot_interval {
+            return None;
+        }
+
+        // A snapshot that can't be decompressed is replaced by a new one.
+        let snapshot = try_decompress_bytes::<A>(&base.snapshot).ok()?;
+        let diff = xor(&data.bytes, &snapshot);
+        let delta = self.compress_bytes(&diff);
+        if delta.len() >= base.snapshot.len() {
+            return None;
+        }
+
+        Some(DeltaCompressed {
+            snapshot: base.snapshot.clone(),
+            delta: Some(delta),
+            num_deltas: base.num_deltas + 1,
+        })
+    }
+}
+
+impl<A: BytesCompression> Compression for DeltaCompression<A> {
+    type Data = DeltaBytes;
+    type CompressedData = DeltaCompressed;
+
+    fn compress(&self, data: &DeltaBytes) -> Compressed<Self> {
+        let compressed = self.try_delta(data).unwrap_or_else(|| DeltaCompressed {
+            snapshot: Arc::new(self.compress_bytes(&data.bytes)),
+            delta: None,
+            num_deltas: 0,
+        });
+
+        Compressed::new(compressed)
+    }
+
+    fn decompress(compressed: &DeltaCompressed) -> DeltaBytes {
+        Self::try_decompress(compressed).unwrap()
+    }
+
+    fn try_decompress(compressed: &DeltaCompressed) -> Result<DeltaBytes, CompressionError> {
+        let snapshot =
+            try_decompress_bytes::<A>(&compressed.snapshot).map_err(CompressionError::new)?;
+        let bytes = match &compressed.delta {
+            Some(delta) => xor(
+                &try_decompress_bytes::<A>(delta).map_err(CompressionError::new)?,
+                &snapshot,
+            ),
+            None => snapshot,
+        };
+
+        Ok(DeltaBytes {
+            bytes,
+            base: Some(Base {
+                snapshot: compressed.snapshot.clone(),
+                num_deltas: compressed.num_deltas,
+            }),
+        })
+    }
+
+    /// Counts the whole snapshot, even though it may be shared with the cached version.
+    fn compressed_size(compressed: &DeltaCompressed) -> usize {
+        std::mem::size_of::<DeltaCompressed>()
+            + compressed.snapshot.len()
+            + compressed.delta.as_ref().map_or(0, |d| d.len())
+    }
+}
+
+fn try_decompress_bytes<A: BytesCompression>(compressed: &[u8]) -> std::io::Result<Vec<u8>> {
+    let mut bytes = Vec::new();
+    A::try_decompress_bytes(compressed, &mut bytes)?;
+
+    Ok(bytes)
+}
+
+/// XORs the overlap of `bytes` with `base`, keeping the length and any tail of `bytes`. Applying it
+/// twice with the same `base` gives back `bytes`.
+fn xor(bytes: &[u8], base: &[u8]) -> Vec<u8> {
+    let mut out = bytes.to_vec();
+    for (b, base) in out.iter_mut().zip(base.iter()) {
+        *b ^= base;
+    }
+
+    out
+}
+
+// ████████╗███████╗███████╗████████╗███████╗
+// ╚══██╔══╝██╔════╝██╔════╝╚══██╔══╝██╔════╝
+//    ██║   █████╗  ███████╗   ██║   ███████╗
+//    ██║   ██╔══╝  ╚════██║   ██║   ╚════██║
+//    ██║   ███████╗███████║   ██║   ███████║
+//    ╚═╝   ╚══════╝╚══════╝   ╚═╝   ╚══════╝
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{CompressibleMap, MaybeCompressed, Rle};
+
+    #[test]
+    fn small_changes_are_stored_as_diffs() {
+        let compression = DeltaCompression::new(Rle { element_size: 1 }, 2);
+        let mut map = CompressibleMap::<_, _, _>::new(compression);
+        let bytes: Vec<u8> = (0u8..200).collect();
+        map.insert(1, DeltaBytes::new(bytes.clone()));
+        map.compress_lru();
+        let is_delta = |map: &CompressibleMap<_, _, DeltaCompression<Rle>>| {
+            map.iter().find_map(|(_, value)| match value {
+                MaybeCompressed::Compressed(c) => Some(c.compressed_data.is_delta()),
+                MaybeCompressed::Decompressed(_) => None,
+            })
+        };
+        assert_eq!(is_delta(&map), Some(false));
+
+        let mut expected = bytes;
+        for version in 0..3u8 {
+            let value = map.get_mut(1).unwrap();
+            value[10] = version;
+            value.push(version);
+            expected[10] = version;
+            expected.push(version);
+            map.compress_lru();
+
+            // The third version exceeds the snapshot interval.
+            assert_eq!(is_delta(&map), Some(version < 2));
+            let copy = map.get_copy_without_caching(&1).unwrap();
+            assert_eq!(copy.as_decompressed().bytes, expected);
+        }
+    }
+
+    #[test]
+    fn corrupt_snapshots_are_errors() {
+        let compression = DeltaCompression::new(Rle { element_size: 1 }, 2);
+        let compressed = compression.compress(&DeltaBytes::new(vec![1, 2, 3])).take();
+        let mut snapshot = compressed.snapshot.to_vec();
+        snapshot.pop();
+        let corrupt = DeltaCompressed {
+            snapshot: Arc::new(snapshot),
+            ..compressed
+        };
+
+        assert!(DeltaCompression::<Rle>::try_decompress(&corrupt).is_err());
+    }
+}
@@ -0,0 +1,193 @@
+use super::{BytesCompression, Compressed, Compression, CompressionError, SeekableCompression};
+
+use serde::{Deserialize, Serialize};
+use std::ops::Range;
+
+/// Splits a byte vector into frames of `frame_size` bytes and compresses each frame independently
+/// with `compression`. This costs a little compression ratio, but any range of bytes can be
+/// decompressed without decompressing the rest of the value, e.g. to read one column of a huge
+/// chunk with `CompressibleMap::get_range`.
+#[derive(Clone, Copy, Debug)]
+pub struct FramedBytesCompression<A> {
+    frame_size: usize,
+    pub compression: A,
+}
+
+impl<A> FramedBytesCompression<A> {
+    /// Panics if `frame_size` is 0.
+    pub fn new(frame_size: usize, compression: A) -> Self {
+        assert!(frame_size > 0, "Frames must not be empty");
+
+        Self {
+            frame_size,
+            compression,
+        }
+    }
+
+    pub fn frame_size(&self) -> usize {
+        self.frame_size
+    }
+}
+
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct FramedBytes {
+    len: usize,
+    frame_size: usize,
+    frames: Vec<Vec<u8>>,
+}
+
+impl FramedBytes {
+    /// The length of the decompressed bytes.
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+}
+
+impl<A> Compression for FramedBytesCompression<A>
+where
+    A: BytesCompression,
+{
+    type Data = Vec<u8>;
+    type CompressedData = FramedBytes;
+
+    fn compress(&self, data: &Self::Data) -> Compressed<Self> {
+        let frames = data
+            .chunks(self.frame_size)
+            .map(|frame| {
+                let mut compressed_frame = Vec::new();
+                self.compression
+                    .compress_bytes(frame, &mut compressed_frame);
+
+                compressed_frame
+            })
+            .collect();
+
+        Compressed::new(FramedBytes {
+            len: data.len(),
+            frame_size: self.frame_size,
+            frames,
+        })
+    }
+
+    fn decompress(compressed: &Self::CompressedData) -> Self::Data {
+        Self::decompress_range(compressed, 0..compressed.len)
+    }
+
+    fn try_decompress(compressed: &Self::CompressedData) -> Result<Self::Data, CompressionError> {
+        let mut bytes = Vec::with_capacity(compressed.len);
+        for frame in &compressed.frames {
+            A::try_decompress_bytes(frame, &mut bytes).map_err(CompressionError::new)?;
+        }
+        if bytes.len() != compressed.len {
+            return Err(CompressionError::new(format!(
+                "decompressed {} bytes, expected {}",
+                bytes.len(),
+                compressed.len
+            )));
+        }
+
+        Ok(bytes)
+    }
+
+    fn decompress_into(compressed: &Self::CompressedData, out: &mut Self::Data) {
+        out.clear();
+        for frame in &compressed.frames {
+            A::decompress_bytes(frame, out);
+        }
+    }
+
+    fn compressed_size(compressed: &Self::CompressedData) -> usize {
+        std::mem::size_of_val(compressed)
+            + compressed
+                .frames
+                .iter()
+                .map(|frame| std::mem::size_of_val(frame) + frame.len())
+                .sum::<usize>()
+    }
+}
+
+impl<A> SeekableCompression for FramedBytesCompression<A>
+where
+    A: BytesCompression,
+{
+    fn decompress_range(compressed: &Self::CompressedData, range: Range<usize>) -> Vec<u8> {
+        assert!(
+            range.start <= range.end && range.end <= compressed.len,
+            "Range {:?} out of bounds for length {}",
+            range,
+            compressed.len
+        );
+        if range.start == range.end {
+            return Vec::new();
+        }
+
+        let first_frame = range.start / compressed.frame_size;
+        let last_frame = (range.end - 1) / compressed.frame_size;
+        let mut bytes = Vec::with_capacity((last_frame + 1 - first_frame) * compressed.frame_size);
+        for frame in &compressed.frames[first_frame..=last_frame] {
+            A::decompress_bytes(frame, &mut bytes);
+        }
+
+        let offset = first_frame * compressed.frame_size;
+        bytes.truncate(range.end - offset);
+        bytes.drain(..range.start - offset);
+
+        bytes
+    }
+}
+
+// ████████╗███████╗███████╗████████╗███████╗
+// ╚══██╔══╝██╔════╝██╔════╝╚══██╔══╝██╔════╝
+//    ██║   █████╗  ███████╗   ██║   ███████╗
+//    ██║   ██╔══╝  ╚════██║   ██║   ╚════██║
+//    ██║   ███████╗███████║   ██║   ███████║
+//    ╚═╝   ╚══════╝╚══════╝   ╚═╝   ╚══════╝
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Rle;
+
+    #[test]
+    fn any_range_decompresses_like_a_slice() {
+        let compression = FramedBytesCompression::new(4, Rle { element_size: 1 });
+        let bytes: Vec<u8> = (0..11).map(|i| i / 3).collect();
+        let compressed = compression.compress(&bytes);
+        assert_eq!(compressed.decompress(), bytes);
+
+        for start in 0..=bytes.len() {
+            for end in start..=bytes.len() {
+                assert_eq!(
+                    FramedBytesCompression::<Rle>::decompress_range(
+                        &compressed.compressed_data,
+                        start..end
+                    ),
+                    &bytes[start..end]
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn corrupt_frames_are_errors() {
+        let compression = FramedBytesCompression::new(4, Rle { element_size: 1 });
+        let bytes: Vec<u8> = (0..11).collect();
+        let mut compressed = compression.compress(&bytes).take();
+        compressed.frames[1].pop();
+        assert!(FramedBytesCompression::<Rle>::try_decompress(&compressed).is_err());
+
+        compressed.frames.pop();
+        compressed.frames[1] = compression.compress(&bytes[4..8].to_vec()).take().frames[0].clone();
+        assert!(FramedBytesCompression::<Rle>::try_decompress(&compressed).is_err());
+    }
+
+    #[test]
+    #[should_panic(expected = "Frames must not be empty")]
+    fn empty_frames_are_rejected() {
+        FramedBytesCompression::new(0, Rle { element_size: 1 });
+    }
+}
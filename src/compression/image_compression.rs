@@ -0,0 +1,87 @@
+use super::{Compressed, Compression, CompressionError};
+
+use image::{ImageFormat, RgbaImage};
+use std::io::Cursor;
+
+/// The image formats that `ImageCompression` can encode.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum ImageCodec {
+    /// Lossless and compact, but slow to encode.
+    Png,
+    /// The [Quite OK Image Format](https://qoiformat.org/). Lossless and much faster than PNG, with
+    /// a similar compression ratio on most images.
+    Qoi,
+}
+
+/// Compresses RGBA images with an image codec. Generic byte compressors like LZ4 do poorly on raw
+/// pixel data, so this makes the map much more effective as a texture or thumbnail cache.
+#[derive(Clone, Copy, Debug)]
+pub struct ImageCompression {
+    pub codec: ImageCodec,
+}
+
+impl Compression for ImageCompression {
+    type Data = RgbaImage;
+    type CompressedData = Vec<u8>;
+
+    fn compress(&self, data: &Self::Data) -> Compressed<Self> {
+        let format = match self.codec {
+            ImageCodec::Png => ImageFormat::Png,
+            ImageCodec::Qoi => ImageFormat::Qoi,
+        };
+        let mut encoded = Cursor::new(Vec::new());
+        data.write_to(&mut encoded, format).unwrap();
+
+        Compressed::new(encoded.into_inner())
+    }
+
+    fn decompress(compressed: &Self::CompressedData) -> Self::Data {
+        Self::try_decompress(compressed).unwrap()
+    }
+
+    fn try_decompress(compressed: &Self::CompressedData) -> Result<Self::Data, CompressionError> {
+        // The format is recognized from the header.
+        let image = image::load_from_memory(compressed).map_err(CompressionError::new)?;
+
+        Ok(image.into_rgba8())
+    }
+
+    fn compressed_size(compressed: &Self::CompressedData) -> usize {
+        compressed.len()
+    }
+}
+
+// ████████╗███████╗███████╗████████╗███████╗
+// ╚══██╔══╝██╔════╝██╔════╝╚══██╔══╝██╔════╝
+//    ██║   █████╗  ███████╗   ██║   ███████╗
+//    ██║   ██╔══╝  ╚════██║   ██║   ╚════██║
+//    ██║   ███████╗███████║   ██║   ███████║
+//    ╚═╝   ╚══════╝╚══════╝   ╚═╝   ╚══════╝
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn lossless_round_trip() {
+        let image = RgbaImage::from_fn(64, 32, |x, y| image::Rgba([x as u8, y as u8, 0, 255]));
+
+        for codec in [ImageCodec::Png, ImageCodec::Qoi].iter() {
+            let compressed = ImageCompression { codec: *codec }.compress(&image);
+            assert!(compressed.size() < 64 * 32 * 4);
+            assert_eq!(compressed.decompress(), image);
+        }
+    }
+
+    #[test]
+    fn corrupt_images_are_errors() {
+        let image = RgbaImage::from_fn(8, 8, |x, y| image::Rgba([x as u8, y as u8, 0, 255]));
+        let compression = ImageCompression {
+            codec: ImageCodec::Png,
+        };
+        let mut compressed = compression.compress(&image).take();
+        compressed.truncate(compressed.len() / 2);
+
+        assert!(ImageCompression::try_decompress(&compressed).is_err());
+    }
+}
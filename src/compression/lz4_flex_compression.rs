@@ -0,0 +1,56 @@
+use super::BytesCompression;
+
+use serde::{Deserialize, Serialize};
+
+/// LZ4 in block mode, like `Lz4Block`, but using the pure Rust `lz4_flex` implementation, making it
+/// suitable for use with the WASM target and for cross-compiling. There is only one compression
+/// level.
+#[derive(Clone, Copy, Debug, Default, Serialize, Deserialize)]
+pub struct Lz4Flex;
+
+impl BytesCompression for Lz4Flex {
+    fn compress_bytes(&self, bytes: &[u8], mut compressed_bytes: impl std::io::Write) {
+        compressed_bytes
+            .write_all(&lz4_flex::compress_prepend_size(bytes))
+            .unwrap();
+    }
+
+    fn decompress_bytes(compressed_bytes: &[u8], bytes: &mut impl std::io::Write) {
+        Self::try_decompress_bytes(compressed_bytes, bytes).unwrap();
+    }
+
+    fn try_decompress_bytes(
+        compressed_bytes: &[u8],
+        bytes: &mut impl std::io::Write,
+    ) -> std::io::Result<()> {
+        let decompressed = lz4_flex::decompress_size_prepended(compressed_bytes)
+            .map_err(|e| std::io::Error::new(std::io::ErrorKind::InvalidData, e))?;
+
+        bytes.write_all(&decompressed)
+    }
+}
+
+// ████████╗███████╗███████╗████████╗███████╗
+// ╚══██╔══╝██╔════╝██╔════╝╚══██╔══╝██╔════╝
+//    ██║   █████╗  ███████╗   ██║   ███████╗
+//    ██║   ██╔══╝  ╚════██║   ██║   ╚════██║
+//    ██║   ███████╗███████║   ██║   ███████║
+//    ╚═╝   ╚══════╝╚══════╝   ╚═╝   ╚══════╝
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn compress_and_decompress_serializable_type() {
+        let bytes: Vec<u8> = (0u8..100).cycle().take(1000).collect();
+
+        let mut compressed_bytes = Vec::new();
+        Lz4Flex.compress_bytes(&bytes, &mut compressed_bytes);
+        assert!(compressed_bytes.len() < bytes.len());
+        let mut decompressed_bytes = Vec::new();
+        Lz4Flex::decompress_bytes(&compressed_bytes, &mut decompressed_bytes);
+
+        assert_eq!(bytes, decompressed_bytes);
+    }
+}
@@ -0,0 +1,301 @@
+use super::{Compressed, Compression};
+
+use memmap2::MmapMut;
+use std::collections::BTreeMap;
+use std::fs::{File, OpenOptions};
+use std::io;
+use std::path::Path;
+use std::sync::{Arc, Mutex};
+
+/// A file that holds compressed bytes on behalf of `MmapCompression`. The file is memory-mapped, so
+/// the OS decides which parts of it are resident, and compressed values don't take up heap space.
+///
+/// Space is allocated first-fit from the holes left by removed values before the file is grown.
+/// The file is never shrunk, except when the values at the end are removed.
+pub struct MmapArena {
+    inner: Mutex<ArenaInner>,
+}
+
+struct ArenaInner {
+    file: File,
+    // `None` until something is stored, since an empty file can't be mapped.
+    map: Option<MmapMut>,
+    capacity: usize,
+    end: usize,
+    used: usize,
+    // Holes below `end`, from offset to length.
+    free: BTreeMap<usize, usize>,
+}
+
+impl MmapArena {
+    /// Creates the file at `path`, truncating it if it exists. The file must not be modified by
+    /// anyone else while the arena exists.
+    pub fn create(path: impl AsRef<Path>) -> io::Result<Arc<Self>> {
+        let file = OpenOptions::new()
+            .read(true)
+            .write(true)
+            .create(true)
+            .truncate(true)
+            .open(path)?;
+
+        Ok(Arc::new(Self {
+            inner: Mutex::new(ArenaInner {
+                file,
+                map: None,
+                capacity: 0,
+                end: 0,
+                used: 0,
+                free: BTreeMap::new(),
+            }),
+        }))
+    }
+
+    /// The number of bytes held by live values.
+    pub fn bytes_used(&self) -> usize {
+        self.inner.lock().unwrap().used
+    }
+
+    /// The size of the file.
+    pub fn capacity(&self) -> usize {
+        self.inner.lock().unwrap().capacity
+    }
+
+    fn store(self: &Arc<Self>, bytes: &[u8]) -> MmapBlob {
+        let offset = if bytes.is_empty() {
+            0
+        } else {
+            self.inner.lock().unwrap().store(bytes)
+        };
+
+        MmapBlob {
+            arena: self.clone(),
+            offset,
+            len: bytes.len(),
+        }
+    }
+
+    fn load(&self, offset: usize, len: usize) -> Vec<u8> {
+        if len == 0 {
+            return Vec::new();
+        }
+        let inner = self.inner.lock().unwrap();
+
+        inner.map.as_ref().unwrap()[offset..offset + len].to_vec()
+    }
+}
+
+impl ArenaInner {
+    fn store(&mut self, bytes: &[u8]) -> usize {
+        let offset = self.allocate(bytes.len());
+        self.map.as_mut().unwrap()[offset..offset + bytes.len()].copy_from_slice(bytes);
+        self.used += bytes.len();
+
+        offset
+    }
+
+    fn allocate(&mut self, len: usize) -> usize {
+        let hole = self
+            .free
+            .iter()
+            .find(|(_, hole_len)| **hole_len >= len)
+            .map(|(offset, hole_len)| (*offset, *hole_len));
+        if let Some((offset, hole_len)) = hole {
+            self.free.remove(&offset);
+            if hole_len > len {
+                self.free.insert(offset + len, hole_len - len);
+            }
+
+            return offset;
+        }
+
+        let offset = self.end;
+        self.end += len;
+        if self.end > self.capacity {
+            self.grow(self.end.max(2 * self.capacity).max(4096));
+        }
+
+        offset
+    }
+
+    fn grow(&mut self, capacity: usize) {
+        self.file
+            .set_len(capacity as u64)
+            .expect("Failed to grow the arena file");
+        // SAFETY: The file is owned by the arena, and the caller of `MmapArena::create` promised
+        // not to modify it.
+        let map = unsafe { MmapMut::map_mut(&self.file) }.expect("Failed to map the arena file");
+        self.map = Some(map);
+        self.capacity = capacity;
+    }
+
+    fn free(&mut self, mut offset: usize, mut len: usize) {
+        self.used -= len;
+
+        if let Some((&prev_offset, &prev_len)) = self.free.range(..offset).next_back() {
+            if prev_offset + prev_len == offset {
+                self.free.remove(&prev_offset);
+                offset = prev_offset;
+                len += prev_len;
+            }
+        }
+        if let Some(next_len) = self.free.remove(&(offset + len)) {
+            len += next_len;
+        }
+
+        if offset + len == self.end {
+            self.end = offset;
+        } else {
+            self.free.insert(offset, len);
+        }
+    }
+}
+
+/// Compressed bytes in an `MmapArena`. The space is freed when the blob is dropped.
+pub struct MmapBlob {
+    arena: Arc<MmapArena>,
+    offset: usize,
+    len: usize,
+}
+
+impl MmapBlob {
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// Copies the bytes out of the arena.
+    pub fn to_vec(&self) -> Vec<u8> {
+        self.arena.load(self.offset, self.len)
+    }
+}
+
+impl Clone for MmapBlob {
+    fn clone(&self) -> Self {
+        self.arena.store(&self.to_vec())
+    }
+}
+
+impl Drop for MmapBlob {
+    fn drop(&mut self) {
+        if self.len > 0 {
+            self.arena.inner.lock().unwrap().free(self.offset, self.len);
+        }
+    }
+}
+
+impl std::fmt::Debug for MmapBlob {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("MmapBlob")
+            .field("offset", &self.offset)
+            .field("len", &self.len)
+            .finish()
+    }
+}
+
+/// Compresses with `compression`, then moves the compressed bytes into a memory-mapped `arena`, so
+/// the OS can page compressed data in and out instead of keeping it all on the heap. Decompressing
+/// copies the bytes back out before decompressing them with `compression`.
+#[derive(Clone)]
+pub struct MmapCompression<A> {
+    pub compression: A,
+    pub arena: Arc<MmapArena>,
+}
+
+impl<A> Compression for MmapCompression<A>
+where
+    A: Compression<CompressedData = Vec<u8>>,
+{
+    type Data = A::Data;
+    type CompressedData = MmapBlob;
+
+    fn compress(&self, data: &Self::Data) -> Compressed<Self> {
+        let bytes = self.compression.compress(data).take();
+
+        Compressed::new(self.arena.store(&bytes))
+    }
+
+    fn decompress(compressed: &Self::CompressedData) -> Self::Data {
+        A::decompress(&compressed.to_vec())
+    }
+
+    fn decompress_into(compressed: &Self::CompressedData, out: &mut Self::Data) {
+        A::decompress_into(&compressed.to_vec(), out)
+    }
+
+    /// Only counts the heap, not the arena.
+    fn compressed_size(compressed: &Self::CompressedData) -> usize {
+        std::mem::size_of_val(compressed)
+    }
+}
+
+// ████████╗███████╗███████╗████████╗███████╗
+// ╚══██╔══╝██╔════╝██╔════╝╚══██╔══╝██╔════╝
+//    ██║   █████╗  ███████╗   ██║   ███████╗
+//    ██║   ██╔══╝  ╚════██║   ██║   ╚════██║
+//    ██║   ███████╗███████║   ██║   ███████║
+//    ╚═╝   ╚══════╝╚══════╝   ╚═╝   ╚══════╝
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{BytesCompression, CompressibleMap, Rle};
+
+    struct RleBytes;
+
+    impl Compression for RleBytes {
+        type Data = Vec<u8>;
+        type CompressedData = Vec<u8>;
+
+        fn compress(&self, data: &Self::Data) -> Compressed<Self> {
+            let mut compressed = Vec::new();
+            Rle { element_size: 1 }.compress_bytes(data, &mut compressed);
+
+            Compressed::new(compressed)
+        }
+
+        fn decompress(compressed: &Self::CompressedData) -> Self::Data {
+            let mut data = Vec::new();
+            Rle::decompress_bytes(compressed, &mut data);
+
+            data
+        }
+    }
+
+    #[test]
+    fn compressed_values_live_in_the_arena() {
+        let path = std::env::temp_dir().join(format!("mmap-arena-{}", std::process::id()));
+        let arena = MmapArena::create(&path).unwrap();
+        let mut map = CompressibleMap::<_, _, _>::new(MmapCompression {
+            compression: RleBytes,
+            arena: arena.clone(),
+        });
+
+        let value = |i: u8| vec![i; 100];
+        for i in 0..4 {
+            map.insert(i, value(i));
+        }
+        for _ in 0..4 {
+            map.compress_lru();
+        }
+        let bytes_used = arena.bytes_used();
+        assert!(bytes_used > 0);
+
+        // Removing a value leaves a hole that's reused by the next one.
+        map.remove(&1);
+        assert!(arena.bytes_used() < bytes_used);
+        map.insert(4, value(4));
+        map.compress_lru();
+        assert_eq!(arena.bytes_used(), bytes_used);
+
+        for i in [0, 2, 3, 4] {
+            assert_eq!(map.get(i), Some(&value(i)));
+        }
+        map.clear();
+        assert_eq!(arena.bytes_used(), 0);
+
+        std::fs::remove_file(path).unwrap();
+    }
+}
@@ -0,0 +1,104 @@
+use super::{BytesCompression, Compressed, Compression, LossyCompression};
+
+use serde::{Deserialize, Serialize};
+
+/// Rounds each `f32` to a multiple of `2 * tolerance` before compressing the quantized integers
+/// with `compression`. Smooth data like heightfields or normals quantizes to small, repetitive
+/// integers that compress much better than raw floats, at the cost of an error of up to `tolerance`
+/// per value.
+#[derive(Clone, Copy, Debug)]
+pub struct QuantizedF32Compression<A> {
+    pub tolerance: f32,
+    pub compression: A,
+}
+
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct QuantizedF32s {
+    step: f32,
+    compressed_bytes: Vec<u8>,
+}
+
+impl<A> Compression for QuantizedF32Compression<A>
+where
+    A: BytesCompression,
+{
+    type Data = Vec<f32>;
+    type CompressedData = QuantizedF32s;
+
+    fn compress(&self, data: &Self::Data) -> Compressed<Self> {
+        assert!(self.tolerance > 0.0, "Tolerance must be positive");
+
+        let step = 2.0 * self.tolerance;
+        let mut bytes = Vec::with_capacity(4 * data.len());
+        for x in data.iter() {
+            let quantized = (x / step).round() as i32;
+            bytes.extend_from_slice(&quantized.to_le_bytes());
+        }
+        let mut compressed_bytes = Vec::new();
+        self.compression
+            .compress_bytes(&bytes, &mut compressed_bytes);
+
+        Compressed::new(QuantizedF32s {
+            step,
+            compressed_bytes,
+        })
+    }
+
+    fn decompress(compressed: &Self::CompressedData) -> Self::Data {
+        let mut bytes = Vec::new();
+        A::decompress_bytes(&compressed.compressed_bytes, &mut bytes);
+
+        bytes
+            .chunks_exact(4)
+            .map(|b| i32::from_le_bytes([b[0], b[1], b[2], b[3]]) as f32 * compressed.step)
+            .collect()
+    }
+
+    fn compressed_size(compressed: &Self::CompressedData) -> usize {
+        std::mem::size_of_val(compressed) + compressed.compressed_bytes.len()
+    }
+}
+
+impl<A> LossyCompression for QuantizedF32Compression<A>
+where
+    A: BytesCompression + Clone,
+{
+    fn tolerance(&self) -> f32 {
+        self.tolerance
+    }
+
+    fn with_tolerance(&self, tolerance: f32) -> Self {
+        Self {
+            tolerance,
+            compression: self.compression.clone(),
+        }
+    }
+}
+
+// ████████╗███████╗███████╗████████╗███████╗
+// ╚══██╔══╝██╔════╝██╔════╝╚══██╔══╝██╔════╝
+//    ██║   █████╗  ███████╗   ██║   ███████╗
+//    ██║   ██╔══╝  ╚════██║   ██║   ╚════██║
+//    ██║   ███████╗███████║   ██║   ███████║
+//    ╚═╝   ╚══════╝╚══════╝   ╚═╝   ╚══════╝
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Rle;
+
+    #[test]
+    fn error_is_within_tolerance() {
+        let compression = QuantizedF32Compression {
+            tolerance: 0.01,
+            compression: Rle { element_size: 4 },
+        };
+        let data: Vec<f32> = (0..100).map(|i| (i as f32 / 10.0).sin() * 50.0).collect();
+        let decompressed = compression.compress(&data).decompress();
+
+        assert_eq!(decompressed.len(), data.len());
+        for (x, y) in data.iter().zip(decompressed.iter()) {
+            assert!((x - y).abs() <= 0.01 * 1.001, "{} != {}", x, y);
+        }
+    }
+}
@@ -0,0 +1,77 @@
+use super::{BytesCompression, Compressed, Compression, CompressionError};
+
+/// Run some compression algorithm `A` directly on values that are already bytes, like `Vec<u8>` or
+/// `Box<[u8]>`. Unlike `BincodeCompression`, there's no serialization round trip.
+#[derive(Clone, Copy)]
+pub struct RawBytesCompression<V, A> {
+    pub compression: A,
+    marker: std::marker::PhantomData<V>,
+}
+
+impl<V, A> RawBytesCompression<V, A> {
+    pub fn new(compression: A) -> Self {
+        Self {
+            compression,
+            marker: Default::default(),
+        }
+    }
+}
+
+impl<V, A> Compression for RawBytesCompression<V, A>
+where
+    V: AsRef<[u8]> + From<Vec<u8>>,
+    A: BytesCompression,
+{
+    type Data = V;
+    type CompressedData = Vec<u8>;
+
+    fn compress(&self, data: &Self::Data) -> Compressed<Self> {
+        let mut compressed_bytes = Vec::new();
+        self.compression
+            .compress_bytes(data.as_ref(), &mut compressed_bytes);
+
+        Compressed::new(compressed_bytes)
+    }
+
+    fn decompress(compressed: &Self::CompressedData) -> Self::Data {
+        Self::try_decompress(compressed).unwrap()
+    }
+
+    fn try_decompress(compressed: &Self::CompressedData) -> Result<Self::Data, CompressionError> {
+        let mut decompressed_bytes = Vec::new();
+        A::try_decompress_bytes(compressed, &mut decompressed_bytes)
+            .map_err(CompressionError::new)?;
+
+        Ok(V::from(decompressed_bytes))
+    }
+
+    fn compressed_size(compressed: &Self::CompressedData) -> usize {
+        compressed.len()
+    }
+}
+
+// ████████╗███████╗███████╗████████╗███████╗
+// ╚══██╔══╝██╔════╝██╔════╝╚══██╔══╝██╔════╝
+//    ██║   █████╗  ███████╗   ██║   ███████╗
+//    ██║   ██╔══╝  ╚════██║   ██║   ╚════██║
+//    ██║   ███████╗███████║   ██║   ███████║
+//    ╚═╝   ╚══════╝╚══════╝   ╚═╝   ╚══════╝
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{CompressibleMap, Rle};
+
+    #[test]
+    fn byte_values_round_trip_without_serialization() {
+        let compression = RawBytesCompression::<Box<[u8]>, _>::new(Rle { element_size: 1 });
+        let mut map = CompressibleMap::<_, _, _>::new(compression);
+        let value: Box<[u8]> = vec![7; 100].into();
+        map.insert(1, value.clone());
+        map.compress_lru();
+
+        let compressed_size = map.iter_metadata().next().unwrap().compressed_size;
+        assert!(compressed_size.unwrap() < 100);
+        assert_eq!(map.get(1), Some(&value));
+    }
+}
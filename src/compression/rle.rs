@@ -0,0 +1,148 @@
+use super::BytesCompression;
+
+use serde::{Deserialize, Serialize};
+
+/// [Run-length encoding](https://en.wikipedia.org/wiki/Run-length_encoding) of fixed-size elements.
+/// Very fast, and very effective on data with long runs of equal elements, like the material IDs
+/// of mostly homogeneous voxel chunks, but it can double the size of noisy data.
+///
+/// The element size is stored in the compressed bytes, so decompression doesn't need to know it.
+#[derive(Clone, Copy, Debug, Serialize, Deserialize)]
+pub struct Rle {
+    /// The number of bytes in one element. The length of the input must be a multiple of this, and
+    /// it can only be 0 if the input is empty.
+    pub element_size: u16,
+}
+
+impl BytesCompression for Rle {
+    fn compress_bytes(&self, bytes: &[u8], mut compressed_bytes: impl std::io::Write) {
+        compressed_bytes
+            .write_all(&self.element_size.to_le_bytes())
+            .unwrap();
+        if bytes.is_empty() {
+            return;
+        }
+
+        let element_size = self.element_size as usize;
+        assert!(element_size > 0, "Elements must not be empty");
+        assert_eq!(bytes.len() % element_size, 0);
+
+        let mut elements = bytes.chunks_exact(element_size).peekable();
+        while let Some(element) = elements.next() {
+            let mut run_length = 1u8;
+            while run_length < u8::MAX && elements.peek() == Some(&element) {
+                elements.next();
+                run_length += 1;
+            }
+            compressed_bytes.write_all(&[run_length]).unwrap();
+            compressed_bytes.write_all(element).unwrap();
+        }
+    }
+
+    fn decompress_bytes(compressed_bytes: &[u8], bytes: &mut impl std::io::Write) {
+        Self::try_decompress_bytes(compressed_bytes, bytes).unwrap();
+    }
+
+    fn try_decompress_bytes(
+        compressed_bytes: &[u8],
+        bytes: &mut impl std::io::Write,
+    ) -> std::io::Result<()> {
+        if compressed_bytes.len() < 2 {
+            return Err(invalid_data("missing element size"));
+        }
+        let (element_size, runs) = compressed_bytes.split_at(2);
+        let element_size = u16::from_le_bytes([element_size[0], element_size[1]]) as usize;
+        if element_size == 0 && !runs.is_empty() {
+            return Err(invalid_data("runs of empty elements"));
+        }
+
+        let runs = runs.chunks_exact(1 + element_size);
+        if !runs.remainder().is_empty() {
+            return Err(invalid_data("truncated run"));
+        }
+        for run in runs {
+            let (run_length, element) = run.split_first().unwrap();
+            for _ in 0..*run_length {
+                bytes.write_all(element)?;
+            }
+        }
+
+        Ok(())
+    }
+}
+
+fn invalid_data(message: &str) -> std::io::Error {
+    std::io::Error::new(std::io::ErrorKind::InvalidData, message)
+}
+
+// ████████╗███████╗███████╗████████╗███████╗
+// ╚══██╔══╝██╔════╝██╔════╝╚══██╔══╝██╔════╝
+//    ██║   █████╗  ███████╗   ██║   ███████╗
+//    ██║   ██╔══╝  ╚════██║   ██║   ╚════██║
+//    ██║   ███████╗███████║   ██║   ███████║
+//    ╚═╝   ╚══════╝╚══════╝   ╚═╝   ╚══════╝
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn compress_and_decompress_runs() {
+        let mut bytes = vec![0u8; 1000];
+        bytes.extend((0u8..100).collect::<Vec<_>>());
+
+        let mut compressed_bytes = Vec::new();
+        Rle { element_size: 1 }.compress_bytes(&bytes, &mut compressed_bytes);
+        let mut decompressed_bytes = Vec::new();
+        Rle::decompress_bytes(&compressed_bytes, &mut decompressed_bytes);
+
+        assert_eq!(bytes, decompressed_bytes);
+        assert!(compressed_bytes.len() < bytes.len() / 2);
+    }
+
+    #[test]
+    fn compress_and_decompress_wide_elements() {
+        let bytes = 300u16.to_le_bytes().repeat(100);
+
+        let mut compressed_bytes = Vec::new();
+        Rle { element_size: 2 }.compress_bytes(&bytes, &mut compressed_bytes);
+        let mut decompressed_bytes = Vec::new();
+        Rle::decompress_bytes(&compressed_bytes, &mut decompressed_bytes);
+
+        assert_eq!(bytes, decompressed_bytes);
+        assert_eq!(compressed_bytes.len(), 2 + 3);
+    }
+
+    #[test]
+    fn compress_and_decompress_empty_and_large_elements() {
+        for element_size in [0, 1, 300] {
+            let mut compressed_bytes = Vec::new();
+            Rle { element_size }.compress_bytes(&[], &mut compressed_bytes);
+            let mut decompressed_bytes = Vec::new();
+            Rle::decompress_bytes(&compressed_bytes, &mut decompressed_bytes);
+            assert!(decompressed_bytes.is_empty());
+        }
+
+        let bytes: Vec<u8> = (0..300).map(|i| i as u8).collect::<Vec<_>>().repeat(3);
+        let mut compressed_bytes = Vec::new();
+        Rle { element_size: 300 }.compress_bytes(&bytes, &mut compressed_bytes);
+        let mut decompressed_bytes = Vec::new();
+        Rle::decompress_bytes(&compressed_bytes, &mut decompressed_bytes);
+        assert_eq!(bytes, decompressed_bytes);
+        assert_eq!(compressed_bytes.len(), 2 + 1 + 300);
+    }
+
+    #[test]
+    fn truncated_runs_are_errors() {
+        let mut compressed_bytes = Vec::new();
+        Rle { element_size: 2 }.compress_bytes(&[1, 2, 1, 2, 3, 4], &mut compressed_bytes);
+
+        for len in [0, 1, compressed_bytes.len() - 1] {
+            let mut decompressed_bytes = Vec::new();
+            assert!(
+                Rle::try_decompress_bytes(&compressed_bytes[..len], &mut decompressed_bytes)
+                    .is_err()
+            );
+        }
+    }
+}
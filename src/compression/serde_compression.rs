@@ -0,0 +1,67 @@
+use super::{BytesCompression, Compressed, Compression, CompressionError};
+
+use serde::{de::DeserializeOwned, Serialize};
+
+/// A serde data format that `SerdeCompression` can serialize values into before compressing them.
+pub trait SerFormat {
+    fn serialize<T: Serialize>(value: &T, bytes: &mut Vec<u8>) -> Result<(), CompressionError>;
+    fn deserialize<T: DeserializeOwned>(bytes: &[u8]) -> Result<T, CompressionError>;
+}
+
+/// Run some compression algorithm `A` after serializing a type `T` with the format `F`. This
+/// provides a decent default compression for any serializable type, and any format can be paired
+/// with any `BytesCompression`.
+#[derive(Clone, Copy)]
+pub struct SerdeCompression<T, F, A> {
+    pub compression: A,
+    marker: std::marker::PhantomData<(T, F)>,
+}
+
+impl<T, F, A> SerdeCompression<T, F, A> {
+    pub fn new(compression: A) -> Self {
+        Self {
+            compression,
+            marker: Default::default(),
+        }
+    }
+}
+
+impl<T, F, A> Compression for SerdeCompression<T, F, A>
+where
+    T: DeserializeOwned + Serialize,
+    F: SerFormat,
+    A: BytesCompression,
+{
+    type Data = T;
+    type CompressedData = Vec<u8>;
+
+    fn compress(&self, data: &Self::Data) -> Compressed<Self> {
+        self.try_compress(data).unwrap()
+    }
+
+    fn decompress(compressed: &Self::CompressedData) -> Self::Data {
+        Self::try_decompress(compressed).unwrap()
+    }
+
+    fn try_compress(&self, data: &Self::Data) -> Result<Compressed<Self>, CompressionError> {
+        let mut serialized = Vec::new();
+        F::serialize(data, &mut serialized)?;
+        let mut compressed_bytes = Vec::new();
+        self.compression
+            .compress_bytes(&serialized, &mut compressed_bytes);
+
+        Ok(Compressed::new(compressed_bytes))
+    }
+
+    fn try_decompress(compressed: &Self::CompressedData) -> Result<Self::Data, CompressionError> {
+        let mut decompressed_bytes = Vec::new();
+        A::try_decompress_bytes(compressed, &mut decompressed_bytes)
+            .map_err(CompressionError::new)?;
+
+        F::deserialize(&decompressed_bytes)
+    }
+
+    fn compressed_size(compressed: &Self::CompressedData) -> usize {
+        compressed.len()
+    }
+}
@@ -0,0 +1,51 @@
+use super::{Compressed, Compression, CompressionError};
+
+use alloc::sync::Arc;
+use serde::{Deserialize, Serialize};
+
+/// Keeps the compressed data of `A` behind an `Arc`, so cloning a compressed value, e.g. for
+/// `CompressibleMap::snapshot`, shares it instead of copying it.
+#[derive(Clone, Copy, Debug, Default, Deserialize, Serialize)]
+pub struct SharedBlobCompression<A> {
+    pub compression: A,
+}
+
+impl<A> SharedBlobCompression<A> {
+    pub fn new(compression: A) -> Self {
+        Self { compression }
+    }
+}
+
+impl<A> Compression for SharedBlobCompression<A>
+where
+    A: Compression,
+{
+    type Data = A::Data;
+    type CompressedData = Arc<A::CompressedData>;
+
+    fn compress(&self, data: &Self::Data) -> Compressed<Self> {
+        Compressed::new(Arc::new(self.compression.compress(data).take()))
+    }
+
+    fn decompress(compressed: &Self::CompressedData) -> Self::Data {
+        A::decompress(compressed)
+    }
+
+    fn try_compress(&self, data: &Self::Data) -> Result<Compressed<Self>, CompressionError> {
+        let compressed = self.compression.try_compress(data)?;
+
+        Ok(Compressed::new(Arc::new(compressed.take())))
+    }
+
+    fn try_decompress(compressed: &Self::CompressedData) -> Result<Self::Data, CompressionError> {
+        A::try_decompress(compressed)
+    }
+
+    fn decompress_into(compressed: &Self::CompressedData, out: &mut Self::Data) {
+        A::decompress_into(compressed, out)
+    }
+
+    fn compressed_size(compressed: &Self::CompressedData) -> usize {
+        A::compressed_size(compressed)
+    }
+}
@@ -0,0 +1,239 @@
+use super::{Compressed, Compression, CompressionError};
+
+use std::io;
+use std::path::Path;
+use std::sync::Arc;
+
+/// A [sled](https://github.com/spacejam/sled) tree that holds compressed bytes on behalf of
+/// `SledCompression`, so the compressed tier of a map lives on disk and only the pages sled keeps
+/// in its own cache take up memory.
+///
+/// `CompressedStorage` has to lend out references to compressed values, which an embedded store
+/// can't do, so the map keeps a small `SledBlob` handle for each compressed value instead.
+pub struct SledStore {
+    db: sled::Db,
+    tree: sled::Tree,
+}
+
+impl SledStore {
+    /// Opens a temporary database at `path`, which is deleted when the store is dropped. The blobs
+    /// don't outlive the map, so there's no reason to keep them.
+    pub fn open(path: impl AsRef<Path>) -> io::Result<Arc<Self>> {
+        let db = sled::Config::new()
+            .path(path)
+            .temporary(true)
+            .open()
+            .map_err(io::Error::other)?;
+
+        Self::with_tree(db, "compressed")
+    }
+
+    /// Stores blobs in the tree called `name` of an existing database. Blobs are keyed by IDs from
+    /// `sled::Db::generate_id`, so whatever is already in the tree is left alone.
+    pub fn with_tree(db: sled::Db, name: &str) -> io::Result<Arc<Self>> {
+        let tree = db.open_tree(name).map_err(io::Error::other)?;
+
+        Ok(Arc::new(Self { db, tree }))
+    }
+
+    /// Like `with_tree`, but clears the tree first, e.g. to delete the blobs left behind by a
+    /// process that didn't drop its map.
+    pub fn with_cleared_tree(db: sled::Db, name: &str) -> io::Result<Arc<Self>> {
+        let store = Self::with_tree(db, name)?;
+        store.tree.clear().map_err(io::Error::other)?;
+
+        Ok(store)
+    }
+
+    /// The number of entries in the tree, including any that aren't blobs.
+    pub fn len(&self) -> usize {
+        self.tree.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.tree.is_empty()
+    }
+
+    fn store(self: &Arc<Self>, bytes: &[u8]) -> io::Result<SledBlob> {
+        let id = self
+            .db
+            .generate_id()
+            .map_err(io::Error::other)?
+            .to_be_bytes();
+        self.tree.insert(id, bytes).map_err(io::Error::other)?;
+
+        Ok(SledBlob(Arc::new(BlobId {
+            store: self.clone(),
+            id,
+            len: bytes.len(),
+        })))
+    }
+
+    fn load(&self, id: &[u8; 8]) -> io::Result<Vec<u8>> {
+        match self.tree.get(id).map_err(io::Error::other)? {
+            Some(bytes) => Ok(bytes.to_vec()),
+            None => Err(io::Error::new(
+                io::ErrorKind::NotFound,
+                "compressed value missing from sled",
+            )),
+        }
+    }
+}
+
+/// Compressed bytes in a `SledStore`. Clones share the same bytes, which are removed from the store
+/// when the last clone is dropped.
+#[derive(Clone)]
+pub struct SledBlob(Arc<BlobId>);
+
+struct BlobId {
+    store: Arc<SledStore>,
+    id: [u8; 8],
+    len: usize,
+}
+
+impl SledBlob {
+    pub fn len(&self) -> usize {
+        self.0.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.0.len == 0
+    }
+
+    /// Reads the bytes from the store.
+    pub fn to_vec(&self) -> io::Result<Vec<u8>> {
+        self.0.store.load(&self.0.id)
+    }
+}
+
+impl Drop for BlobId {
+    fn drop(&mut self) {
+        // A failure here only leaks the bytes until the store is dropped.
+        let _ = self.store.tree.remove(self.id);
+    }
+}
+
+impl std::fmt::Debug for SledBlob {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("SledBlob")
+            .field("id", &u64::from_be_bytes(self.0.id))
+            .field("len", &self.0.len)
+            .finish()
+    }
+}
+
+/// Compresses with `compression`, then writes the compressed bytes to a sled `store`, like
+/// `MmapCompression` does with a memory-mapped file. Decompressing reads the bytes back before
+/// decompressing them with `compression`. Use `try_compress` and `try_decompress` to get sled's
+/// I/O errors instead of panicking.
+#[derive(Clone)]
+pub struct SledCompression<A> {
+    pub compression: A,
+    pub store: Arc<SledStore>,
+}
+
+impl<A> Compression for SledCompression<A>
+where
+    A: Compression<CompressedData = Vec<u8>>,
+{
+    type Data = A::Data;
+    type CompressedData = SledBlob;
+
+    fn compress(&self, data: &Self::Data) -> Compressed<Self> {
+        self.try_compress(data)
+            .expect("Failed to write a compressed value to sled")
+    }
+
+    fn decompress(compressed: &Self::CompressedData) -> Self::Data {
+        Self::try_decompress(compressed).expect("Failed to read a compressed value from sled")
+    }
+
+    fn try_compress(&self, data: &Self::Data) -> Result<Compressed<Self>, CompressionError> {
+        let bytes = self.compression.try_compress(data)?.take();
+        let blob = self.store.store(&bytes).map_err(CompressionError::new)?;
+
+        Ok(Compressed::new(blob))
+    }
+
+    fn try_decompress(compressed: &Self::CompressedData) -> Result<Self::Data, CompressionError> {
+        A::try_decompress(&compressed.to_vec().map_err(CompressionError::new)?)
+    }
+
+    fn decompress_into(compressed: &Self::CompressedData, out: &mut Self::Data) {
+        let bytes = compressed
+            .to_vec()
+            .expect("Failed to read a compressed value from sled");
+        A::decompress_into(&bytes, out)
+    }
+
+    /// Only counts the heap, not the store.
+    fn compressed_size(compressed: &Self::CompressedData) -> usize {
+        std::mem::size_of_val(compressed)
+    }
+}
+
+// ████████╗███████╗███████╗████████╗███████╗
+// ╚══██╔══╝██╔════╝██╔════╝╚══██╔══╝██╔════╝
+//    ██║   █████╗  ███████╗   ██║   ███████╗
+//    ██║   ██╔══╝  ╚════██║   ██║   ╚════██║
+//    ██║   ███████╗███████║   ██║   ███████║
+//    ╚═╝   ╚══════╝╚══════╝   ╚═╝   ╚══════╝
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{CompressibleMap, RawBytesCompression, Rle};
+
+    #[test]
+    fn compressed_values_live_in_sled() {
+        let path = std::env::temp_dir().join(format!("sled-store-{}", std::process::id()));
+        let store = SledStore::open(&path).unwrap();
+        let mut map = CompressibleMap::<_, Vec<u8>, _>::new(SledCompression {
+            compression: RawBytesCompression::new(Rle { element_size: 1 }),
+            store: store.clone(),
+        });
+
+        let value = |i: u8| vec![i; 100];
+        for i in 0..4 {
+            map.insert(i, value(i));
+        }
+        for _ in 0..4 {
+            map.compress_lru();
+        }
+        assert_eq!(store.len(), 4);
+
+        map.remove(&1);
+        assert_eq!(store.len(), 3);
+
+        for i in [0, 2, 3] {
+            assert_eq!(map.get(i), Some(&value(i)));
+        }
+        map.clear();
+        assert!(store.is_empty());
+    }
+
+    #[test]
+    fn existing_trees_are_kept_and_io_errors_are_returned() {
+        let db = sled::Config::new().temporary(true).open().unwrap();
+        let tree = db.open_tree("blobs").unwrap();
+        tree.insert("other", "data").unwrap();
+        let store = SledStore::with_tree(db.clone(), "blobs").unwrap();
+        let compression = SledCompression {
+            compression: RawBytesCompression::new(Rle { element_size: 1 }),
+            store: store.clone(),
+        };
+
+        let compressed = compression.try_compress(&vec![7; 100]).unwrap();
+        let copy = compressed.clone();
+        drop(compressed);
+        assert_eq!(store.len(), 2);
+        assert_eq!(copy.try_decompress().unwrap(), vec![7; 100]);
+
+        tree.clear().unwrap();
+        assert!(copy.try_decompress().is_err());
+
+        tree.insert("other", "data").unwrap();
+        let store = SledStore::with_cleared_tree(db, "blobs").unwrap();
+        assert!(store.is_empty());
+    }
+}
@@ -15,8 +15,17 @@ impl BytesCompression for Snappy {
     }
 
     fn decompress_bytes(compressed_bytes: &[u8], bytes: &mut impl std::io::Write) {
+        Self::try_decompress_bytes(compressed_bytes, bytes).unwrap();
+    }
+
+    fn try_decompress_bytes(
+        compressed_bytes: &[u8],
+        bytes: &mut impl std::io::Write,
+    ) -> std::io::Result<()> {
         let mut decoder = snap::read::FrameDecoder::new(compressed_bytes);
-        std::io::copy(&mut decoder, bytes).unwrap();
+        std::io::copy(&mut decoder, bytes)?;
+
+        Ok(())
     }
 }
 
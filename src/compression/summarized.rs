@@ -0,0 +1,92 @@
+use super::{Compressed, Compression};
+
+use serde::{Deserialize, Serialize};
+
+/// A value that can be described by a small summary, e.g. a bounding box, an occupancy flag, or a
+/// checksum.
+pub trait Summarize {
+    type Summary;
+
+    fn summarize(&self) -> Self::Summary;
+}
+
+/// Keeps the summary of each value next to its compressed data, so
+/// `CompressibleMap::summary` can answer cheap questions about compressed values without
+/// decompressing them.
+#[derive(Clone, Copy, Debug, Default, Deserialize, Serialize)]
+pub struct SummarizedCompression<A> {
+    pub compression: A,
+}
+
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct Summarized<S, C> {
+    pub summary: S,
+    pub compressed: C,
+}
+
+impl<A> Compression for SummarizedCompression<A>
+where
+    A: Compression,
+    A::Data: Summarize,
+{
+    type Data = A::Data;
+    type CompressedData = Summarized<<A::Data as Summarize>::Summary, A::CompressedData>;
+
+    fn compress(&self, data: &Self::Data) -> Compressed<Self> {
+        Compressed::new(Summarized {
+            summary: data.summarize(),
+            compressed: self.compression.compress(data).take(),
+        })
+    }
+
+    fn decompress(compressed: &Self::CompressedData) -> Self::Data {
+        A::decompress(&compressed.compressed)
+    }
+
+    fn decompress_into(compressed: &Self::CompressedData, out: &mut Self::Data) {
+        A::decompress_into(&compressed.compressed, out)
+    }
+
+    fn compressed_size(compressed: &Self::CompressedData) -> usize {
+        core::mem::size_of_val(&compressed.summary) + A::compressed_size(&compressed.compressed)
+    }
+}
+
+// ████████╗███████╗███████╗████████╗███████╗
+// ╚══██╔══╝██╔════╝██╔════╝╚══██╔══╝██╔════╝
+//    ██║   █████╗  ███████╗   ██║   ███████╗
+//    ██║   ██╔══╝  ╚════██║   ██║   ╚════██║
+//    ██║   ███████╗███████║   ██║   ███████║
+//    ╚═╝   ╚══════╝╚══════╝   ╚═╝   ╚══════╝
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::{FakeFooCompression, Foo};
+    use crate::CompressibleMap;
+
+    use alloc::borrow::Cow;
+
+    impl Summarize for Foo {
+        type Summary = bool;
+
+        fn summarize(&self) -> bool {
+            self.0.is_multiple_of(2)
+        }
+    }
+
+    #[test]
+    fn summaries_of_cached_and_compressed_values() {
+        let mut map = CompressibleMap::<_, _, _>::new(SummarizedCompression {
+            compression: FakeFooCompression,
+        });
+        map.insert(1, Foo(1));
+        map.insert(2, Foo(2));
+        map.compress_lru();
+
+        assert!(matches!(map.summary(&1), Some(Cow::Borrowed(false))));
+        assert!(matches!(map.summary(&2), Some(Cow::Owned(true))));
+        assert_eq!(map.summary(&3), None);
+        assert_eq!(map.len_cached(), 1);
+    }
+}
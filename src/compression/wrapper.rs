@@ -0,0 +1,123 @@
+use super::{Compressed, Compression};
+
+use alloc::borrow::Cow;
+use alloc::boxed::Box;
+use alloc::rc::Rc;
+use alloc::sync::Arc;
+use core::ops::Deref;
+
+/// A pointer-like type that owns or shares one value, like `Arc<T>`.
+pub trait Wrapper: Deref + Sized
+where
+    Self::Target: Sized,
+{
+    fn wrap(inner: Self::Target) -> Self;
+}
+
+impl<T> Wrapper for Arc<T> {
+    fn wrap(inner: T) -> Self {
+        Arc::new(inner)
+    }
+}
+
+impl<T> Wrapper for Rc<T> {
+    fn wrap(inner: T) -> Self {
+        Rc::new(inner)
+    }
+}
+
+impl<T> Wrapper for Box<T> {
+    fn wrap(inner: T) -> Self {
+        Box::new(inner)
+    }
+}
+
+impl<T: Clone> Wrapper for Cow<'static, T> {
+    fn wrap(inner: T) -> Self {
+        Cow::Owned(inner)
+    }
+}
+
+/// Compresses values of a `Wrapper` type `W`, like `Arc<T>`, with the compression for the wrapped
+/// type, so values that are shared with other systems can be stored without unwrapping them. A
+/// decompressed value gets a new wrapper, so it's no longer shared.
+pub struct WrapperCompression<W, A> {
+    pub compression: A,
+    marker: core::marker::PhantomData<fn() -> W>,
+}
+
+pub type ArcCompression<A> = WrapperCompression<Arc<<A as Compression>::Data>, A>;
+pub type RcCompression<A> = WrapperCompression<Rc<<A as Compression>::Data>, A>;
+pub type BoxCompression<A> = WrapperCompression<Box<<A as Compression>::Data>, A>;
+pub type CowCompression<A> = WrapperCompression<Cow<'static, <A as Compression>::Data>, A>;
+
+impl<W, A> WrapperCompression<W, A> {
+    pub fn new(compression: A) -> Self {
+        Self {
+            compression,
+            marker: Default::default(),
+        }
+    }
+}
+
+impl<W, A: Clone> Clone for WrapperCompression<W, A> {
+    fn clone(&self) -> Self {
+        Self::new(self.compression.clone())
+    }
+}
+
+impl<W, A: Default> Default for WrapperCompression<W, A> {
+    fn default() -> Self {
+        Self::new(A::default())
+    }
+}
+
+impl<W, A> Compression for WrapperCompression<W, A>
+where
+    A: Compression,
+    W: Wrapper<Target = A::Data>,
+{
+    type Data = W;
+    type CompressedData = A::CompressedData;
+
+    fn compress(&self, data: &Self::Data) -> Compressed<Self> {
+        Compressed::new(self.compression.compress(data).take())
+    }
+
+    fn decompress(compressed: &Self::CompressedData) -> Self::Data {
+        W::wrap(A::decompress(compressed))
+    }
+
+    fn compressed_size(compressed: &Self::CompressedData) -> usize {
+        A::compressed_size(compressed)
+    }
+}
+
+// ████████╗███████╗███████╗████████╗███████╗
+// ╚══██╔══╝██╔════╝██╔════╝╚══██╔══╝██╔════╝
+//    ██║   █████╗  ███████╗   ██║   ███████╗
+//    ██║   ██╔══╝  ╚════██║   ██║   ╚════██║
+//    ██║   ███████╗███████║   ██║   ███████║
+//    ╚═╝   ╚══════╝╚══════╝   ╚═╝   ╚══════╝
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::{FakeFooCompression, Foo};
+    use crate::CompressibleMap;
+
+    #[test]
+    fn shared_values_round_trip() {
+        let shared = Arc::new(Foo(1));
+        let mut map = CompressibleMap::<_, _, _>::new(ArcCompression::new(FakeFooCompression));
+        map.insert(1, shared.clone());
+        map.compress_lru();
+        assert_eq!(Arc::strong_count(&shared), 1);
+        assert_eq!(map.get(1).map(|v| &**v), Some(&Foo(3)));
+
+        let mut map = CompressibleMap::<_, _, _>::new(CowCompression::new(FakeFooCompression));
+        map.insert(1, Cow::Owned(Foo(1)));
+        map.compress_lru();
+        assert!(matches!(map.get(1), Some(Cow::Owned(Foo(3)))));
+    }
+}
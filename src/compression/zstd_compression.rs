@@ -0,0 +1,187 @@
+use super::{BytesCompression, Compressed, Compression, LeveledCompression, TrainableCompression};
+
+use serde::{Deserialize, Serialize};
+use std::sync::Arc;
+
+/// The [Zstandard compression algorithm](https://en.wikipedia.org/wiki/Zstd). Slower than LZ4,
+/// but usually gives much better compression ratios.
+#[derive(Clone, Copy, Debug, Serialize, Deserialize)]
+pub struct Zstd {
+    /// The compression level, from 1 to 22. 0 means the default level, which is currently 3.
+    /// Higher levels are slower and more aggressive.
+    pub level: i32,
+}
+
+impl BytesCompression for Zstd {
+    fn compress_bytes(&self, bytes: &[u8], compressed_bytes: impl std::io::Write) {
+        zstd::stream::copy_encode(bytes, compressed_bytes, self.level).unwrap();
+    }
+
+    fn decompress_bytes(compressed_bytes: &[u8], bytes: &mut impl std::io::Write) {
+        Self::try_decompress_bytes(compressed_bytes, bytes).unwrap();
+    }
+
+    fn try_decompress_bytes(
+        compressed_bytes: &[u8],
+        bytes: &mut impl std::io::Write,
+    ) -> std::io::Result<()> {
+        zstd::stream::copy_decode(compressed_bytes, bytes)
+    }
+}
+
+impl LeveledCompression for Zstd {
+    const LEVELS: std::ops::RangeInclusive<i32> = 1..=22;
+
+    fn level(&self) -> i32 {
+        self.level
+    }
+
+    fn with_level(&self, level: i32) -> Self {
+        Self { level }
+    }
+}
+
+/// Zstandard compression of byte vectors against a dictionary shared by all entries, which gives
+/// much better ratios for many small, similar values than compressing each value on its own. Train
+/// the dictionary from the cached values with `CompressibleMap::retrain_compression`; until then,
+/// values are compressed without a dictionary.
+#[derive(Clone, Debug)]
+pub struct ZstdDict {
+    pub level: i32,
+    /// The maximum size of a trained dictionary, in bytes.
+    pub max_dictionary_size: usize,
+    dictionary: Option<Arc<Vec<u8>>>,
+}
+
+impl ZstdDict {
+    pub fn new(level: i32, max_dictionary_size: usize) -> Self {
+        Self {
+            level,
+            max_dictionary_size,
+            dictionary: None,
+        }
+    }
+
+    /// Uses a dictionary that was trained elsewhere, e.g. with `zstd::dict::from_samples`.
+    pub fn with_dictionary(level: i32, dictionary: Vec<u8>) -> Self {
+        Self {
+            level,
+            max_dictionary_size: dictionary.len(),
+            dictionary: Some(Arc::new(dictionary)),
+        }
+    }
+
+    pub fn dictionary(&self) -> Option<&[u8]> {
+        self.dictionary.as_deref().map(Vec::as_slice)
+    }
+}
+
+/// Bytes compressed by `ZstdDict`, along with the dictionary needed to decompress them.
+#[derive(Clone, Debug)]
+pub struct ZstdDictBytes {
+    dictionary: Option<Arc<Vec<u8>>>,
+    bytes: Vec<u8>,
+}
+
+impl Compression for ZstdDict {
+    type Data = Vec<u8>;
+    type CompressedData = ZstdDictBytes;
+
+    fn compress(&self, data: &Self::Data) -> Compressed<Self> {
+        let dictionary = self.dictionary().unwrap_or(&[]);
+        let mut encoder =
+            zstd::stream::Encoder::with_dictionary(Vec::new(), self.level, dictionary).unwrap();
+        std::io::Write::write_all(&mut encoder, data).unwrap();
+
+        Compressed::new(ZstdDictBytes {
+            dictionary: self.dictionary.clone(),
+            bytes: encoder.finish().unwrap(),
+        })
+    }
+
+    fn decompress(compressed: &Self::CompressedData) -> Self::Data {
+        let mut data = Vec::new();
+        Self::decompress_into(compressed, &mut data);
+
+        data
+    }
+
+    fn decompress_into(compressed: &Self::CompressedData, out: &mut Self::Data) {
+        let dictionary = compressed
+            .dictionary
+            .as_deref()
+            .map_or(&[][..], Vec::as_slice);
+        let mut decoder =
+            zstd::stream::Decoder::with_dictionary(compressed.bytes.as_slice(), dictionary)
+                .unwrap();
+        out.clear();
+        std::io::copy(&mut decoder, out).unwrap();
+    }
+
+    /// Doesn't count the dictionary, since it's shared by all values.
+    fn compressed_size(compressed: &Self::CompressedData) -> usize {
+        std::mem::size_of_val(compressed) + compressed.bytes.len()
+    }
+}
+
+impl TrainableCompression for ZstdDict {
+    /// Keeps the current dictionary if training fails, e.g. because there are too few samples.
+    fn train<'a>(&self, samples: impl Iterator<Item = &'a Vec<u8>>) -> Self {
+        let samples: Vec<&[u8]> = samples.map(Vec::as_slice).collect();
+
+        match zstd::dict::from_samples(&samples, self.max_dictionary_size) {
+            Ok(dictionary) => Self {
+                dictionary: Some(Arc::new(dictionary)),
+                ..self.clone()
+            },
+            Err(_) => self.clone(),
+        }
+    }
+}
+
+// ████████╗███████╗███████╗████████╗███████╗
+// ╚══██╔══╝██╔════╝██╔════╝╚══██╔══╝██╔════╝
+//    ██║   █████╗  ███████╗   ██║   ███████╗
+//    ██║   ██╔══╝  ╚════██║   ██║   ╚════██║
+//    ██║   ███████╗███████║   ██║   ███████║
+//    ╚═╝   ╚══════╝╚══════╝   ╚═╝   ╚══════╝
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::CompressibleMap;
+
+    #[test]
+    fn compress_and_decompress_serializable_type() {
+        let bytes: Vec<u8> = (0u8..100).collect();
+
+        let mut compressed_bytes = Vec::new();
+        Zstd { level: 19 }.compress_bytes(&bytes, &mut compressed_bytes);
+        let mut decompressed_bytes = Vec::new();
+        Zstd::decompress_bytes(&compressed_bytes, &mut decompressed_bytes);
+
+        assert_eq!(bytes, decompressed_bytes);
+    }
+
+    #[test]
+    fn train_shared_dictionary_from_cached_values() {
+        let value = |i: u32| format!("{{ id: {}, kind: stone, lit: false }}", i).into_bytes();
+        let untrained = ZstdDict::new(3, 1024);
+
+        let mut map = CompressibleMap::<_, _, _>::new(untrained.clone());
+        for i in 0..500 {
+            map.insert(i, value(i));
+        }
+        map.compress_lru();
+        map.retrain_compression(usize::MAX);
+        assert_eq!(map.recompress_stale(usize::MAX), 1);
+        map.compress_lru();
+
+        let trained = map.compression_params();
+        assert!(trained.dictionary().is_some());
+        assert!(trained.compress(&value(7)).size() < untrained.compress(&value(7)).size());
+        for i in [0, 1, 250, 499] {
+            assert_eq!(map.get(i), Some(&value(i)));
+        }
+    }
+}
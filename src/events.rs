@@ -0,0 +1,65 @@
+#[cfg(feature = "std")]
+use std::sync::mpsc::{channel, Receiver, Sender};
+
+/// A change to the contents of a `CompressibleMap`, as observed by a subscriber.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum MapEvent<K> {
+    /// A value was inserted, either cached or compressed.
+    Inserted(K),
+    /// An entry was removed.
+    Removed(K),
+    /// A cached value was compressed.
+    Compressed(K),
+    /// A compressed value was decompressed into the cache.
+    Decompressed(K),
+    /// All entries were removed.
+    Cleared,
+}
+
+/// The sending halves of all channels created by `subscribe`. Disconnected subscribers are dropped
+/// the next time an event is sent. Without `std` there are no channels, so nobody can subscribe.
+pub struct Subscribers<K> {
+    #[cfg(feature = "std")]
+    senders: Vec<Sender<MapEvent<K>>>,
+    #[cfg(not(feature = "std"))]
+    marker: core::marker::PhantomData<fn(K)>,
+}
+
+impl<K> Default for Subscribers<K> {
+    fn default() -> Self {
+        Self {
+            #[cfg(feature = "std")]
+            senders: Vec::new(),
+            #[cfg(not(feature = "std"))]
+            marker: core::marker::PhantomData,
+        }
+    }
+}
+
+impl<K> Subscribers<K>
+where
+    K: Clone,
+{
+    #[cfg(feature = "std")]
+    pub fn subscribe(&mut self) -> Receiver<MapEvent<K>> {
+        let (tx, rx) = channel();
+        self.senders.push(tx);
+
+        rx
+    }
+
+    /// Sends an event to all subscribers. The event is only constructed if there is someone to
+    /// receive it.
+    #[cfg(feature = "std")]
+    pub fn notify(&mut self, make_event: impl FnOnce() -> MapEvent<K>) {
+        if self.senders.is_empty() {
+            return;
+        }
+
+        let event = make_event();
+        self.senders.retain(|tx| tx.send(event.clone()).is_ok());
+    }
+
+    #[cfg(not(feature = "std"))]
+    pub fn notify(&mut self, _make_event: impl FnOnce() -> MapEvent<K>) {}
+}
@@ -0,0 +1,681 @@
+use crate::DefaultHashBuilder;
+
+use alloc::{boxed::Box, collections::VecDeque, vec::Vec};
+use core::hash::{BuildHasher, Hasher};
+use hashbrown::HashMap;
+
+/// Chooses which cached value is compressed next by `CompressibleMap::compress_lru` and every other
+/// method that compresses the "LRU" value. Without a policy, the map uses plain LRU, which thrashes
+/// under sequential sweeps over more values than fit in the cache.
+///
+/// The cache keeps each value in a numbered slot for as long as it stays cached, and tells the
+/// policy whenever a slot is filled, accessed, or emptied. Slot numbers are small and reused, so
+/// they can index into a `Vec`.
+pub trait EvictionPolicy: CloneEvictionPolicy + Send + Sync {
+    /// A value was cached in `slot`.
+    fn on_insert(&mut self, slot: usize);
+
+    /// The cached value in `slot` was accessed.
+    fn on_access(&mut self, slot: usize);
+
+    /// The value in `slot` left the cache, either because it was evicted or removed.
+    fn on_remove(&mut self, slot: usize);
+
+    /// The value in `slot` was weighed by the map's weigher, after it was cached or modified. Only
+    /// called if the map has a weigher.
+    fn on_weigh(&mut self, _slot: usize, _weight: usize) {}
+
+    /// Whether the policy wants `on_key_hash` to be called, e.g. to recognize values that were
+    /// cached before. Off by default, since it takes hashing every key again.
+    fn wants_key_hashes(&self) -> bool {
+        false
+    }
+
+    /// The key of the value cached in `slot` has this hash. Called after `on_insert`, but only if
+    /// `wants_key_hashes` returns `true`. Different keys can have the same hash.
+    fn on_key_hash(&mut self, _slot: usize, _hash: u64) {}
+
+    /// The slot of the value to evict next, or `None` if nothing is cached.
+    fn victim(&self) -> Option<usize>;
+
+    /// Everything was removed from the cache.
+    fn clear(&mut self);
+}
+
+/// Allows cloning a boxed `EvictionPolicy`. Implemented for every policy that implements `Clone`.
+pub trait CloneEvictionPolicy {
+    fn clone_box(&self) -> Box<dyn EvictionPolicy>;
+}
+
+impl<P: EvictionPolicy + Clone + 'static> CloneEvictionPolicy for P {
+    fn clone_box(&self) -> Box<dyn EvictionPolicy> {
+        Box::new(self.clone())
+    }
+}
+
+impl Clone for Box<dyn EvictionPolicy> {
+    fn clone(&self) -> Self {
+        self.clone_box()
+    }
+}
+
+impl core::fmt::Debug for dyn EvictionPolicy {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        f.write_str("EvictionPolicy")
+    }
+}
+
+const NIL: usize = usize::MAX;
+
+/// A doubly-linked queue of slots with constant-time removal from the middle, for policies that
+/// keep their own order.
+#[derive(Clone, Debug, Default)]
+pub struct SlotQueue {
+    // (prev, next) for each slot, where "prev" is towards the front.
+    links: Vec<Option<(usize, usize)>>,
+    front: Option<usize>,
+    back: Option<usize>,
+    len: usize,
+}
+
+impl SlotQueue {
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    pub fn contains(&self, slot: usize) -> bool {
+        self.links.get(slot).is_some_and(|link| link.is_some())
+    }
+
+    pub fn front(&self) -> Option<usize> {
+        self.front
+    }
+
+    pub fn back(&self) -> Option<usize> {
+        self.back
+    }
+
+    /// The slot in front of `slot`, i.e. the next one to leave after it from the back.
+    pub fn prev(&self, slot: usize) -> Option<usize> {
+        let (prev, _) = self.links.get(slot).copied().flatten()?;
+
+        (prev != NIL).then_some(prev)
+    }
+
+    /// Iterates from the back of the queue to the front.
+    pub fn iter_from_back(&self) -> impl Iterator<Item = usize> + '_ {
+        core::iter::successors(self.back, move |slot| self.prev(*slot))
+    }
+
+    /// Puts `slot` at the front of the queue, moving it if it's already queued.
+    pub fn push_front(&mut self, slot: usize) {
+        self.remove(slot);
+        if self.links.len() <= slot {
+            self.links.resize(slot + 1, None);
+        }
+
+        let next = self.front.unwrap_or(NIL);
+        self.links[slot] = Some((NIL, next));
+        match self.front {
+            Some(front) => self.set_prev(front, slot),
+            None => self.back = Some(slot),
+        }
+        self.front = Some(slot);
+        self.len += 1;
+    }
+
+    /// Takes `slot` out of the queue. Returns `false` if it wasn't queued.
+    pub fn remove(&mut self, slot: usize) -> bool {
+        let (prev, next) = match self.links.get_mut(slot).and_then(|link| link.take()) {
+            Some(link) => link,
+            None => return false,
+        };
+
+        if prev == NIL {
+            self.front = (next != NIL).then_some(next);
+        } else {
+            self.set_next(prev, next);
+        }
+        if next == NIL {
+            self.back = (prev != NIL).then_some(prev);
+        } else {
+            self.set_prev(next, prev);
+        }
+        self.len -= 1;
+
+        true
+    }
+
+    pub fn pop_back(&mut self) -> Option<usize> {
+        let back = self.back?;
+        self.remove(back);
+
+        Some(back)
+    }
+
+    pub fn clear(&mut self) {
+        self.links.clear();
+        self.front = None;
+        self.back = None;
+        self.len = 0;
+    }
+
+    fn set_prev(&mut self, slot: usize, prev: usize) {
+        if let Some(link) = self.links[slot].as_mut() {
+            link.0 = prev;
+        }
+    }
+
+    fn set_next(&mut self, slot: usize, next: usize) {
+        if let Some(link) = self.links[slot].as_mut() {
+            link.1 = next;
+        }
+    }
+}
+
+/// Evicts the least recently used value. This is what the map does without a policy.
+#[derive(Clone, Debug, Default)]
+pub struct LruPolicy {
+    queue: SlotQueue,
+}
+
+impl EvictionPolicy for LruPolicy {
+    fn on_insert(&mut self, slot: usize) {
+        self.queue.push_front(slot);
+    }
+
+    fn on_access(&mut self, slot: usize) {
+        self.queue.push_front(slot);
+    }
+
+    fn on_remove(&mut self, slot: usize) {
+        self.queue.remove(slot);
+    }
+
+    fn victim(&self) -> Option<usize> {
+        self.queue.back()
+    }
+
+    fn clear(&mut self) {
+        self.queue.clear();
+    }
+}
+
+/// Evicts the value that was cached first, regardless of how often it was accessed since.
+#[derive(Clone, Debug, Default)]
+pub struct FifoPolicy {
+    queue: SlotQueue,
+}
+
+impl EvictionPolicy for FifoPolicy {
+    fn on_insert(&mut self, slot: usize) {
+        self.queue.push_front(slot);
+    }
+
+    fn on_access(&mut self, _slot: usize) {}
+
+    fn on_remove(&mut self, slot: usize) {
+        self.queue.remove(slot);
+    }
+
+    fn victim(&self) -> Option<usize> {
+        self.queue.back()
+    }
+
+    fn clear(&mut self) {
+        self.queue.clear();
+    }
+}
+
+/// Approximates LRU like an OS page cache: values are queued in FIFO order, but a value that was
+/// accessed since it was last considered gets a second chance and goes back to the front.
+/// Accesses are cheaper than with `LruPolicy`, since they only set a bit.
+///
+/// The back of the queue is the clock hand, and it always rests on an unreferenced value, so
+/// finding the victim takes constant time. The hand only moves past a value when that value is
+/// evicted or referenced, clearing the bit of each referenced value it passes.
+#[derive(Clone, Debug, Default)]
+pub struct ClockPolicy {
+    queue: SlotQueue,
+    referenced: Vec<bool>,
+}
+
+impl ClockPolicy {
+    /// Gives each referenced value at the hand its second chance, until the hand rests on an
+    /// unreferenced value. Each step clears a bit set by an access, so this takes amortized
+    /// constant time.
+    fn advance_hand(&mut self) {
+        while let Some(back) = self.queue.back().filter(|back| self.referenced[*back]) {
+            self.referenced[back] = false;
+            self.queue.push_front(back);
+        }
+    }
+}
+
+impl EvictionPolicy for ClockPolicy {
+    fn on_insert(&mut self, slot: usize) {
+        if self.referenced.len() <= slot {
+            self.referenced.resize(slot + 1, false);
+        }
+        self.referenced[slot] = false;
+        self.queue.push_front(slot);
+    }
+
+    fn on_access(&mut self, slot: usize) {
+        self.referenced[slot] = true;
+        if self.queue.back() == Some(slot) {
+            self.advance_hand();
+        }
+    }
+
+    fn on_remove(&mut self, slot: usize) {
+        let was_hand = self.queue.back() == Some(slot);
+        self.queue.remove(slot);
+        if was_hand {
+            self.advance_hand();
+        }
+    }
+
+    fn victim(&self) -> Option<usize> {
+        self.queue.back()
+    }
+
+    fn clear(&mut self) {
+        self.queue.clear();
+        self.referenced.clear();
+    }
+}
+
+/// Evicts a value chosen uniformly at random. Immune to access patterns that defeat LRU, like
+/// cyclic sweeps over slightly more values than fit in the cache.
+#[derive(Clone, Debug)]
+pub struct RandomPolicy {
+    slots: Vec<usize>,
+    // The position of each slot in `slots`.
+    positions: Vec<usize>,
+    state: u64,
+}
+
+impl RandomPolicy {
+    /// Uses a fixed `seed`, so the choice of victims is reproducible.
+    pub fn with_seed(seed: u64) -> Self {
+        Self {
+            slots: Vec::new(),
+            positions: Vec::new(),
+            // Xorshift gets stuck at 0.
+            state: seed | 1,
+        }
+    }
+
+    fn advance(&mut self) {
+        self.state ^= self.state << 13;
+        self.state ^= self.state >> 7;
+        self.state ^= self.state << 17;
+    }
+}
+
+impl Default for RandomPolicy {
+    fn default() -> Self {
+        Self::with_seed(DefaultHashBuilder::default().build_hasher().finish())
+    }
+}
+
+impl EvictionPolicy for RandomPolicy {
+    fn on_insert(&mut self, slot: usize) {
+        if self.positions.len() <= slot {
+            self.positions.resize(slot + 1, NIL);
+        }
+        self.positions[slot] = self.slots.len();
+        self.slots.push(slot);
+    }
+
+    fn on_access(&mut self, _slot: usize) {}
+
+    fn on_remove(&mut self, slot: usize) {
+        if self.victim() == Some(slot) {
+            self.advance();
+        }
+        let position = core::mem::replace(&mut self.positions[slot], NIL);
+        self.slots.swap_remove(position);
+        if let Some(moved) = self.slots.get(position) {
+            self.positions[*moved] = position;
+        }
+    }
+
+    fn victim(&self) -> Option<usize> {
+        if self.slots.is_empty() {
+            return None;
+        }
+
+        Some(self.slots[(self.state % self.slots.len() as u64) as usize])
+    }
+
+    fn clear(&mut self) {
+        self.slots.clear();
+        self.positions.clear();
+    }
+}
+
+/// Resists scan pollution with the 2Q algorithm: new values enter a probationary FIFO queue, and
+/// the keys of values evicted from it are remembered in a ghost queue. Only a value that's cached
+/// again while its key is remembered, i.e. that was needed again soon after it was compressed, is
+/// promoted to a protected LRU queue. Accesses to probationary values don't promote them, so a pass
+/// over the whole map, which accesses each value once, can't push out the hot values.
+///
+/// Probationary values are evicted first, as long as they make up more than
+/// `probation_fraction` of the cached values. The ghost queue remembers up to `ghost_fraction`
+/// times the most values that were ever cached at once. Keys are only remembered by their hash, so
+/// on a collision, a new value can be promoted early.
+#[derive(Clone, Debug)]
+pub struct TwoQueuePolicy {
+    pub probation_fraction: f32,
+    pub ghost_fraction: f32,
+    probation: SlotQueue,
+    protected: SlotQueue,
+    // The key hash of each cached value.
+    hashes: Vec<u64>,
+    // The key hashes of values evicted from probation, oldest first, and how many times each one is
+    // in the queue.
+    ghosts: VecDeque<u64>,
+    ghost_counts: HashMap<u64, usize>,
+    max_len: usize,
+}
+
+impl TwoQueuePolicy {
+    pub fn new(probation_fraction: f32, ghost_fraction: f32) -> Self {
+        Self {
+            probation_fraction,
+            ghost_fraction,
+            probation: SlotQueue::default(),
+            protected: SlotQueue::default(),
+            hashes: Vec::new(),
+            ghosts: VecDeque::new(),
+            ghost_counts: HashMap::new(),
+            max_len: 0,
+        }
+    }
+
+    fn len(&self) -> usize {
+        self.probation.len() + self.protected.len()
+    }
+
+    fn remember(&mut self, hash: u64) {
+        self.ghosts.push_back(hash);
+        *self.ghost_counts.entry(hash).or_default() += 1;
+
+        let max_ghosts = (self.max_len as f32 * self.ghost_fraction) as usize;
+        while self.ghosts.len() > max_ghosts {
+            let forgotten = self.ghosts.pop_front().unwrap();
+            if let Some(count) = self.ghost_counts.get_mut(&forgotten) {
+                *count -= 1;
+                if *count == 0 {
+                    self.ghost_counts.remove(&forgotten);
+                }
+            }
+        }
+    }
+}
+
+impl Default for TwoQueuePolicy {
+    /// The parameters recommended by the authors of 2Q.
+    fn default() -> Self {
+        Self::new(0.25, 0.5)
+    }
+}
+
+impl EvictionPolicy for TwoQueuePolicy {
+    fn on_insert(&mut self, slot: usize) {
+        self.probation.push_front(slot);
+        self.max_len = self.max_len.max(self.len());
+    }
+
+    fn on_access(&mut self, slot: usize) {
+        if self.protected.contains(slot) {
+            self.protected.push_front(slot);
+        }
+    }
+
+    fn on_remove(&mut self, slot: usize) {
+        if self.probation.remove(slot) {
+            if let Some(hash) = self.hashes.get(slot).copied() {
+                self.remember(hash);
+            }
+        } else {
+            self.protected.remove(slot);
+        }
+    }
+
+    fn wants_key_hashes(&self) -> bool {
+        true
+    }
+
+    fn on_key_hash(&mut self, slot: usize, hash: u64) {
+        if self.hashes.len() <= slot {
+            self.hashes.resize(slot + 1, 0);
+        }
+        self.hashes[slot] = hash;
+        if self.ghost_counts.contains_key(&hash) {
+            self.probation.remove(slot);
+            self.protected.push_front(slot);
+        }
+    }
+
+    fn victim(&self) -> Option<usize> {
+        let max_probation = ((self.len() as f32 * self.probation_fraction) as usize).max(1);
+        if self.probation.len() > max_probation || self.protected.is_empty() {
+            self.probation.back()
+        } else {
+            self.protected.back()
+        }
+    }
+
+    fn clear(&mut self) {
+        self.probation.clear();
+        self.protected.clear();
+        self.ghosts.clear();
+        self.ghost_counts.clear();
+        self.max_len = 0;
+    }
+}
+
+/// Evicts the heaviest of the `window` least recently used values, as measured by the map's weigher
+/// (see `CompressibleMap::set_weigher`), so one huge cold value is compressed before several tiny
+/// ones. Ties go to the least recently used value. Without a weigher, this is plain LRU.
+#[derive(Clone, Debug)]
+pub struct LargestFirstPolicy {
+    pub window: usize,
+    queue: SlotQueue,
+    weights: Vec<usize>,
+}
+
+impl LargestFirstPolicy {
+    pub fn new(window: usize) -> Self {
+        assert!(window > 0, "The window must hold at least one value");
+
+        Self {
+            window,
+            queue: SlotQueue::default(),
+            weights: Vec::new(),
+        }
+    }
+}
+
+impl Default for LargestFirstPolicy {
+    fn default() -> Self {
+        Self::new(8)
+    }
+}
+
+impl EvictionPolicy for LargestFirstPolicy {
+    fn on_insert(&mut self, slot: usize) {
+        self.queue.push_front(slot);
+    }
+
+    fn on_access(&mut self, slot: usize) {
+        self.queue.push_front(slot);
+    }
+
+    fn on_remove(&mut self, slot: usize) {
+        self.queue.remove(slot);
+        if let Some(weight) = self.weights.get_mut(slot) {
+            *weight = 0;
+        }
+    }
+
+    fn on_weigh(&mut self, slot: usize, weight: usize) {
+        if self.weights.len() <= slot {
+            self.weights.resize(slot + 1, 0);
+        }
+        self.weights[slot] = weight;
+    }
+
+    fn victim(&self) -> Option<usize> {
+        let weight = |slot: usize| self.weights.get(slot).copied().unwrap_or(0);
+
+        self.queue
+            .iter_from_back()
+            .take(self.window)
+            .min_by_key(|slot| core::cmp::Reverse(weight(*slot)))
+    }
+
+    fn clear(&mut self) {
+        self.queue.clear();
+        self.weights.clear();
+    }
+}
+
+// ████████╗███████╗███████╗████████╗███████╗
+// ╚══██╔══╝██╔════╝██╔════╝╚══██╔══╝██╔════╝
+//    ██║   █████╗  ███████╗   ██║   ███████╗
+//    ██║   ██╔══╝  ╚════██║   ██║   ╚════██║
+//    ██║   ███████╗███████║   ██║   ███████║
+//    ╚═╝   ╚══════╝╚══════╝   ╚═╝   ╚══════╝
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::{FakeFooCompression, Foo};
+    use crate::CompressibleMap;
+
+    fn compressed_keys(policy: impl EvictionPolicy + 'static) -> Vec<u32> {
+        let mut map = CompressibleMap::<_, _, _>::new(FakeFooCompression);
+        map.set_cache_policy(policy);
+        for i in 0..4 {
+            map.insert(i, Foo(i));
+        }
+        map.get(0);
+        map.get(1);
+        map.compress_lru();
+        map.compress_lru();
+
+        let mut keys: Vec<u32> = (0..4).filter(|i| map.is_compressed(i)).collect();
+        keys.sort_unstable();
+
+        keys
+    }
+
+    #[test]
+    fn policies_choose_different_victims() {
+        assert_eq!(compressed_keys(LruPolicy::default()), vec![2, 3]);
+        assert_eq!(compressed_keys(FifoPolicy::default()), vec![0, 1]);
+        assert_eq!(compressed_keys(ClockPolicy::default()), vec![2, 3]);
+        assert_eq!(compressed_keys(RandomPolicy::with_seed(7)).len(), 2);
+    }
+
+    #[test]
+    fn clock_clears_every_reference_after_a_full_sweep() {
+        let mut policy = ClockPolicy::default();
+        for slot in 0..3 {
+            policy.on_insert(slot);
+            policy.on_access(slot);
+        }
+        assert_eq!(policy.victim(), Some(0));
+        policy.on_remove(0);
+
+        // The hand went all the way around, so 1 and 2 used up their second chance.
+        policy.on_access(1);
+        assert_eq!(policy.victim(), Some(2));
+    }
+
+    #[test]
+    fn clock_hand_stays_put_between_evictions() {
+        let mut policy = ClockPolicy::default();
+        for slot in 0..4 {
+            policy.on_insert(slot);
+        }
+        for slot in [1, 2] {
+            policy.on_access(slot);
+        }
+
+        // Removing another value leaves the hand alone, and evicting the value at the hand moves it
+        // past the referenced values behind it.
+        policy.on_remove(3);
+        assert_eq!(policy.victim(), Some(0));
+        policy.on_remove(0);
+        assert_eq!(policy.victim(), Some(1));
+        policy.on_access(1);
+        assert_eq!(policy.victim(), Some(2));
+    }
+
+    #[test]
+    fn two_queues_keep_the_hot_set_through_a_full_scan() {
+        let mut map = CompressibleMap::<_, _, _>::new(FakeFooCompression);
+        map.set_cache_policy(TwoQueuePolicy::default());
+        for i in 0..8 {
+            map.insert(i, Foo(i));
+        }
+        // 0 and 1 are needed again soon after they're compressed, so they're hot.
+        assert_eq!(map.compress_lru_n(2), 2);
+        map.get(0);
+        map.get(1);
+
+        // A pass over the whole map, and some new values.
+        for i in 0..16 {
+            map.get_or_insert_with(i, || Foo(i));
+        }
+        map.compress_lru_n(12);
+
+        let mut cached: Vec<u32> = (0..16).filter(|i| map.is_cached(i)).collect();
+        cached.sort_unstable();
+        assert_eq!(cached, vec![0, 1, 14, 15]);
+    }
+
+    #[test]
+    fn largest_cold_values_go_first() {
+        let mut map = CompressibleMap::<_, _, _>::new(FakeFooCompression);
+        map.set_weigher(|key: &u32, foo: &Foo| (key + foo.0) as usize);
+        map.set_cache_policy(LargestFirstPolicy::new(3));
+        for i in [5, 1, 9, 2] {
+            map.insert(i, Foo(i));
+        }
+
+        // 2 is too recent to be considered.
+        map.compress_lru();
+        assert!(map.is_compressed(&9));
+        map.compress_lru();
+        assert!(map.is_compressed(&5));
+        // Modified values are weighed again.
+        map.get_mut(1).unwrap().0 = 100;
+        map.compress_lru();
+        assert!(map.is_compressed(&1));
+    }
+
+    #[test]
+    fn slot_queue_removes_from_the_middle() {
+        let mut queue = SlotQueue::default();
+        for slot in [3, 0, 5, 1] {
+            queue.push_front(slot);
+        }
+        assert!(queue.remove(5));
+        assert!(!queue.remove(5));
+        queue.push_front(3);
+
+        assert_eq!(queue.iter_from_back().collect::<Vec<_>>(), vec![0, 1, 3]);
+        assert_eq!(queue.pop_back(), Some(0));
+        assert_eq!(queue.len(), 2);
+    }
+}
@@ -0,0 +1,195 @@
+//! C bindings for a map from `u64` keys to byte buffers that are compressed with LZ4. See
+//! `include/compressible_map.h` for the declarations.
+//!
+//! A `CompressibleByteMap` must only be used by one thread at a time.
+//!
+//! Unwinding into C is undefined behavior, so a panic in any of these functions is caught, and
+//! the function returns null, `false` or 0 instead. The map may be missing the value that was
+//! being modified when that happens.
+
+use crate::{BytesCompression, Compressed, CompressibleMap, Compression, Lz4};
+
+use std::panic::{self, AssertUnwindSafe};
+use std::ptr;
+
+/// The LZ4 compression of a whole byte buffer.
+struct Lz4Bytes(Lz4);
+
+impl Compression for Lz4Bytes {
+    type Data = Vec<u8>;
+    type CompressedData = Vec<u8>;
+
+    fn compress(&self, data: &Vec<u8>) -> Compressed<Self> {
+        let mut compressed = Vec::new();
+        self.0.compress_bytes(data, &mut compressed);
+
+        Compressed::new(compressed)
+    }
+
+    fn decompress(compressed: &Vec<u8>) -> Vec<u8> {
+        let mut bytes = Vec::new();
+        Lz4::decompress_bytes(compressed, &mut bytes);
+
+        bytes
+    }
+
+    fn decompress_into(compressed: &Vec<u8>, out: &mut Vec<u8>) {
+        out.clear();
+        Lz4::decompress_bytes(compressed, out);
+    }
+}
+
+/// Runs `f`, returning `on_panic` if it panics.
+fn catch_panic<T>(on_panic: T, f: impl FnOnce() -> T) -> T {
+    panic::catch_unwind(AssertUnwindSafe(f)).unwrap_or(on_panic)
+}
+
+/// An opaque handle to a map.
+pub struct CompressibleByteMap {
+    map: CompressibleMap<u64, Vec<u8>, Lz4Bytes>,
+}
+
+/// Creates a map that compresses with the given LZ4 level (0 to 10). Must be freed with
+/// `compressible_map_destroy`.
+#[no_mangle]
+pub extern "C" fn compressible_map_create(lz4_level: u32) -> *mut CompressibleByteMap {
+    catch_panic(ptr::null_mut(), || {
+        Box::into_raw(Box::new(CompressibleByteMap {
+            map: CompressibleMap::new(Lz4Bytes(Lz4 { level: lz4_level })),
+        }))
+    })
+}
+
+/// # Safety
+///
+/// `map` must have been returned by `compressible_map_create` and not destroyed yet, or be null.
+#[no_mangle]
+pub unsafe extern "C" fn compressible_map_destroy(map: *mut CompressibleByteMap) {
+    catch_panic((), || {
+        if !map.is_null() {
+            drop(Box::from_raw(map));
+        }
+    })
+}
+
+/// Copies `len` bytes from `data` into the map, replacing any old value for `key`.
+///
+/// # Safety
+///
+/// `map` must be a live handle, and `data` must point to `len` readable bytes.
+#[no_mangle]
+pub unsafe extern "C" fn compressible_map_insert(
+    map: *mut CompressibleByteMap,
+    key: u64,
+    data: *const u8,
+    len: usize,
+) {
+    catch_panic((), || {
+        let bytes = if len == 0 {
+            Vec::new()
+        } else {
+            std::slice::from_raw_parts(data, len).to_vec()
+        };
+        (*map).map.insert(key, bytes);
+    })
+}
+
+/// Returns a pointer to the bytes for `key` and writes their length to `out_len`, decompressing
+/// them into the cache if necessary. Returns null if there is no value. The pointer is only valid
+/// until the next call that modifies the map.
+///
+/// # Safety
+///
+/// `map` must be a live handle, and `out_len` must be writable.
+#[no_mangle]
+pub unsafe extern "C" fn compressible_map_get(
+    map: *mut CompressibleByteMap,
+    key: u64,
+    out_len: *mut usize,
+) -> *const u8 {
+    *out_len = 0;
+
+    catch_panic(ptr::null(), || match (*map).map.get(key) {
+        Some(bytes) => {
+            *out_len = bytes.len();
+
+            bytes.as_ptr()
+        }
+        None => ptr::null(),
+    })
+}
+
+/// Returns true if there was a value for `key`.
+///
+/// # Safety
+///
+/// `map` must be a live handle.
+#[no_mangle]
+pub unsafe extern "C" fn compressible_map_remove(map: *mut CompressibleByteMap, key: u64) -> bool {
+    catch_panic(false, || (*map).map.remove(&key).is_some())
+}
+
+/// # Safety
+///
+/// `map` must be a live handle.
+#[no_mangle]
+pub unsafe extern "C" fn compressible_map_compress_lru(map: *mut CompressibleByteMap) {
+    catch_panic((), || (*map).map.compress_lru())
+}
+
+/// # Safety
+///
+/// `map` must be a live handle.
+#[no_mangle]
+pub unsafe extern "C" fn compressible_map_len_cached(map: *const CompressibleByteMap) -> usize {
+    catch_panic(0, || (*map).map.len_cached())
+}
+
+/// # Safety
+///
+/// `map` must be a live handle.
+#[no_mangle]
+pub unsafe extern "C" fn compressible_map_len_compressed(map: *const CompressibleByteMap) -> usize {
+    catch_panic(0, || (*map).map.len_compressed())
+}
+
+// ████████╗███████╗███████╗████████╗███████╗
+// ╚══██╔══╝██╔════╝██╔════╝╚══██╔══╝██╔════╝
+//    ██║   █████╗  ███████╗   ██║   ███████╗
+//    ██║   ██╔══╝  ╚════██║   ██║   ╚════██║
+//    ██║   ███████╗███████║   ██║   ███████║
+//    ╚═╝   ╚══════╝╚══════╝   ╚═╝   ╚══════╝
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn round_trip_through_c_api() {
+        unsafe {
+            let map = compressible_map_create(4);
+            let data = [7u8; 100];
+            compressible_map_insert(map, 1, data.as_ptr(), data.len());
+            compressible_map_insert(map, 2, ptr::null(), 0);
+            compressible_map_compress_lru(map);
+            assert_eq!(compressible_map_len_compressed(map), 1);
+
+            let mut len = 0;
+            let bytes = compressible_map_get(map, 1, &mut len);
+            assert_eq!(std::slice::from_raw_parts(bytes, len), &data[..]);
+            assert_eq!(compressible_map_len_cached(map), 2);
+
+            assert!(compressible_map_get(map, 3, &mut len).is_null());
+            assert!(compressible_map_remove(map, 1));
+            assert!(!compressible_map_remove(map, 1));
+
+            compressible_map_destroy(map);
+        }
+    }
+
+    #[test]
+    fn panics_return_the_fallback() {
+        assert_eq!(catch_panic(0, || panic!("unwinding into C")), 0);
+        assert_eq!(catch_panic(0, || 5), 5);
+    }
+}
@@ -1,8 +1,53 @@
+#![cfg_attr(not(feature = "std"), no_std)]
+
+extern crate alloc;
+
+mod compressed_storage;
+mod compressed_values;
 mod compressible_map;
 mod compression;
+mod events;
+mod eviction_policy;
+#[cfg(feature = "ffi")]
+pub mod ffi;
 mod local_cache;
 mod lru_cache;
+mod modification_stamps;
+mod op_log;
+#[cfg(feature = "python")]
+mod python;
+mod reader;
+mod sharded_map;
+mod size_histogram;
+
+#[cfg(test)]
+mod test_util;
 
-pub use self::compressible_map::{CompressibleMap, MaybeCompressed};
+#[cfg(feature = "std")]
+pub use self::compressible_map::ConcurrentCompressibleMap;
+#[cfg(feature = "rayon")]
+pub use self::compressible_map::PerThreadLocalCaches;
+pub use self::compressible_map::{
+    AccessAge, BulkLoadOptions, CompressibleBTreeMap, CompressibleMap, CompressibleMapBuilder,
+    CompressibleMapSnapshot, CompressibleSlab, CompressorConfig, Cursor, Entry, EntryMetadata,
+    EvictionDecision, FrozenCompressibleMap, Full, IndexHasher, IntoIter, Iter, Job, JobOutcome,
+    MaybeCompressed, MemoryPressure, Namespace, NamespaceStats, OccupiedEntry, PinnedRef,
+    RecencyGuard, RetrainPolicy, RetrainReport, SharedCompressibleMap, SlabStorage, Stats,
+    VacantEntry, Watermarks,
+};
+#[cfg(feature = "left-right")]
+pub use self::compressible_map::{LeftRightReader, LeftRightWriter};
+pub use compressed_storage::CompressedStorage;
 pub use compression::*;
+pub use events::MapEvent;
+pub use eviction_policy::{
+    ClockPolicy, CloneEvictionPolicy, EvictionPolicy, FifoPolicy, LargestFirstPolicy, LruPolicy,
+    RandomPolicy, SlotQueue, TwoQueuePolicy,
+};
 pub use local_cache::LocalCache;
+#[cfg(feature = "std")]
+pub use local_cache::SyncLocalCache;
+pub use op_log::{Op, OpLog, OpLogReplayer};
+pub use reader::CompressibleMapReader;
+pub use sharded_map::{DefaultHashBuilder, ShardedHashMap};
+pub use size_histogram::SizeHistogram;
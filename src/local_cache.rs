@@ -1,7 +1,12 @@
+use alloc::boxed::Box;
+use core::cell::UnsafeCell;
 use core::hash::{BuildHasher, Hash};
-use std::cell::UnsafeCell;
-use std::collections::{hash_map, HashMap};
-use std::pin::Pin;
+#[cfg(feature = "std")]
+use core::marker::PhantomData;
+use core::pin::Pin;
+use hashbrown::{hash_map, HashMap};
+#[cfg(feature = "std")]
+use std::sync::Mutex;
 
 /// When immutable cache access is required, use this `LocalCache` to store evicted values. Then
 /// when you get mutable cache access, call `into_iter` to update the cache manually.
@@ -11,30 +16,36 @@ use std::pin::Pin;
 /// heap.
 #[derive(Default)]
 pub struct LocalCache<K, V, H> {
-    accesses: UnsafeCell<HashMap<K, LocalAccess<Pin<Box<V>>>, H>>,
+    accesses: UnsafeCell<AccessMap<K, V, H>>,
 }
 
+type AccessMap<K, V, H> = HashMap<K, LocalAccess<Pin<Box<V>>>, H>;
+
 pub enum LocalAccess<V> {
     /// Represents a global cache hit that we want to remember so we can update the LRU order later.
     Cached,
     /// Represents a miss of the global cache that required us to cache the value locally.
     /// `Pin<Box>` is used to maintain a stable address for the value, even if the the map it lives
-    /// in is mutated.
-    Missed(V),
+    /// in is mutated. The modification stamp of the entry at the time of the read is kept so a
+    /// stale value isn't flushed over a newer one.
+    Missed { value: V, stamp: u64 },
 }
 
 impl<V> LocalAccess<V> {
     fn unwrap_ref(&self) -> &V {
         match self {
             LocalAccess::Cached => panic!("Tried to unwrap access without value"),
-            LocalAccess::Missed(value) => &value,
+            LocalAccess::Missed { value, .. } => value,
         }
     }
 
     fn map<T>(self, f: impl FnOnce(V) -> T) -> LocalAccess<T> {
         match self {
             LocalAccess::Cached => LocalAccess::Cached,
-            LocalAccess::Missed(value) => LocalAccess::Missed(f(value)),
+            LocalAccess::Missed { value, stamp } => LocalAccess::Missed {
+                value: f(value),
+                stamp,
+            },
         }
     }
 }
@@ -62,28 +73,35 @@ where
         mut_accesses.entry(key).or_insert(LocalAccess::Cached);
     }
 
-    pub fn get_or_insert_with(&self, key: K, f: impl FnOnce() -> V) -> &V {
+    /// Gets the value for `key`, or caches the value from `f`, which was read when the entry had
+    /// the given modification `stamp`.
+    pub fn get_or_insert_with(&self, key: K, stamp: u64, f: impl FnOnce() -> V) -> &V {
         let mut_accesses = unsafe { &mut *self.accesses.get() };
+        let missed = |value| LocalAccess::Missed {
+            value: Box::pin(value),
+            stamp,
+        };
         match mut_accesses.entry(key) {
             hash_map::Entry::Occupied(occupied) => {
                 let access_ref = occupied.into_mut();
                 match access_ref {
                     LocalAccess::Cached => {
-                        *access_ref = LocalAccess::Missed(Box::pin(f()));
+                        *access_ref = missed(f());
 
                         access_ref.unwrap_ref()
                     }
-                    LocalAccess::Missed(value) => value,
+                    LocalAccess::Missed { value, .. } => value,
                 }
             }
             hash_map::Entry::Vacant(vacant) => {
-                let access_ref = vacant.insert(LocalAccess::Missed(Box::pin(f())));
+                let access_ref = vacant.insert(missed(f()));
 
                 access_ref.unwrap_ref()
             }
         }
     }
 
+    #[allow(clippy::should_implement_trait)]
     pub fn into_iter(self) -> impl Iterator<Item = (K, LocalAccess<V>)> {
         self.accesses.into_inner().into_iter().map(|(k, access)| {
             (
@@ -92,4 +110,146 @@ where
             )
         })
     }
+
+    /// Like `into_iter`, but leaves the (empty) cache behind so its allocation can be reused.
+    /// Having `&mut self` guarantees that no references returned by `get_or_insert_with` are still
+    /// alive.
+    pub fn drain(&mut self) -> impl Iterator<Item = (K, LocalAccess<V>)> + '_ {
+        self.accesses.get_mut().drain().map(|(k, access)| {
+            (
+                k,
+                access.map(|value| unsafe { *Pin::into_inner_unchecked(value) }),
+            )
+        })
+    }
+
+    /// Moves all accesses from `other` into this cache, e.g. to combine the caches of several
+    /// worker threads so the map only needs to flush once. A decompressed value takes precedence
+    /// over a cached access to the same key. Having `&mut self` guarantees that no references
+    /// returned by `get_or_insert_with` are still alive.
+    pub fn merge(&mut self, other: Self) {
+        let accesses = self.accesses.get_mut();
+        for (key, access) in other.accesses.into_inner() {
+            match accesses.entry(key) {
+                hash_map::Entry::Occupied(mut occupied) => {
+                    if let LocalAccess::Cached = occupied.get() {
+                        occupied.insert(access);
+                    }
+                }
+                hash_map::Entry::Vacant(vacant) => {
+                    vacant.insert(access);
+                }
+            }
+        }
+    }
+
+    pub fn is_empty(&mut self) -> bool {
+        self.accesses.get_mut().is_empty()
+    }
+}
+
+/// Like `LocalCache`, but it can be shared by many threads during a parallel read phase, so there's
+/// only one cache to flush afterwards. Read from the map with `CompressibleMap::get_const_sync`,
+/// then flush with `flush_local_cache(cache.into_local_cache())`.
+///
+/// Accesses are spread over a fixed number of mutex-guarded shards. A shard is only locked to look
+/// up or insert an access, never while decompressing, so if two threads miss the same key at once,
+/// both decompress it and the first one to finish wins. Like in `LocalCache`, values are never
+/// removed or moved once they're inserted, so references to them stay valid. Needs the `std`
+/// feature for the mutexes.
+#[cfg(feature = "std")]
+pub struct SyncLocalCache<K, V, H> {
+    shards: Box<[Mutex<AccessMap<K, V, H>>]>,
+    hasher: H,
+    // References to values are shared between threads, so `V` must be `Sync`, which the `Mutex`
+    // alone wouldn't require.
+    marker: PhantomData<*const V>,
+}
+
+// SAFE: Keys and values are only moved between threads through the shard mutexes. References to
+// values can be shared by many threads, which requires `V: Sync`.
+#[cfg(feature = "std")]
+unsafe impl<K: Send, V: Send, H: Send> Send for SyncLocalCache<K, V, H> {}
+#[cfg(feature = "std")]
+unsafe impl<K: Send, V: Send + Sync, H: Sync> Sync for SyncLocalCache<K, V, H> {}
+
+#[cfg(feature = "std")]
+const NUM_SHARDS: usize = 16;
+
+#[cfg(feature = "std")]
+impl<K, V, H> SyncLocalCache<K, V, H>
+where
+    K: Eq + Hash,
+    H: Default + BuildHasher,
+{
+    pub fn new() -> Self {
+        SyncLocalCache {
+            shards: (0..NUM_SHARDS)
+                .map(|_| Mutex::new(HashMap::with_hasher(Default::default())))
+                .collect(),
+            hasher: Default::default(),
+            marker: PhantomData,
+        }
+    }
+
+    fn shard(&self, key: &K) -> &Mutex<AccessMap<K, V, H>> {
+        &self.shards[self.hasher.hash_one(key) as usize % self.shards.len()]
+    }
+
+    pub fn remember_cached_access(&self, key: K) {
+        self.shard(&key)
+            .lock()
+            .unwrap()
+            .entry(key)
+            .or_insert(LocalAccess::Cached);
+    }
+
+    /// Gets the value for `key`, or caches the value from `f`, which was read when the entry had
+    /// the given modification `stamp`. `f` is called without holding any locks.
+    pub fn get_or_insert_with(&self, key: K, stamp: u64, f: impl FnOnce() -> V) -> &V {
+        let shard = self.shard(&key);
+        if let Some(LocalAccess::Missed { value, .. }) = shard.lock().unwrap().get(&key) {
+            // SAFE: See `LocalCache`. The boxed value outlives the lock guard.
+            return unsafe { &*(&**value as *const V) };
+        }
+
+        let value = Box::pin(f());
+        let mut accesses = shard.lock().unwrap();
+        let access_ref = accesses.entry(key).or_insert(LocalAccess::Cached);
+        if let LocalAccess::Cached = access_ref {
+            *access_ref = LocalAccess::Missed { value, stamp };
+        }
+
+        unsafe { &*(&**access_ref.unwrap_ref() as *const V) }
+    }
+
+    /// Combines the shards into a `LocalCache` to be flushed with
+    /// `CompressibleMap::flush_local_cache`.
+    pub fn into_local_cache(self) -> LocalCache<K, V, H> {
+        let mut merged = LocalCache::new();
+        for shard in self.shards.into_vec() {
+            merged.merge(LocalCache {
+                accesses: UnsafeCell::new(shard.into_inner().unwrap()),
+            });
+        }
+
+        merged
+    }
+
+    pub fn is_empty(&mut self) -> bool {
+        self.shards
+            .iter_mut()
+            .all(|shard| shard.get_mut().unwrap().is_empty())
+    }
+}
+
+#[cfg(feature = "std")]
+impl<K, V, H> Default for SyncLocalCache<K, V, H>
+where
+    K: Eq + Hash,
+    H: Default + BuildHasher,
+{
+    fn default() -> Self {
+        Self::new()
+    }
 }
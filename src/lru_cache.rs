@@ -1,5 +1,12 @@
+use crate::{
+    eviction_policy::EvictionPolicy, sharded_map::INLINE_LEN, DefaultHashBuilder, ShardedHashMap,
+};
+
+use alloc::{boxed::Box, sync::Arc, vec::Vec};
 use core::hash::{BuildHasher, Hash};
-use std::collections::{hash_map, HashMap};
+use smallvec::SmallVec;
+#[cfg(feature = "std")]
+use std::time::Instant;
 
 /// A cache that tracks the Least Recently Used element for next eviction. Here, "used" means read
 /// or written.
@@ -16,11 +23,68 @@ use std::collections::{hash_map, HashMap};
 /// Because accessing a value, even just to read it, will update the cache's LRU order, most methods
 /// require `&mut self`. However, the exception is `get_const`, which doesn't not update the LRU
 /// order.
+///
+/// Every access that updates the LRU order also advances a logical clock, and the cached entry
+/// remembers the clock tick of its last access. Reading the wall clock isn't free, so the time of
+/// the access is only recorded while `set_track_time` is on.
+///
+/// An `EvictionPolicy` can choose a different value to evict than the LRU one. The LRU order is
+/// still maintained for everything else that depends on it.
 #[derive(Clone, Debug)]
 pub struct LruCache<K, V, H> {
-    store: HashMap<K, EntryState<usize>, H>,
-    order: LruList<(K, V)>,
+    store: ShardedHashMap<K, EntryState<usize>, H>,
+    order: LruList<(K, V, LastAccess, usize)>,
     num_evicted: usize,
+    clock: u64,
+    #[cfg(feature = "std")]
+    track_time: bool,
+    weigher: Option<Weigher<K, V>>,
+    total_weight: usize,
+    // The index of a value that was handed out by mutable reference since the last modification.
+    unsettled: Option<usize>,
+    // Whether all values were handed out by mutable reference since the last modification.
+    all_unsettled: bool,
+    policy: Option<Box<dyn EvictionPolicy>>,
+    // Hashes keys for policies that want them, independently of the store, so the hashes don't
+    // change when the store is split into shards.
+    key_hasher: DefaultHashBuilder,
+}
+
+/// Estimates the number of bytes used by an entry, including any heap memory it owns.
+pub struct Weigher<K, V>(Arc<WeighFn<K, V>>);
+
+type WeighFn<K, V> = dyn Fn(&K, &V) -> usize + Send + Sync;
+
+impl<K, V> Weigher<K, V> {
+    pub fn new(weigh: impl Fn(&K, &V) -> usize + Send + Sync + 'static) -> Self {
+        Weigher(Arc::new(weigh))
+    }
+}
+
+impl<K, V> Clone for Weigher<K, V> {
+    fn clone(&self) -> Self {
+        Weigher(self.0.clone())
+    }
+}
+
+impl<K, V> core::fmt::Debug for Weigher<K, V> {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        f.write_str("Weigher")
+    }
+}
+
+fn weigh<K, V>(weigher: &Option<Weigher<K, V>>, key: &K, value: &V) -> usize {
+    weigher.as_ref().map_or(0, |w| (w.0)(key, value))
+}
+
+/// When a cached entry was last accessed.
+#[derive(Clone, Copy, Debug)]
+pub struct LastAccess {
+    /// The value of the cache's logical clock at the time of access.
+    pub tick: u64,
+    /// `None` if the time wasn't tracked at the time of access.
+    #[cfg(feature = "std")]
+    pub time: Option<Instant>,
 }
 
 #[derive(Clone, Copy, Debug, Eq, PartialEq)]
@@ -61,9 +125,18 @@ where
 {
     pub fn with_hasher(hasher_builder: H) -> LruCache<K, V, H> {
         LruCache {
-            store: HashMap::with_hasher(hasher_builder),
+            store: ShardedHashMap::with_hasher(hasher_builder),
             order: LruList::new(),
             num_evicted: 0,
+            clock: 0,
+            #[cfg(feature = "std")]
+            track_time: false,
+            weigher: None,
+            total_weight: 0,
+            unsettled: None,
+            all_unsettled: false,
+            policy: None,
+            key_hasher: DefaultHashBuilder::default(),
         }
     }
 }
@@ -71,22 +144,173 @@ where
 impl<K, V, H> LruCache<K, V, H>
 where
     K: Hash + Eq + Clone,
-    H: BuildHasher,
+    H: BuildHasher + Default,
 {
-    pub fn get_mut(&mut self, key: &K) -> Option<EntryState<&mut V>> {
-        let Self { store, order, .. } = self;
-        store.get(key).map(move |&entry| {
-            entry.map(move |index| {
-                order.move_to_front(index);
+    /// Reserves room for `additional` more tracked keys, of which `additional_cached` will be
+    /// cached at the same time.
+    pub fn reserve(&mut self, additional: usize, additional_cached: usize) {
+        self.store.reserve(additional);
+        self.order.entries.reserve(additional_cached);
+    }
+
+    /// Sets the function used to estimate the size of each cached value, and re-weighs all of them.
+    pub fn set_weigher(&mut self, weigher: Option<Weigher<K, V>>) {
+        self.weigher = weigher;
+        self.reweigh_all();
+    }
+
+    fn reweigh_all(&mut self) {
+        self.unsettled = None;
+        self.all_unsettled = false;
+        self.total_weight = 0;
+        let indices: Vec<usize> = self.order.indices_from_front().collect();
+        for index in indices {
+            let (key, value, _, weight) = self.order.get_mut(index);
+            *weight = weigh(&self.weigher, key, value);
+            self.total_weight += *weight;
+            if let Some(policy) = self.policy.as_mut() {
+                policy.on_weigh(index, *weight);
+            }
+        }
+    }
 
-                &mut order.get_mut(index).1
-            })
-        })
+    /// Replaces the eviction policy, telling the new one about all cached values in LRU order.
+    pub fn set_policy(&mut self, mut policy: Option<Box<dyn EvictionPolicy>>) {
+        if let Some(policy) = policy.as_mut() {
+            self.settle();
+            for index in self.order.indices_from_back() {
+                let (key, _, _, weight) = self.order.get(index);
+                policy.on_insert(index);
+                policy.on_weigh(index, *weight);
+                if policy.wants_key_hashes() {
+                    policy.on_key_hash(index, self.key_hasher.hash_one(key));
+                }
+            }
+        }
+        self.policy = policy;
+    }
+
+    /// The index of the value to evict next.
+    fn victim(&self) -> usize {
+        match &self.policy {
+            Some(policy) => policy
+                .victim()
+                .expect("Eviction policy lost track of a value"),
+            None => self.order.back(),
+        }
+    }
+
+    pub fn has_weigher(&self) -> bool {
+        self.weigher.is_some()
+    }
+
+    pub fn weigh(&self, key: &K, value: &V) -> usize {
+        weigh(&self.weigher, key, value)
+    }
+
+    /// The sum of the weights of all cached values. Always 0 without a weigher.
+    pub fn total_weight(&self) -> usize {
+        if self.all_unsettled {
+            return self.iter().map(|(k, v)| self.weigh(k, v)).sum();
+        }
+        match (self.unsettled, &self.weigher) {
+            (Some(index), Some(weigher)) => {
+                let (key, value, _, weight) = self.order.get(index);
+
+                self.total_weight - weight + (weigher.0)(key, value)
+            }
+            _ => self.total_weight,
+        }
+    }
+
+    /// Re-weighs the last value that was handed out by mutable reference, since it might have
+    /// changed. Must be called before any other modification of the cache.
+    fn settle(&mut self) {
+        if self.all_unsettled {
+            return self.reweigh_all();
+        }
+        if let Some(index) = self.unsettled.take() {
+            let (key, value, _, weight) = self.order.get_mut(index);
+            let new_weight = weigh(&self.weigher, key, value);
+            self.total_weight = self.total_weight - *weight + new_weight;
+            *weight = new_weight;
+            if let Some(policy) = self.policy.as_mut() {
+                policy.on_weigh(index, new_weight);
+            }
+        }
+    }
+
+    fn mark_unsettled(&mut self, index: usize) {
+        if self.weigher.is_some() {
+            self.unsettled = Some(index);
+        }
+    }
+
+    /// Makes the cached value for `key` the most recently used.
+    fn touch(&mut self, key: &K) -> Option<EntryState<usize>> {
+        self.settle();
+        let entry = *self.store.get(key)?;
+        if let EntryState::Cached(index) = entry {
+            self.order.move_to_front(index);
+            let access = self.next_access();
+            self.order.get_mut(index).2 = access;
+            if let Some(policy) = self.policy.as_mut() {
+                policy.on_access(index);
+            }
+        }
+
+        Some(entry)
+    }
+
+    /// Advances the logical clock for a new access.
+    fn next_access(&mut self) -> LastAccess {
+        self.clock += 1;
+
+        LastAccess {
+            tick: self.clock,
+            #[cfg(feature = "std")]
+            time: self.track_time.then(Instant::now),
+        }
+    }
+
+    /// Puts a new value at the front of the LRU order, returning its index.
+    fn push_front(&mut self, key: K, value: V) -> usize {
+        let weight = weigh(&self.weigher, &key, &value);
+        self.total_weight += weight;
+        let access = self.next_access();
+        let hash = self
+            .policy
+            .as_ref()
+            .filter(|policy| policy.wants_key_hashes())
+            .map(|_| self.key_hasher.hash_one(&key));
+
+        let index = self.order.push_front(Some((key, value, access, weight)));
+        if let Some(policy) = self.policy.as_mut() {
+            policy.on_insert(index);
+            policy.on_weigh(index, weight);
+            if let Some(hash) = hash {
+                policy.on_key_hash(index, hash);
+            }
+        }
+
+        index
+    }
+
+    /// Takes the value at `index` out of the LRU order.
+    fn remove_index(&mut self, index: usize) -> (K, V) {
+        let (key, value, _, weight) = self.order.remove(index);
+        self.total_weight -= weight;
+        if let Some(policy) = self.policy.as_mut() {
+            policy.on_remove(index);
+        }
+
+        (key, value)
     }
 
     pub fn get(&mut self, key: &K) -> Option<EntryState<&V>> {
-        // Hopefully downgrading the reference is a NOOP.
-        self.get_mut(key).map(|e| e.map(|v| &*v))
+        let entry = self.touch(key)?;
+
+        Some(entry.map(move |index| &self.order.get(index).1))
     }
 
     /// Allows us to get a const reference without having `&mut self`. WARNING: This will not update
@@ -100,30 +324,20 @@ where
 
     /// Inserts a new `val` for `key`, returning the old entry if it exists.
     pub fn insert(&mut self, key: K, val: V) -> Option<EntryState<V>> {
-        let Self { store, order, .. } = self;
-        match store.entry(key.clone()) {
-            hash_map::Entry::Occupied(mut occupied) => match *occupied.get() {
-                EntryState::Cached(index) => {
-                    order.move_to_front(index);
-
-                    order
-                        .set(index, (key, val))
-                        .map(|(_, v)| EntryState::Cached(v))
-                }
-                EntryState::Evicted => {
-                    let new_index = order.push_front(Some((key, val)));
-                    occupied.insert(EntryState::Cached(new_index));
-                    self.num_evicted -= 1;
-
-                    Some(EntryState::Evicted)
-                }
-            },
-            hash_map::Entry::Vacant(vacant) => {
-                vacant.insert(EntryState::Cached(order.push_front(Some((key, val)))));
+        self.settle();
+        let old_entry = self.store.get(&key).cloned();
+        let old = old_entry.map(|entry| match entry {
+            EntryState::Cached(index) => EntryState::Cached(self.remove_index(index).1),
+            EntryState::Evicted => {
+                self.num_evicted -= 1;
 
-                None
+                EntryState::Evicted
             }
-        }
+        });
+        let index = self.push_front(key.clone(), val);
+        self.store.insert(key, EntryState::Cached(index));
+
+        old
     }
 
     /// Tries to get the value for `key`, returning it if it exists. If the entry state is evicted,
@@ -133,24 +347,19 @@ where
         key: K,
         on_evicted: impl FnOnce() -> V,
     ) -> Option<&mut V> {
-        let Self { store, order, .. } = self;
-        match store.entry(key.clone()) {
-            hash_map::Entry::Occupied(mut occupied) => match *occupied.get() {
-                EntryState::Cached(index) => {
-                    order.move_to_front(index);
+        let index = match self.touch(&key)? {
+            EntryState::Cached(index) => index,
+            EntryState::Evicted => {
+                let index = self.push_front(key.clone(), on_evicted());
+                self.store.insert(key, EntryState::Cached(index));
+                self.num_evicted -= 1;
 
-                    Some(&mut order.get_mut(index).1)
-                }
-                EntryState::Evicted => {
-                    let new_index = order.push_front(Some((key, on_evicted())));
-                    occupied.insert(EntryState::Cached(new_index));
-                    self.num_evicted -= 1;
+                index
+            }
+        };
+        self.mark_unsettled(index);
 
-                    Some(&mut order.get_mut(new_index).1)
-                }
-            },
-            hash_map::Entry::Vacant(_) => None,
-        }
+        Some(&mut self.order.get_mut(index).1)
     }
 
     /// Tries to get the value for `key`, returning it if it exists. If the entry state is evicted,
@@ -162,36 +371,33 @@ where
         on_evicted: impl FnOnce() -> V,
         on_missing: impl FnOnce() -> V,
     ) -> &mut V {
-        let Self { store, order, .. } = self;
-        match store.entry(key.clone()) {
-            hash_map::Entry::Occupied(mut occupied) => match *occupied.get() {
-                EntryState::Cached(index) => {
-                    order.move_to_front(index);
-
-                    &mut order.get_mut(index).1
-                }
-                EntryState::Evicted => {
-                    let new_index = order.push_front(Some((key, on_evicted())));
-                    occupied.insert(EntryState::Cached(new_index));
-                    self.num_evicted -= 1;
+        let index = match self.touch(&key) {
+            Some(EntryState::Cached(index)) => index,
+            Some(EntryState::Evicted) => {
+                let index = self.push_front(key.clone(), on_evicted());
+                self.store.insert(key, EntryState::Cached(index));
+                self.num_evicted -= 1;
 
-                    &mut order.get_mut(new_index).1
-                }
-            },
-            hash_map::Entry::Vacant(vacant) => {
-                let new_index = order.push_front(Some((key, on_missing())));
-                vacant.insert(EntryState::Cached(new_index));
+                index
+            }
+            None => {
+                let index = self.push_front(key.clone(), on_missing());
+                self.store.insert(key, EntryState::Cached(index));
 
-                &mut order.get_mut(new_index).1
+                index
             }
-        }
+        };
+        self.mark_unsettled(index);
+
+        &mut self.order.get_mut(index).1
     }
 
     /// Removes any trace of `key`, such that further accesses will return `None` until a new value
     /// is inserted.
     pub fn remove(&mut self, key: &K) -> Option<EntryState<V>> {
+        self.settle();
         self.store.remove(key).map(|entry| match entry {
-            EntryState::Cached(index) => EntryState::Cached(self.order.remove(index).1),
+            EntryState::Cached(index) => EntryState::Cached(self.remove_index(index).1),
             EntryState::Evicted => {
                 self.num_evicted -= 1;
 
@@ -203,11 +409,12 @@ where
     /// Evicts a specific `key`. This will leave a sentinel behind so that further accesses will
     /// return `Some(EntryState::Evicted)` until the key is removed or a new entry is inserted.
     pub fn evict(&mut self, key: K) -> Option<EntryState<V>> {
+        self.settle();
         self.num_evicted += 1;
         self.store
             .insert(key, EntryState::Evicted)
             .map(|entry| match entry {
-                EntryState::Cached(index) => EntryState::Cached(self.order.remove(index).1),
+                EntryState::Cached(index) => EntryState::Cached(self.remove_index(index).1),
                 EntryState::Evicted => {
                     self.num_evicted -= 1;
 
@@ -216,37 +423,121 @@ where
             })
     }
 
-    /// Evicts the least-recently used value. This will leave a sentinel behind so that further
-    /// accesses will return `Some(EntryState::Evicted)` until the key is removed or a new entry is
-    /// inserted.
+    /// Evicts the least-recently used value, or the one chosen by the eviction policy. This will
+    /// leave a sentinel behind so that further accesses will return `Some(EntryState::Evicted)`
+    /// until the key is removed or a new entry is inserted.
     pub fn evict_lru(&mut self) -> Option<(K, V)> {
         if self.len_cached() == 0 {
             return None;
         }
 
-        let (key, value) = self.order.pop_back();
+        self.settle();
+        let (key, value) = self.remove_index(self.victim());
         *self.store.get_mut(&key).unwrap() = EntryState::Evicted;
         self.num_evicted += 1;
 
         Some((key, value))
     }
 
-    /// Removes the least-recently used value, leaving no trace.
+    /// Removes the least-recently used value, or the one chosen by the eviction policy, leaving no
+    /// trace.
     pub fn remove_lru(&mut self) -> Option<(K, V)> {
         if self.len_cached() == 0 {
             return None;
         }
 
-        let (key, value) = self.order.pop_back();
+        self.settle();
+        let (key, value) = self.remove_index(self.victim());
         self.store.remove(&key).unwrap();
 
         Some((key, value))
     }
 
+    /// Takes the cached value for `key` out of the LRU order and puts it back in front, as if it
+    /// was just inserted, so the eviction policy also treats it as new.
+    pub fn requeue(&mut self, key: &K) {
+        self.settle();
+        if let Some(&EntryState::Cached(index)) = self.store.get(key) {
+            let (key, value) = self.remove_index(index);
+            let index = self.push_front(key.clone(), value);
+            *self.store.get_mut(&key).unwrap() = EntryState::Cached(index);
+        }
+    }
+
+    /// Starts or stops recording the time of each access. Values that were accessed before the time
+    /// was tracked count as accessed just now.
+    #[cfg(feature = "std")]
+    pub fn set_track_time(&mut self, track_time: bool) {
+        if track_time && !self.track_time {
+            let now = Instant::now();
+            for entry in self.order.entries.iter_mut() {
+                if let Some((_, _, access, _)) = entry.value.as_mut() {
+                    access.time = Some(now);
+                }
+            }
+        }
+        self.track_time = track_time;
+    }
+
+    /// The current value of the logical clock, i.e. the tick of the most recent access.
+    pub fn clock(&self) -> u64 {
+        self.clock
+    }
+
+    /// When the cached value for `key` was last accessed.
+    pub fn last_access(&self, key: &K) -> Option<LastAccess> {
+        self.store
+            .get(key)
+            .and_then(|e| e.some_if_cached())
+            .map(|index| self.order.get(index).2)
+    }
+
+    /// The position of the cached value for `key` in the LRU order, where 0 is the most recently
+    /// used. This takes time linear in the rank.
+    pub fn recency_rank(&self, key: &K) -> Option<usize> {
+        let index = self.store.get(key).and_then(|e| e.some_if_cached())?;
+
+        self.order.indices_from_front().position(|i| i == index)
+    }
+
+    /// Iterates over the cached entries, starting with the least recently used.
+    pub fn iter_lru_first(&self) -> impl Iterator<Item = (&K, &V)> {
+        self.order.indices_from_back().map(move |i| {
+            let (k, v, _, _) = self.order.get(i);
+
+            (k, v)
+        })
+    }
+
+    /// The entry that `evict_lru` would evict, without evicting it.
+    pub fn peek_lru(&self) -> Option<(&K, &V)> {
+        if self.len_cached() == 0 {
+            return None;
+        }
+        let (key, value, _, _) = self.order.get(self.victim());
+
+        Some((key, value))
+    }
+
+    /// When the value that `evict_lru` would evict was last accessed.
+    pub fn lru_last_access(&self) -> Option<LastAccess> {
+        if self.len_cached() == 0 {
+            return None;
+        }
+
+        Some(self.order.get(self.victim()).2)
+    }
+
     pub fn clear(&mut self) {
         self.store.clear();
         self.order.clear();
+        if let Some(policy) = self.policy.as_mut() {
+            policy.clear();
+        }
         self.num_evicted = 0;
+        self.total_weight = 0;
+        self.unsettled = None;
+        self.all_unsettled = false;
     }
 
     pub fn len_cached(&self) -> usize {
@@ -271,6 +562,26 @@ where
             .filter_map(move |(k, e)| e.some_if_cached().map(|i| (k, &self.order.get(i).1)))
     }
 
+    /// Iterates mutably over the cached entries in arbitrary order, without changing the LRU order.
+    /// All values are re-weighed before the next modification.
+    pub fn iter_mut(&mut self) -> impl Iterator<Item = (&K, &mut V)> {
+        self.settle();
+        self.all_unsettled = self.weigher.is_some();
+
+        self.order
+            .entries
+            .iter_mut()
+            .filter_map(|entry| entry.value.as_mut().map(|(k, v, _, _)| (&*k, v)))
+    }
+
+    /// Iterates over the cached keys and when they were last accessed, without touching values.
+    pub fn iter_last_access(&self) -> impl Iterator<Item = (&K, LastAccess)> {
+        self.store
+            .iter()
+            .filter_map(move |(k, e)| e.some_if_cached().map(|i| (k, self.order.get(i).2)))
+    }
+
+    #[allow(clippy::should_implement_trait)]
     pub fn into_iter(self) -> impl Iterator<Item = (K, V)> {
         let LruCache {
             store, mut order, ..
@@ -282,10 +593,11 @@ where
     }
 }
 
-/// Doubly-linked list using Vec as storage.
+/// Doubly-linked list using a `SmallVec` as storage, so the first `INLINE_LEN` entries are stored
+/// inline.
 #[derive(Clone, Debug)]
 struct LruList<T> {
-    entries: Vec<ListEntry<T>>,
+    entries: SmallVec<[ListEntry<T>; INLINE_LEN + 2]>,
 }
 
 #[derive(Clone, Debug)]
@@ -297,24 +609,31 @@ struct ListEntry<T> {
 
 /// Free and occupied cells are each linked into a cyclic list with one auxiliary cell.
 /// Cell #0 is on the list of free cells, element #1 is on the list of occupied cells.
+///
+/// The auxiliary cells are only created on the first push, and up to `INLINE_LEN` entries are stored
+/// inline, so a small list doesn't allocate. This matters when there are many small maps, most of
+/// which stay empty or nearly so.
 impl<T> LruList<T> {
     const FREE: usize = 0;
     const OCCUPIED: usize = 1;
 
     fn new() -> LruList<T> {
-        let mut entries = Vec::with_capacity(2);
-        entries.push(ListEntry::<T> {
+        LruList {
+            entries: SmallVec::new(),
+        }
+    }
+
+    fn push_auxiliary_cells(&mut self) {
+        self.entries.push(ListEntry::<T> {
             value: None,
-            next: 0,
-            prev: 0,
+            next: Self::FREE,
+            prev: Self::FREE,
         });
-        entries.push(ListEntry::<T> {
+        self.entries.push(ListEntry::<T> {
             value: None,
-            next: 1,
-            prev: 1,
+            next: Self::OCCUPIED,
+            prev: Self::OCCUPIED,
         });
-
-        LruList { entries }
     }
 
     fn unlink(&mut self, index: usize) {
@@ -338,6 +657,9 @@ impl<T> LruList<T> {
     }
 
     fn push_front(&mut self, value: Option<T>) -> usize {
+        if self.entries.is_empty() {
+            self.push_auxiliary_cells();
+        }
         if self.entries[Self::FREE].next == Self::FREE {
             self.entries.push(ListEntry::<T> {
                 value: None,
@@ -365,10 +687,38 @@ impl<T> LruList<T> {
         self.entries[Self::OCCUPIED].prev
     }
 
-    fn pop_back(&mut self) -> T {
-        let index = self.back();
+    /// Iterates over the indices of occupied cells, from back to front.
+    fn indices_from_back(&self) -> impl Iterator<Item = usize> + '_ {
+        let mut index = Self::OCCUPIED;
+        core::iter::from_fn(move || {
+            if self.entries.is_empty() {
+                return None;
+            }
+            index = self.entries[index].prev;
 
-        self.remove(index)
+            if index == Self::OCCUPIED {
+                None
+            } else {
+                Some(index)
+            }
+        })
+    }
+
+    /// Iterates over the indices of occupied cells, from front to back.
+    fn indices_from_front(&self) -> impl Iterator<Item = usize> + '_ {
+        let mut index = Self::OCCUPIED;
+        core::iter::from_fn(move || {
+            if self.entries.is_empty() {
+                return None;
+            }
+            index = self.entries[index].next;
+
+            if index == Self::OCCUPIED {
+                None
+            } else {
+                Some(index)
+            }
+        })
     }
 
     fn get(&self, index: usize) -> &T {
@@ -379,22 +729,8 @@ impl<T> LruList<T> {
         self.entries[index].value.as_mut().expect("invalid index")
     }
 
-    fn set(&mut self, index: usize, value: T) -> Option<T> {
-        std::mem::replace(&mut self.entries[index].value, Some(value))
-    }
-
     fn clear(&mut self) {
         self.entries.clear();
-        self.entries.push(ListEntry::<T> {
-            value: None,
-            next: 0,
-            prev: 0,
-        });
-        self.entries.push(ListEntry::<T> {
-            value: None,
-            next: 1,
-            prev: 1,
-        });
     }
 }
 
@@ -429,6 +765,54 @@ mod tests {
         assert_eq!(cache.len_evicted(), 0);
     }
 
+    #[test]
+    fn empty_cache_does_not_allocate() {
+        let mut cache = LruCache::<u32, u32, _>::with_hasher(RandomState::default());
+        assert!(!cache.order.entries.spilled());
+        assert!(cache.store.is_inline());
+        assert_eq!(cache.iter_lru_first().count(), 0);
+        assert_eq!(cache.recency_rank(&1), None);
+        assert_eq!(cache.evict_lru(), None);
+
+        cache.insert(1, 2);
+        cache.clear();
+        assert_eq!(cache.iter_lru_first().count(), 0);
+        cache.insert(1, 2);
+        assert_eq!(cache.get(&1), Some(EntryState::Cached(&2)));
+
+        // Neither the keys nor the order allocate until there are more than `INLINE_LEN` entries.
+        for i in 2..=INLINE_LEN as u32 {
+            cache.insert(i, i);
+        }
+        assert!(!cache.order.entries.spilled());
+        assert!(cache.store.is_inline());
+        cache.insert(0, 0);
+        assert!(cache.order.entries.spilled());
+        assert!(!cache.store.is_inline());
+    }
+
+    #[test]
+    fn weight_follows_mutations() {
+        let mut cache = LruCache::<u32, Vec<u8>, _>::with_hasher(RandomState::default());
+        cache.insert(1, vec![0; 3]);
+        cache.set_weigher(Some(Weigher::new(|_, v: &Vec<u8>| v.len())));
+        assert_eq!(cache.total_weight(), 3);
+
+        cache.insert(2, vec![0; 5]);
+        cache
+            .get_or_repopulate_with(1, || unreachable!())
+            .unwrap()
+            .push(0);
+        assert_eq!(cache.total_weight(), 9);
+        cache.evict(1);
+        assert_eq!(cache.total_weight(), 5);
+        cache.get_or_repopulate_with(1, || vec![0; 2]);
+        cache.remove_lru();
+        assert_eq!(cache.total_weight(), 2);
+        cache.clear();
+        assert_eq!(cache.total_weight(), 0);
+    }
+
     #[test]
     fn get_after_insert_and_remove() {
         let mut cache = LruCache::with_hasher(RandomState::default());
@@ -472,6 +856,38 @@ mod tests {
         assert!(cache.len_evicted() == 4);
     }
 
+    #[test]
+    fn recency_rank_follows_accesses() {
+        let mut cache = LruCache::with_hasher(RandomState::default());
+
+        cache.insert(1, 2);
+        cache.insert(2, 3);
+        cache.insert(3, 4);
+        assert_eq!(cache.recency_rank(&3), Some(0));
+        assert_eq!(cache.recency_rank(&1), Some(2));
+
+        cache.get(&1);
+        assert_eq!(cache.recency_rank(&1), Some(0));
+        assert_eq!(cache.recency_rank(&2), Some(2));
+
+        cache.evict_lru();
+        assert_eq!(cache.recency_rank(&2), None);
+        assert_eq!(cache.recency_rank(&4), None);
+    }
+
+    #[test]
+    fn iter_lru_first_matches_eviction_order() {
+        let mut cache = LruCache::with_hasher(RandomState::default());
+
+        cache.insert(1, 2);
+        cache.insert(2, 3);
+        cache.insert(3, 4);
+        cache.get(&1);
+
+        let order: Vec<_> = cache.iter_lru_first().map(|(k, v)| (*k, *v)).collect();
+        assert_eq!(order, vec![(2, 3), (3, 4), (1, 2)]);
+    }
+
     #[test]
     fn get_const_does_not_affect_lru_order() {
         let mut cache = LruCache::with_hasher(RandomState::default());
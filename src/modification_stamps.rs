@@ -0,0 +1,141 @@
+use crate::{sharded_map::INLINE_LEN, ShardedHashMap};
+
+use alloc::collections::BTreeMap;
+use core::hash::{BuildHasher, Hash};
+use smallvec::SmallVec;
+
+/// Records a monotonically increasing "stamp" for each key every time its value is modified. This
+/// makes it cheap to find out which entries changed since some earlier point in time.
+///
+/// Keys without a stamp are treated as if they were last modified at stamp 0, i.e. before any
+/// stamp that could have been observed by a user.
+///
+/// Each key is also "dirty" from the time it's modified until it's marked clean, i.e. when the
+/// value is compressed, so the compressed form is known to be up to date.
+#[derive(Clone)]
+pub struct ModificationStamps<K, H> {
+    // The latest stamp of each key, and whether it's dirty.
+    stamps: ShardedHashMap<K, (u64, bool), H>,
+    // The keys ordered by their latest stamp, so finding recent changes doesn't scan every key.
+    // Empty while the stamps are inline, since scanning a handful of keys is cheaper than
+    // allocating the tree.
+    by_stamp: BTreeMap<u64, K>,
+    latest: u64,
+}
+
+impl<K, H> Default for ModificationStamps<K, H>
+where
+    H: Default,
+{
+    fn default() -> Self {
+        Self {
+            stamps: ShardedHashMap::default(),
+            by_stamp: BTreeMap::new(),
+            latest: 0,
+        }
+    }
+}
+
+impl<K, H> ModificationStamps<K, H>
+where
+    K: Clone + Eq + Hash,
+    H: BuildHasher + Default,
+{
+    /// The most recent stamp given to any key.
+    pub fn latest(&self) -> u64 {
+        self.latest
+    }
+
+    pub fn reserve(&mut self, additional: usize) {
+        let was_inline = self.stamps.is_inline();
+        self.stamps.reserve(additional);
+        if was_inline && !self.stamps.is_inline() {
+            self.index_stamps();
+        }
+    }
+
+    /// Records that `key` was just modified, which makes it dirty.
+    pub fn stamp(&mut self, key: K) {
+        self.latest += 1;
+        let was_inline = self.stamps.is_inline();
+        let old = self.stamps.insert(key.clone(), (self.latest, true));
+        if self.stamps.is_inline() {
+            return;
+        }
+        if was_inline {
+            self.index_stamps();
+
+            return;
+        }
+        if let Some((old, _)) = old {
+            self.by_stamp.remove(&old);
+        }
+        self.by_stamp.insert(self.latest, key);
+    }
+
+    /// Builds `by_stamp` once the stamps have moved out of their inline storage.
+    fn index_stamps(&mut self) {
+        self.by_stamp = self
+            .stamps
+            .iter()
+            .map(|(key, (stamp, _))| (*stamp, key.clone()))
+            .collect();
+    }
+
+    /// Records that `key` may have been modified without knowing whether it actually was, e.g.
+    /// when handing out a mutable reference. This makes it dirty without giving it a new stamp.
+    pub fn mark_dirty(&mut self, key: &K) {
+        if let Some((_, dirty)) = self.stamps.get_mut(key) {
+            *dirty = true;
+        }
+    }
+
+    /// The stamp of the last modification of `key`, or 0 if it has none.
+    pub fn get(&self, key: &K) -> u64 {
+        self.stamps.get(key).map_or(0, |(stamp, _)| *stamp)
+    }
+
+    /// Whether `key` was modified since it was last marked clean.
+    pub fn is_dirty(&self, key: &K) -> bool {
+        self.stamps.get(key).is_some_and(|(_, dirty)| *dirty)
+    }
+
+    /// Records that the compressed value of `key` is up to date with the latest modification.
+    pub fn mark_clean(&mut self, key: &K) {
+        if let Some((_, dirty)) = self.stamps.get_mut(key) {
+            *dirty = false;
+        }
+    }
+
+    pub fn remove(&mut self, key: &K) {
+        if let Some((stamp, _)) = self.stamps.remove(key) {
+            self.by_stamp.remove(&stamp);
+        }
+    }
+
+    pub fn clear(&mut self) {
+        self.stamps.clear();
+        self.by_stamp.clear();
+    }
+
+    /// All keys modified strictly after `stamp`, in the order they were modified. Only visits the
+    /// changed keys.
+    pub fn changed_since(&self, stamp: u64) -> impl Iterator<Item = &K> {
+        let mut inline = SmallVec::<[(u64, &K); INLINE_LEN]>::new();
+        if self.stamps.is_inline() {
+            inline.extend(
+                self.stamps
+                    .iter()
+                    .filter(|(_, (s, _))| *s > stamp)
+                    .map(|(k, (s, _))| (*s, k)),
+            );
+            inline.sort_unstable_by_key(|(s, _)| *s);
+        }
+
+        inline.into_iter().map(|(_, k)| k).chain(
+            self.by_stamp
+                .range(stamp.saturating_add(1)..)
+                .map(|(_, k)| k),
+        )
+    }
+}
@@ -0,0 +1,234 @@
+use crate::{
+    lru_cache::{EntryState, LruCache},
+    DefaultHashBuilder,
+};
+
+use alloc::vec::Vec;
+use core::hash::Hash;
+use hashbrown::HashMap;
+use serde::{Deserialize, Serialize};
+
+/// One operation on a `CompressibleMap` that changed its contents, LRU order, or the tier of an
+/// entry. Values are not recorded, only the keys and compressed sizes.
+#[derive(Clone, Debug, Deserialize, Eq, PartialEq, Serialize)]
+pub enum Op<K> {
+    /// A value was inserted into the cache.
+    Insert(K),
+    /// A compressed value was inserted.
+    InsertCompressed {
+        key: K,
+        compressed_size: usize,
+    },
+    /// A cached value was accessed, making it the most recently used.
+    Access(K),
+    /// A cached value was compressed.
+    Compress {
+        key: K,
+        compressed_size: usize,
+    },
+    /// A compressed value was decompressed into the cache.
+    Decompress(K),
+    Remove(K),
+    Clear,
+}
+
+/// Records `Op`s while recording is enabled.
+pub struct OpRecorder<K> {
+    log: Option<OpLog<K>>,
+}
+
+impl<K> Default for OpRecorder<K> {
+    fn default() -> Self {
+        Self { log: None }
+    }
+}
+
+impl<K> OpRecorder<K> {
+    pub fn start(&mut self) {
+        if self.log.is_none() {
+            self.log = Some(OpLog { ops: Vec::new() });
+        }
+    }
+
+    pub fn stop(&mut self) -> Option<OpLog<K>> {
+        self.log.take()
+    }
+
+    /// The op is only constructed if recording is enabled.
+    pub fn record(&mut self, make_op: impl FnOnce() -> Op<K>) {
+        if let Some(log) = &mut self.log {
+            log.ops.push(make_op());
+        }
+    }
+}
+
+/// The operations recorded between `CompressibleMap::start_recording` and
+/// `CompressibleMap::stop_recording`, in order. Since the map is deterministic, this is enough to
+/// reconstruct which entries were cached or compressed at every step, e.g. to reproduce a bug in
+/// the caching behavior from a log sent in by a user.
+#[derive(Clone, Debug, Deserialize, Eq, PartialEq, Serialize)]
+pub struct OpLog<K> {
+    ops: Vec<Op<K>>,
+}
+
+impl<K> OpLog<K> {
+    pub fn ops(&self) -> &[Op<K>] {
+        &self.ops
+    }
+
+    /// Replays the log from an empty map.
+    pub fn replay(&self) -> OpLogReplayer<'_, K>
+    where
+        K: Clone + Eq + Hash,
+    {
+        OpLogReplayer {
+            ops: self.ops.iter(),
+            cache: LruCache::default(),
+            compressed_sizes: HashMap::new(),
+        }
+    }
+}
+
+#[cfg(feature = "bincode")]
+impl<K> OpLog<K>
+where
+    K: Serialize + serde::de::DeserializeOwned,
+{
+    pub fn save(&self, writer: impl std::io::Write) -> bincode::Result<()> {
+        bincode::serialize_into(writer, self)
+    }
+
+    pub fn load(reader: impl std::io::Read) -> bincode::Result<Self> {
+        bincode::deserialize_from(reader)
+    }
+}
+
+/// Rebuilds the state of a map one `Op` at a time. Only keys and compressed sizes are tracked.
+pub struct OpLogReplayer<'a, K> {
+    ops: core::slice::Iter<'a, Op<K>>,
+    cache: LruCache<K, (), DefaultHashBuilder>,
+    compressed_sizes: HashMap<K, usize>,
+}
+
+impl<'a, K> OpLogReplayer<'a, K>
+where
+    K: Clone + Eq + Hash,
+{
+    /// Applies the next op and returns it, or `None` if the end of the log was reached.
+    pub fn step(&mut self) -> Option<&'a Op<K>> {
+        let op = self.ops.next()?;
+        match op {
+            Op::Insert(key) => {
+                self.cache.insert(key.clone(), ());
+                self.compressed_sizes.remove(key);
+            }
+            Op::InsertCompressed {
+                key,
+                compressed_size,
+            } => {
+                self.cache.evict(key.clone());
+                self.compressed_sizes.insert(key.clone(), *compressed_size);
+            }
+            Op::Access(key) => {
+                self.cache.get(key);
+            }
+            Op::Compress {
+                key,
+                compressed_size,
+            } => {
+                self.cache.evict(key.clone());
+                self.compressed_sizes.insert(key.clone(), *compressed_size);
+            }
+            Op::Decompress(key) => {
+                self.cache.get_or_repopulate_with(key.clone(), || ());
+                self.compressed_sizes.remove(key);
+            }
+            Op::Remove(key) => {
+                self.cache.remove(key);
+                self.compressed_sizes.remove(key);
+            }
+            Op::Clear => {
+                self.cache.clear();
+                self.compressed_sizes.clear();
+            }
+        }
+
+        Some(op)
+    }
+
+    pub fn is_cached(&self, key: &K) -> bool {
+        matches!(self.cache.get_const(key), Some(EntryState::Cached(_)))
+    }
+
+    pub fn compressed_size(&self, key: &K) -> Option<usize> {
+        self.compressed_sizes.get(key).cloned()
+    }
+
+    pub fn len_cached(&self) -> usize {
+        self.cache.len_cached()
+    }
+
+    pub fn len_compressed(&self) -> usize {
+        self.compressed_sizes.len()
+    }
+
+    /// The cached keys, starting with the one that would be compressed next.
+    pub fn cached_keys_lru_first(&self) -> impl Iterator<Item = &K> {
+        self.cache.iter_lru_first().map(|(k, _)| k)
+    }
+}
+
+// ████████╗███████╗███████╗████████╗███████╗
+// ╚══██╔══╝██╔════╝██╔════╝╚══██╔══╝██╔════╝
+//    ██║   █████╗  ███████╗   ██║   ███████╗
+//    ██║   ██╔══╝  ╚════██║   ██║   ╚════██║
+//    ██║   ███████╗███████║   ██║   ███████║
+//    ╚═╝   ╚══════╝╚══════╝   ╚═╝   ╚══════╝
+
+#[cfg(test)]
+mod tests {
+    use crate::test_util::{FakeFooCompression, Foo};
+    use crate::{CompressibleMap, LocalCache, Op};
+
+    #[test]
+    fn replay_matches_recorded_map() {
+        let mut map = CompressibleMap::<_, _, _>::new(FakeFooCompression);
+        map.insert(0, Foo(0));
+        map.start_recording();
+        for i in 1..5 {
+            map.insert(i, Foo(i));
+        }
+        map.compress_lru();
+        map.compress_lru();
+        map.get(2);
+        map.get(4);
+        map.remove(&3);
+        let local_cache = LocalCache::new();
+        map.get_const(0, &local_cache);
+        map.flush_local_cache(local_cache);
+        let log = map.stop_recording().unwrap();
+        map.insert(5, Foo(5));
+
+        assert_eq!(
+            log.ops()[4],
+            Op::Compress {
+                key: 0,
+                compressed_size: core::mem::size_of::<Foo>(),
+            }
+        );
+        assert!(map.stop_recording().is_none());
+
+        let mut replayer = log.replay();
+        while replayer.step().is_some() {}
+        // Key 0 was inserted before recording started, but the replay learns about it when it's
+        // compressed.
+        let keys: Vec<_> = replayer.cached_keys_lru_first().cloned().collect();
+        assert_eq!(keys, vec![2, 4, 0]);
+        assert_eq!(replayer.len_compressed(), 1);
+        assert_eq!(
+            replayer.compressed_size(&1),
+            Some(core::mem::size_of::<Foo>())
+        );
+        assert!(!replayer.is_cached(&1));
+    }
+}
@@ -0,0 +1,129 @@
+//! Python bindings for a map from `str` keys to `bytes` values that are compressed with LZ4.
+
+use crate::{BincodeCompression, CompressibleMap, Lz4};
+
+use pyo3::exceptions::PyKeyError;
+use pyo3::prelude::*;
+use pyo3::types::PyBytes;
+use std::collections::HashMap;
+
+/// A dict-like map from `str` to `bytes`. Call `compress_lru` to compress the least recently used
+/// value. Reading a compressed value decompresses it into the cache.
+#[pyclass(name = "CompressibleMap")]
+pub struct PyCompressibleMap {
+    map: CompressibleMap<String, Vec<u8>, BincodeCompression<Vec<u8>, Lz4>>,
+}
+
+#[pymethods]
+impl PyCompressibleMap {
+    #[new]
+    #[pyo3(signature = (lz4_level = 4))]
+    fn new(lz4_level: u32) -> Self {
+        let mut map = CompressibleMap::new(BincodeCompression::new(Lz4 { level: lz4_level }));
+        map.set_size_estimator(Vec::len);
+
+        Self { map }
+    }
+
+    fn __len__(&self) -> usize {
+        self.map.len()
+    }
+
+    fn __contains__(&self, key: String) -> bool {
+        self.map.contains_key(&key)
+    }
+
+    fn __getitem__<'py>(&mut self, py: Python<'py>, key: String) -> PyResult<Bound<'py, PyBytes>> {
+        match self.map.get(key.clone()) {
+            Some(bytes) => Ok(PyBytes::new_bound(py, bytes)),
+            None => Err(PyKeyError::new_err(key)),
+        }
+    }
+
+    fn __setitem__(&mut self, key: String, value: &[u8]) {
+        self.map.insert(key, value.to_vec());
+    }
+
+    fn __delitem__(&mut self, key: String) -> PyResult<()> {
+        match self.map.remove(&key) {
+            Some(_) => Ok(()),
+            None => Err(PyKeyError::new_err(key)),
+        }
+    }
+
+    fn keys(&self) -> Vec<String> {
+        self.map.keys().cloned().collect()
+    }
+
+    fn compress_lru(&mut self) {
+        self.map.compress_lru();
+    }
+
+    /// Returns a dict with the number of cached and compressed values, and the number of bytes
+    /// they use.
+    fn stats(&self) -> HashMap<&'static str, usize> {
+        let mut stats = HashMap::new();
+        stats.insert("len_cached", self.map.len_cached());
+        stats.insert("len_compressed", self.map.len_compressed());
+        stats.insert("cached_bytes", self.map.bytes_cached_estimate());
+        stats.insert("compressed_bytes", self.map.bytes_compressed());
+
+        stats
+    }
+}
+
+#[pymodule]
+fn compressible_map(module: &Bound<'_, PyModule>) -> PyResult<()> {
+    module.add_class::<PyCompressibleMap>()
+}
+
+// ████████╗███████╗███████╗████████╗███████╗
+// ╚══██╔══╝██╔════╝██╔════╝╚══██╔══╝██╔════╝
+//    ██║   █████╗  ███████╗   ██║   ███████╗
+//    ██║   ██╔══╝  ╚════██║   ██║   ╚════██║
+//    ██║   ███████╗███████║   ██║   ███████║
+//    ╚═╝   ╚══════╝╚══════╝   ╚═╝   ╚══════╝
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use pyo3::types::PyDict;
+
+    #[test]
+    fn dict_like_access_from_python() {
+        pyo3::prepare_freethreaded_python();
+        Python::with_gil(|py| {
+            let locals = PyDict::new_bound(py);
+            locals
+                .set_item("m", Bound::new(py, PyCompressibleMap::new(4)).unwrap())
+                .unwrap();
+            py.run_bound(
+                r#"
+m["a"] = b"x" * 100
+m["b"] = b""
+m.compress_lru()
+stats = m.stats()
+assert stats["len_compressed"] == 1, stats
+assert stats["compressed_bytes"] < 100, stats
+assert stats["cached_bytes"] == 0, stats
+m["c"] = b"y" * 10
+assert m.stats()["cached_bytes"] == 10
+del m["c"]
+assert m["a"] == b"x" * 100
+assert "b" in m and "c" not in m
+del m["b"]
+assert len(m) == 1
+try:
+    m["c"]
+    assert False
+except KeyError:
+    pass
+"#,
+                None,
+                Some(&locals),
+            )
+            .unwrap();
+        });
+    }
+}
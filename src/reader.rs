@@ -0,0 +1,107 @@
+use crate::{
+    Compressed, CompressedStorage, CompressibleMap, Compression, DefaultHashBuilder, LocalCache,
+    ShardedHashMap,
+};
+
+use core::hash::{BuildHasher, Hash};
+
+/// A read-only view of a `CompressibleMap` that owns its own `LocalCache`. Readers can be created
+/// from a shared reference to the map, so each thread can have one.
+///
+/// Reads never modify the map. Values that had to be decompressed are kept in the reader's local
+/// cache, and cache hits are remembered so the LRU order can be updated later. Call
+/// `into_local_cache` and pass the result to `CompressibleMap::flush_local_cache` to apply them.
+pub struct CompressibleMapReader<
+    'a,
+    K,
+    V,
+    A,
+    H = DefaultHashBuilder,
+    S = ShardedHashMap<K, Compressed<A>, H>,
+> where
+    A: Compression<Data = V>,
+{
+    map: &'a CompressibleMap<K, V, A, H, S>,
+    local_cache: LocalCache<K, V, H>,
+}
+
+impl<'a, K, V, A, H, S> CompressibleMapReader<'a, K, V, A, H, S>
+where
+    K: Clone + Eq + Hash,
+    H: BuildHasher + Default,
+    A: Compression<Data = V>,
+    S: CompressedStorage<K, Compressed<A>>,
+{
+    pub fn new(map: &'a CompressibleMap<K, V, A, H, S>) -> Self {
+        Self {
+            map,
+            local_cache: LocalCache::new(),
+        }
+    }
+
+    pub fn get(&self, key: &K) -> Option<&V> {
+        self.map.get_const(key.clone(), &self.local_cache)
+    }
+
+    pub fn map(&self) -> &'a CompressibleMap<K, V, A, H, S> {
+        self.map
+    }
+
+    /// Ends the read phase, returning the accesses that should be flushed back into the map.
+    pub fn into_local_cache(self) -> LocalCache<K, V, H> {
+        self.local_cache
+    }
+}
+
+// ████████╗███████╗███████╗████████╗███████╗
+// ╚══██╔══╝██╔════╝██╔════╝╚══██╔══╝██╔════╝
+//    ██║   █████╗  ███████╗   ██║   ███████╗
+//    ██║   ██╔══╝  ╚════██║   ██║   ╚════██║
+//    ██║   ███████╗███████║   ██║   ███████║
+//    ╚═╝   ╚══════╝╚══════╝   ╚═╝   ╚══════╝
+
+#[cfg(test)]
+mod tests {
+    use crate::test_util::{FakeFooCompression, Foo};
+    use crate::CompressibleMap;
+
+    #[test]
+    fn readers_on_many_threads_then_flush() {
+        use crossbeam::thread;
+
+        let mut map = CompressibleMap::<_, _, _>::new(FakeFooCompression);
+        for i in 0..10 {
+            map.insert(i, Foo(i));
+        }
+        for _ in 0..5 {
+            map.compress_lru();
+        }
+
+        let local_caches = thread::scope(|s| {
+            let handles: Vec<_> = (0..10)
+                .map(|i| {
+                    let reader = map.reader();
+                    s.spawn(move |_| {
+                        let expected = if i < 5 { Foo(i + 2) } else { Foo(i) };
+                        assert_eq!(reader.get(&i), Some(&expected));
+                        assert_eq!(reader.get(&100), None);
+
+                        reader.into_local_cache()
+                    })
+                })
+                .collect();
+
+            handles
+                .into_iter()
+                .map(|h| h.join().unwrap())
+                .collect::<Vec<_>>()
+        })
+        .unwrap();
+
+        for local_cache in local_caches {
+            map.flush_local_cache(local_cache);
+        }
+
+        assert_eq!(map.len_cached(), 10);
+    }
+}
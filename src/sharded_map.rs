@@ -0,0 +1,461 @@
+use alloc::vec::Vec;
+use core::fmt;
+use core::hash::{BuildHasher, Hash};
+use core::marker::PhantomData;
+use hashbrown::{hash_map, HashMap};
+use serde::{
+    de::{MapAccess, Visitor},
+    Deserialize, Deserializer, Serialize, Serializer,
+};
+use smallvec::SmallVec;
+
+/// The hasher of every map when none is named: the standard library's `RandomState` with the `std`
+/// feature, and hashbrown's default hasher without it.
+#[cfg(feature = "std")]
+pub type DefaultHashBuilder = std::collections::hash_map::RandomState;
+#[cfg(not(feature = "std"))]
+pub type DefaultHashBuilder = hashbrown::DefaultHashBuilder;
+
+/// The number of entries a `ShardedHashMap` keeps inline before it allocates a table. The LRU
+/// order of a `CompressibleMap` keeps as many cached values inline.
+pub(crate) const INLINE_LEN: usize = 4;
+/// The number of entries at which a `ShardedHashMap` splits into shards.
+const SPLIT_LEN: usize = 1 << 14;
+const SHARD_BITS: u32 = 6;
+const NUM_SHARDS: usize = 1 << SHARD_BITS;
+
+/// A hash map that splits into a fixed number of shards once it's large.
+///
+/// Growing a `HashMap` rehashes every entry at once, which takes long enough to cause a visible
+/// hitch when there are millions of entries. Each shard only holds a fraction of the entries, so
+/// growing the map only rehashes one shard at a time. Maps of medium size are a single `HashMap`,
+/// so they don't pay for hashing each key twice, and maps with up to `INLINE_LEN` entries keep
+/// them inline and search them linearly, so tiny maps, like per-region sub-maps, don't allocate.
+///
+/// A `CompressibleMap` uses this to track its keys, and to store the compressed values unless
+/// another `S` parameter is named.
+#[derive(Clone, Debug)]
+pub struct ShardedHashMap<K, V, H = DefaultHashBuilder> {
+    tables: Tables<K, V, H>,
+}
+
+#[derive(Clone, Debug)]
+enum Tables<K, V, H> {
+    // The hasher is kept for the table the entries move to.
+    Inline {
+        hasher: H,
+        entries: SmallVec<[(K, V); INLINE_LEN]>,
+    },
+    Single(HashMap<K, V, H>),
+    // The shard of a key is picked with a separate hasher, so the shards' own hashers see keys
+    // that are spread evenly.
+    Sharded {
+        hasher: H,
+        shards: Vec<HashMap<K, V, H>>,
+    },
+}
+
+impl<K, V, H> Default for ShardedHashMap<K, V, H>
+where
+    H: Default,
+{
+    fn default() -> Self {
+        Self::with_hasher(Default::default())
+    }
+}
+
+impl<K, V, H> ShardedHashMap<K, V, H> {
+    pub fn with_hasher(hasher_builder: H) -> Self {
+        Self {
+            tables: Tables::Inline {
+                hasher: hasher_builder,
+                entries: SmallVec::new(),
+            },
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.inline().len() + self.tables().iter().map(HashMap::len).sum::<usize>()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.inline().is_empty() && self.tables().iter().all(HashMap::is_empty)
+    }
+
+    /// Whether the entries are still inline, i.e. there have never been more than `INLINE_LEN`.
+    pub fn is_inline(&self) -> bool {
+        matches!(self.tables, Tables::Inline { .. })
+    }
+
+    /// Whether the map has been split into shards.
+    pub fn is_sharded(&self) -> bool {
+        matches!(self.tables, Tables::Sharded { .. })
+    }
+
+    /// The number of entries the map can hold without allocating.
+    pub fn capacity(&self) -> usize {
+        match &self.tables {
+            Tables::Inline { entries, .. } => entries.capacity(),
+            _ => self.tables().iter().map(HashMap::capacity).sum(),
+        }
+    }
+
+    pub fn keys(&self) -> impl Iterator<Item = &K> {
+        self.iter().map(|(k, _)| k)
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (&K, &V)> {
+        self.inline()
+            .iter()
+            .map(|(k, v)| (k, v))
+            .chain(self.tables().iter().flat_map(HashMap::iter))
+    }
+
+    /// Removes every entry, but keeps the tables and the memory they've allocated.
+    pub fn clear(&mut self) {
+        match &mut self.tables {
+            Tables::Inline { entries, .. } => entries.clear(),
+            Tables::Single(table) => table.clear(),
+            Tables::Sharded { shards, .. } => shards.iter_mut().for_each(HashMap::clear),
+        }
+    }
+
+    fn inline(&self) -> &[(K, V)] {
+        match &self.tables {
+            Tables::Inline { entries, .. } => entries,
+            _ => &[],
+        }
+    }
+
+    fn tables(&self) -> &[HashMap<K, V, H>] {
+        match &self.tables {
+            Tables::Inline { .. } => &[],
+            Tables::Single(table) => core::slice::from_ref(table),
+            Tables::Sharded { shards, .. } => shards,
+        }
+    }
+}
+
+impl<K, V, H> ShardedHashMap<K, V, H>
+where
+    K: Eq + Hash,
+    H: BuildHasher,
+{
+    pub fn get(&self, key: &K) -> Option<&V> {
+        match &self.tables {
+            Tables::Inline { entries, .. } => {
+                entries.iter().find(|(k, _)| k == key).map(|(_, v)| v)
+            }
+            _ => self.table(key).get(key),
+        }
+    }
+
+    pub fn get_mut(&mut self, key: &K) -> Option<&mut V> {
+        match &mut self.tables {
+            Tables::Inline { entries, .. } => {
+                entries.iter_mut().find(|(k, _)| k == key).map(|(_, v)| v)
+            }
+            Tables::Single(table) => table.get_mut(key),
+            Tables::Sharded { hasher, shards } => shards[shard_index(hasher, key)].get_mut(key),
+        }
+    }
+
+    pub fn contains_key(&self, key: &K) -> bool {
+        self.get(key).is_some()
+    }
+
+    pub fn remove(&mut self, key: &K) -> Option<V> {
+        match &mut self.tables {
+            Tables::Inline { entries, .. } => {
+                let position = entries.iter().position(|(k, _)| k == key)?;
+
+                Some(entries.swap_remove(position).1)
+            }
+            _ => self.table_mut(key).remove(key),
+        }
+    }
+
+    /// The table for `key`. Must not be called on inline entries.
+    fn table(&self, key: &K) -> &HashMap<K, V, H> {
+        match &self.tables {
+            Tables::Inline { .. } => unreachable!("Inline entries have no table"),
+            Tables::Single(table) => table,
+            Tables::Sharded { hasher, shards } => &shards[shard_index(hasher, key)],
+        }
+    }
+
+    /// The table for `key`. Must not be called on inline entries.
+    fn table_mut(&mut self, key: &K) -> &mut HashMap<K, V, H> {
+        match &mut self.tables {
+            Tables::Inline { .. } => unreachable!("Inline entries have no table"),
+            Tables::Single(table) => table,
+            Tables::Sharded { hasher, shards } => &mut shards[shard_index(hasher, key)],
+        }
+    }
+}
+
+impl<K, V, H> ShardedHashMap<K, V, H>
+where
+    K: Eq + Hash,
+    H: BuildHasher + Default,
+{
+    pub fn insert(&mut self, key: K, value: V) -> Option<V> {
+        if let Tables::Inline { entries, .. } = &mut self.tables {
+            if let Some((_, old)) = entries.iter_mut().find(|(k, _)| *k == key) {
+                return Some(core::mem::replace(old, value));
+            }
+            if entries.len() < INLINE_LEN {
+                entries.push((key, value));
+
+                return None;
+            }
+            self.move_to_table(0);
+        }
+        if matches!(&self.tables, Tables::Single(table) if table.len() >= SPLIT_LEN) {
+            self.split(0);
+        }
+
+        self.table_mut(&key).insert(key, value)
+    }
+
+    /// Makes room for `additional` more entries. Reserving more than fits inline moves the entries
+    /// to a table, and reserving more than a single table should hold splits the map right away.
+    pub fn reserve(&mut self, additional: usize) {
+        match &mut self.tables {
+            Tables::Inline { entries, .. } if entries.len() + additional <= INLINE_LEN => {}
+            Tables::Inline { .. } => {
+                self.move_to_table(0);
+                self.reserve(additional);
+            }
+            Tables::Single(table) if table.len() + additional < SPLIT_LEN => {
+                table.reserve(additional)
+            }
+            Tables::Single(_) => self.split(additional),
+            Tables::Sharded { shards, .. } => {
+                for shard in shards.iter_mut() {
+                    shard.reserve(additional / NUM_SHARDS + 1);
+                }
+            }
+        }
+    }
+
+    /// Moves inline entries into a single table with room for twice as many entries, plus
+    /// `additional`.
+    fn move_to_table(&mut self, additional: usize) {
+        let (hasher, entries) = match &mut self.tables {
+            Tables::Inline { hasher, entries } => {
+                (core::mem::take(hasher), core::mem::take(entries))
+            }
+            _ => return,
+        };
+
+        let mut table = HashMap::with_capacity_and_hasher(2 * entries.len() + additional, hasher);
+        table.extend(entries);
+        self.tables = Tables::Single(table);
+    }
+
+    /// Moves the entries of a single table into shards with room for the same number of entries
+    /// again, plus `additional`. This rehashes every entry once.
+    fn split(&mut self, additional: usize) {
+        let table = match &mut self.tables {
+            Tables::Single(table) => core::mem::replace(table, HashMap::with_hasher(H::default())),
+            _ => return,
+        };
+
+        let capacity = (2 * table.len() + additional) / NUM_SHARDS + 1;
+        let hasher = H::default();
+        let mut shards: Vec<_> = (0..NUM_SHARDS)
+            .map(|_| HashMap::with_capacity_and_hasher(capacity, H::default()))
+            .collect();
+        for (key, value) in table {
+            shards[shard_index(&hasher, &key)].insert(key, value);
+        }
+
+        self.tables = Tables::Sharded { hasher, shards };
+    }
+}
+
+impl<K, V, H> IntoIterator for ShardedHashMap<K, V, H> {
+    type Item = (K, V);
+    type IntoIter = core::iter::Chain<
+        smallvec::IntoIter<[(K, V); INLINE_LEN]>,
+        core::iter::FlatMap<
+            core::iter::Chain<
+                core::option::IntoIter<HashMap<K, V, H>>,
+                alloc::vec::IntoIter<HashMap<K, V, H>>,
+            >,
+            hash_map::IntoIter<K, V>,
+            fn(HashMap<K, V, H>) -> hash_map::IntoIter<K, V>,
+        >,
+    >;
+
+    fn into_iter(self) -> Self::IntoIter {
+        let (inline, first, rest) = match self.tables {
+            Tables::Inline { entries, .. } => (entries, None, Vec::new()),
+            Tables::Single(table) => (SmallVec::new(), Some(table), Vec::new()),
+            Tables::Sharded { shards, .. } => (SmallVec::new(), None, shards),
+        };
+
+        inline.into_iter().chain(
+            first
+                .into_iter()
+                .chain(rest)
+                .flat_map(IntoIterator::into_iter as fn(_) -> _),
+        )
+    }
+}
+
+/// Serialized like a `HashMap`, so the two can be swapped without changing the format.
+impl<K, V, H> Serialize for ShardedHashMap<K, V, H>
+where
+    K: Serialize,
+    V: Serialize,
+{
+    fn serialize<Z: Serializer>(&self, serializer: Z) -> Result<Z::Ok, Z::Error> {
+        serializer.collect_map(self.iter())
+    }
+}
+
+impl<'de, K, V, H> Deserialize<'de> for ShardedHashMap<K, V, H>
+where
+    K: Eq + Hash + Deserialize<'de>,
+    V: Deserialize<'de>,
+    H: BuildHasher + Default,
+{
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        struct MapVisitor<K, V, H>(PhantomData<ShardedHashMap<K, V, H>>);
+
+        impl<'de, K, V, H> Visitor<'de> for MapVisitor<K, V, H>
+        where
+            K: Eq + Hash + Deserialize<'de>,
+            V: Deserialize<'de>,
+            H: BuildHasher + Default,
+        {
+            type Value = ShardedHashMap<K, V, H>;
+
+            fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+                f.write_str("a map")
+            }
+
+            fn visit_map<M: MapAccess<'de>>(self, mut access: M) -> Result<Self::Value, M::Error> {
+                let mut map = ShardedHashMap::default();
+                // Don't trust the length too much, like serde's own `HashMap` impl.
+                map.reserve(access.size_hint().unwrap_or(0).min(4096));
+                while let Some((key, value)) = access.next_entry()? {
+                    map.insert(key, value);
+                }
+
+                Ok(map)
+            }
+        }
+
+        deserializer.deserialize_map(MapVisitor(PhantomData))
+    }
+}
+
+/// Picks the shard from the high bits of a multiplicative hash, so keys are spread over the shards
+/// even if the hasher passes integers through unchanged, like `IndexHasher`.
+fn shard_index<K: Hash, H: BuildHasher>(hasher: &H, key: &K) -> usize {
+    let hash = hasher.hash_one(key).wrapping_mul(0x9e37_79b9_7f4a_7c15);
+
+    (hash >> (64 - SHARD_BITS)) as usize
+}
+
+// ████████╗███████╗███████╗████████╗███████╗
+// ╚══██╔══╝██╔════╝██╔════╝╚══██╔══╝██╔════╝
+//    ██║   █████╗  ███████╗   ██║   ███████╗
+//    ██║   ██╔══╝  ╚════██║   ██║   ╚════██║
+//    ██║   ███████╗███████║   ██║   ███████║
+//    ╚═╝   ╚══════╝╚══════╝   ╚═╝   ╚══════╝
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        test_util::{FakeFooCompression, Foo},
+        CompressibleMap, IndexHasher,
+    };
+
+    use core::hash::BuildHasherDefault;
+
+    #[test]
+    fn small_maps_stay_inline() {
+        let mut map = ShardedHashMap::<u32, u32>::default();
+        for i in 0..INLINE_LEN as u32 {
+            assert_eq!(map.insert(i, i), None);
+        }
+        assert_eq!(map.insert(0, 10), Some(0));
+        assert_eq!(map.remove(&1), Some(1));
+        assert!(map.is_inline());
+        assert_eq!(map.capacity(), INLINE_LEN);
+
+        for i in 4..=INLINE_LEN as u32 + 1 {
+            map.insert(i, i);
+        }
+        assert!(!map.is_inline());
+        assert_eq!(map.len(), INLINE_LEN + 1);
+        assert_eq!(map.get(&0), Some(&10));
+        assert_eq!(map.get(&1), None);
+
+        let mut map = ShardedHashMap::<u32, u32>::default();
+        map.insert(1, 1);
+        map.reserve(INLINE_LEN);
+        assert!(!map.is_inline());
+        assert_eq!(map.into_iter().collect::<Vec<_>>(), vec![(1, 1)]);
+    }
+
+    #[test]
+    fn large_maps_split_into_shards() {
+        let mut map = ShardedHashMap::<usize, usize, BuildHasherDefault<IndexHasher>>::default();
+        for i in 0..SPLIT_LEN {
+            map.insert(i, i);
+        }
+        assert!(!map.is_sharded());
+
+        map.insert(SPLIT_LEN, SPLIT_LEN);
+        assert!(map.is_sharded());
+        assert_eq!(map.len(), SPLIT_LEN + 1);
+        // Even keys that aren't hashed are spread evenly.
+        if let Tables::Sharded { shards, .. } = &map.tables {
+            assert!(shards.iter().all(|s| s.len() < 2 * SPLIT_LEN / NUM_SHARDS));
+        }
+
+        for i in 0..=SPLIT_LEN {
+            assert_eq!(map.get(&i), Some(&i));
+        }
+        assert_eq!(map.remove(&3), Some(3));
+        assert!(!map.contains_key(&3));
+        assert_eq!(map.keys().count(), SPLIT_LEN);
+        assert_eq!(
+            map.into_iter().map(|(k, _)| k).sum::<usize>(),
+            (0..=SPLIT_LEN).sum::<usize>() - 3
+        );
+    }
+
+    #[test]
+    fn reserving_a_lot_splits_right_away() {
+        let mut map = ShardedHashMap::<u32, u32>::default();
+        map.insert(1, 1);
+        map.reserve(SPLIT_LEN);
+        assert!(map.is_sharded());
+        assert_eq!(map.get(&1), Some(&1));
+
+        map.clear();
+        assert!(map.is_empty());
+        assert!(map.is_sharded());
+    }
+
+    #[test]
+    fn map_with_sharded_storage() {
+        let mut map = CompressibleMap::<_, _, _, DefaultHashBuilder, ShardedHashMap<_, _>>::new(
+            FakeFooCompression,
+        );
+        map.reserve(SPLIT_LEN, 0);
+        for i in 0..4 {
+            map.insert(i, Foo(i));
+        }
+        map.compress_lru();
+        assert_eq!(map.len_compressed(), 1);
+        assert_eq!(map.get(0), Some(&Foo(2)));
+    }
+}
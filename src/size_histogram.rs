@@ -0,0 +1,88 @@
+use alloc::vec::Vec;
+use core::ops::RangeInclusive;
+
+/// Counts of cached and compressed values by their size in bytes. Bucket `i` counts sizes in the
+/// range `[2^i, 2^(i+1))`, except that bucket 0 also counts empty values.
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+pub struct SizeHistogram {
+    pub cached: Vec<usize>,
+    pub compressed: Vec<usize>,
+}
+
+impl SizeHistogram {
+    /// The index of the bucket that counts values of `size` bytes.
+    pub fn bucket(size: usize) -> usize {
+        if size == 0 {
+            0
+        } else {
+            (usize::BITS - 1 - size.leading_zeros()) as usize
+        }
+    }
+
+    /// The sizes counted by bucket `i`. The range is inclusive, so the last bucket can end at
+    /// `usize::MAX`. Panics if `i >= usize::BITS`, since there's no such bucket.
+    pub fn bucket_range(i: usize) -> RangeInclusive<usize> {
+        assert!(i < usize::BITS as usize, "No bucket {}", i);
+        let start = if i == 0 { 0 } else { 1 << i };
+        // 2^(i+1) - 1, without overflowing for the last bucket.
+        let end = usize::MAX >> (usize::BITS as usize - 1 - i);
+
+        start..=end
+    }
+
+    pub fn add_cached(&mut self, size: usize) {
+        Self::add(&mut self.cached, size)
+    }
+
+    pub fn add_compressed(&mut self, size: usize) {
+        Self::add(&mut self.compressed, size)
+    }
+
+    fn add(buckets: &mut Vec<usize>, size: usize) {
+        let i = Self::bucket(size);
+        if buckets.len() <= i {
+            buckets.resize(i + 1, 0);
+        }
+        buckets[i] += 1;
+    }
+}
+
+// ████████╗███████╗███████╗████████╗███████╗
+// ╚══██╔══╝██╔════╝██╔════╝╚══██╔══╝██╔════╝
+//    ██║   █████╗  ███████╗   ██║   ███████╗
+//    ██║   ██╔══╝  ╚════██║   ██║   ╚════██║
+//    ██║   ███████╗███████║   ██║   ███████║
+//    ╚═╝   ╚══════╝╚══════╝   ╚═╝   ╚══════╝
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn sizes_land_in_power_of_two_buckets() {
+        for size in [0, 1, 2, 3, 4, 1000, 1023, 1024, 1025].iter() {
+            assert!(SizeHistogram::bucket_range(SizeHistogram::bucket(*size)).contains(size));
+        }
+        assert_eq!(SizeHistogram::bucket_range(0), 0..=1);
+        assert_eq!(SizeHistogram::bucket_range(10), 1024..=2047);
+
+        let mut histogram = SizeHistogram::default();
+        histogram.add_cached(0);
+        histogram.add_cached(1);
+        histogram.add_cached(5);
+        histogram.add_compressed(2);
+
+        assert_eq!(histogram.cached, vec![2, 0, 1]);
+        assert_eq!(histogram.compressed, vec![0, 1]);
+    }
+
+    #[test]
+    fn largest_bucket_does_not_overflow() {
+        let last = SizeHistogram::bucket(usize::MAX);
+        assert_eq!(last, usize::BITS as usize - 1);
+
+        let range = SizeHistogram::bucket_range(last);
+        assert_eq!(range, (1 << last)..=usize::MAX);
+        assert!(range.contains(&usize::MAX));
+    }
+}
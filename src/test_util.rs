@@ -0,0 +1,26 @@
+//! Fakes shared by the unit tests of several modules.
+
+use crate::{Compressed, Compression};
+
+use serde::{Deserialize, Serialize};
+
+/// "Compresses" by adding 1 to the value, and "decompresses" by adding 1 again, so tests can tell
+/// whether a value went through a compression round trip.
+#[derive(Clone, Copy, Debug, Default, Deserialize, Serialize)]
+pub struct FakeFooCompression;
+
+impl Compression for FakeFooCompression {
+    type Data = Foo;
+    type CompressedData = Foo;
+
+    fn compress(&self, data: &Self::Data) -> Compressed<Self> {
+        Compressed::new(Foo(data.0 + 1))
+    }
+
+    fn decompress(compressed: &Self::CompressedData) -> Self::Data {
+        Foo(compressed.0 + 1)
+    }
+}
+
+#[derive(Clone, Debug, Default, Deserialize, Eq, PartialEq, Serialize)]
+pub struct Foo(pub u32);